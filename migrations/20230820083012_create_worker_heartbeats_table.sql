@@ -0,0 +1,9 @@
+CREATE TABLE worker_heartbeats (
+    worker_name TEXT NOT NULL,
+    last_heartbeat_at timestamptz NOT NULL,
+    -- How long until the worker is expected to send the next heartbeat
+    expected_interval_millis BIGINT NOT NULL,
+    succeeded_count BIGINT NOT NULL,
+    failed_count BIGINT NOT NULL,
+    PRIMARY KEY (worker_name)
+);
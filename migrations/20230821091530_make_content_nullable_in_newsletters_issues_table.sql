@@ -0,0 +1,3 @@
+-- Newsletters issue can contain only text content or only HTML content
+ALTER TABLE newsletters_issues ALTER COLUMN text_content DROP NOT NULL;
+ALTER TABLE newsletters_issues ALTER COLUMN html_content DROP NOT NULL;
@@ -0,0 +1,6 @@
+CREATE TABLE confirmation_emails_delivery_queue (
+    subscription_token TEXT NOT NULL REFERENCES subscription_tokens(subscription_token),
+    subscriber_email TEXT NOT NULL,
+    enqueued_at timestamptz NOT NULL,
+    PRIMARY KEY (subscription_token)
+);
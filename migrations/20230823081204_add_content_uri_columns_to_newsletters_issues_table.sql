@@ -0,0 +1,3 @@
+-- Content is stored in external content store when URI is present
+ALTER TABLE newsletters_issues ADD COLUMN text_content_uri TEXT NULL;
+ALTER TABLE newsletters_issues ADD COLUMN html_content_uri TEXT NULL;
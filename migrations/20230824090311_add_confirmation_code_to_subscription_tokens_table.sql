@@ -0,0 +1,7 @@
+BEGIN;
+-- Short numeric code is an alternative to confirmation link, only its hash is stored
+ALTER TABLE subscription_tokens ADD COLUMN confirmation_code_hash TEXT NULL;
+ALTER TABLE subscription_tokens ADD COLUMN created_at timestamptz NOT NULL DEFAULT now();
+-- Queued confirmation email needs plain code to send it later, code is deleted together with the task
+ALTER TABLE confirmation_emails_delivery_queue ADD COLUMN confirmation_code TEXT NULL;
+COMMIT;
@@ -0,0 +1,3 @@
+-- Confirmation code is invalidated after too many wrong guesses
+ALTER TABLE subscription_tokens
+    ADD COLUMN failed_confirmation_code_attempts INTEGER NOT NULL DEFAULT 0;
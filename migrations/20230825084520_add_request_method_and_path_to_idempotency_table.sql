@@ -0,0 +1,4 @@
+-- Idempotency key reused on a different endpoint must not replay another endpoint's response
+-- Existing records don't know their request, so columns are nullable
+ALTER TABLE idempotency ADD COLUMN request_method TEXT NULL;
+ALTER TABLE idempotency ADD COLUMN request_path TEXT NULL;
@@ -0,0 +1,3 @@
+-- Keep track of how many attempts have already taken place and when to try again
+ALTER TABLE confirmation_emails_delivery_queue ADD COLUMN n_retries INT NOT NULL DEFAULT 0;
+ALTER TABLE confirmation_emails_delivery_queue ADD COLUMN execute_after timestamptz NOT NULL DEFAULT now();
@@ -0,0 +1,2 @@
+-- Base32 encoded TOTP secret, NULL when user hasn't enrolled two-factor authentication
+ALTER TABLE users ADD COLUMN totp_secret TEXT NULL;
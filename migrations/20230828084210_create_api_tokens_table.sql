@@ -0,0 +1,10 @@
+CREATE TABLE api_tokens (
+    token_id uuid NOT NULL,
+    user_id uuid NOT NULL
+        REFERENCES users (user_id),
+    -- Only SHA-256 hash of token secret is stored
+    token_hash TEXT NOT NULL,
+    created_at timestamptz NOT NULL,
+    last_used_at timestamptz NULL,
+    PRIMARY KEY (token_id)
+);
@@ -0,0 +1,4 @@
+-- Keep track of failed delivery attempts so admins can see which recipients failed and why
+ALTER TABLE newsletters_issues_delivery_queue ADD COLUMN n_attempts INT NOT NULL DEFAULT 0;
+ALTER TABLE newsletters_issues_delivery_queue ADD COLUMN last_attempted_at timestamptz NULL;
+ALTER TABLE newsletters_issues_delivery_queue ADD COLUMN last_error text NULL;
@@ -0,0 +1,3 @@
+-- Tasks that are dropped from delivery queue because recipient email is invalid
+-- They are still counted in finished_n_tasks so issue can be completed
+ALTER TABLE newsletters_issues ADD COLUMN failed_n_tasks INT NOT NULL DEFAULT 0;
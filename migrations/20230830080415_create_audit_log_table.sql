@@ -0,0 +1,12 @@
+-- Append-only record of sensitive admin actions
+CREATE TABLE audit_log (
+    id uuid NOT NULL,
+    user_id uuid NOT NULL
+        REFERENCES users (user_id),
+    action TEXT NOT NULL,
+    -- What the action was applied to, e.g. id of published newsletters issue
+    target TEXT NULL,
+    request_id TEXT NULL,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (id)
+);
@@ -0,0 +1,3 @@
+-- Where subscribers came from, NULL for subscribers without tracking info
+ALTER TABLE subscriptions ADD COLUMN source TEXT NULL;
+ALTER TABLE subscriptions ADD COLUMN utm_campaign TEXT NULL;
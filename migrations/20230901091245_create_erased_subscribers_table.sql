@@ -0,0 +1,7 @@
+-- Tombstones of subscribers erased on request, plaintext email is never kept
+-- Hash of normalized email is enough to refuse importing them again
+CREATE TABLE erased_subscribers (
+    email_hash TEXT NOT NULL,
+    erased_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (email_hash)
+);
@@ -0,0 +1,2 @@
+-- Issues with higher priority are delivered before ones published earlier
+ALTER TABLE newsletters_issues ADD COLUMN priority INT NOT NULL DEFAULT 0;
@@ -0,0 +1,8 @@
+-- Subscribe is anonymous, its idempotency records have no user
+-- Nil uuid stands for anonymous in unique index, so anonymous keys are deduped too
+ALTER TABLE idempotency DROP CONSTRAINT idempotency_pkey;
+ALTER TABLE idempotency ALTER COLUMN user_id DROP NOT NULL;
+CREATE UNIQUE INDEX idempotency_user_id_idempotency_key_idx ON idempotency (
+    COALESCE(user_id, '00000000-0000-0000-0000-000000000000'::uuid),
+    idempotency_key
+);
@@ -0,0 +1,2 @@
+-- Tag of plus-addressed email, e.g. `blog` of `foo+blog@example.com`, email is stored without it
+ALTER TABLE subscriptions ADD COLUMN plus_tag TEXT NULL;
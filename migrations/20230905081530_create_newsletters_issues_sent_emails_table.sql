@@ -0,0 +1,9 @@
+-- Emails that newsletters issue was sent to, recorded right after each send outside of delivery transaction
+-- So when that transaction fails to commit, its tasks are left in queue but aren't sent again
+-- Rows are deleted along with their tasks, once deleting them is committed
+CREATE TABLE newsletters_issues_sent_emails (
+    id uuid NOT NULL REFERENCES newsletters_issues(id),
+    subscriber_email text NOT NULL,
+    sent_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (id, subscriber_email)
+);
@@ -0,0 +1,2 @@
+-- Set once archive copy of issue is sent, so each issue is archived only once
+ALTER TABLE newsletters_issues ADD COLUMN archived_at timestamptz NULL;
@@ -0,0 +1,9 @@
+-- Email as subscriber entered it, mail is delivered there while `email` keeps canonical form
+-- that subscribers are deduplicated and looked up by
+ALTER TABLE subscriptions ADD COLUMN delivery_email TEXT NULL;
+UPDATE subscriptions
+SET delivery_email = CASE
+    WHEN plus_tag IS NULL THEN email
+    ELSE split_part(email, '@', 1) || '+' || plus_tag || '@' || split_part(email, '@', 2)
+END;
+ALTER TABLE subscriptions ALTER COLUMN delivery_email SET NOT NULL;
@@ -0,0 +1,3 @@
+-- Time step of the last accepted TOTP code, codes of this step or earlier ones are rejected
+-- So a code observed by someone else can't be replayed while it's still valid
+ALTER TABLE users ADD COLUMN totp_last_used_step BIGINT NULL;
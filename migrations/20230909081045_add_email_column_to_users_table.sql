@@ -0,0 +1,2 @@
+-- Address of admin user, e.g. test emails are sent there
+ALTER TABLE users ADD COLUMN email TEXT NULL;
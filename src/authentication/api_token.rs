@@ -0,0 +1,126 @@
+use crate::authentication::AuthError;
+use anyhow::Context;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use subtle::ConstantTimeEq;
+use uuid::Uuid;
+
+// Token format: `<token_id>.<secret>`
+// Token id is used to look up the token, so secret hash can be compared in constant time
+const TOKEN_SEPARATOR: char = '.';
+
+fn hash_api_token_secret(secret: &str) -> String {
+    hex::encode(Sha256::digest(secret.as_bytes()))
+}
+
+// Generate Alphanumeric 32-characters-long secret
+// High entropy random secret doesn't need slow password hashing like argon2
+fn generate_api_token_secret() -> String {
+    let mut rng = rand::thread_rng();
+    std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(32)
+        .collect()
+}
+
+// Return token id and token, token is only shown once and can't be recovered later
+#[tracing::instrument(name = "Create a new API token", skip(pg_pool))]
+pub async fn create_api_token(
+    pg_pool: &PgPool,
+    user_id: &Uuid,
+) -> Result<(Uuid, Secret<String>), sqlx::Error> {
+    let token_id = Uuid::new_v4();
+    let secret = generate_api_token_secret();
+    sqlx::query!(
+        r#"
+        INSERT INTO api_tokens (token_id, user_id, token_hash, created_at)
+        VALUES ($1, $2, $3, now())
+        "#,
+        token_id,
+        user_id,
+        hash_api_token_secret(&secret)
+    )
+    .execute(pg_pool)
+    .await?;
+
+    let token = format!("{}{}{}", token_id.simple(), TOKEN_SEPARATOR, secret);
+    Ok((token_id, Secret::new(token)))
+}
+
+// Return false if there is no such token owned by user
+#[tracing::instrument(name = "Revoke an API token", skip(pg_pool))]
+pub async fn revoke_api_token(
+    pg_pool: &PgPool,
+    user_id: &Uuid,
+    token_id: &Uuid,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        DELETE FROM api_tokens
+        WHERE token_id = $1 AND user_id = $2
+        "#,
+        token_id,
+        user_id
+    )
+    .execute(pg_pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+#[tracing::instrument(name = "Validate API token", skip_all)]
+pub async fn validate_api_token(
+    pg_pool: &PgPool,
+    token: Secret<String>,
+) -> Result<Uuid, AuthError> {
+    let (token_id, secret) = token
+        .expose_secret()
+        .split_once(TOKEN_SEPARATOR)
+        .context("API token is malformed")
+        .map_err(AuthError::InvalidCredentials)?;
+    let token_id = Uuid::parse_str(token_id)
+        .context("API token id is malformed")
+        .map_err(AuthError::InvalidCredentials)?;
+
+    let stored_token = sqlx::query!(
+        r#"
+        SELECT user_id, token_hash
+        FROM api_tokens
+        WHERE token_id = $1
+        "#,
+        token_id
+    )
+    .fetch_optional(pg_pool)
+    .await
+    .context("Failed to fetch API token from database")?
+    .context("API token doesn't exist or is revoked")
+    .map_err(AuthError::InvalidCredentials)?;
+
+    // Avoid leaking how many leading characters of hash match through response time
+    let is_valid: bool = hash_api_token_secret(secret)
+        .as_bytes()
+        .ct_eq(stored_token.token_hash.as_bytes())
+        .into();
+    if !is_valid {
+        return Err(AuthError::InvalidCredentials(anyhow::anyhow!(
+            "Invalid API token"
+        )));
+    }
+
+    sqlx::query!(
+        r#"
+        UPDATE api_tokens
+        SET last_used_at = now()
+        WHERE token_id = $1
+        "#,
+        token_id
+    )
+    .execute(pg_pool)
+    .await
+    .context("Failed to update API token last used time")?;
+
+    Ok(stored_token.user_id)
+}
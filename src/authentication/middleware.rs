@@ -1,10 +1,19 @@
-use crate::authentication::UserSession;
+use crate::authentication::{
+    get_credentials_from_basic_auth, get_user_totp_secret, validate_api_token,
+    validate_credentials, AuthError, SessionLifetime, UserSession,
+};
 use crate::utils::{e500, see_other};
 use actix_web::body::MessageBody;
 use actix_web::dev::{ServiceRequest, ServiceResponse};
 use actix_web::error::InternalError;
-use actix_web::{FromRequest, HttpMessage};
+use actix_web::guard::GuardContext;
+use actix_web::http::header::{HeaderMap, AUTHORIZATION, WWW_AUTHENTICATE};
+use actix_web::web::Data;
+use actix_web::{FromRequest, HttpMessage, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
 use actix_web_lab::middleware::Next;
+use secrecy::Secret;
+use sqlx::PgPool;
 use std::fmt::Display;
 use std::ops::Deref;
 use uuid::Uuid;
@@ -34,15 +43,119 @@ pub async fn reject_anonymous_users(
         UserSession::from_request(http_req, payload).await?
     };
 
-    match session.get_user_id().map_err(e500)? {
-        Some(user_id) => {
-            req.extensions_mut().insert(UserId(user_id));
-            Ok(next.call(req).await?)
-        }
+    let user_id = match session.get_user_id().map_err(e500)? {
+        Some(user_id) => user_id,
         None => {
             let response = see_other("/login");
             let error = anyhow::anyhow!("Login required");
-            Err(InternalError::from_response(error, response).into())
+            return Err(InternalError::from_response(error, response).into());
+        }
+    };
+
+    if let Some(lifetime) = req.app_data::<Data<SessionLifetime>>() {
+        if session.is_expired(lifetime).map_err(e500)? {
+            session.logout();
+            FlashMessage::info("Your session has expired, please login again").send();
+            let response = see_other("/login");
+            let error = anyhow::anyhow!("Session expired");
+            return Err(InternalError::from_response(error, response).into());
         }
     }
+
+    // Keep session alive while user is active
+    session.touch().map_err(e500)?;
+    req.extensions_mut().insert(UserId(user_id));
+    Ok(next.call(req).await?)
+}
+
+fn get_bearer_token(headers: &HeaderMap) -> Option<Secret<String>> {
+    headers
+        .get(AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(|token| Secret::new(token.trim().to_string()))
+}
+
+// Scripts authenticate with API token, while browsers authenticate with session cookie
+pub fn is_api_token_request(headers: &HeaderMap) -> bool {
+    get_bearer_token(headers).is_some()
+}
+
+// Route requests that carry an API token to routes guarded by `reject_invalid_api_tokens`
+pub fn has_bearer_token(ctx: &GuardContext) -> bool {
+    is_api_token_request(ctx.head().headers())
+}
+
+// Authenticate scripts with `Authorization: Bearer <token>` instead of session cookie
+pub async fn reject_invalid_api_tokens(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let pg_pool = req
+        .app_data::<Data<PgPool>>()
+        .expect("PgPool is not registered as app data")
+        .clone();
+    let token = get_bearer_token(req.headers());
+    let error = match token {
+        Some(token) => match validate_api_token(&pg_pool, token).await {
+            Ok(user_id) => {
+                req.extensions_mut().insert(UserId(user_id));
+                return Ok(next.call(req).await?);
+            }
+            Err(AuthError::UnexpectedError(e)) => return Err(e500(e)),
+            Err(AuthError::InvalidCredentials(e)) => e,
+        },
+        None => anyhow::anyhow!("Missing API token"),
+    };
+
+    let response = HttpResponse::Unauthorized()
+        .insert_header((WWW_AUTHENTICATE, r#"Bearer realm="admin""#))
+        .finish();
+    Err(InternalError::from_response(error, response).into())
+}
+
+// Route requests that carry Basic credentials to routes guarded by `reject_invalid_basic_auth_credentials`
+pub fn has_basic_auth(ctx: &GuardContext) -> bool {
+    ctx.head()
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.starts_with("Basic "))
+        .unwrap_or(false)
+}
+
+// Authenticate legacy scripts with `Authorization: Basic` instead of session cookie
+pub async fn reject_invalid_basic_auth_credentials(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let pg_pool = req
+        .app_data::<Data<PgPool>>()
+        .expect("PgPool is not registered as app data")
+        .clone();
+    let error = match get_credentials_from_basic_auth(req.headers()) {
+        Ok(credentials) => match validate_credentials(&pg_pool, credentials).await {
+            Ok(user_id) => match get_user_totp_secret(&pg_pool, &user_id)
+                .await
+                .map_err(e500)?
+            {
+                None => {
+                    req.extensions_mut().insert(UserId(user_id));
+                    return Ok(next.call(req).await?);
+                }
+                Some(_) => anyhow::anyhow!(
+                    "User with two-factor authentication must publish with API token"
+                ),
+            },
+            Err(AuthError::UnexpectedError(e)) => return Err(e500(e)),
+            Err(AuthError::InvalidCredentials(e)) => e,
+        },
+        Err(e) => e,
+    };
+
+    let response = HttpResponse::Unauthorized()
+        .insert_header((WWW_AUTHENTICATE, r#"Basic realm="admin""#))
+        .finish();
+    Err(InternalError::from_response(error, response).into())
 }
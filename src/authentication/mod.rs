@@ -1,5 +1,14 @@
+mod api_token;
 mod middleware;
 mod password;
+mod session_store;
+mod totp;
 
-pub use middleware::{reject_anonymous_users, UserId};
+pub use api_token::*;
+pub use middleware::{
+    has_basic_auth, has_bearer_token, is_api_token_request, reject_anonymous_users,
+    reject_invalid_api_tokens, reject_invalid_basic_auth_credentials, UserId,
+};
 pub use password::*;
+pub use session_store::*;
+pub use totp::*;
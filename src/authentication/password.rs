@@ -1,3 +1,4 @@
+use crate::configuration::ApplicationSettings;
 use crate::utils::{error_chain_fmt, spawn_blocking_task_with_tracing};
 use actix_session::{Session, SessionExt, SessionGetError, SessionInsertError};
 use actix_web::dev::Payload;
@@ -8,10 +9,13 @@ use argon2::password_hash::rand_core::OsRng;
 use argon2::password_hash::SaltString;
 use argon2::{Algorithm, Argon2, Params, PasswordHash, PasswordHasher, PasswordVerifier, Version};
 use base64::Engine;
+use chrono::Utc;
 use secrecy::{ExposeSecret, Secret};
 use sqlx::PgPool;
+use std::collections::HashMap;
 use std::fmt::Debug;
 use std::future::{ready, Ready};
+use std::time::Duration;
 use uuid::Uuid;
 
 #[derive(thiserror::Error)]
@@ -33,10 +37,64 @@ pub struct Credentials {
     pub password: Secret<String>,
 }
 
+// How long a logged in session is valid
+#[derive(Clone, Copy, Debug)]
+pub struct SessionLifetime {
+    pub idle_timeout: Duration,
+    pub max_lifetime: Duration,
+    pub remember_me_ttl: Duration,
+}
+
+impl SessionLifetime {
+    pub fn from_settings(settings: &ApplicationSettings) -> Self {
+        Self {
+            idle_timeout: Duration::from_millis(settings.session_idle_timeout_millis),
+            max_lifetime: Duration::from_millis(settings.session_max_lifetime_millis),
+            remember_me_ttl: Duration::from_millis(settings.session_remember_me_ttl_millis),
+        }
+    }
+
+    // How long session state is kept in session store from now
+    // Logged in sessions never outlive their absolute lifetime, counted from login
+    // Anonymous sessions only live as long as idle timeout
+    pub fn state_ttl(&self, state: &HashMap<String, String>) -> Duration {
+        let get = |key: &str| {
+            state
+                .get(key)
+                .and_then(|value| serde_json::from_str(value).ok())
+        };
+        let logged_in_at: i64 = match get(UserSession::LOGGED_IN_AT_KEY) {
+            Some(logged_in_at) => logged_in_at,
+            None => return self.idle_timeout,
+        };
+        let elapsed = Duration::from_millis(
+            u64::try_from(Utc::now().timestamp_millis() - logged_in_at).unwrap_or_default(),
+        );
+
+        let is_remembered: bool = get(UserSession::REMEMBER_ME_KEY).unwrap_or_default();
+        match is_remembered {
+            true => self.remember_me_ttl.saturating_sub(elapsed),
+            false => self
+                .idle_timeout
+                .min(self.max_lifetime.saturating_sub(elapsed)),
+        }
+    }
+}
+
 pub struct UserSession(Session);
 
 impl UserSession {
     const USER_ID_KEY: &'static str = "user_id";
+    // Unix timestamps in milliseconds
+    const LOGGED_IN_AT_KEY: &'static str = "logged_in_at";
+    const LAST_SEEN_AT_KEY: &'static str = "last_seen_at";
+    const REMEMBER_ME_KEY: &'static str = "remember_me";
+    // User passed password validation but still needs to verify TOTP code
+    const AWAITING_2FA_USER_ID_KEY: &'static str = "awaiting_2fa_user_id";
+    const FAILED_2FA_ATTEMPTS_KEY: &'static str = "failed_2fa_attempts";
+    // Secret is only stored to database after user verified a code generated from it
+    const PENDING_TOTP_SECRET_KEY: &'static str = "pending_totp_secret";
+    const LOGIN_REDIRECT_KEY: &'static str = "login_redirect";
 
     pub fn new(session: Session) -> Self {
         Self(session)
@@ -47,13 +105,110 @@ impl UserSession {
     }
 
     pub fn insert_user_id(&self, user_id: Uuid) -> Result<(), SessionInsertError> {
+        let now = Utc::now().timestamp_millis();
+        self.0.insert(Self::LOGGED_IN_AT_KEY, now)?;
+        self.0.insert(Self::LAST_SEEN_AT_KEY, now)?;
         self.0.insert(Self::USER_ID_KEY, user_id)
     }
 
+    // Session without timestamps is treated as expired
+    pub fn is_expired(&self, lifetime: &SessionLifetime) -> Result<bool, SessionGetError> {
+        let (logged_in_at, last_seen_at) = match (
+            self.0.get::<i64>(Self::LOGGED_IN_AT_KEY)?,
+            self.0.get::<i64>(Self::LAST_SEEN_AT_KEY)?,
+        ) {
+            (Some(logged_in_at), Some(last_seen_at)) => (logged_in_at, last_seen_at),
+            _ => return Ok(true),
+        };
+        let now = Utc::now().timestamp_millis();
+        let elapsed_millis = |since: i64| u128::try_from(now - since).unwrap_or_default();
+
+        if self.is_remembered()? {
+            return Ok(elapsed_millis(logged_in_at) > lifetime.remember_me_ttl.as_millis());
+        }
+        Ok(
+            elapsed_millis(last_seen_at) > lifetime.idle_timeout.as_millis()
+                || elapsed_millis(logged_in_at) > lifetime.max_lifetime.as_millis(),
+        )
+    }
+
+    pub fn insert_remember_me(&self, remember_me: bool) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::REMEMBER_ME_KEY, remember_me)
+    }
+
+    pub fn is_remembered(&self) -> Result<bool, SessionGetError> {
+        Ok(self
+            .0
+            .get::<bool>(Self::REMEMBER_ME_KEY)?
+            .unwrap_or_default())
+    }
+
+    pub fn touch(&self) -> Result<(), SessionInsertError> {
+        self.0
+            .insert(Self::LAST_SEEN_AT_KEY, Utc::now().timestamp_millis())
+    }
+
     pub fn get_user_id(&self) -> Result<Option<Uuid>, SessionGetError> {
         self.0.get(Self::USER_ID_KEY)
     }
 
+    pub fn insert_awaiting_2fa_user_id(&self, user_id: Uuid) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::AWAITING_2FA_USER_ID_KEY, user_id)
+    }
+
+    pub fn get_awaiting_2fa_user_id(&self) -> Result<Option<Uuid>, SessionGetError> {
+        self.0.get(Self::AWAITING_2FA_USER_ID_KEY)
+    }
+
+    // Return number of failed attempts including this one
+    pub fn increase_failed_2fa_attempts(&self) -> Result<u32, anyhow::Error> {
+        let n_attempts = self
+            .0
+            .get::<u32>(Self::FAILED_2FA_ATTEMPTS_KEY)?
+            .unwrap_or_default()
+            + 1;
+        self.0.insert(Self::FAILED_2FA_ATTEMPTS_KEY, n_attempts)?;
+        Ok(n_attempts)
+    }
+
+    // Keep where user goes after login across two-factor authentication step
+    pub fn insert_login_redirect(&self, next: &str) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::LOGIN_REDIRECT_KEY, next)
+    }
+
+    pub fn take_login_redirect(&self) -> Result<Option<String>, SessionGetError> {
+        let next = self.0.get(Self::LOGIN_REDIRECT_KEY)?;
+        self.0.remove(Self::LOGIN_REDIRECT_KEY);
+        Ok(next)
+    }
+
+    // Promote user awaiting 2fa to logged in user
+    pub fn complete_2fa(&self, user_id: Uuid) -> Result<(), SessionInsertError> {
+        self.0.renew();
+        self.0.remove(Self::AWAITING_2FA_USER_ID_KEY);
+        self.0.remove(Self::FAILED_2FA_ATTEMPTS_KEY);
+        self.insert_user_id(user_id)
+    }
+
+    pub fn insert_pending_totp_secret(
+        &self,
+        secret: &Secret<String>,
+    ) -> Result<(), SessionInsertError> {
+        self.0
+            .insert(Self::PENDING_TOTP_SECRET_KEY, secret.expose_secret())
+    }
+
+    pub fn get_pending_totp_secret(&self) -> Result<Option<Secret<String>>, SessionGetError> {
+        Ok(self
+            .0
+            .get::<String>(Self::PENDING_TOTP_SECRET_KEY)?
+            .map(Secret::new))
+    }
+
+    pub fn remove_pending_totp_secret(&self) {
+        self.0.remove(Self::PENDING_TOTP_SECRET_KEY);
+    }
+
     pub fn logout(&self) {
         self.0.purge();
     }
@@ -130,14 +285,17 @@ async fn get_credentials_from_database(
     Ok(credentials)
 }
 
+// Verified against when username doesn't exist, so response time doesn't reveal valid usernames
+// Must be hashed with the same parameters as `hash_password` to cost the same time to verify
+const HASHED_PASSWORD_IF_INVALID_USERNAME: &str = "$argon2d$v=19$m=15000,t=2,p=1\
+    $QhQyHN2/VvKTi5QYqo+VZA\
+    $JkXwR/rdESxDi2DfcCf8lk2U4+ShyN3CXZATJQvP0lg";
+
 #[tracing::instrument(name = "Validate credentials from database", skip_all)]
 pub async fn validate_credentials(
     pg_pool: &PgPool,
     credentials: Credentials,
 ) -> Result<Uuid, AuthError> {
-    const HASHED_PASSWORD_IF_INVALID_USERNAME: &str = "$argon2d$v=19$m=15000,t=2,p=1\
-        $QhQyHN2/VvKTi5QYqo+VZA\
-        $JkXwR/rdESxDi2DfcCf8lk2U4+ShyN3CXZATJQvP0lg";
     let mut user_id = None;
     let mut expected_password_hash = Secret::new(HASHED_PASSWORD_IF_INVALID_USERNAME.to_string());
 
@@ -210,3 +368,88 @@ pub async fn update_user_password_to_database(
     .await?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::authentication::password::HASHED_PASSWORD_IF_INVALID_USERNAME;
+    use crate::authentication::{hash_password, verify_password_hash, AuthError, SessionLifetime};
+    use argon2::PasswordHash;
+    use chrono::Utc;
+    use secrecy::Secret;
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    const HOUR: Duration = Duration::from_secs(60 * 60);
+
+    fn session_lifetime() -> SessionLifetime {
+        SessionLifetime {
+            idle_timeout: HOUR,
+            max_lifetime: 12 * HOUR,
+            remember_me_ttl: 30 * 24 * HOUR,
+        }
+    }
+
+    fn logged_in_state(logged_in_ago: Duration, remember_me: bool) -> HashMap<String, String> {
+        let logged_in_at = Utc::now().timestamp_millis() - logged_in_ago.as_millis() as i64;
+        HashMap::from([
+            ("logged_in_at".to_string(), logged_in_at.to_string()),
+            ("remember_me".to_string(), remember_me.to_string()),
+        ])
+    }
+
+    #[test]
+    fn fallback_hash_costs_the_same_as_real_password_hash() {
+        let real_hash = hash_password("password").unwrap();
+        let real_hash = PasswordHash::new(&real_hash).unwrap();
+        let fallback_hash = PasswordHash::new(HASHED_PASSWORD_IF_INVALID_USERNAME).unwrap();
+
+        assert_eq!(fallback_hash.algorithm, real_hash.algorithm);
+        assert_eq!(fallback_hash.version, real_hash.version);
+        assert_eq!(fallback_hash.params, real_hash.params);
+    }
+
+    #[test]
+    fn password_is_verified_against_fallback_hash_for_unknown_username() {
+        let result = verify_password_hash(
+            Secret::new(uuid::Uuid::new_v4().to_string()),
+            Secret::new(HASHED_PASSWORD_IF_INVALID_USERNAME.to_string()),
+        );
+
+        // Hash is parsed and verified, rather than failing early
+        assert!(matches!(result, Err(AuthError::InvalidCredentials(_))));
+    }
+
+    #[test]
+    fn anonymous_session_state_lives_as_long_as_idle_timeout() {
+        let ttl = session_lifetime().state_ttl(&HashMap::new());
+
+        assert_eq!(ttl, HOUR);
+    }
+
+    #[test]
+    fn session_state_ttl_depends_on_remember_me() {
+        let lifetime = session_lifetime();
+
+        let ttl = lifetime.state_ttl(&logged_in_state(Duration::ZERO, false));
+        let remembered_ttl = lifetime.state_ttl(&logged_in_state(Duration::ZERO, true));
+
+        assert!(ttl <= HOUR);
+        assert!(remembered_ttl > 29 * 24 * HOUR);
+    }
+
+    #[test]
+    fn session_state_never_outlives_absolute_lifetime() {
+        let lifetime = session_lifetime();
+
+        let half_hour = HOUR / 2;
+
+        let ttl = lifetime.state_ttl(&logged_in_state(11 * HOUR + half_hour, false));
+        let expired_ttl = lifetime.state_ttl(&logged_in_state(12 * HOUR, false));
+        let remembered_ttl = lifetime.state_ttl(&logged_in_state(30 * 24 * HOUR, true));
+
+        // Shorter than idle timeout, because session reaches its max lifetime first
+        assert!(ttl <= half_hour && ttl > half_hour - Duration::from_secs(60));
+        assert_eq!(expired_ttl, Duration::ZERO);
+        assert_eq!(remembered_ttl, Duration::ZERO);
+    }
+}
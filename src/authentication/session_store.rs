@@ -0,0 +1,74 @@
+use crate::authentication::SessionLifetime;
+use actix_session::storage::{LoadError, SaveError, SessionKey, SessionStore, UpdateError};
+use actix_web::cookie::time;
+use std::collections::HashMap;
+
+// Redis refuses zero TTL, so sessions at the end of their lifetime are kept one more second
+const MIN_STATE_TTL: time::Duration = time::Duration::SECOND;
+
+// SessionMiddleware applies one TTL to every session state
+// Replace it with TTL of each session, so remembered sessions are kept for `remember_me_ttl`,
+// while others are dropped after idle timeout, and no session outlives its absolute lifetime
+#[derive(Clone)]
+pub struct SessionLifetimeStore<S> {
+    inner: S,
+    lifetime: SessionLifetime,
+}
+
+impl<S: SessionStore> SessionLifetimeStore<S> {
+    pub fn new(inner: S, lifetime: SessionLifetime) -> Self {
+        Self { inner, lifetime }
+    }
+
+    fn state_ttl(&self, state: &HashMap<String, String>) -> time::Duration {
+        let ttl = self.lifetime.state_ttl(state);
+        time::Duration::milliseconds(i64::try_from(ttl.as_millis()).unwrap_or(i64::MAX))
+            .max(MIN_STATE_TTL)
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl<S: SessionStore> SessionStore for SessionLifetimeStore<S> {
+    async fn load(
+        &self,
+        session_key: &SessionKey,
+    ) -> Result<Option<HashMap<String, String>>, LoadError> {
+        self.inner.load(session_key).await
+    }
+
+    async fn save(
+        &self,
+        session_state: HashMap<String, String>,
+        _ttl: &time::Duration,
+    ) -> Result<SessionKey, SaveError> {
+        let ttl = self.state_ttl(&session_state);
+        self.inner.save(session_state, &ttl).await
+    }
+
+    async fn update(
+        &self,
+        session_key: SessionKey,
+        session_state: HashMap<String, String>,
+        _ttl: &time::Duration,
+    ) -> Result<SessionKey, UpdateError> {
+        let ttl = self.state_ttl(&session_state);
+        self.inner.update(session_key, session_state, &ttl).await
+    }
+
+    async fn update_ttl(
+        &self,
+        session_key: &SessionKey,
+        _ttl: &time::Duration,
+    ) -> Result<(), anyhow::Error> {
+        let session_state = match self.inner.load(session_key).await? {
+            Some(session_state) => session_state,
+            None => return Ok(()),
+        };
+        let ttl = self.state_ttl(&session_state);
+        self.inner.update_ttl(session_key, &ttl).await
+    }
+
+    async fn delete(&self, session_key: &SessionKey) -> Result<(), anyhow::Error> {
+        self.inner.delete(session_key).await
+    }
+}
@@ -0,0 +1,124 @@
+use anyhow::Context;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use std::time::{SystemTime, UNIX_EPOCH};
+use subtle::ConstantTimeEq;
+use totp_rs::{Algorithm, Secret as TotpSecret, TOTP};
+use uuid::Uuid;
+
+const TOTP_ISSUER: &str = "Zero2Prod";
+const TOTP_DIGITS: usize = 6;
+const TOTP_STEP_SECS: u64 = 30;
+// Accept codes of previous and next time steps to tolerate clock drift of authenticator apps
+const TOTP_SKEW: u8 = 1;
+
+// Generate a random base32 encoded secret to be shared with authenticator app
+pub fn generate_totp_secret() -> Secret<String> {
+    Secret::new(TotpSecret::generate_secret().to_encoded().to_string())
+}
+
+fn build_totp(secret: &Secret<String>, account_name: String) -> Result<TOTP, anyhow::Error> {
+    let secret = TotpSecret::Encoded(secret.expose_secret().clone())
+        .to_bytes()
+        .map_err(|e| anyhow::anyhow!("Invalid TOTP secret: {:?}", e))?;
+    TOTP::new(
+        Algorithm::SHA1,
+        TOTP_DIGITS,
+        TOTP_SKEW,
+        TOTP_STEP_SECS,
+        secret,
+        Some(TOTP_ISSUER.to_string()),
+        account_name,
+    )
+    .context("Failed to build TOTP")
+}
+
+// `otpauth://` URI that authenticator apps use to enroll the secret
+pub fn get_totp_uri(secret: &Secret<String>, username: &str) -> Result<String, anyhow::Error> {
+    Ok(build_totp(secret, username.to_string())?.get_url())
+}
+
+// Return time step the code belongs to, so caller can reject codes of steps already used
+#[tracing::instrument(name = "Verify TOTP code", skip_all)]
+pub fn verify_totp_code(secret: &Secret<String>, code: &str) -> Result<Option<u64>, anyhow::Error> {
+    // Account name is only used to build otpauth URI
+    let totp = build_totp(secret, String::new())?;
+    let current_step = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .context("Failed to get current system time")?
+        .as_secs()
+        / TOTP_STEP_SECS;
+    let code = code.trim().as_bytes();
+    let first_step = current_step.saturating_sub(TOTP_SKEW as u64);
+    let last_step = current_step + TOTP_SKEW as u64;
+    Ok((first_step..=last_step)
+        .find(|step| bool::from(totp.generate(step * TOTP_STEP_SECS).as_bytes().ct_eq(code))))
+}
+
+// Step is only moved forward, so concurrent logins with the same code can't both be accepted
+// Return false when a code of the same or a later step was already accepted
+#[tracing::instrument(name = "Record accepted TOTP step to database", skip(pg_pool))]
+pub async fn record_totp_step(
+    pg_pool: &PgPool,
+    user_id: &Uuid,
+    step: u64,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE users
+        SET totp_last_used_step = $2
+        WHERE user_id = $1
+            AND (totp_last_used_step IS NULL OR totp_last_used_step < $2)
+        "#,
+        user_id,
+        step as i64
+    )
+    .execute(pg_pool)
+    .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+#[tracing::instrument(name = "Get user's TOTP secret from database", skip(pg_pool))]
+pub async fn get_user_totp_secret(
+    pg_pool: &PgPool,
+    user_id: &Uuid,
+) -> Result<Option<Secret<String>>, anyhow::Error> {
+    let result = sqlx::query!(
+        r#"
+        SELECT totp_secret
+        FROM users
+        WHERE user_id = $1
+        "#,
+        user_id
+    )
+    .fetch_one(pg_pool)
+    .await
+    .context("Failed to fetch TOTP secret from database")?;
+
+    Ok(result.totp_secret.map(Secret::new))
+}
+
+// Step of the code enrollment was verified with is recorded, so it can't be used to login
+#[tracing::instrument(name = "Update user's TOTP secret to database", skip(pg_pool, secret))]
+pub async fn update_user_totp_secret(
+    pg_pool: &PgPool,
+    user_id: &Uuid,
+    secret: &Secret<String>,
+    verified_step: u64,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET totp_secret = $2, totp_last_used_step = $3
+        WHERE user_id = $1
+        "#,
+        user_id,
+        secret.expose_secret(),
+        verified_step as i64
+    )
+    .execute(pg_pool)
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,200 @@
+use crate::authentication::{hash_password, update_user_password_to_database};
+use crate::configuration::Settings;
+use crate::startup::get_pg_pool;
+use crate::utils::spawn_blocking_task_with_tracing;
+use anyhow::Context;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use std::time::Duration;
+use uuid::Uuid;
+
+// Run server when no subcommand is given
+#[derive(clap::Parser)]
+#[command(name = "zero2prod", about = "Newsletter delivery service")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// Validate configuration and connections to Redis and Postgres, then exit
+    #[arg(long)]
+    pub check_config: bool,
+}
+
+#[derive(clap::Subcommand)]
+pub enum Command {
+    /// Create a new user, password is prompted
+    CreateUser {
+        #[arg(long)]
+        username: String,
+    },
+    /// Reset password of an existing user, new password is prompted
+    ResetPassword {
+        #[arg(long)]
+        username: String,
+    },
+}
+
+impl Command {
+    pub async fn run(self, settings: &Settings) -> Result<(), anyhow::Error> {
+        settings.database.validate()?;
+        let pg_pool = get_pg_pool(&settings.database);
+        match self {
+            Command::CreateUser { username } => {
+                let password = prompt_new_password()?;
+                let user_id = create_user(&pg_pool, &username, password).await?;
+                println!("Created user `{}` with id {}", username, user_id);
+            }
+            Command::ResetPassword { username } => {
+                let password = prompt_new_password()?;
+                reset_password(&pg_pool, &username, password).await?;
+                println!("Reset password of user `{}`", username);
+            }
+        }
+        Ok(())
+    }
+}
+
+// Deploy pipelines only look at exit code of `--check-config`, so any failed check exits non-zero
+pub async fn check_config_exit_code(settings: &Settings) -> i32 {
+    match check_config(settings).await {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+// Connections are checked even when settings are invalid, so every problem is reported at once
+// Nothing is bound or spawned, deploy pipelines can run it before starting the server
+pub async fn check_config(settings: &Settings) -> Result<(), anyhow::Error> {
+    const CONNECTION_TIMEOUT: Duration = Duration::from_secs(5);
+    let checks = [
+        (
+            "Configuration",
+            settings.validate().map_err(anyhow::Error::from),
+        ),
+        (
+            "Redis",
+            tokio::time::timeout(CONNECTION_TIMEOUT, check_redis(settings))
+                .await
+                .unwrap_or_else(|_| Err(anyhow::anyhow!("Timed out connecting to Redis"))),
+        ),
+        (
+            "Postgres",
+            tokio::time::timeout(CONNECTION_TIMEOUT, check_postgres(settings))
+                .await
+                .unwrap_or_else(|_| Err(anyhow::anyhow!("Timed out connecting to Postgres"))),
+        ),
+    ];
+
+    let mut problems = vec![];
+    for (name, outcome) in checks {
+        match outcome {
+            Ok(()) => println!("{}: ok", name),
+            Err(e) => {
+                println!("{}: FAILED, {:#}", name, e);
+                problems.push(name);
+            }
+        }
+    }
+    if !problems.is_empty() {
+        anyhow::bail!("Configuration check failed: {}", problems.join(", "));
+    }
+    Ok(())
+}
+
+async fn check_redis(settings: &Settings) -> Result<(), anyhow::Error> {
+    let client = redis::Client::open(settings.application.redis_url.expose_secret().as_str())
+        .context("Invalid Redis url")?;
+    let mut connection = client
+        .get_async_connection()
+        .await
+        .context("Failed to connect to Redis")?;
+    redis::cmd("PING")
+        .query_async::<_, String>(&mut connection)
+        .await
+        .context("Failed to ping Redis")?;
+    Ok(())
+}
+
+async fn check_postgres(settings: &Settings) -> Result<(), anyhow::Error> {
+    let pg_pool = get_pg_pool(&settings.database);
+    sqlx::query("SELECT 1")
+        .execute(&pg_pool)
+        .await
+        .context("Failed to query Postgres")?;
+    Ok(())
+}
+
+// Read password without echoing it to terminal
+fn prompt_new_password() -> Result<Secret<String>, anyhow::Error> {
+    let password = rpassword::prompt_password("Password: ").context("Failed to read password")?;
+    let confirm_password =
+        rpassword::prompt_password("Confirm password: ").context("Failed to read password")?;
+    if password != confirm_password {
+        anyhow::bail!("Passwords don't match");
+    }
+    if password.is_empty() {
+        anyhow::bail!("Password must not be empty");
+    }
+    Ok(Secret::new(password))
+}
+
+async fn hash_password_in_blocking_task(password: Secret<String>) -> Result<String, anyhow::Error> {
+    spawn_blocking_task_with_tracing(move || hash_password(password.expose_secret()))
+        .await
+        .context("Failed to spawn blocking task")?
+        .context("Failed to hash password")
+}
+
+#[tracing::instrument(name = "Create a new user from CLI", skip(pg_pool, password))]
+pub async fn create_user(
+    pg_pool: &PgPool,
+    username: &str,
+    password: Secret<String>,
+) -> Result<Uuid, anyhow::Error> {
+    let password_hash = hash_password_in_blocking_task(password).await?;
+    let user_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO users (user_id, username, password_hash)
+        VALUES ($1, $2, $3)
+        "#,
+        user_id,
+        username,
+        password_hash
+    )
+    .execute(pg_pool)
+    .await
+    .context("Failed to insert user into database, username may already exist")?;
+
+    Ok(user_id)
+}
+
+#[tracing::instrument(name = "Reset user's password from CLI", skip(pg_pool, password))]
+pub async fn reset_password(
+    pg_pool: &PgPool,
+    username: &str,
+    password: Secret<String>,
+) -> Result<(), anyhow::Error> {
+    let user_id = sqlx::query!(
+        r#"
+        SELECT user_id
+        FROM users
+        WHERE username = $1
+        "#,
+        username
+    )
+    .fetch_optional(pg_pool)
+    .await
+    .context("Failed to fetch user from database")?
+    .with_context(|| format!("User `{}` doesn't exist", username))?
+    .user_id;
+
+    let password_hash = hash_password_in_blocking_task(password).await?;
+    update_user_password_to_database(&user_id, &password_hash, pg_pool)
+        .await
+        .context("Failed to update user password in database")?;
+
+    Ok(())
+}
@@ -1,6 +1,7 @@
 use secrecy::{ExposeSecret, Secret};
 use serde_aux::prelude::{deserialize_number_from_string, deserialize_option_number_from_string};
 use sqlx::postgres::{PgConnectOptions, PgSslMode};
+use std::net::IpAddr;
 
 const APP_ENV_STATE: &str = "APP_ENV_STATE";
 const LOCAL: &str = "local";
@@ -11,6 +12,14 @@ pub struct Settings {
     pub application: ApplicationSettings,
     pub database: DatabaseSettings,
     pub email_client: EmailClientSettings,
+    #[serde(default)]
+    pub content_store: ContentStoreSettings,
+    #[serde(default)]
+    pub confirmation_email: ConfirmationEmailSettings,
+    #[serde(default)]
+    pub welcome_email: WelcomeEmailSettings,
+    #[serde(default)]
+    pub html_sanitizer: HtmlSanitizerSettings,
 }
 
 impl Settings {
@@ -35,14 +44,156 @@ impl Settings {
 
         // Read the configuration from the file
         // supported file extensions: json, toml, yaml, etc
-        config::Config::builder()
+        let mut settings: Settings = config::Config::builder()
             .add_source(config::File::from(config_dir.clone().join("share")))
             // ConfigBuilder will merge multiple sources to one when build
             .add_source(config::File::from(config_dir.join(app_env_state.as_str())))
             .add_source(config_env)
             .build()?
             // Deserialize the configuration into a Settings struct
-            .try_deserialize()
+            .try_deserialize()?;
+
+        settings.load_secret_files()?;
+        settings.confirmation_email.load_template_files()?;
+        settings.welcome_email.load_template_files()?;
+        Ok(settings)
+    }
+
+    // Secrets can be mounted as files (e.g. Docker/Kubernetes secrets) instead of plaintext env vars
+    // `<field>_file` is a path to a file containing the secret, which overrides `<field>`
+    pub fn load_secret_files(&mut self) -> Result<(), config::ConfigError> {
+        let application = &mut self.application;
+        load_secret_file(
+            "application.flash_msg_key",
+            &application.flash_msg_key_file,
+            &mut application.flash_msg_key,
+        )?;
+        load_secret_file(
+            "application.redis_url",
+            &application.redis_url_file,
+            &mut application.redis_url,
+        )?;
+        load_secret_file(
+            "application.redis_session_key",
+            &application.redis_session_key_file,
+            &mut application.redis_session_key,
+        )?;
+        load_secret_file(
+            "application.email_events_webhook_secret",
+            &application.email_events_webhook_secret_file,
+            &mut application.email_events_webhook_secret,
+        )?;
+        load_secret_file(
+            "application.erased_email_hash_key",
+            &application.erased_email_hash_key_file,
+            &mut application.erased_email_hash_key,
+        )?;
+        load_secret_file(
+            "application.log_pii_hash_key",
+            &application.log_pii_hash_key_file,
+            &mut application.log_pii_hash_key,
+        )?;
+        load_secret_file(
+            "database.password",
+            &self.database.password_file,
+            &mut self.database.password,
+        )?;
+        if let Some(path) = &self.email_client.password_file {
+            self.email_client.password = Some(read_secret_file("email_client.password", path)?);
+        }
+        for (index, provider) in self.email_client.failover_providers.iter_mut().enumerate() {
+            if let Some(path) = &provider.password_file {
+                provider.password = Some(read_secret_file(
+                    &format!("email_client.failover_providers[{}].password", index),
+                    path,
+                )?);
+            }
+        }
+        Ok(())
+    }
+
+    // Check invariants that deserialization can't express
+    // So invalid configuration fails at startup instead of panicking deep in application
+    pub fn validate(&self) -> Result<(), config::ConfigError> {
+        self.application.validate()?;
+        self.database.validate()?;
+        self.email_client.validate()?;
+        self.content_store.validate()?;
+        self.html_sanitizer.validate()
+    }
+}
+
+fn read_secret_file(field: &str, path: &str) -> Result<Secret<String>, config::ConfigError> {
+    std::fs::read_to_string(path)
+        // Files usually end with a new line
+        .map(|content| Secret::new(content.trim().to_string()))
+        .map_err(|e| {
+            config::ConfigError::Message(format!(
+                "Failed to read `{}` from file `{}`: {}",
+                field, path, e
+            ))
+        })
+}
+
+fn load_secret_file(
+    field: &str,
+    path: &Option<String>,
+    secret: &mut Secret<String>,
+) -> Result<(), config::ConfigError> {
+    if let Some(path) = path {
+        *secret = read_secret_file(field, path)?;
+    }
+    Ok(())
+}
+
+// Secret fields that can be loaded from file are optional in configuration files
+fn empty_secret() -> Secret<String> {
+    Secret::new(String::new())
+}
+
+// 22 URL-safe base64 characters carry 132 bits of entropy, shorter tokens could be guessed
+const MIN_SUBSCRIPTION_TOKEN_LENGTH: usize = 22;
+
+// Cookie signing keys need at least 64 bytes, `Key::from` panics on shorter keys
+const MIN_COOKIE_KEY_LENGTH: usize = 64;
+
+fn invalid_field(field: &str, reason: &str) -> config::ConfigError {
+    config::ConfigError::Message(format!("Invalid `{}`: {}", field, reason))
+}
+
+// Malformed URLs are rejected here instead of failing when clients are built from them
+// Parse error doesn't include the URL, so credentials in it aren't leaked into the error
+fn parse_url(field: &str, value: &str, schemes: &[&str]) -> Result<url::Url, config::ConfigError> {
+    let url = url::Url::parse(value)
+        .map_err(|e| invalid_field(field, &format!("must be a valid URL, {}", e)))?;
+    if !schemes.contains(&url.scheme()) {
+        let schemes: Vec<_> = schemes
+            .iter()
+            .map(|scheme| format!("`{}://`", scheme))
+            .collect();
+        return Err(invalid_field(
+            field,
+            &format!("must start with {}", schemes.join(" or ")),
+        ));
+    }
+    if url.host_str().map_or(true, str::is_empty) {
+        return Err(invalid_field(field, "must have a host"));
+    }
+    Ok(url)
+}
+
+// `host:port` is split at the last colon, IPv6 hosts are enclosed in brackets
+fn strip_port(authority: &str) -> &str {
+    match authority.rsplit_once(':') {
+        Some((host, port)) if port.parse::<u16>().is_ok() => host,
+        _ => authority,
+    }
+}
+
+fn ensure_not_zero(field: &str, value: u64) -> Result<(), config::ConfigError> {
+    match value {
+        0 => Err(invalid_field(field, "must be greater than 0")),
+        _ => Ok(()),
     }
 }
 
@@ -52,38 +203,816 @@ pub struct ApplicationSettings {
     pub rust_log: String,
     pub host: String,
     pub base_url: String,
+    // Links sent to users, e.g. confirmation links, are built from these when set,
+    // e.g. app serves plain HTTP behind a proxy that terminates TLS for a public domain
+    // Each of them is derived from `base_url` when not set
+    #[serde(default)]
+    pub public_scheme: Option<String>,
+    #[serde(default)]
+    pub public_host: Option<String>,
+    // Port of `base_url` isn't kept when `public_host` is set, it's where app is reached directly
+    #[serde(default)]
+    pub public_port: Option<u16>,
+    // Prefix that all routes are mounted under, e.g. "/newsletter", empty means root
+    #[serde(default)]
+    pub base_path: String,
     #[serde(deserialize_with = "deserialize_number_from_string")]
     pub port: u16,
+    #[serde(default = "empty_secret")]
     pub flash_msg_key: Secret<String>,
+    #[serde(default)]
+    pub flash_msg_key_file: Option<String>,
+    #[serde(default = "empty_secret")]
     pub redis_url: Secret<String>,
+    #[serde(default)]
+    pub redis_url_file: Option<String>,
+    #[serde(default = "empty_secret")]
     pub redis_session_key: Secret<String>,
+    #[serde(default)]
+    pub redis_session_key_file: Option<String>,
+    // Shared secret that email provider signs bounce and complaint events with
+    // Email events webhook rejects every request when it's empty
+    #[serde(default = "empty_secret")]
+    pub email_events_webhook_secret: Secret<String>,
+    #[serde(default)]
+    pub email_events_webhook_secret_file: Option<String>,
+    // Key of HMAC that tombstones of erased subscribers are hashed with
+    // Changing it lets erased subscribers be imported again, their tombstones no longer match
+    #[serde(default = "empty_secret")]
+    pub erased_email_hash_key: Secret<String>,
+    #[serde(default)]
+    pub erased_email_hash_key_file: Option<String>,
+    // Key of HMAC that PII is hashed with when `log_pii_redaction` is `hash`
+    // Plain digests of emails can be reversed by hashing a list of known addresses
+    #[serde(default = "empty_secret")]
+    pub log_pii_hash_key: Secret<String>,
+    #[serde(default)]
+    pub log_pii_hash_key_file: Option<String>,
     pub idempotency_expiration_millis: u64,
+    // Stored responses older than this are reprocessed instead of replayed, it can be shorter than
+    // expiration, which only decides when records are deleted
+    #[serde(default)]
+    pub idempotency_replay_max_age_millis: Option<u64>,
+    // Larger response bodies are not stored, their status and headers are still replayed
+    pub idempotency_max_stored_body_size_bytes: usize,
+    // How often expired idempotency records are deleted, independent of their expiration
+    pub idempotency_sweep_interval_millis: u64,
+    // How often a summary of queue health is logged
+    pub queue_metrics_interval_millis: u64,
+    pub worker_heartbeat_interval_millis: u64,
+    // Delivery worker re-checks queue after this long without notification, so a lost notification
+    // doesn't leave enqueued issues undelivered, heartbeats in between don't re-check queue
+    pub worker_poll_interval_millis: u64,
+    // Workers back off exponentially from base delay up to max delay on consecutive failures
+    pub worker_backoff_base_millis: u64,
+    pub worker_backoff_max_millis: u64,
+    pub subscription_token_expiration_secs: u64,
+    // How often expired tokens of pending subscriptions are deleted
+    pub subscription_token_sweep_interval_millis: u64,
+    pub subscription_token_length: usize,
+    pub max_request_headers_count: usize,
+    pub max_request_headers_size_bytes: usize,
+    // Requests with larger body are rejected with 413 before they are parsed
+    pub max_request_body_size_bytes: usize,
+    pub max_subscribe_body_size_bytes: usize,
+    pub max_newsletters_body_size_bytes: usize,
+    pub max_subscribers_import_body_size_bytes: usize,
+    // Sanitized HTML of newsletters issue larger than this is rejected, providers clip long emails
+    pub max_newsletters_html_size_bytes: usize,
+    // Issues targeting more subscribers are only published with explicit `confirm_large_send`
+    pub max_recipients_per_issue: u64,
+    // Failed confirmation emails are not retried when max retries is 0
+    pub confirmation_email_max_retries: u32,
+    pub confirmation_email_retry_interval_millis: u64,
+    #[serde(default)]
+    pub request_log: RequestLogSettings,
+    // How subscriber emails, names and usernames are written to spans
+    #[serde(default)]
+    pub log_pii_redaction: PiiRedactionSettings,
+    // Compress responses with encoding negotiated by `Accept-Encoding`
+    // Disable it when a reverse proxy already compresses responses
+    pub compress_responses: bool,
+    // Accept `Authorization: Basic` on `POST /admin/newsletters` for scripts written against the
+    // legacy `/newsletters` endpoint, password alone bypasses two-factor authentication so it's opt-in
+    #[serde(default)]
+    pub allow_newsletters_basic_auth: bool,
+    #[serde(default)]
+    pub flash_message_store: FlashMessageStoreSettings,
+    // Logged in session is purged when user is inactive longer than idle timeout
+    pub session_idle_timeout_millis: u64,
+    // Re-login is required after max lifetime regardless of activity
+    pub session_max_lifetime_millis: u64,
+    // Lifetime of persistent session when user chooses "remember me" on login
+    // Idle timeout and max lifetime don't apply to remembered sessions
+    pub session_remember_me_ttl_millis: u64,
+    // Requests to subscribe are not limited when not provided
+    #[serde(default)]
+    pub subscribe_rate_limit: Option<RateLimitSettings>,
+    // Only applied to public routes, admin routes never allow cross-origin requests
+    #[serde(default)]
+    pub cors: CorsSettings,
+    pub security_headers: SecurityHeadersSettings,
+    #[serde(default)]
+    pub mx_check: MxCheckSettings,
+    pub maintenance: MaintenanceSettings,
 }
 
 impl ApplicationSettings {
     pub fn get_url(&self) -> String {
         format!("{}:{}", self.host, self.port)
     }
+
+    // URL that links sent to users are built from
+    pub fn get_public_url(&self) -> String {
+        // `base_url` is validated to start with a scheme
+        let (scheme, authority) = self
+            .base_url
+            .split_once("://")
+            .unwrap_or(("http", &self.base_url));
+        let authority = authority.trim_end_matches('/');
+        let scheme = self.public_scheme.as_deref().unwrap_or(scheme);
+        let authority = match (&self.public_host, self.public_port) {
+            (Some(host), Some(port)) => format!("{}:{}", host, port),
+            (Some(host), None) => host.clone(),
+            (None, Some(port)) => format!("{}:{}", strip_port(authority), port),
+            (None, None) => authority.to_string(),
+        };
+        format!("{}://{}{}", scheme, authority, self.base_path)
+    }
+
+    fn validate(&self) -> Result<(), config::ConfigError> {
+        parse_url("application.base_url", &self.base_url, &["http", "https"])?;
+        if let Some(scheme) = &self.public_scheme {
+            if scheme != "http" && scheme != "https" {
+                return Err(invalid_field(
+                    "application.public_scheme",
+                    "must be `http` or `https`",
+                ));
+            }
+        }
+        if let Some(host) = &self.public_host {
+            if host.is_empty() || host.contains(&['/', ':', '@', ' '][..]) {
+                return Err(invalid_field(
+                    "application.public_host",
+                    "must be a host name without scheme, port or path",
+                ));
+            }
+        }
+        if let Some(port) = self.public_port {
+            ensure_not_zero("application.public_port", port.into())?;
+        }
+        if !self.base_path.is_empty()
+            && (!self.base_path.starts_with('/') || self.base_path.ends_with('/'))
+        {
+            return Err(invalid_field(
+                "application.base_path",
+                "must start with `/` and not end with `/`, or be empty",
+            ));
+        }
+        for (field, key) in [
+            ("application.flash_msg_key", &self.flash_msg_key),
+            ("application.redis_session_key", &self.redis_session_key),
+        ] {
+            if key.expose_secret().len() < MIN_COOKIE_KEY_LENGTH {
+                return Err(invalid_field(
+                    field,
+                    &format!("must be at least {} bytes long", MIN_COOKIE_KEY_LENGTH),
+                ));
+            }
+        }
+        if self.erased_email_hash_key.expose_secret().is_empty() {
+            return Err(invalid_field(
+                "application.erased_email_hash_key",
+                "must not be empty",
+            ));
+        }
+        if self.log_pii_redaction == PiiRedactionSettings::Hash
+            && self.log_pii_hash_key.expose_secret().is_empty()
+        {
+            return Err(invalid_field(
+                "application.log_pii_hash_key",
+                "must be set when `log_pii_redaction` is `hash`",
+            ));
+        }
+        parse_url(
+            "application.redis_url",
+            self.redis_url.expose_secret(),
+            &["redis", "rediss"],
+        )?;
+        ensure_not_zero(
+            "application.idempotency_expiration_millis",
+            self.idempotency_expiration_millis,
+        )?;
+        if let Some(max_age_millis) = self.idempotency_replay_max_age_millis {
+            ensure_not_zero(
+                "application.idempotency_replay_max_age_millis",
+                max_age_millis,
+            )?;
+        }
+        ensure_not_zero(
+            "application.idempotency_sweep_interval_millis",
+            self.idempotency_sweep_interval_millis,
+        )?;
+        ensure_not_zero(
+            "application.queue_metrics_interval_millis",
+            self.queue_metrics_interval_millis,
+        )?;
+        ensure_not_zero(
+            "application.worker_heartbeat_interval_millis",
+            self.worker_heartbeat_interval_millis,
+        )?;
+        ensure_not_zero(
+            "application.worker_poll_interval_millis",
+            self.worker_poll_interval_millis,
+        )?;
+        ensure_not_zero(
+            "application.worker_backoff_base_millis",
+            self.worker_backoff_base_millis,
+        )?;
+        if self.worker_backoff_max_millis < self.worker_backoff_base_millis {
+            return Err(invalid_field(
+                "application.worker_backoff_max_millis",
+                "must not be less than `worker_backoff_base_millis`",
+            ));
+        }
+        ensure_not_zero(
+            "application.subscription_token_expiration_secs",
+            self.subscription_token_expiration_secs,
+        )?;
+        ensure_not_zero(
+            "application.subscription_token_sweep_interval_millis",
+            self.subscription_token_sweep_interval_millis,
+        )?;
+        if self.subscription_token_length < MIN_SUBSCRIPTION_TOKEN_LENGTH {
+            return Err(invalid_field(
+                "application.subscription_token_length",
+                &format!("must be at least {}", MIN_SUBSCRIPTION_TOKEN_LENGTH),
+            ));
+        }
+        ensure_not_zero(
+            "application.max_request_headers_count",
+            self.max_request_headers_count as u64,
+        )?;
+        ensure_not_zero(
+            "application.max_request_headers_size_bytes",
+            self.max_request_headers_size_bytes as u64,
+        )?;
+        for (field, size) in [
+            (
+                "application.max_request_body_size_bytes",
+                self.max_request_body_size_bytes,
+            ),
+            (
+                "application.max_subscribe_body_size_bytes",
+                self.max_subscribe_body_size_bytes,
+            ),
+            (
+                "application.max_newsletters_body_size_bytes",
+                self.max_newsletters_body_size_bytes,
+            ),
+            (
+                "application.max_subscribers_import_body_size_bytes",
+                self.max_subscribers_import_body_size_bytes,
+            ),
+            (
+                "application.max_newsletters_html_size_bytes",
+                self.max_newsletters_html_size_bytes,
+            ),
+        ] {
+            ensure_not_zero(field, size as u64)?;
+        }
+        ensure_not_zero(
+            "application.session_idle_timeout_millis",
+            self.session_idle_timeout_millis,
+        )?;
+        ensure_not_zero(
+            "application.session_max_lifetime_millis",
+            self.session_max_lifetime_millis,
+        )?;
+        ensure_not_zero(
+            "application.session_remember_me_ttl_millis",
+            self.session_remember_me_ttl_millis,
+        )?;
+        if let Some(rate_limit) = &self.subscribe_rate_limit {
+            rate_limit.validate("application.subscribe_rate_limit")?;
+        }
+        self.cors.validate()?;
+        self.security_headers.validate()?;
+        self.mx_check.validate()?;
+        ensure_not_zero(
+            "application.maintenance.retry_after_secs",
+            self.maintenance.retry_after_secs,
+        )
+    }
+}
+
+// Subscribe rejects email domains without MX records when enabled
+// DNS resolver is only compiled with `mx-check` feature, checked when application is built
+#[derive(serde::Deserialize, Clone, Debug, Default)]
+pub struct MxCheckSettings {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub timeout_millis: u64,
+}
+
+impl MxCheckSettings {
+    fn validate(&self) -> Result<(), config::ConfigError> {
+        if !self.enabled {
+            return Ok(());
+        }
+        ensure_not_zero("application.mx_check.timeout_millis", self.timeout_millis)
+    }
+}
+
+// Public subscription routes return 503 during maintenance, admin and health routes stay available
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct MaintenanceSettings {
+    pub enabled: bool,
+    pub retry_after_secs: u64,
+}
+
+// Added to every response, `X-Content-Type-Options` and `X-Frame-Options` are always sent
+// Pages have no inline scripts or styles, so a strict Content-Security-Policy works for them
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct SecurityHeadersSettings {
+    // Header isn't sent when it's empty
+    pub content_security_policy: String,
+    pub referrer_policy: String,
+}
+
+impl SecurityHeadersSettings {
+    fn validate(&self) -> Result<(), config::ConfigError> {
+        for (field, value) in [
+            (
+                "application.security_headers.content_security_policy",
+                &self.content_security_policy,
+            ),
+            (
+                "application.security_headers.referrer_policy",
+                &self.referrer_policy,
+            ),
+        ] {
+            if actix_web::http::header::HeaderValue::from_str(value).is_err() {
+                return Err(invalid_field(field, "must be a valid header value"));
+            }
+        }
+        if self.referrer_policy.is_empty() {
+            return Err(invalid_field(
+                "application.security_headers.referrer_policy",
+                "must not be empty",
+            ));
+        }
+        Ok(())
+    }
+}
+
+// CORS is disabled when no origin is allowed
+#[derive(serde::Deserialize, Clone, Debug, Default)]
+pub struct CorsSettings {
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+    #[serde(default)]
+    pub allowed_methods: Vec<String>,
+    #[serde(default)]
+    pub allowed_headers: Vec<String>,
+    #[serde(default)]
+    pub max_age_secs: usize,
+}
+
+impl CorsSettings {
+    pub fn is_enabled(&self) -> bool {
+        !self.allowed_origins.is_empty()
+    }
+
+    // actix-cors only reports invalid values when workers build services, so reject them earlier
+    fn validate(&self) -> Result<(), config::ConfigError> {
+        for origin in &self.allowed_origins {
+            if (!origin.starts_with("http://") && !origin.starts_with("https://"))
+                || origin.ends_with('/')
+            {
+                return Err(invalid_field(
+                    "application.cors.allowed_origins",
+                    &format!(
+                        "`{}` must be a scheme and host, e.g. `https://example.com`",
+                        origin
+                    ),
+                ));
+            }
+        }
+        for method in &self.allowed_methods {
+            if actix_web::http::Method::from_bytes(method.as_bytes()).is_err() {
+                return Err(invalid_field(
+                    "application.cors.allowed_methods",
+                    &format!("`{}` is not a valid HTTP method", method),
+                ));
+            }
+        }
+        for header in &self.allowed_headers {
+            if actix_web::http::header::HeaderName::from_bytes(header.as_bytes()).is_err() {
+                return Err(invalid_field(
+                    "application.cors.allowed_headers",
+                    &format!("`{}` is not a valid header name", header),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+// Sliding window rate limit per client IP
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct RateLimitSettings {
+    pub max_requests: u32,
+    pub window_secs: u64,
+    // `X-Forwarded-For` is only read from requests of these proxies, peer address is used otherwise
+    // Only hops appended by them are trusted, clients can spoof any hop before
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpAddr>,
+}
+
+impl RateLimitSettings {
+    fn validate(&self, field: &str) -> Result<(), config::ConfigError> {
+        ensure_not_zero(&format!("{}.max_requests", field), self.max_requests as u64)?;
+        ensure_not_zero(&format!("{}.window_secs", field), self.window_secs)
+    }
+}
+
+// Which logs are emitted per request
+// `access_log` emits one compact structured event per request, which is easier to ingest by log systems
+#[derive(serde::Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RequestLogSettings {
+    #[default]
+    TracingLogger,
+    AccessLog,
+    Both,
+}
+
+impl RequestLogSettings {
+    pub fn is_tracing_logger_enabled(&self) -> bool {
+        matches!(self, Self::TracingLogger | Self::Both)
+    }
+
+    pub fn is_access_log_enabled(&self) -> bool {
+        matches!(self, Self::AccessLog | Self::Both)
+    }
+}
+
+// `mask` keeps first character and email domain, `hash` writes HMAC-SHA256 hex keyed by
+// `log_pii_hash_key` so records of the same subscriber can still be correlated, `off` writes values as is for debugging
+#[derive(serde::Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PiiRedactionSettings {
+    #[default]
+    Off,
+    Mask,
+    Hash,
+}
+
+// Where flash messages are kept between a redirect and the page that shows them
+// `session` keeps them server-side in Redis instead of a signed cookie readable by client,
+// but messages sent after session is purged (e.g. on logout) are dropped
+#[derive(serde::Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FlashMessageStoreSettings {
+    #[default]
+    Cookie,
+    Session,
 }
 
 #[derive(serde::Deserialize, Clone)]
 pub struct EmailClientSettings {
     pub username: Option<Secret<String>>,
     pub password: Option<Secret<String>>,
+    #[serde(default)]
+    pub password_file: Option<String>,
     pub host: String,
     #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
     pub port: Option<u16>,
     pub sender_email: String,
-    pub require_tls: bool,
+    // Display name in `From` header
+    pub from_name: String,
+    // Replies go to this address instead of the no-reply sender when provided
+    pub reply_to: Option<String>,
+    // SMTP envelope sender (return path) where bounces are routed, `sender_email` when not provided
+    // Set it for DMARC alignment when bounces must go to another domain than header `From`
+    #[serde(default)]
+    pub envelope_from: Option<String>,
+    #[serde(default)]
+    pub tls_mode: Option<SmtpTlsMode>,
+    // Deprecated in favor of `tls_mode`, `true` means `tls` and `false` means `none`
+    #[serde(default)]
+    pub require_tls: Option<bool>,
+    // PEM files of client certificate and its private key, for relays that authenticate clients by certificate
+    #[serde(default)]
+    pub client_certificate_file: Option<String>,
+    #[serde(default)]
+    pub client_key_file: Option<String>,
     #[serde(deserialize_with = "deserialize_number_from_string")]
     pub request_timeout_millis: u64,
+    // Unlimited if not provided
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub send_rate_per_second: Option<u32>,
+    // Total size of attachments in one email, unlimited if not provided
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub max_attachments_size_bytes: Option<usize>,
+    // `List-Id` header of newsletters, let subscribers filter newsletters in their inbox
+    #[serde(default)]
+    pub list_id: Option<String>,
+    // Prepended to subject of newsletters, e.g. "[Zero2Prod] ", empty means no prefix
+    #[serde(default)]
+    pub subject_prefix: String,
+    // One copy of each newsletters issue is sent here for archival, confirmation emails aren't
+    #[serde(default)]
+    pub archive_email: Option<String>,
+    // Tried in order when provider above fails with transient errors, e.g. it is down
+    #[serde(default)]
+    pub failover_providers: Vec<SmtpProviderSettings>,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct SmtpProviderSettings {
+    pub username: Option<Secret<String>>,
+    pub password: Option<Secret<String>>,
+    #[serde(default)]
+    pub password_file: Option<String>,
+    pub host: String,
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub tls_mode: Option<SmtpTlsMode>,
+    // Deprecated in favor of `tls_mode`
+    #[serde(default)]
+    pub require_tls: Option<bool>,
+    #[serde(default)]
+    pub client_certificate_file: Option<String>,
+    #[serde(default)]
+    pub client_key_file: Option<String>,
+}
+
+// How connection to SMTP server is secured
+// `starttls` upgrades a plaintext connection (usually port 587), `tls` connects over TLS (usually port 465)
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SmtpTlsMode {
+    None,
+    Starttls,
+    Tls,
+}
+
+// `tls_mode` takes precedence, old `require_tls` is still honored so existing configurations keep working
+fn resolve_smtp_tls_mode(tls_mode: Option<SmtpTlsMode>, require_tls: Option<bool>) -> SmtpTlsMode {
+    match (tls_mode, require_tls) {
+        (Some(tls_mode), _) => tls_mode,
+        (None, Some(false)) => SmtpTlsMode::None,
+        (None, _) => SmtpTlsMode::Tls,
+    }
+}
+
+fn validate_smtp_tls(
+    field_prefix: &str,
+    tls_mode: Option<SmtpTlsMode>,
+    require_tls: Option<bool>,
+    client_certificate_file: &Option<String>,
+    client_key_file: &Option<String>,
+) -> Result<(), config::ConfigError> {
+    if tls_mode.is_some() && require_tls.is_some() {
+        return Err(invalid_field(
+            &format!("{}.require_tls", field_prefix),
+            "is replaced by `tls_mode`, they can't be provided together",
+        ));
+    }
+    if client_certificate_file.is_some() != client_key_file.is_some() {
+        return Err(invalid_field(
+            &format!("{}.client_certificate_file", field_prefix),
+            "client certificate and key must be provided together",
+        ));
+    }
+    if client_certificate_file.is_some()
+        && resolve_smtp_tls_mode(tls_mode, require_tls) == SmtpTlsMode::None
+    {
+        return Err(invalid_field(
+            &format!("{}.client_certificate_file", field_prefix),
+            "client certificate requires `tls_mode` to be `starttls` or `tls`",
+        ));
+    }
+    Ok(())
+}
+
+impl SmtpProviderSettings {
+    pub fn get_tls_mode(&self) -> SmtpTlsMode {
+        resolve_smtp_tls_mode(self.tls_mode, self.require_tls)
+    }
+
+    fn validate(&self, index: usize) -> Result<(), config::ConfigError> {
+        if self.host.trim().is_empty() {
+            return Err(invalid_field(
+                &format!("email_client.failover_providers[{}].host", index),
+                "must not be empty",
+            ));
+        }
+        if self.username.is_some() != self.password.is_some() {
+            return Err(invalid_field(
+                &format!("email_client.failover_providers[{}].username", index),
+                "username and password must be provided together",
+            ));
+        }
+        validate_smtp_tls(
+            &format!("email_client.failover_providers[{}]", index),
+            self.tls_mode,
+            self.require_tls,
+            &self.client_certificate_file,
+            &self.client_key_file,
+        )
+    }
+}
+
+impl EmailClientSettings {
+    pub fn get_tls_mode(&self) -> SmtpTlsMode {
+        resolve_smtp_tls_mode(self.tls_mode, self.require_tls)
+    }
+
+    fn validate(&self) -> Result<(), config::ConfigError> {
+        if self.host.trim().is_empty() {
+            return Err(invalid_field("email_client.host", "must not be empty"));
+        }
+        if self.username.is_some() != self.password.is_some() {
+            return Err(invalid_field(
+                "email_client.username",
+                "username and password must be provided together",
+            ));
+        }
+        validate_smtp_tls(
+            "email_client",
+            self.tls_mode,
+            self.require_tls,
+            &self.client_certificate_file,
+            &self.client_key_file,
+        )?;
+        if self.sender_email.trim().is_empty() {
+            return Err(invalid_field(
+                "email_client.sender_email",
+                "must not be empty",
+            ));
+        }
+        ensure_not_zero(
+            "email_client.request_timeout_millis",
+            self.request_timeout_millis,
+        )?;
+        if self.send_rate_per_second == Some(0) {
+            return Err(invalid_field(
+                "email_client.send_rate_per_second",
+                "must be greater than 0, omit it to send without limit",
+            ));
+        }
+        if matches!(&self.list_id, Some(list_id) if list_id.trim().is_empty()) {
+            return Err(invalid_field(
+                "email_client.list_id",
+                "must not be empty, omit it to send without `List-Id` header",
+            ));
+        }
+        if matches!(&self.archive_email, Some(archive_email) if archive_email.trim().is_empty()) {
+            return Err(invalid_field(
+                "email_client.archive_email",
+                "must not be empty, omit it to not archive newsletters",
+            ));
+        }
+        for (index, provider) in self.failover_providers.iter().enumerate() {
+            provider.validate(index)?;
+        }
+        Ok(())
+    }
+}
+
+// Copy of confirmation email, built-in copy is used for parts that are not provided
+// Placeholders `{{confirmation_link}}` and `{{confirmation_code}}` are substituted when sending
+#[derive(serde::Deserialize, Clone, Default)]
+pub struct ConfirmationEmailSettings {
+    #[serde(default)]
+    pub method: ConfirmationMethodSettings,
+    pub subject: Option<String>,
+    pub html_body: Option<String>,
+    // Path to a template file, which overrides `html_body`
+    pub html_body_file: Option<String>,
+    pub text_body: Option<String>,
+    // Path to a template file, which overrides `text_body`
+    pub text_body_file: Option<String>,
+}
+
+impl ConfirmationEmailSettings {
+    fn load_template_files(&mut self) -> Result<(), config::ConfigError> {
+        if let Some(path) = &self.html_body_file {
+            self.html_body = Some(read_template_file("confirmation_email.html_body", path)?);
+        }
+        if let Some(path) = &self.text_body_file {
+            self.text_body = Some(read_template_file("confirmation_email.text_body", path)?);
+        }
+        Ok(())
+    }
+}
+
+// Ways subscribers are offered to confirm with, built-in copy only mentions offered ones
+// Confirmation code isn't generated with `link`, so `POST /subscriptions/confirm_code` never matches
+#[derive(serde::Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfirmationMethodSettings {
+    #[default]
+    LinkAndCode,
+    Link,
+    Code,
+}
+
+// Copy of email sent once subscription is confirmed, built-in copy is used for parts that are not provided
+// Placeholder `{{name}}` is substituted with subscriber's name when sending
+#[derive(serde::Deserialize, Clone, Default)]
+pub struct WelcomeEmailSettings {
+    pub subject: Option<String>,
+    pub html_body: Option<String>,
+    // Path to a template file, which overrides `html_body`
+    pub html_body_file: Option<String>,
+    pub text_body: Option<String>,
+    // Path to a template file, which overrides `text_body`
+    pub text_body_file: Option<String>,
+}
+
+impl WelcomeEmailSettings {
+    fn load_template_files(&mut self) -> Result<(), config::ConfigError> {
+        if let Some(path) = &self.html_body_file {
+            self.html_body = Some(read_template_file("welcome_email.html_body", path)?);
+        }
+        if let Some(path) = &self.text_body_file {
+            self.text_body = Some(read_template_file("welcome_email.text_body", path)?);
+        }
+        Ok(())
+    }
+}
+
+fn read_template_file(field: &str, path: &str) -> Result<String, config::ConfigError> {
+    std::fs::read_to_string(path).map_err(|e| {
+        config::ConfigError::Message(format!(
+            "Failed to read `{}` from file `{}`: {}",
+            field, path, e
+        ))
+    })
+}
+
+// Allowlist of newsletters HTML, built-in allowlist of basic formatting and links is used
+// for parts that are not provided
+#[derive(serde::Deserialize, Clone, Default)]
+pub struct HtmlSanitizerSettings {
+    pub allowed_tags: Option<Vec<String>>,
+    pub allowed_url_schemes: Option<Vec<String>>,
+}
+
+impl HtmlSanitizerSettings {
+    fn validate(&self) -> Result<(), config::ConfigError> {
+        // Contents of these tags are always removed, they can't be allowed
+        if let Some(tags) = &self.allowed_tags {
+            if let Some(tag) = tags
+                .iter()
+                .find(|tag| tag.eq_ignore_ascii_case("script") || tag.eq_ignore_ascii_case("style"))
+            {
+                return Err(invalid_field(
+                    "html_sanitizer.allowed_tags",
+                    &format!("must not contain `{}`", tag),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+// Where newsletters issue contents are stored
+#[derive(serde::Deserialize, Clone, Default)]
+#[serde(tag = "backend", rename_all = "snake_case")]
+pub enum ContentStoreSettings {
+    #[default]
+    Inline,
+    Filesystem {
+        path: String,
+    },
+}
+
+impl ContentStoreSettings {
+    fn validate(&self) -> Result<(), config::ConfigError> {
+        match self {
+            Self::Filesystem { path } if path.trim().is_empty() => {
+                Err(invalid_field("content_store.path", "must not be empty"))
+            }
+            _ => Ok(()),
+        }
+    }
 }
 
 #[derive(serde::Deserialize, Clone)]
 pub struct DatabaseSettings {
     pub engine: String,
     pub username: String,
+    #[serde(default = "empty_secret")]
     pub password: Secret<String>,
+    #[serde(default)]
+    pub password_file: Option<String>,
     #[serde(deserialize_with = "deserialize_number_from_string")]
     pub port: u16,
     pub host: String,
@@ -91,6 +1020,32 @@ pub struct DatabaseSettings {
     pub require_ssl: bool,
     #[serde(deserialize_with = "deserialize_number_from_string")]
     pub query_timeout_secs: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_connections: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub min_connections: u32,
+    // Idle connections are closed after this duration
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub idle_timeout_secs: u64,
+    // Connections are recycled after this duration, even when they are still in use regularly
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_lifetime_secs: u64,
+    // Background workers get their own pool so they don't starve the API of connections
+    // Delivery worker uses 2 at once, one for batch's transaction and one to record sent emails
+    // Delivery worker's notification listener connects on its own, it isn't counted here
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub worker_max_connections: u32,
+    // Pools connect lazily, so unreachable database only surfaces on first request without it
+    #[serde(default)]
+    pub startup_check: Option<DatabaseStartupCheckSettings>,
+}
+
+// Database is pinged when app is built, retrying with exponential backoff until it answers
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct DatabaseStartupCheckSettings {
+    pub max_retries: u32,
+    pub backoff_base_millis: u64,
+    pub backoff_max_millis: u64,
 }
 
 impl DatabaseSettings {
@@ -114,6 +1069,46 @@ impl DatabaseSettings {
             .ssl_mode(self.get_ssl_mode())
     }
 
+    // Subcommands only touch database, so they don't need the rest of settings to be valid
+    pub fn validate(&self) -> Result<(), config::ConfigError> {
+        if self.host.trim().is_empty() {
+            return Err(invalid_field("database.host", "must not be empty"));
+        }
+        if self.database_name.trim().is_empty() {
+            return Err(invalid_field("database.database_name", "must not be empty"));
+        }
+        ensure_not_zero("database.query_timeout_secs", self.query_timeout_secs)?;
+        ensure_not_zero("database.max_connections", self.max_connections as u64)?;
+        if self.max_connections < self.min_connections {
+            return Err(invalid_field(
+                "database.min_connections",
+                "must not be greater than `database.max_connections`",
+            ));
+        }
+        // Delivery worker records sent emails on another connection while batch's transaction is open
+        if self.worker_max_connections < 2 {
+            return Err(invalid_field(
+                "database.worker_max_connections",
+                "must be at least 2",
+            ));
+        }
+        ensure_not_zero("database.idle_timeout_secs", self.idle_timeout_secs)?;
+        ensure_not_zero("database.max_lifetime_secs", self.max_lifetime_secs)?;
+        if let Some(startup_check) = &self.startup_check {
+            ensure_not_zero(
+                "database.startup_check.backoff_base_millis",
+                startup_check.backoff_base_millis,
+            )?;
+            if startup_check.backoff_max_millis < startup_check.backoff_base_millis {
+                return Err(invalid_field(
+                    "database.startup_check.backoff_max_millis",
+                    "must not be less than `database.startup_check.backoff_base_millis`",
+                ));
+            }
+        }
+        Ok(())
+    }
+
     pub fn get_ssl_mode(&self) -> PgSslMode {
         match self.require_ssl {
             true => PgSslMode::Require,
@@ -146,3 +1141,449 @@ impl TryFrom<String> for Environment {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::configuration::{
+        ContentStoreSettings, PiiRedactionSettings, Settings, SmtpProviderSettings, SmtpTlsMode,
+    };
+    use claims::{assert_err, assert_ok};
+    use secrecy::{ExposeSecret, Secret};
+
+    fn valid_settings() -> Settings {
+        Settings::get_configuration().expect("Failed to read configuration")
+    }
+
+    fn assert_invalid_field(settings: Settings, field: &str) {
+        let error = assert_err!(settings.validate());
+        assert!(
+            error.to_string().contains(field),
+            "Error `{}` doesn't name field `{}`",
+            error,
+            field
+        );
+    }
+
+    #[test]
+    fn secret_is_loaded_from_file() {
+        let mut settings = valid_settings();
+        let path = std::env::temp_dir().join(format!("{}.secret", uuid::Uuid::new_v4()));
+        std::fs::write(&path, "password-from-file\n").unwrap();
+        settings.database.password_file = Some(path.to_string_lossy().to_string());
+
+        assert_ok!(settings.load_secret_files());
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            settings.database.password.expose_secret(),
+            "password-from-file"
+        );
+    }
+
+    #[test]
+    fn missing_secret_file_is_rejected() {
+        let mut settings = valid_settings();
+        settings.application.redis_session_key_file = Some("/nonexistent/secret".to_string());
+
+        let error = assert_err!(settings.load_secret_files());
+
+        assert!(error.to_string().contains("application.redis_session_key"));
+        assert!(error.to_string().contains("/nonexistent/secret"));
+    }
+
+    #[test]
+    fn default_configuration_is_valid() {
+        assert_ok!(valid_settings().validate());
+    }
+
+    #[test]
+    fn short_cookie_key_is_rejected() {
+        let mut settings = valid_settings();
+        settings.application.flash_msg_key = Secret::new("too-short".to_string());
+        assert_invalid_field(settings, "application.flash_msg_key");
+    }
+
+    #[test]
+    fn pii_hash_mode_without_key_is_rejected() {
+        let mut settings = valid_settings();
+        settings.application.log_pii_redaction = PiiRedactionSettings::Hash;
+        settings.application.log_pii_hash_key = Secret::new("".to_string());
+        assert_invalid_field(settings, "application.log_pii_hash_key");
+    }
+
+    #[test]
+    fn empty_erased_email_hash_key_is_rejected() {
+        let mut settings = valid_settings();
+        settings.application.erased_email_hash_key = Secret::new("".to_string());
+        assert_invalid_field(settings, "application.erased_email_hash_key");
+    }
+
+    #[test]
+    fn empty_redis_url_is_rejected() {
+        let mut settings = valid_settings();
+        settings.application.redis_url = Secret::new("".to_string());
+        assert_invalid_field(settings, "application.redis_url");
+    }
+
+    #[test]
+    fn malformed_redis_url_is_rejected() {
+        for redis_url in [
+            "redis://127.0.0.1:not-a-port",
+            "redis://",
+            "http://127.0.0.1:6379",
+            "127.0.0.1:6379",
+        ] {
+            let mut settings = valid_settings();
+            settings.application.redis_url = Secret::new(redis_url.to_string());
+            assert_invalid_field(settings, "application.redis_url");
+        }
+    }
+
+    #[test]
+    fn malformed_base_url_is_rejected() {
+        for base_url in ["http://", "http://exa mple.com", "ftp://example.com"] {
+            let mut settings = valid_settings();
+            settings.application.base_url = base_url.to_string();
+            assert_invalid_field(settings, "application.base_url");
+        }
+    }
+
+    #[test]
+    fn zero_maintenance_retry_after_is_rejected() {
+        let mut settings = valid_settings();
+        settings.application.maintenance.retry_after_secs = 0;
+        assert_invalid_field(settings, "application.maintenance.retry_after_secs");
+    }
+
+    #[test]
+    fn zero_idempotency_replay_max_age_is_rejected() {
+        let mut settings = valid_settings();
+        settings.application.idempotency_replay_max_age_millis = Some(0);
+        assert_invalid_field(settings, "application.idempotency_replay_max_age_millis");
+    }
+
+    #[test]
+    fn zero_idempotency_expiration_is_rejected() {
+        let mut settings = valid_settings();
+        settings.application.idempotency_expiration_millis = 0;
+        assert_invalid_field(settings, "application.idempotency_expiration_millis");
+    }
+
+    #[test]
+    fn zero_idempotency_sweep_interval_is_rejected() {
+        let mut settings = valid_settings();
+        settings.application.idempotency_sweep_interval_millis = 0;
+        assert_invalid_field(settings, "application.idempotency_sweep_interval_millis");
+    }
+
+    #[test]
+    fn zero_subscription_token_sweep_interval_is_rejected() {
+        let mut settings = valid_settings();
+        settings
+            .application
+            .subscription_token_sweep_interval_millis = 0;
+        assert_invalid_field(
+            settings,
+            "application.subscription_token_sweep_interval_millis",
+        );
+    }
+
+    #[test]
+    fn script_in_allowed_html_tags_is_rejected() {
+        let mut settings = valid_settings();
+        settings.html_sanitizer.allowed_tags = Some(vec!["p".to_string(), "script".to_string()]);
+        assert_invalid_field(settings, "html_sanitizer.allowed_tags");
+    }
+
+    #[test]
+    fn content_security_policy_with_newline_is_rejected() {
+        let mut settings = valid_settings();
+        settings
+            .application
+            .security_headers
+            .content_security_policy = "default-src 'self';\nscript-src 'none'".to_string();
+        assert_invalid_field(
+            settings,
+            "application.security_headers.content_security_policy",
+        );
+    }
+
+    #[test]
+    fn zero_mx_check_timeout_is_rejected_when_enabled() {
+        let mut settings = valid_settings();
+        settings.application.mx_check.enabled = true;
+        settings.application.mx_check.timeout_millis = 0;
+        assert_invalid_field(settings, "application.mx_check.timeout_millis");
+    }
+
+    #[test]
+    fn cors_origin_with_path_is_rejected() {
+        let mut settings = valid_settings();
+        settings.application.cors.allowed_origins = vec!["https://example.com/".to_string()];
+        assert_invalid_field(settings, "application.cors.allowed_origins");
+    }
+
+    #[test]
+    fn wildcard_cors_origin_is_rejected() {
+        let mut settings = valid_settings();
+        settings.application.cors.allowed_origins = vec!["*".to_string()];
+        assert_invalid_field(settings, "application.cors.allowed_origins");
+    }
+
+    #[test]
+    fn zero_worker_poll_interval_is_rejected() {
+        let mut settings = valid_settings();
+        settings.application.worker_poll_interval_millis = 0;
+        assert_invalid_field(settings, "application.worker_poll_interval_millis");
+    }
+
+    #[test]
+    fn worker_backoff_max_less_than_base_is_rejected() {
+        let mut settings = valid_settings();
+        settings.application.worker_backoff_base_millis = 2000;
+        settings.application.worker_backoff_max_millis = 1000;
+        assert_invalid_field(settings, "application.worker_backoff_max_millis");
+    }
+
+    #[test]
+    fn short_subscription_token_length_is_rejected() {
+        let mut settings = valid_settings();
+        settings.application.subscription_token_length = 16;
+        assert_invalid_field(settings, "application.subscription_token_length");
+    }
+
+    #[test]
+    fn public_url_is_derived_from_base_url_by_default() {
+        let mut settings = valid_settings();
+        settings.application.base_url = "http://127.0.0.1:8000".to_string();
+        settings.application.base_path = "/newsletter".to_string();
+
+        assert_eq!(
+            settings.application.get_public_url(),
+            "http://127.0.0.1:8000/newsletter"
+        );
+    }
+
+    #[test]
+    fn public_url_uses_configured_scheme_host_and_port() {
+        let mut settings = valid_settings();
+        settings.application.base_url = "http://127.0.0.1:8000".to_string();
+        settings.application.base_path = String::new();
+        settings.application.public_scheme = Some("https".to_string());
+        settings.application.public_host = Some("newsletter.example.com".to_string());
+
+        // Bind port isn't kept with public host
+        assert_eq!(
+            settings.application.get_public_url(),
+            "https://newsletter.example.com"
+        );
+
+        settings.application.public_port = Some(8443);
+        assert_eq!(
+            settings.application.get_public_url(),
+            "https://newsletter.example.com:8443"
+        );
+    }
+
+    #[test]
+    fn public_port_replaces_port_of_base_url() {
+        let mut settings = valid_settings();
+        settings.application.base_url = "http://127.0.0.1:8000".to_string();
+        settings.application.base_path = String::new();
+        settings.application.public_port = Some(80);
+
+        assert_eq!(settings.application.get_public_url(), "http://127.0.0.1:80");
+    }
+
+    #[test]
+    fn invalid_public_url_parts_are_rejected() {
+        let mut settings = valid_settings();
+        settings.application.public_scheme = Some("ftp".to_string());
+        assert_invalid_field(settings, "application.public_scheme");
+
+        let mut settings = valid_settings();
+        settings.application.public_host = Some("https://example.com".to_string());
+        assert_invalid_field(settings, "application.public_host");
+
+        let mut settings = valid_settings();
+        settings.application.public_port = Some(0);
+        assert_invalid_field(settings, "application.public_port");
+    }
+
+    #[test]
+    fn base_path_with_trailing_slash_is_rejected() {
+        let mut settings = valid_settings();
+        settings.application.base_path = "/newsletter/".to_string();
+        assert_invalid_field(settings, "application.base_path");
+    }
+
+    #[test]
+    fn zero_max_subscribe_body_size_is_rejected() {
+        let mut settings = valid_settings();
+        settings.application.max_subscribe_body_size_bytes = 0;
+        assert_invalid_field(settings, "application.max_subscribe_body_size_bytes");
+    }
+
+    #[test]
+    fn zero_subscribe_rate_limit_window_is_rejected() {
+        let mut settings = valid_settings();
+        if let Some(rate_limit) = settings.application.subscribe_rate_limit.as_mut() {
+            rate_limit.window_secs = 0;
+        }
+        assert_invalid_field(settings, "application.subscribe_rate_limit.window_secs");
+    }
+
+    #[test]
+    fn min_connections_greater_than_max_connections_is_rejected() {
+        let mut settings = valid_settings();
+        settings.database.max_connections = 2;
+        settings.database.min_connections = 5;
+        assert_invalid_field(settings, "database.min_connections");
+    }
+
+    #[test]
+    fn zero_worker_max_connections_is_rejected() {
+        let mut settings = valid_settings();
+        settings.database.worker_max_connections = 0;
+        assert_invalid_field(settings, "database.worker_max_connections");
+    }
+
+    #[test]
+    fn single_worker_connection_is_rejected() {
+        let mut settings = valid_settings();
+        settings.database.worker_max_connections = 1;
+        assert_invalid_field(settings, "database.worker_max_connections");
+    }
+
+    #[test]
+    fn blank_list_id_is_rejected() {
+        let mut settings = valid_settings();
+        settings.email_client.list_id = Some("  ".to_string());
+        assert_invalid_field(settings, "email_client.list_id");
+    }
+
+    #[test]
+    fn blank_archive_email_is_rejected() {
+        let mut settings = valid_settings();
+        settings.email_client.archive_email = Some("  ".to_string());
+        assert_invalid_field(settings, "email_client.archive_email");
+    }
+
+    fn failover_provider(host: &str) -> SmtpProviderSettings {
+        SmtpProviderSettings {
+            username: None,
+            password: None,
+            password_file: None,
+            host: host.to_string(),
+            port: None,
+            tls_mode: Some(SmtpTlsMode::Tls),
+            require_tls: None,
+            client_certificate_file: None,
+            client_key_file: None,
+        }
+    }
+
+    #[test]
+    fn failover_provider_without_host_is_rejected() {
+        let mut settings = valid_settings();
+        settings
+            .email_client
+            .failover_providers
+            .push(failover_provider(" "));
+        assert_invalid_field(settings, "email_client.failover_providers[0].host");
+    }
+
+    #[test]
+    fn failover_provider_password_is_loaded_from_file() {
+        let mut settings = valid_settings();
+        let path = std::env::temp_dir().join(format!("{}.secret", uuid::Uuid::new_v4()));
+        std::fs::write(&path, "failover-password\n").unwrap();
+        let mut provider = failover_provider("smtp.example.com");
+        provider.username = Some(Secret::new("failover".to_string()));
+        provider.password_file = Some(path.to_string_lossy().to_string());
+        settings.email_client.failover_providers.push(provider);
+
+        assert_ok!(settings.load_secret_files());
+        std::fs::remove_file(&path).unwrap();
+
+        let provider = &settings.email_client.failover_providers[0];
+        assert_eq!(
+            provider.password.as_ref().unwrap().expose_secret(),
+            "failover-password"
+        );
+        assert_ok!(settings.validate());
+    }
+
+    #[test]
+    fn missing_failover_provider_password_file_is_rejected() {
+        let mut settings = valid_settings();
+        let mut provider = failover_provider("smtp.example.com");
+        provider.password_file = Some("/nonexistent/secret".to_string());
+        settings.email_client.failover_providers.push(provider);
+
+        let error = assert_err!(settings.load_secret_files());
+
+        assert!(error
+            .to_string()
+            .contains("email_client.failover_providers[0].password"));
+    }
+
+    #[test]
+    fn deprecated_require_tls_maps_to_tls_mode() {
+        let mut settings = valid_settings();
+        settings.email_client.tls_mode = None;
+        settings.email_client.require_tls = Some(false);
+        assert_eq!(settings.email_client.get_tls_mode(), SmtpTlsMode::None);
+        settings.email_client.require_tls = Some(true);
+        assert_eq!(settings.email_client.get_tls_mode(), SmtpTlsMode::Tls);
+    }
+
+    #[test]
+    fn tls_mode_with_deprecated_require_tls_is_rejected() {
+        let mut settings = valid_settings();
+        settings.email_client.tls_mode = Some(SmtpTlsMode::Starttls);
+        settings.email_client.require_tls = Some(true);
+        assert_invalid_field(settings, "email_client.require_tls");
+    }
+
+    #[test]
+    fn client_certificate_without_key_is_rejected() {
+        let mut settings = valid_settings();
+        settings.email_client.tls_mode = Some(SmtpTlsMode::Tls);
+        settings.email_client.client_certificate_file = Some("client.pem".to_string());
+        assert_invalid_field(settings, "email_client.client_certificate_file");
+    }
+
+    #[test]
+    fn client_certificate_without_tls_is_rejected() {
+        let mut settings = valid_settings();
+        settings.email_client.tls_mode = Some(SmtpTlsMode::None);
+        settings.email_client.client_certificate_file = Some("client.pem".to_string());
+        settings.email_client.client_key_file = Some("client.key".to_string());
+        assert_invalid_field(settings, "email_client.client_certificate_file");
+    }
+
+    #[test]
+    fn username_without_password_is_rejected() {
+        let mut settings = valid_settings();
+        settings.email_client.username = Some(Secret::new("admin".to_string()));
+        settings.email_client.password = None;
+        assert_invalid_field(settings, "email_client.username");
+    }
+
+    #[test]
+    fn zero_send_rate_is_rejected() {
+        let mut settings = valid_settings();
+        settings.email_client.send_rate_per_second = Some(0);
+        assert_invalid_field(settings, "email_client.send_rate_per_second");
+    }
+
+    #[test]
+    fn empty_filesystem_content_store_path_is_rejected() {
+        let mut settings = valid_settings();
+        settings.content_store = ContentStoreSettings::Filesystem {
+            path: " ".to_string(),
+        };
+        assert_invalid_field(settings, "content_store.path");
+    }
+}
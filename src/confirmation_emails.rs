@@ -0,0 +1,586 @@
+use crate::configuration::{
+    ApplicationSettings, ConfirmationEmailSettings, ConfirmationMethodSettings, Settings,
+    WelcomeEmailSettings,
+};
+use crate::email_client::{EmailClient, SendRateLimiter};
+use crate::newsletters_issues::WorkerBackoff;
+use crate::routes::subscriptions::send_confirmation_email;
+use crate::routes::{SubscriberEmail, SubscriptionStatus};
+use crate::startup::{build_email_client, WorkerPgPool};
+use crate::telemetry::redact_pii;
+use crate::worker_status::{try_record_worker_heartbeat, WorkerHeartbeat, WorkerName};
+use sqlx::postgres::types::PgInterval;
+use sqlx::{PgPool, Postgres, Transaction};
+use std::sync::Arc;
+use std::time::Duration;
+
+type PgTransaction = sqlx::Transaction<'static, sqlx::Postgres>;
+
+// How confirmation emails that failed to be sent are retried by worker
+#[derive(Clone, Copy, Debug)]
+pub struct ConfirmationEmailRetryPolicy {
+    // Retry is disabled when max retries is 0
+    pub max_retries: u32,
+    pub retry_interval: Duration,
+}
+
+impl ConfirmationEmailRetryPolicy {
+    pub fn from_settings(settings: &ApplicationSettings) -> Self {
+        Self {
+            max_retries: settings.confirmation_email_max_retries,
+            retry_interval: Duration::from_millis(
+                settings.confirmation_email_retry_interval_millis,
+            ),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.max_retries > 0
+    }
+}
+
+const DEFAULT_SUBJECT: &str = "Confirmation";
+const DEFAULT_HTML_BODY: &str = "<p>\
+    Welcome to our newsletter!<br />\
+    Click <a href=\"{{confirmation_link}}\">here</a> to confirm your subscription.<br />\
+    Or enter this confirmation code: <b>{{confirmation_code}}</b>\
+    </p>";
+const DEFAULT_TEXT_BODY: &str = "Welcome to our newsletter!\n\
+    Go to this link: {{confirmation_link}} to confirm your subscription.\n\
+    Or enter this confirmation code: {{confirmation_code}}";
+const DEFAULT_LINK_HTML_BODY: &str = "<p>\
+    Welcome to our newsletter!<br />\
+    Click <a href=\"{{confirmation_link}}\">here</a> to confirm your subscription.\
+    </p>";
+const DEFAULT_LINK_TEXT_BODY: &str = "Welcome to our newsletter!\n\
+    Go to this link: {{confirmation_link}} to confirm your subscription.";
+const DEFAULT_CODE_HTML_BODY: &str = "<p>\
+    Welcome to our newsletter!<br />\
+    Enter this confirmation code to confirm your subscription: <b>{{confirmation_code}}</b>\
+    </p>";
+const DEFAULT_CODE_TEXT_BODY: &str = "Welcome to our newsletter!\n\
+    Enter this confirmation code to confirm your subscription: {{confirmation_code}}";
+
+// Templates of confirmation email, falling back to built-in copy when not configured
+#[derive(Clone, Debug)]
+pub struct ConfirmationEmailTemplate {
+    method: ConfirmationMethodSettings,
+    subject: String,
+    html_body: String,
+    text_body: String,
+}
+
+pub struct ConfirmationEmail {
+    pub subject: String,
+    pub html_body: String,
+    pub text_body: String,
+}
+
+impl ConfirmationEmailTemplate {
+    pub fn from_settings(settings: &ConfirmationEmailSettings) -> Self {
+        let (default_html_body, default_text_body) = match settings.method {
+            ConfirmationMethodSettings::LinkAndCode => (DEFAULT_HTML_BODY, DEFAULT_TEXT_BODY),
+            ConfirmationMethodSettings::Link => (DEFAULT_LINK_HTML_BODY, DEFAULT_LINK_TEXT_BODY),
+            ConfirmationMethodSettings::Code => (DEFAULT_CODE_HTML_BODY, DEFAULT_CODE_TEXT_BODY),
+        };
+        Self {
+            method: settings.method,
+            subject: settings
+                .subject
+                .clone()
+                .unwrap_or_else(|| DEFAULT_SUBJECT.to_string()),
+            html_body: settings
+                .html_body
+                .clone()
+                .unwrap_or_else(|| default_html_body.to_string()),
+            text_body: settings
+                .text_body
+                .clone()
+                .unwrap_or_else(|| default_text_body.to_string()),
+        }
+    }
+
+    // Subscribers can only confirm by code when it's offered
+    pub fn offers_code(&self) -> bool {
+        self.method != ConfirmationMethodSettings::Link
+    }
+
+    pub fn render(&self, confirmation_link: &str, confirmation_code: &str) -> ConfirmationEmail {
+        let render = |template: &str| {
+            template
+                .replace("{{confirmation_link}}", confirmation_link)
+                .replace("{{confirmation_code}}", confirmation_code)
+        };
+        ConfirmationEmail {
+            subject: render(&self.subject),
+            html_body: render(&self.html_body),
+            text_body: render(&self.text_body),
+        }
+    }
+}
+
+pub const DEFAULT_WELCOME_SUBJECT: &str = "Welcome";
+const DEFAULT_WELCOME_HTML_BODY: &str = "<p>\
+    Hi {{name}},<br />\
+    Your subscription is confirmed, upcoming newsletters will be sent to this address.\
+    </p>";
+const DEFAULT_WELCOME_TEXT_BODY: &str = "Hi {{name}},\n\
+    Your subscription is confirmed, upcoming newsletters will be sent to this address.";
+
+// Templates of welcome email sent after subscription is confirmed,
+// falling back to built-in copy when not configured
+#[derive(Clone, Debug)]
+pub struct WelcomeEmailTemplate {
+    subject: String,
+    html_body: String,
+    text_body: String,
+}
+
+pub struct WelcomeEmail {
+    pub subject: String,
+    pub html_body: String,
+    pub text_body: String,
+}
+
+impl WelcomeEmailTemplate {
+    pub fn from_settings(settings: &WelcomeEmailSettings) -> Self {
+        Self {
+            subject: settings
+                .subject
+                .clone()
+                .unwrap_or_else(|| DEFAULT_WELCOME_SUBJECT.to_string()),
+            html_body: settings
+                .html_body
+                .clone()
+                .unwrap_or_else(|| DEFAULT_WELCOME_HTML_BODY.to_string()),
+            text_body: settings
+                .text_body
+                .clone()
+                .unwrap_or_else(|| DEFAULT_WELCOME_TEXT_BODY.to_string()),
+        }
+    }
+
+    // Name is typed in by subscriber, so it's escaped in HTML body
+    pub fn render(&self, subscriber_name: &str) -> WelcomeEmail {
+        WelcomeEmail {
+            subject: self.subject.replace("{{name}}", subscriber_name),
+            html_body: self
+                .html_body
+                .replace("{{name}}", &htmlescape::encode_minimal(subscriber_name)),
+            text_body: self.text_body.replace("{{name}}", subscriber_name),
+        }
+    }
+}
+
+// Deliver confirmation emails that couldn't be sent right away when subscribing
+// e.g. sending rate of email client is exceeded or email service provider is down
+pub struct ConfirmationEmailsDeliveryWorker {
+    settings: Settings,
+    pg_pool: WorkerPgPool,
+    send_rate_limiter: Option<Arc<SendRateLimiter>>,
+}
+
+impl ConfirmationEmailsDeliveryWorker {
+    pub fn builder(settings: Settings) -> Self {
+        Self {
+            settings,
+            pg_pool: WorkerPgPool::default(),
+            send_rate_limiter: None,
+        }
+    }
+
+    pub fn set_pg_pool(mut self, pg_pool: PgPool) -> Self {
+        self.pg_pool.set_pg_pool(pg_pool);
+        self
+    }
+
+    pub fn set_pg_pool_max_connections(mut self, max_connections: u32) -> Self {
+        self.pg_pool.set_max_connections(max_connections);
+        self
+    }
+
+    // Share limiter with API and other workers sending emails in the same process
+    pub fn set_send_rate_limiter(
+        mut self,
+        send_rate_limiter: Option<Arc<SendRateLimiter>>,
+    ) -> Self {
+        self.send_rate_limiter = send_rate_limiter;
+        self
+    }
+
+    fn get_or_build_pg_pool(&self) -> PgPool {
+        self.pg_pool.get_or_build(&self.settings.database)
+    }
+
+    pub async fn run_until_terminated(self) -> Result<(), anyhow::Error> {
+        let pg_pool = self.get_or_build_pg_pool();
+        let email_client = build_email_client(
+            self.settings.email_client.clone(),
+            self.send_rate_limiter.clone(),
+        )?;
+        let heartbeat_interval =
+            Duration::from_millis(self.settings.application.worker_heartbeat_interval_millis);
+        let retry_policy = ConfirmationEmailRetryPolicy::from_settings(&self.settings.application);
+        let template = ConfirmationEmailTemplate::from_settings(&self.settings.confirmation_email);
+        worker_loop(
+            pg_pool,
+            email_client,
+            self.settings.application.get_public_url(),
+            template,
+            retry_policy,
+            heartbeat_interval,
+        )
+        .await;
+        Ok(())
+    }
+}
+
+async fn worker_loop(
+    pg_pool: PgPool,
+    email_client: EmailClient,
+    app_base_url: String,
+    template: ConfirmationEmailTemplate,
+    retry_policy: ConfirmationEmailRetryPolicy,
+    heartbeat_interval: Duration,
+) {
+    // Queue is filled only when confirmation emails can't be sent right away
+    // So polling it is cheap enough
+    const POLL_INTERVAL: Duration = Duration::from_millis(500);
+    let mut heartbeat =
+        WorkerHeartbeat::new(WorkerName::ConfirmationEmailsDelivery, heartbeat_interval);
+    loop {
+        let outcome = try_execute_task(
+            &pg_pool,
+            &email_client,
+            &app_base_url,
+            &template,
+            &retry_policy,
+        )
+        .await;
+        let (succeeded_count, failed_count) = match outcome {
+            Ok(ExecutionResult::TaskCompleted) => (1, 0),
+            Ok(ExecutionResult::EmptyQueue) => (0, 0),
+            Ok(ExecutionResult::TaskFailed) | Err(_) => (0, 1),
+        };
+        heartbeat
+            .beat(&pg_pool, succeeded_count, failed_count)
+            .await;
+
+        match outcome {
+            Ok(ExecutionResult::EmptyQueue) => tokio::time::sleep(POLL_INTERVAL).await,
+            Err(_) => tokio::time::sleep(Duration::from_secs(1)).await,
+            Ok(ExecutionResult::TaskCompleted) | Ok(ExecutionResult::TaskFailed) => {}
+        }
+    }
+}
+
+pub enum ExecutionResult {
+    EmptyQueue,
+    TaskCompleted,
+    // Task is rescheduled or dropped after exceeding max retries
+    TaskFailed,
+}
+
+#[tracing::instrument(
+    name = "Execute confirmation email task",
+    skip_all,
+    fields(
+        subscriber_email = tracing::field::Empty,
+    )
+)]
+pub async fn try_execute_task(
+    pg_pool: &PgPool,
+    email_client: &EmailClient,
+    app_base_url: &str,
+    template: &ConfirmationEmailTemplate,
+    retry_policy: &ConfirmationEmailRetryPolicy,
+) -> anyhow::Result<ExecutionResult> {
+    let task = dequeue_task(pg_pool).await?;
+    if task.is_none() {
+        return Ok(ExecutionResult::EmptyQueue);
+    }
+    let (mut transaction, task) = task.unwrap();
+    tracing::Span::current().record(
+        "subscriber_email",
+        &tracing::field::display(redact_pii(&task.subscriber_email)),
+    );
+
+    match SubscriberEmail::parse(task.subscriber_email) {
+        Ok(subscriber_email) => {
+            if let Err(e) = send_confirmation_email(
+                app_base_url,
+                email_client,
+                template,
+                &subscriber_email,
+                &task.subscription_token,
+                task.confirmation_code.as_deref().unwrap_or_default(),
+            )
+            .await
+            {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    n_retries = task.n_retries,
+                    "Failed to send confirmation email"
+                );
+                if (task.n_retries as u32) < retry_policy.max_retries {
+                    reschedule_task(
+                        &mut transaction,
+                        &task.subscription_token,
+                        retry_policy.retry_interval,
+                    )
+                    .await?;
+                } else {
+                    tracing::error!("Give up sending confirmation email after max retries");
+                    delete_task(&mut transaction, &task.subscription_token).await?;
+                }
+                transaction.commit().await?;
+                return Ok(ExecutionResult::TaskFailed);
+            }
+        }
+        Err(e) => {
+            tracing::error!(
+                error.message = %e,
+                "Skip sending confirmation email to invalid subscriber email"
+            );
+        }
+    }
+
+    delete_task(&mut transaction, &task.subscription_token).await?;
+    transaction.commit().await?;
+    Ok(ExecutionResult::TaskCompleted)
+}
+
+#[tracing::instrument(name = "Enqueue confirmation email into database", skip_all)]
+pub async fn enqueue_confirmation_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscription_token: &str,
+    confirmation_code: Option<&str>,
+    subscriber_email: &SubscriberEmail,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO confirmation_emails_delivery_queue (
+            subscription_token,
+            confirmation_code,
+            subscriber_email,
+            enqueued_at
+        )
+        VALUES ($1, $2, $3, now())
+        "#,
+        subscription_token,
+        confirmation_code,
+        subscriber_email.as_ref()
+    )
+    .execute(transaction)
+    .await?;
+
+    Ok(())
+}
+
+struct ConfirmationEmailTask {
+    subscription_token: String,
+    confirmation_code: Option<String>,
+    subscriber_email: String,
+    n_retries: i32,
+}
+
+#[tracing::instrument(name = "Dequeue confirmation email from database", skip_all)]
+async fn dequeue_task(
+    pg_pool: &PgPool,
+) -> Result<Option<(PgTransaction, ConfirmationEmailTask)>, sqlx::Error> {
+    let mut transaction = pg_pool.begin().await?;
+    let result = sqlx::query_as!(
+        ConfirmationEmailTask,
+        r#"
+        SELECT subscription_token, confirmation_code, subscriber_email, n_retries
+        FROM confirmation_emails_delivery_queue
+        WHERE execute_after <= now()
+        ORDER BY enqueued_at
+        FOR UPDATE
+        SKIP LOCKED
+        LIMIT 1
+        "#,
+    )
+    .fetch_optional(&mut transaction)
+    .await?;
+
+    Ok(result.map(|task| (transaction, task)))
+}
+
+#[tracing::instrument(name = "Reschedule confirmation email task in database", skip_all)]
+async fn reschedule_task(
+    transaction: &mut PgTransaction,
+    subscription_token: &str,
+    retry_interval: Duration,
+) -> Result<(), anyhow::Error> {
+    let retry_interval = PgInterval::try_from(retry_interval).map_err(|e| anyhow::anyhow!(e))?;
+    sqlx::query!(
+        r#"
+        UPDATE confirmation_emails_delivery_queue
+        SET
+            n_retries = n_retries + 1,
+            execute_after = now() + $2
+        WHERE subscription_token = $1
+        "#,
+        subscription_token,
+        retry_interval
+    )
+    .execute(transaction)
+    .await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(name = "Delete confirmation email task from database", skip_all)]
+async fn delete_task(
+    transaction: &mut PgTransaction,
+    subscription_token: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        DELETE FROM confirmation_emails_delivery_queue
+        WHERE subscription_token = $1
+        "#,
+        subscription_token
+    )
+    .execute(transaction)
+    .await?;
+
+    Ok(())
+}
+
+// Expired tokens of pending subscriptions can't confirm anything, a new one is created on resend
+// Tokens of confirmed subscriptions are kept, unsubscribe links are built from them
+pub struct DeleteExpiredSubscriptionTokensWorker {
+    settings: Settings,
+    pg_pool: WorkerPgPool,
+}
+
+impl DeleteExpiredSubscriptionTokensWorker {
+    pub fn builder(settings: Settings) -> Self {
+        Self {
+            settings,
+            pg_pool: WorkerPgPool::default(),
+        }
+    }
+
+    pub fn set_pg_pool(mut self, pg_pool: PgPool) -> Self {
+        self.pg_pool.set_pg_pool(pg_pool);
+        self
+    }
+
+    pub fn set_pg_pool_max_connections(mut self, max_connections: u32) -> Self {
+        self.pg_pool.set_max_connections(max_connections);
+        self
+    }
+
+    fn get_or_build_pg_pool(&self) -> PgPool {
+        self.pg_pool.get_or_build(&self.settings.database)
+    }
+
+    pub async fn run_until_terminated(self) -> Result<(), std::io::Error> {
+        let expiration =
+            Duration::from_secs(self.settings.application.subscription_token_expiration_secs);
+        let sweep_interval = Duration::from_millis(
+            self.settings
+                .application
+                .subscription_token_sweep_interval_millis,
+        );
+        let pg_pool = self.get_or_build_pg_pool();
+        delete_expired_subscription_tokens_worker_loop(
+            pg_pool,
+            expiration,
+            sweep_interval,
+            WorkerBackoff::from_settings(&self.settings.application),
+        )
+        .await;
+        Ok(())
+    }
+}
+
+async fn delete_expired_subscription_tokens_worker_loop(
+    pg_pool: PgPool,
+    expiration: Duration,
+    sweep_interval: Duration,
+    backoff: WorkerBackoff,
+) {
+    let mut n_consecutive_failures = 0;
+    loop {
+        match delete_expired_subscription_tokens(&pg_pool, expiration).await {
+            Ok(_) => {
+                n_consecutive_failures = 0;
+                try_record_worker_heartbeat(
+                    &pg_pool,
+                    WorkerName::DeleteExpiredSubscriptionTokens,
+                    sweep_interval,
+                    1,
+                    0,
+                )
+                .await;
+                tokio::time::sleep(sweep_interval).await
+            }
+            Err(e) => {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to delete expired subscription tokens"
+                );
+                n_consecutive_failures += 1;
+                let delay = backoff.delay(n_consecutive_failures);
+                // Worker is expected to be back after backoff delay
+                try_record_worker_heartbeat(
+                    &pg_pool,
+                    WorkerName::DeleteExpiredSubscriptionTokens,
+                    delay,
+                    0,
+                    1,
+                )
+                .await;
+                tokio::time::sleep(delay).await;
+            }
+        }
+    }
+}
+
+// Pending confirmation emails reference the tokens, so they're deleted first
+#[tracing::instrument(
+    name = "Delete expired subscription tokens in database",
+    skip(pg_pool, expiration)
+)]
+async fn delete_expired_subscription_tokens(
+    pg_pool: &PgPool,
+    expiration: Duration,
+) -> Result<(), anyhow::Error> {
+    let mut transaction = pg_pool.begin().await?;
+    sqlx::query!(
+        r#"
+        DELETE FROM confirmation_emails_delivery_queue
+        WHERE subscription_token IN (
+            SELECT t.subscription_token
+            FROM subscription_tokens t
+            JOIN subscriptions s ON s.id = t.subscription_id
+            WHERE s.status = $1 AND t.created_at + make_interval(secs => $2) < now()
+        )
+        "#,
+        SubscriptionStatus::Pending.as_ref(),
+        expiration.as_secs_f64()
+    )
+    .execute(&mut transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+        DELETE FROM subscription_tokens t
+        USING subscriptions s
+        WHERE s.id = t.subscription_id
+            AND s.status = $1
+            AND t.created_at + make_interval(secs => $2) < now()
+        "#,
+        SubscriptionStatus::Pending.as_ref(),
+        expiration.as_secs_f64()
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(())
+}
@@ -0,0 +1,214 @@
+use crate::configuration::ContentStoreSettings;
+use anyhow::Context;
+use std::path::PathBuf;
+
+const FILE_URI_SCHEME: &str = "file://";
+
+// Store large newsletters issue contents outside of database
+// Database only keeps URI reference to the content
+pub enum ContentStore {
+    // Keep content inline in database
+    Inline,
+    Filesystem { base_path: PathBuf },
+}
+
+impl ContentStore {
+    pub fn from_settings(settings: &ContentStoreSettings) -> Self {
+        match settings {
+            ContentStoreSettings::Inline => Self::Inline,
+            ContentStoreSettings::Filesystem { path } => Self::Filesystem {
+                base_path: PathBuf::from(path),
+            },
+        }
+    }
+
+    // Return URI of stored content, or None if content should be kept inline
+    #[tracing::instrument(name = "Put content into content store", skip(self, content))]
+    pub async fn put(&self, key: &str, content: &str) -> Result<Option<String>, anyhow::Error> {
+        match self {
+            Self::Inline => Ok(None),
+            Self::Filesystem { base_path } => {
+                let path = base_path.join(key);
+                if let Some(parent) = path.parent() {
+                    tokio::fs::create_dir_all(parent)
+                        .await
+                        .context("Failed to create content directory")?;
+                }
+                tokio::fs::write(&path, content)
+                    .await
+                    .context("Failed to write content into file")?;
+                let path = path
+                    .canonicalize()
+                    .context("Failed to resolve content file path")?;
+                Ok(Some(format!("{}{}", FILE_URI_SCHEME, path.display())))
+            }
+        }
+    }
+
+    // Contents put under `key` are removed when returned guard is dropped without being kept
+    // Used to not leave orphan contents behind when their issue isn't committed
+    pub fn track_uncommitted(&self, key: &str) -> UncommittedContents {
+        UncommittedContents {
+            path: match self {
+                Self::Inline => None,
+                Self::Filesystem { base_path } => Some(base_path.join(key)),
+            },
+        }
+    }
+
+    // Resolve content by URI scheme instead of current backend
+    // So contents stored before switching backend are still readable
+    #[tracing::instrument(name = "Get content from content store", skip(self))]
+    pub async fn get(&self, uri: &str) -> Result<String, anyhow::Error> {
+        match uri.strip_prefix(FILE_URI_SCHEME) {
+            Some(path) => tokio::fs::read_to_string(path)
+                .await
+                .with_context(|| format!("Failed to read content from {}", uri)),
+            None => anyhow::bail!("Unsupported content URI: {}", uri),
+        }
+    }
+
+    // Put content into store if it is stored by reference
+    // Return (inline content, content URI)
+    pub async fn put_optional(
+        &self,
+        key: &str,
+        content: Option<String>,
+    ) -> Result<(Option<String>, Option<String>), anyhow::Error> {
+        match content {
+            Some(content) => match self.put(key, &content).await? {
+                Some(uri) => Ok((None, Some(uri))),
+                None => Ok((Some(content), None)),
+            },
+            None => Ok((None, None)),
+        }
+    }
+
+    // Resolve content from inline value or from store by URI
+    pub async fn get_optional(
+        &self,
+        inline_content: Option<String>,
+        uri: Option<String>,
+    ) -> Result<Option<String>, anyhow::Error> {
+        match uri {
+            Some(uri) => Ok(Some(self.get(&uri).await?)),
+            None => Ok(inline_content),
+        }
+    }
+}
+
+#[must_use = "Contents are removed as soon as guard is dropped, call `keep` once they're committed"]
+pub struct UncommittedContents {
+    path: Option<PathBuf>,
+}
+
+impl UncommittedContents {
+    pub fn keep(mut self) {
+        self.path = None;
+    }
+}
+
+impl Drop for UncommittedContents {
+    // Drop can't await, contents of one issue are only a couple of small local files
+    fn drop(&mut self) {
+        let path = match self.path.take() {
+            Some(path) => path,
+            None => return,
+        };
+        match std::fs::remove_dir_all(&path) {
+            Ok(_) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => tracing::warn!(
+                error.message = %e,
+                "Failed to remove uncommitted contents at {}",
+                path.display()
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::content_store::ContentStore;
+    use claims::{assert_none, assert_some};
+    use fake::faker::lorem::en::Paragraph;
+    use fake::Fake;
+    use uuid::Uuid;
+
+    fn filesystem_store() -> ContentStore {
+        ContentStore::Filesystem {
+            base_path: std::env::temp_dir().join(Uuid::new_v4().to_string()),
+        }
+    }
+
+    #[tokio::test]
+    async fn filesystem_store_round_trips_content() {
+        let store = filesystem_store();
+        let content: String = Paragraph(1..10).fake();
+
+        let uri = assert_some!(store.put("issue/text.txt", &content).await.unwrap());
+        assert!(uri.starts_with("file://"));
+
+        assert_eq!(store.get(&uri).await.unwrap(), content);
+    }
+
+    #[tokio::test]
+    async fn filesystem_store_round_trips_optional_content() {
+        let store = filesystem_store();
+        let content = format!("<p>{}</p>", Paragraph(1..10).fake::<String>());
+
+        let (inline_content, uri) = store
+            .put_optional("issue/html.html", Some(content.clone()))
+            .await
+            .unwrap();
+        assert_none!(&inline_content);
+        assert_some!(&uri);
+
+        let resolved = store.get_optional(inline_content, uri).await.unwrap();
+        assert_eq!(resolved, Some(content));
+    }
+
+    #[tokio::test]
+    async fn inline_store_keeps_content_inline() {
+        let store = ContentStore::Inline;
+        let content: String = Paragraph(1..10).fake();
+
+        let (inline_content, uri) = store
+            .put_optional("issue/text.txt", Some(content.clone()))
+            .await
+            .unwrap();
+        assert_none!(&uri);
+
+        let resolved = store.get_optional(inline_content, uri).await.unwrap();
+        assert_eq!(resolved, Some(content));
+    }
+
+    #[tokio::test]
+    async fn uncommitted_contents_are_removed_unless_kept() {
+        let store = filesystem_store();
+        let content: String = Paragraph(1..10).fake();
+        let removed_uri = {
+            let _contents = store.track_uncommitted("removed");
+            store
+                .put("removed/text.txt", &content)
+                .await
+                .unwrap()
+                .unwrap()
+        };
+        let kept_uri = {
+            let contents = store.track_uncommitted("kept");
+            let uri = store.put("kept/text.txt", &content).await.unwrap().unwrap();
+            contents.keep();
+            uri
+        };
+
+        assert!(store.get(&removed_uri).await.is_err());
+        assert_eq!(store.get(&kept_uri).await.unwrap(), content);
+    }
+
+    #[tokio::test]
+    async fn unsupported_uri_is_rejected() {
+        let store = filesystem_store();
+        assert!(store.get("s3://bucket/key").await.is_err());
+    }
+}
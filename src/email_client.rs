@@ -1,94 +1,649 @@
+use crate::configuration::SmtpTlsMode;
 use crate::routes::SubscriberEmail;
 use anyhow::Context;
+use lettre::address::Envelope;
+use lettre::message::header::{Header, HeaderName, HeaderValue};
 use lettre::transport::smtp;
+use lettre::transport::smtp::client::{Identity, Tls, TlsParameters};
 use lettre::{message, AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
 use secrecy::{ExposeSecret, Secret};
-use std::time::Duration;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 // This api app use Email service provider to send email
 // So this app is a client of Email service
 pub struct EmailClient {
-    smtp_transport: AsyncSmtpTransport<Tokio1Executor>,
+    // Providers are tried in order, next one is only used when previous one fails transiently
+    smtp_transports: Vec<AsyncSmtpTransport<Tokio1Executor>>,
+    request_timeout: Duration,
     sender_email: SubscriberEmail,
+    from_name: String,
+    reply_to: Option<SubscriberEmail>,
+    // SMTP envelope sender, `sender_email` is used when it's not set
+    envelope_from: Option<SubscriberEmail>,
+    // Shared by every email client built in the same process, see `set_send_rate_limiter`
+    send_rate_limiter: Option<Arc<SendRateLimiter>>,
+    list_id: Option<String>,
+    subject_prefix: String,
+    // Mailbox receiving one copy of each newsletters issue
+    archive_email: Option<SubscriberEmail>,
+    // Unlimited if not set
+    max_attachments_size_bytes: Option<usize>,
+}
+
+pub struct EmailAttachment {
+    pub filename: String,
+    // MIME type, e.g. "application/pdf"
+    pub content_type: String,
+    pub content: Vec<u8>,
+}
+
+// How connection to an SMTP server is secured
+#[derive(Clone)]
+pub struct SmtpTls {
+    pub mode: SmtpTlsMode,
+    pub client_certificate: Option<SmtpClientCertificate>,
+}
+
+// PEM encoded client certificate and its private key
+#[derive(Clone)]
+pub struct SmtpClientCertificate {
+    pub certificate_pem: Vec<u8>,
+    pub key_pem: Secret<Vec<u8>>,
+}
+
+impl From<SmtpTlsMode> for SmtpTls {
+    fn from(mode: SmtpTlsMode) -> Self {
+        Self {
+            mode,
+            client_certificate: None,
+        }
+    }
+}
+
+// `List-Id` header (RFC 2919) identifies mailing list that newsletters are sent from
+#[derive(Clone)]
+struct ListId(String);
+
+impl Header for ListId {
+    fn name() -> HeaderName {
+        HeaderName::new_from_ascii_str("List-Id")
+    }
+
+    fn parse(s: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Self(s.to_string()))
+    }
+
+    fn display(&self) -> HeaderValue {
+        HeaderValue::new(Self::name(), self.0.clone())
+    }
+}
+
+// Token bucket that limits the number of emails can be sent per second
+// Email service providers usually reject or throttle clients that send too fast
+pub struct SendRateLimiter {
+    rate_per_second: f64,
+    state: Mutex<SendRateLimiterState>,
+}
+
+struct SendRateLimiterState {
+    available_permits: f64,
+    last_refilled_at: Instant,
+}
+
+impl SendRateLimiter {
+    pub fn new(rate_per_second: u32) -> Self {
+        let rate_per_second = rate_per_second.max(1) as f64;
+        Self {
+            rate_per_second,
+            state: Mutex::new(SendRateLimiterState {
+                available_permits: rate_per_second,
+                last_refilled_at: Instant::now(),
+            }),
+        }
+    }
+
+    // Return how long to wait until next permit is available if there is no permit left
+    fn try_acquire(&self) -> Result<(), Duration> {
+        let mut state = self.state.lock().unwrap();
+        self.refill(&mut state);
+
+        if state.available_permits >= 1.0 {
+            state.available_permits -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - state.available_permits) / self.rate_per_second,
+            ))
+        }
+    }
+
+    fn has_available_permit(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        self.refill(&mut state);
+        state.available_permits >= 1.0
+    }
+
+    fn refill(&self, state: &mut SendRateLimiterState) {
+        let now = Instant::now();
+        let elapsed_secs = now.duration_since(state.last_refilled_at).as_secs_f64();
+        state.available_permits = (state.available_permits + elapsed_secs * self.rate_per_second)
+            .min(self.rate_per_second);
+        state.last_refilled_at = now;
+    }
+}
+
+// Named setters instead of positional arguments of `EmailClient::new`
+// Settings are only validated on `build`, so they can be set in any order
+#[derive(Default)]
+pub struct EmailClientBuilder {
+    host: String,
+    sender_email: String,
+    from_name: String,
+    reply_to: Option<String>,
+    credentials: Option<(Secret<String>, Secret<String>)>,
+    port: Option<u16>,
+    // TLS wrapper is used if not set
+    tls: Option<SmtpTls>,
+    request_timeout_millis: u64,
+}
+
+impl EmailClientBuilder {
+    pub fn host(mut self, host: impl Into<String>) -> Self {
+        self.host = host.into();
+        self
+    }
+
+    pub fn sender_email(mut self, sender_email: impl Into<String>) -> Self {
+        self.sender_email = sender_email.into();
+        self
+    }
+
+    pub fn from_name(mut self, from_name: impl Into<String>) -> Self {
+        self.from_name = from_name.into();
+        self
+    }
+
+    pub fn reply_to(mut self, reply_to: impl Into<String>) -> Self {
+        self.reply_to = Some(reply_to.into());
+        self
+    }
+
+    pub fn credentials(mut self, username: Secret<String>, password: Secret<String>) -> Self {
+        self.credentials = Some((username, password));
+        self
+    }
+
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = Some(port);
+        self
+    }
+
+    pub fn tls(mut self, tls: impl Into<SmtpTls>) -> Self {
+        self.tls = Some(tls.into());
+        self
+    }
+
+    pub fn request_timeout_millis(mut self, request_timeout_millis: u64) -> Self {
+        self.request_timeout_millis = request_timeout_millis;
+        self
+    }
+
+    pub fn build(self) -> Result<EmailClient, anyhow::Error> {
+        if self.host.trim().is_empty() {
+            anyhow::bail!("SMTP host must not be empty");
+        }
+        if self.request_timeout_millis == 0 {
+            anyhow::bail!("Email client request timeout must be greater than 0");
+        }
+        let sender_email = SubscriberEmail::parse(self.sender_email)
+            .map_err(|e| anyhow::anyhow!("Invalid sender email: {}", e))?;
+        let reply_to = match self.reply_to {
+            Some(reply_to) => Some(
+                SubscriberEmail::parse(reply_to)
+                    .map_err(|e| anyhow::anyhow!("Invalid reply-to email: {}", e))?,
+            ),
+            None => None,
+        };
+        let tls = self.tls.unwrap_or_else(|| SmtpTlsMode::Tls.into());
+        let (username, password) = match self.credentials {
+            Some((username, password)) => (Some(username), Some(password)),
+            None => (None, None),
+        };
+
+        let request_timeout = Duration::from_millis(self.request_timeout_millis);
+        let smtp_transport = build_smtp_transport(
+            &self.host,
+            username,
+            password,
+            self.port,
+            &tls,
+            request_timeout,
+        )?;
+
+        Ok(EmailClient {
+            smtp_transports: vec![smtp_transport],
+            request_timeout,
+            sender_email,
+            from_name: self.from_name,
+            reply_to,
+            envelope_from: None,
+            send_rate_limiter: None,
+            list_id: None,
+            subject_prefix: String::new(),
+            archive_email: None,
+            max_attachments_size_bytes: None,
+        })
+    }
 }
 
 impl EmailClient {
+    pub fn builder() -> EmailClientBuilder {
+        EmailClientBuilder::default()
+    }
+
+    // Kept for existing callers, prefer `EmailClient::builder`
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         host: String,
         sender_email: SubscriberEmail,
+        from_name: String,
+        reply_to: Option<SubscriberEmail>,
         username: Option<Secret<String>>,
         password: Option<Secret<String>>,
         port: Option<u16>,
-        require_tls: bool,
+        tls: SmtpTls,
         request_timeout_millis: u64,
     ) -> Result<Self, anyhow::Error> {
-        let mut smtp_transport = match require_tls {
-            true => AsyncSmtpTransport::<Tokio1Executor>::relay(&host)
-                .context("Failed to create smtp transport")?,
-            false => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&host),
-        };
-
+        let mut builder = Self::builder()
+            .host(host)
+            .sender_email(sender_email.into_inner())
+            .from_name(from_name)
+            .tls(tls)
+            .request_timeout_millis(request_timeout_millis);
+        if let Some(reply_to) = reply_to {
+            builder = builder.reply_to(reply_to.into_inner());
+        }
         if let (Some(username), Some(password)) = (username, password) {
-            let credentials = smtp::authentication::Credentials::new(
-                username.expose_secret().to_string(),
-                password.expose_secret().to_string(),
-            );
-            smtp_transport = smtp_transport.credentials(credentials);
+            builder = builder.credentials(username, password);
         }
-
         if let Some(port) = port {
-            smtp_transport = smtp_transport.port(port);
+            builder = builder.port(port);
         }
+        builder.build()
+    }
 
-        let smtp_transport = smtp_transport
-            .timeout(Some(Duration::from_millis(request_timeout_millis)))
-            .build();
+    // Failover provider is used when all previous providers fail with transient errors
+    pub fn add_failover_provider(
+        mut self,
+        host: String,
+        username: Option<Secret<String>>,
+        password: Option<Secret<String>>,
+        port: Option<u16>,
+        tls: SmtpTls,
+    ) -> Result<Self, anyhow::Error> {
+        let smtp_transport =
+            build_smtp_transport(&host, username, password, port, &tls, self.request_timeout)?;
+        self.smtp_transports.push(smtp_transport);
+        Ok(self)
+    }
 
-        Ok(Self {
-            smtp_transport,
-            sender_email,
-        })
+    pub fn set_envelope_from(mut self, envelope_from: SubscriberEmail) -> Self {
+        self.envelope_from = Some(envelope_from);
+        self
+    }
+
+    pub fn set_list_id(mut self, list_id: String) -> Self {
+        self.list_id = Some(list_id);
+        self
+    }
+
+    pub fn set_archive_email(mut self, archive_email: SubscriberEmail) -> Self {
+        self.archive_email = Some(archive_email);
+        self
+    }
+
+    pub fn archive_email(&self) -> Option<&SubscriberEmail> {
+        self.archive_email.as_ref()
+    }
+
+    pub fn set_subject_prefix(mut self, subject_prefix: String) -> Self {
+        self.subject_prefix = subject_prefix;
+        self
+    }
+
+    pub fn set_max_attachments_size_bytes(mut self, max_attachments_size_bytes: usize) -> Self {
+        self.max_attachments_size_bytes = Some(max_attachments_size_bytes);
+        self
+    }
+
+    pub fn set_send_rate_per_second(self, rate_per_second: u32) -> Self {
+        self.set_send_rate_limiter(Arc::new(SendRateLimiter::new(rate_per_second)))
+    }
+
+    // API server and workers each build their own client, sharing one limiter keeps their
+    // sends together under provider's rate instead of each of them being allowed the full rate
+    pub fn set_send_rate_limiter(mut self, send_rate_limiter: Arc<SendRateLimiter>) -> Self {
+        self.send_rate_limiter = Some(send_rate_limiter);
+        self
+    }
+
+    // Sending waits for a permit, callers that shouldn't wait can check it beforehand
+    pub fn is_send_rate_exceeded(&self) -> bool {
+        match &self.send_rate_limiter {
+            Some(limiter) => !limiter.has_available_permit(),
+            None => false,
+        }
+    }
+
+    async fn acquire_send_permit(&self) {
+        if let Some(limiter) = &self.send_rate_limiter {
+            while let Err(wait_time) = limiter.try_acquire() {
+                tokio::time::sleep(wait_time).await;
+            }
+        }
     }
 
     pub fn sender_email(&self) -> &str {
         self.sender_email.as_ref()
     }
 
+    // Transactional emails like confirmation emails are not part of mailing list
     pub async fn send_multipart_email(
         &self,
         recipient_email: &SubscriberEmail,
         subject: impl Into<String>,
-        text_content: impl Into<String>,
-        html_content: impl Into<String>,
+        text_content: Option<&str>,
+        html_content: Option<&str>,
     ) -> Result<smtp::response::Response, anyhow::Error> {
-        let message = Message::builder()
-            .from(
-                format!("{} <{}>", "Zero2Prod", self.sender_email.as_ref())
+        self.send_email(
+            recipient_email,
+            subject.into(),
+            text_content,
+            html_content,
+            None,
+            &[],
+        )
+        .await
+    }
+
+    // Attachments are sent alongside text and HTML content in a `mixed` multipart
+    pub async fn send_multipart_email_with_attachments(
+        &self,
+        recipient_email: &SubscriberEmail,
+        subject: impl Into<String>,
+        text_content: Option<&str>,
+        html_content: Option<&str>,
+        attachments: &[EmailAttachment],
+    ) -> Result<smtp::response::Response, anyhow::Error> {
+        self.send_email(
+            recipient_email,
+            subject.into(),
+            text_content,
+            html_content,
+            None,
+            attachments,
+        )
+        .await
+    }
+
+    // Newsletters carry `List-Id` header and subject prefix when configured
+    pub async fn send_newsletter_email(
+        &self,
+        recipient_email: &SubscriberEmail,
+        subject: &str,
+        text_content: Option<&str>,
+        html_content: Option<&str>,
+    ) -> Result<smtp::response::Response, anyhow::Error> {
+        self.send_email(
+            recipient_email,
+            format!("{}{}", self.subject_prefix, subject),
+            text_content,
+            html_content,
+            self.list_id.as_deref(),
+            &[],
+        )
+        .await
+    }
+
+    // Build a multipart alternative message when both text and HTML content are provided
+    // Otherwise build a singlepart message with the available content
+    // Content is wrapped in a multipart mixed message together with attachments when there are any
+    async fn send_email(
+        &self,
+        recipient_email: &SubscriberEmail,
+        subject: String,
+        text_content: Option<&str>,
+        html_content: Option<&str>,
+        list_id: Option<&str>,
+        attachments: &[EmailAttachment],
+    ) -> Result<smtp::response::Response, anyhow::Error> {
+        let text_content = text_content.filter(|content| !content.trim().is_empty());
+        let html_content = html_content.filter(|content| !content.trim().is_empty());
+
+        if let Some(max_attachments_size_bytes) = self.max_attachments_size_bytes {
+            let attachments_size: usize = attachments.iter().map(|a| a.content.len()).sum();
+            if attachments_size > max_attachments_size_bytes {
+                anyhow::bail!(
+                    "Attachments must not be larger than {} bytes in total, got {} bytes",
+                    max_attachments_size_bytes,
+                    attachments_size
+                );
+            }
+        }
+
+        let mut builder = Message::builder()
+            .from(message::Mailbox::new(
+                Some(self.from_name.clone()),
+                self.sender_email
+                    .as_ref()
                     .parse()
-                    .unwrap(),
-            )
+                    .context("Failed to parse sender email address")?,
+            ))
             .to(format!("<{}>", recipient_email.as_ref()).parse().unwrap())
-            .subject(subject)
-            .multipart(
-                message::MultiPart::alternative()
-                    .singlepart(
-                        message::SinglePart::builder()
-                            .header(message::header::ContentType::TEXT_PLAIN)
-                            .body(text_content.into()),
-                    )
-                    .singlepart(
-                        message::SinglePart::builder()
-                            .header(message::header::ContentType::TEXT_HTML)
-                            .body(html_content.into()),
-                    ),
+            .subject(subject);
+
+        if let Some(reply_to) = &self.reply_to {
+            builder = builder.reply_to(
+                reply_to
+                    .as_ref()
+                    .parse()
+                    .context("Failed to parse reply-to email address")?,
+            );
+        }
+
+        if let Some(list_id) = list_id {
+            builder = builder.header(ListId(list_id.to_string()));
+        }
+
+        // Envelope is derived from headers when it's not set explicitly
+        if let Some(envelope_from) = &self.envelope_from {
+            let envelope = Envelope::new(
+                Some(
+                    envelope_from
+                        .as_ref()
+                        .parse()
+                        .context("Failed to parse envelope sender email address")?,
+                ),
+                vec![recipient_email
+                    .as_ref()
+                    .parse()
+                    .context("Failed to parse recipient email address")?],
             )
-            .context("Failed to create email message")?;
+            .context("Failed to create email envelope")?;
+            builder = builder.envelope(envelope);
+        }
 
-        self.smtp_transport
-            .send(message)
-            .await
-            .context("Failed to send message to email service")
+        let text_part = text_content.map(|text_content| {
+            message::SinglePart::builder()
+                .header(message::header::ContentType::TEXT_PLAIN)
+                .body(text_content.to_string())
+        });
+        let html_part = html_content.map(|html_content| {
+            message::SinglePart::builder()
+                .header(message::header::ContentType::TEXT_HTML)
+                .body(html_content.to_string())
+        });
+
+        let message = if attachments.is_empty() {
+            match (text_part, html_part) {
+                (Some(text_part), Some(html_part)) => builder.multipart(
+                    message::MultiPart::alternative()
+                        .singlepart(text_part)
+                        .singlepart(html_part),
+                ),
+                (Some(part), None) | (None, Some(part)) => builder.singlepart(part),
+                (None, None) => anyhow::bail!("Email must contain text or HTML content"),
+            }
+        } else {
+            let mixed = match (text_part, html_part) {
+                (Some(text_part), Some(html_part)) => message::MultiPart::mixed().multipart(
+                    message::MultiPart::alternative()
+                        .singlepart(text_part)
+                        .singlepart(html_part),
+                ),
+                (Some(part), None) | (None, Some(part)) => {
+                    message::MultiPart::mixed().singlepart(part)
+                }
+                (None, None) => anyhow::bail!("Email must contain text or HTML content"),
+            };
+            let mixed = attachments.iter().try_fold(
+                mixed,
+                |mixed, attachment| -> Result<_, anyhow::Error> {
+                    let content_type =
+                        message::header::ContentType::parse(&attachment.content_type)
+                            .with_context(|| {
+                                format!(
+                                    "Invalid content type of attachment `{}`",
+                                    attachment.filename
+                                )
+                            })?;
+                    Ok(mixed.singlepart(
+                        message::Attachment::new(attachment.filename.clone())
+                            .body(attachment.content.clone(), content_type),
+                    ))
+                },
+            )?;
+            builder.multipart(mixed)
+        }
+        .context("Failed to create email message")?;
+
+        // Every kind of email counts towards sending rate, failing over doesn't take another permit
+        self.acquire_send_permit().await;
+        let mut last_error = None;
+        for (provider_index, smtp_transport) in self.smtp_transports.iter().enumerate() {
+            match smtp_transport.send(message.clone()).await {
+                Ok(response) => return Ok(response),
+                // Other providers would reject the same message, so don't fail over
+                Err(e) if e.is_permanent() => {
+                    return Err(e).context("Email service rejected message permanently")
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        error.cause_chain = ?e,
+                        error.message = %e,
+                        provider_index,
+                        "Failed to send message to email service, fail over to next one"
+                    );
+                    last_error = Some(e);
+                }
+            }
+        }
+        match last_error {
+            Some(e) => Err(e).context("Failed to send message to email service"),
+            None => anyhow::bail!("No email service provider is configured"),
+        }
+    }
+}
+
+// Longest retry delay honored from provider, so a bogus hint can't stall delivery for long
+const MAX_SUGGESTED_RETRY_DELAY: Duration = Duration::from_secs(600);
+
+// Providers throttle sending with transient (4xx) replies
+// Some of them tell when to retry in reply text, e.g. "421 4.7.0 Try again in 60 seconds"
+pub fn suggested_retry_delay(error: &anyhow::Error) -> Option<Duration> {
+    let smtp_error = error.downcast_ref::<smtp::Error>()?;
+    if !smtp_error.is_transient() {
+        return None;
     }
+    parse_retry_delay(&smtp_error.to_string())
+}
+
+fn parse_retry_delay(message: &str) -> Option<Duration> {
+    let message = message.to_lowercase();
+    if !message.contains("try again") && !message.contains("retry") {
+        return None;
+    }
+    let words: Vec<&str> = message
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect();
+    words.iter().enumerate().find_map(|(index, word)| {
+        // Unit is either glued to number, e.g. "30s", or the next word
+        let digits_end = word
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(word.len());
+        let n: u64 = word[..digits_end].parse().ok()?;
+        let unit = match &word[digits_end..] {
+            "" => *words.get(index + 1)?,
+            unit => unit,
+        };
+        let secs = match unit {
+            "s" | "sec" | "secs" | "second" | "seconds" => n,
+            "m" | "min" | "mins" | "minute" | "minutes" => n.saturating_mul(60),
+            _ => return None,
+        };
+        Some(Duration::from_secs(secs).min(MAX_SUGGESTED_RETRY_DELAY))
+    })
+}
+
+fn build_smtp_transport(
+    host: &str,
+    username: Option<Secret<String>>,
+    password: Option<Secret<String>>,
+    port: Option<u16>,
+    tls: &SmtpTls,
+    request_timeout: Duration,
+) -> Result<AsyncSmtpTransport<Tokio1Executor>, anyhow::Error> {
+    // Same as `relay` and `starttls_relay` of lettre, plus client certificate when provided
+    let mut smtp_transport = match tls.mode {
+        SmtpTlsMode::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+        SmtpTlsMode::Starttls => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host)
+            .port(smtp::SUBMISSION_PORT)
+            .tls(Tls::Required(build_tls_parameters(host, tls)?)),
+        SmtpTlsMode::Tls => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host)
+            .port(smtp::SUBMISSIONS_PORT)
+            .tls(Tls::Wrapper(build_tls_parameters(host, tls)?)),
+    };
+
+    if let (Some(username), Some(password)) = (username, password) {
+        let credentials = smtp::authentication::Credentials::new(
+            username.expose_secret().to_string(),
+            password.expose_secret().to_string(),
+        );
+        smtp_transport = smtp_transport.credentials(credentials);
+    }
+
+    if let Some(port) = port {
+        smtp_transport = smtp_transport.port(port);
+    }
+
+    Ok(smtp_transport.timeout(Some(request_timeout)).build())
+}
+
+fn build_tls_parameters(host: &str, tls: &SmtpTls) -> Result<TlsParameters, anyhow::Error> {
+    let mut tls_parameters = TlsParameters::builder(host.to_string());
+    if let Some(client_certificate) = &tls.client_certificate {
+        let identity = Identity::from_pem(
+            &client_certificate.certificate_pem,
+            client_certificate.key_pem.expose_secret(),
+        )
+        .context("Invalid SMTP client certificate or key")?;
+        tls_parameters = tls_parameters.identify_with(identity);
+    }
+    tls_parameters
+        .build()
+        .context("Failed to build TLS parameters")
 }
 
 #[derive(serde::Serialize)]
@@ -103,11 +658,19 @@ struct SendEmailRequest<'a> {
 
 #[cfg(test)]
 mod tests {
-    use crate::email_client::EmailClient;
+    use crate::configuration::SmtpTlsMode;
+    use crate::email_client::{
+        parse_retry_delay, suggested_retry_delay, EmailAttachment, EmailClient, SendRateLimiter,
+        SmtpClientCertificate, SmtpTls,
+    };
     use crate::routes::SubscriberEmail;
     use fake::faker::internet::en::SafeEmail;
     use fake::faker::lorem::en::{Paragraph, Sentence};
     use fake::Fake;
+    use secrecy::Secret;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 
     fn subject() -> String {
         Sentence(1..2).fake()
@@ -129,10 +692,60 @@ mod tests {
         SubscriberEmail::parse(SafeEmail().fake()).unwrap()
     }
 
+    fn from_name() -> String {
+        "Zero2Prod".to_string()
+    }
+
     fn timeout_millis() -> u64 {
         100
     }
 
+    #[tokio::test]
+    async fn builder_builds_email_client_with_named_settings() {
+        let sender_email = sender_email();
+
+        let email_client = EmailClient::builder()
+            .host("localhost")
+            .sender_email(sender_email.as_ref())
+            .from_name(from_name())
+            .reply_to(SafeEmail().fake::<String>())
+            .port(1025)
+            .tls(SmtpTlsMode::None)
+            .request_timeout_millis(timeout_millis())
+            .build()
+            .expect("Failed to build email client");
+
+        assert_eq!(email_client.sender_email(), sender_email.as_ref());
+    }
+
+    #[tokio::test]
+    async fn builder_rejects_invalid_settings() {
+        let builder = || {
+            EmailClient::builder()
+                .host("localhost")
+                .sender_email(sender_email().as_ref())
+                .request_timeout_millis(timeout_millis())
+        };
+
+        for (builder, expected_error) in [
+            (builder().host(" "), "SMTP host must not be empty"),
+            (
+                builder().sender_email("not-an-email"),
+                "Invalid sender email",
+            ),
+            (
+                builder().request_timeout_millis(0),
+                "Email client request timeout must be greater than 0",
+            ),
+        ] {
+            let error = match builder.build() {
+                Ok(_) => panic!("Expected `{}`", expected_error),
+                Err(e) => e.to_string(),
+            };
+            assert!(error.starts_with(expected_error), "{}", error);
+        }
+    }
+
     // TODO: this test depends on external dependencies (mailcrab), so it's satisfying a unit test -> need to refactor
     // NOTE: these tests depending on mailcrab to host mock smtp server
     // make sure to launch mailcrab on local machine or docker before running the tests
@@ -142,10 +755,12 @@ mod tests {
         let email_client = EmailClient::new(
             "localhost".to_string(),
             sender_email(),
+            from_name(),
+            None,
             None,
             None,
             Some(1025),
-            false,
+            SmtpTlsMode::None.into(),
             timeout_millis(),
         )
         .expect("Failed to create email client");
@@ -156,7 +771,12 @@ mod tests {
         let recipient_email = subscriber_email();
 
         let response = email_client
-            .send_multipart_email(&recipient_email, &subject, &plain_text, &html_text)
+            .send_multipart_email(
+                &recipient_email,
+                &subject,
+                Some(&plain_text),
+                Some(&html_text),
+            )
             .await
             .expect(
                 "Failed to send email to smtp server \
@@ -185,4 +805,502 @@ mod tests {
         assert_eq!(body["subject"], subject);
         assert_eq!(body["to"][0]["email"], recipient_email.as_ref());
     }
+
+    #[tokio::test]
+    async fn send_email_with_attachment() {
+        let email_client = EmailClient::new(
+            "localhost".to_string(),
+            sender_email(),
+            from_name(),
+            None,
+            None,
+            None,
+            Some(1025),
+            SmtpTlsMode::None.into(),
+            timeout_millis(),
+        )
+        .expect("Failed to create email client");
+        let attachment = EmailAttachment {
+            filename: "year-in-review.pdf".to_string(),
+            content_type: "application/pdf".to_string(),
+            content: b"%PDF-1.4 year in review".to_vec(),
+        };
+
+        let response = email_client
+            .send_multipart_email_with_attachments(
+                &subscriber_email(),
+                &subject(),
+                Some(&plain_text()),
+                Some(&html_text()),
+                &[attachment],
+            )
+            .await
+            .expect("Failed to send email to smtp server");
+
+        let message = response.message().next().unwrap();
+        let message_id = message.strip_prefix("2.0.0 Ok: queued as ").unwrap();
+        let body: serde_json::Value = reqwest::Client::new()
+            .get(format!("http://localhost:1080/api/message/{}", message_id))
+            .send()
+            .await
+            .expect("Failed to get messages from mailcrab")
+            .json()
+            .await
+            .expect("Failed to get messages from mailcrab");
+        let raw = body["raw"].as_str().unwrap();
+        assert!(raw.contains("multipart/mixed"));
+        assert!(raw.contains("multipart/alternative"));
+        assert!(raw.contains("Content-Type: application/pdf"));
+        assert!(raw.contains(r#"filename="year-in-review.pdf""#));
+    }
+
+    #[tokio::test]
+    async fn attachments_larger_than_max_size_are_rejected() {
+        let email_client = EmailClient::new(
+            "localhost".to_string(),
+            sender_email(),
+            from_name(),
+            None,
+            None,
+            None,
+            Some(1025),
+            SmtpTlsMode::None.into(),
+            timeout_millis(),
+        )
+        .expect("Failed to create email client")
+        .set_max_attachments_size_bytes(8);
+        let attachments: Vec<_> = ["a.txt", "b.txt"]
+            .into_iter()
+            .map(|filename| EmailAttachment {
+                filename: filename.to_string(),
+                content_type: "text/plain".to_string(),
+                content: vec![b'a'; 5],
+            })
+            .collect();
+
+        let result = email_client
+            .send_multipart_email_with_attachments(
+                &subscriber_email(),
+                &subject(),
+                Some(&plain_text()),
+                None,
+                &attachments,
+            )
+            .await;
+
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn send_email_without_content_is_rejected() {
+        let email_client = EmailClient::new(
+            "localhost".to_string(),
+            sender_email(),
+            from_name(),
+            None,
+            None,
+            None,
+            Some(1025),
+            SmtpTlsMode::None.into(),
+            timeout_millis(),
+        )
+        .expect("Failed to create email client");
+
+        let result = email_client
+            .send_multipart_email(&subscriber_email(), &subject(), None, Some("  "))
+            .await;
+
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn send_email_with_configured_from_name_and_reply_to() {
+        let from_name = "Zero2Prod Newsletter".to_string();
+        let reply_to = subscriber_email();
+        let email_client = EmailClient::new(
+            "localhost".to_string(),
+            sender_email(),
+            from_name.clone(),
+            Some(SubscriberEmail::parse(reply_to.as_ref().to_string()).unwrap()),
+            None,
+            None,
+            Some(1025),
+            SmtpTlsMode::None.into(),
+            timeout_millis(),
+        )
+        .expect("Failed to create email client");
+
+        let subject = subject();
+        let response = email_client
+            .send_multipart_email(&subscriber_email(), &subject, Some(&plain_text()), None)
+            .await
+            .expect("Failed to send email to smtp server");
+
+        let message = response.message().next().unwrap();
+        let message_id = message.strip_prefix("2.0.0 Ok: queued as ").unwrap();
+
+        let body: serde_json::Value = reqwest::Client::new()
+            .get(format!("http://localhost:1080/api/message/{}", message_id))
+            .send()
+            .await
+            .expect("Failed to get messages from mailcrab")
+            .json()
+            .await
+            .expect("Failed to get messages from mailcrab");
+
+        assert_eq!(body["subject"], subject);
+        assert_eq!(body["from"]["name"], from_name);
+        assert!(body["raw"]
+            .as_str()
+            .unwrap()
+            .contains(&format!("Reply-To: {}", reply_to.as_ref())));
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_over_to_next_provider_when_first_one_is_unreachable() {
+        // Nothing listens on port 1, so first provider fails to connect
+        let email_client = EmailClient::new(
+            "localhost".to_string(),
+            sender_email(),
+            from_name(),
+            None,
+            None,
+            None,
+            Some(1),
+            SmtpTlsMode::None.into(),
+            timeout_millis(),
+        )
+        .expect("Failed to create email client")
+        .add_failover_provider(
+            "localhost".to_string(),
+            None,
+            None,
+            Some(1025),
+            SmtpTlsMode::None.into(),
+        )
+        .expect("Failed to add failover provider");
+
+        let subject = subject();
+        let recipient_email = subscriber_email();
+        let response = email_client
+            .send_multipart_email(&recipient_email, &subject, Some(&plain_text()), None)
+            .await
+            .expect("Failed to fail over to mailcrab");
+
+        let message = response.message().next().unwrap();
+        let message_id = message.strip_prefix("2.0.0 Ok: queued as ").unwrap();
+        let body: serde_json::Value = reqwest::Client::new()
+            .get(format!("http://localhost:1080/api/message/{}", message_id))
+            .send()
+            .await
+            .expect("Failed to get messages from mailcrab")
+            .json()
+            .await
+            .expect("Failed to get messages from mailcrab");
+
+        assert_eq!(body["subject"], subject);
+        assert_eq!(body["to"][0]["email"], recipient_email.as_ref());
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_when_all_providers_are_unreachable() {
+        let email_client = EmailClient::new(
+            "localhost".to_string(),
+            sender_email(),
+            from_name(),
+            None,
+            None,
+            None,
+            Some(1),
+            SmtpTlsMode::None.into(),
+            timeout_millis(),
+        )
+        .expect("Failed to create email client")
+        .add_failover_provider(
+            "localhost".to_string(),
+            None,
+            None,
+            Some(2),
+            SmtpTlsMode::None.into(),
+        )
+        .expect("Failed to add failover provider");
+
+        let result = email_client
+            .send_multipart_email(&subscriber_email(), &subject(), Some(&plain_text()), None)
+            .await;
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn send_rate_limiter_rejects_sending_over_rate() {
+        let limiter = SendRateLimiter::new(2);
+
+        assert!(limiter.try_acquire().is_ok());
+        assert!(limiter.try_acquire().is_ok());
+        let wait_time = limiter.try_acquire().unwrap_err();
+        assert!(wait_time <= std::time::Duration::from_millis(500));
+    }
+
+    #[test]
+    fn email_client_without_send_rate_always_acquires_permit() {
+        let email_client = EmailClient::new(
+            "localhost".to_string(),
+            sender_email(),
+            from_name(),
+            None,
+            None,
+            None,
+            Some(1025),
+            SmtpTlsMode::None.into(),
+            timeout_millis(),
+        )
+        .expect("Failed to create email client");
+
+        for _ in 0..100 {
+            assert!(!email_client.is_send_rate_exceeded());
+        }
+    }
+
+    #[tokio::test]
+    async fn email_clients_sharing_send_rate_limiter_share_its_permits() {
+        let send_rate_limiter = Arc::new(SendRateLimiter::new(1));
+        let build_email_client = || {
+            EmailClient::new(
+                "localhost".to_string(),
+                sender_email(),
+                from_name(),
+                None,
+                None,
+                None,
+                Some(1025),
+                SmtpTlsMode::None.into(),
+                timeout_millis(),
+            )
+            .expect("Failed to create email client")
+            .set_send_rate_limiter(send_rate_limiter.clone())
+        };
+        let api_email_client = build_email_client();
+        let worker_email_client = build_email_client();
+
+        api_email_client.acquire_send_permit().await;
+
+        assert!(worker_email_client.is_send_rate_exceeded());
+    }
+
+    const MAIL_FROM_ACCEPTED: &str = "250 Ok";
+
+    // Mock SMTP server that records addresses of `MAIL FROM` commands and replies `mail_from_reply` to them
+    // Messages are accepted when `mail_from_reply` accepts the sender
+    async fn spawn_mock_smtp_server(
+        mail_from_reply: &'static str,
+    ) -> (u16, Arc<Mutex<Vec<String>>>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let mail_from = Arc::new(Mutex::new(vec![]));
+        let recorded_mail_from = mail_from.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let (reader, mut writer) = stream.into_split();
+                let mut lines = BufReader::new(reader).lines();
+                if writer.write_all(b"220 localhost ESMTP\r\n").await.is_err() {
+                    continue;
+                }
+                let mut is_reading_data = false;
+                while let Ok(Some(line)) = lines.next_line().await {
+                    let command = line.to_uppercase();
+                    let response = if is_reading_data {
+                        if line != "." {
+                            continue;
+                        }
+                        is_reading_data = false;
+                        "250 Ok\r\n".to_string()
+                    } else if command.starts_with("EHLO") || command.starts_with("HELO") {
+                        "250 localhost\r\n".to_string()
+                    } else if command.starts_with("MAIL FROM:") {
+                        recorded_mail_from
+                            .lock()
+                            .unwrap()
+                            .push(line["MAIL FROM:".len()..].to_string());
+                        format!("{}\r\n", mail_from_reply)
+                    } else if command.starts_with("DATA") {
+                        is_reading_data = true;
+                        "354 End data with <CR><LF>.<CR><LF>\r\n".to_string()
+                    } else if command.starts_with("QUIT") {
+                        "221 Bye\r\n".to_string()
+                    } else {
+                        "250 Ok\r\n".to_string()
+                    };
+                    if writer.write_all(response.as_bytes()).await.is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+        (port, mail_from)
+    }
+
+    #[tokio::test]
+    async fn envelope_sender_defaults_to_sender_email() {
+        let (port, mail_from) = spawn_mock_smtp_server(MAIL_FROM_ACCEPTED).await;
+        let email_client = email_client_with_port(port);
+
+        email_client
+            .send_multipart_email(&subscriber_email(), &subject(), Some(&plain_text()), None)
+            .await
+            .expect("Failed to send email");
+
+        let mail_from = mail_from.lock().unwrap();
+        assert_eq!(mail_from.len(), 1);
+        assert!(mail_from[0].contains(email_client.sender_email()));
+    }
+
+    #[tokio::test]
+    async fn configured_envelope_sender_is_used_in_mail_from() {
+        let (port, mail_from) = spawn_mock_smtp_server(MAIL_FROM_ACCEPTED).await;
+        let envelope_from = SubscriberEmail::parse("bounces@example.com".to_string()).unwrap();
+        let email_client = email_client_with_port(port).set_envelope_from(envelope_from);
+
+        email_client
+            .send_multipart_email(&subscriber_email(), &subject(), Some(&plain_text()), None)
+            .await
+            .expect("Failed to send email");
+
+        let mail_from = mail_from.lock().unwrap();
+        assert_eq!(mail_from.len(), 1);
+        assert!(mail_from[0].starts_with("<bounces@example.com>"));
+    }
+
+    fn email_client_with_port(port: u16) -> EmailClient {
+        EmailClient::new(
+            "127.0.0.1".to_string(),
+            sender_email(),
+            from_name(),
+            None,
+            None,
+            None,
+            Some(port),
+            SmtpTlsMode::None.into(),
+            1000,
+        )
+        .expect("Failed to create email client")
+    }
+
+    #[tokio::test]
+    async fn throttled_send_suggests_retry_delay_from_provider_reply() {
+        let (port, _) =
+            spawn_mock_smtp_server("451 4.7.1 Rate limited, try again in 7 seconds").await;
+        let email_client = email_client_with_port(port);
+
+        let error = email_client
+            .send_multipart_email(&subscriber_email(), &subject(), Some(&plain_text()), None)
+            .await
+            .expect_err("Throttled send must fail");
+
+        assert_eq!(suggested_retry_delay(&error), Some(Duration::from_secs(7)));
+    }
+
+    #[tokio::test]
+    async fn permanent_failure_suggests_no_retry_delay() {
+        let (port, _) = spawn_mock_smtp_server("550 5.7.1 Rejected, try again in 7 seconds").await;
+        let email_client = email_client_with_port(port);
+
+        let error = email_client
+            .send_multipart_email(&subscriber_email(), &subject(), Some(&plain_text()), None)
+            .await
+            .expect_err("Rejected send must fail");
+
+        assert_eq!(suggested_retry_delay(&error), None);
+    }
+
+    #[tokio::test]
+    async fn permanent_rejection_does_not_fail_over_to_next_provider() {
+        let (rejecting_port, _) = spawn_mock_smtp_server("550 5.7.1 Rejected").await;
+        let (failover_port, failover_mail_from) = spawn_mock_smtp_server(MAIL_FROM_ACCEPTED).await;
+        let email_client = email_client_with_port(rejecting_port)
+            .add_failover_provider(
+                "127.0.0.1".to_string(),
+                None,
+                None,
+                Some(failover_port),
+                SmtpTlsMode::None.into(),
+            )
+            .expect("Failed to add failover provider");
+
+        let result = email_client
+            .send_multipart_email(&subscriber_email(), &subject(), Some(&plain_text()), None)
+            .await;
+
+        assert!(result.is_err());
+        assert!(failover_mail_from.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn retry_delay_is_parsed_from_reply_text() {
+        assert_eq!(
+            parse_retry_delay("421 4.7.0 Too many messages, try again in 2 minutes"),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            parse_retry_delay("450 Please retry after 30s"),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(parse_retry_delay("451 4.3.0 Temporary failure"), None);
+    }
+
+    #[test]
+    fn retry_delay_is_capped() {
+        assert_eq!(
+            parse_retry_delay("421 try again in 100000 minutes"),
+            Some(Duration::from_secs(600))
+        );
+    }
+
+    #[tokio::test]
+    async fn email_client_is_built_in_every_tls_mode() {
+        for tls_mode in [SmtpTlsMode::None, SmtpTlsMode::Starttls, SmtpTlsMode::Tls] {
+            let email_client = EmailClient::new(
+                "localhost".to_string(),
+                sender_email(),
+                from_name(),
+                None,
+                None,
+                None,
+                Some(1025),
+                tls_mode.into(),
+                timeout_millis(),
+            );
+
+            assert!(
+                email_client.is_ok(),
+                "Failed to build {:?} client",
+                tls_mode
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn invalid_client_certificate_is_rejected() {
+        let tls = SmtpTls {
+            mode: SmtpTlsMode::Starttls,
+            client_certificate: Some(SmtpClientCertificate {
+                certificate_pem: b"not a certificate".to_vec(),
+                key_pem: Secret::new(b"not a key".to_vec()),
+            }),
+        };
+
+        let email_client = EmailClient::new(
+            "localhost".to_string(),
+            sender_email(),
+            from_name(),
+            None,
+            None,
+            None,
+            Some(1025),
+            tls,
+            timeout_millis(),
+        );
+
+        assert!(email_client.is_err());
+    }
 }
@@ -0,0 +1,83 @@
+use crate::configuration::HtmlSanitizerSettings;
+use std::collections::HashSet;
+
+// Strip scripts, event handlers and tags out of allowlist from user supplied HTML
+// So newsletters can't carry XSS or phishing payloads to subscribers
+pub struct HtmlSanitizer {
+    allowed_tags: Option<HashSet<String>>,
+    allowed_url_schemes: Option<HashSet<String>>,
+}
+
+impl HtmlSanitizer {
+    pub fn from_settings(settings: &HtmlSanitizerSettings) -> Self {
+        // Parsed HTML tags are always lowercase
+        let to_lowercase_set =
+            |values: &Vec<String>| values.iter().map(|value| value.to_lowercase()).collect();
+        Self {
+            allowed_tags: settings.allowed_tags.as_ref().map(to_lowercase_set),
+            allowed_url_schemes: settings.allowed_url_schemes.as_ref().map(to_lowercase_set),
+        }
+    }
+
+    pub fn clean(&self, html: &str) -> String {
+        let mut builder = ammonia::Builder::default();
+        if let Some(tags) = &self.allowed_tags {
+            builder.tags(tags.iter().map(String::as_str).collect());
+        }
+        if let Some(url_schemes) = &self.allowed_url_schemes {
+            builder.url_schemes(url_schemes.iter().map(String::as_str).collect());
+        }
+        builder.clean(html).to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::configuration::HtmlSanitizerSettings;
+    use crate::html_sanitizer::HtmlSanitizer;
+
+    #[test]
+    fn scripts_and_event_handlers_are_removed() {
+        let sanitizer = HtmlSanitizer::from_settings(&HtmlSanitizerSettings::default());
+
+        let html = sanitizer.clean(
+            r#"<p onclick="steal()">Hello</p><script>alert("xss")</script><img src=x onerror=alert(1)>"#,
+        );
+
+        assert!(!html.contains("script"));
+        assert!(!html.contains("onclick"));
+        assert!(!html.contains("onerror"));
+        assert!(html.contains("<p>Hello</p>"));
+    }
+
+    #[test]
+    fn basic_formatting_and_links_are_kept() {
+        let sanitizer = HtmlSanitizer::from_settings(&HtmlSanitizerSettings::default());
+
+        let html = sanitizer.clean(r#"<p><b>Bold</b> <a href="https://example.com">link</a></p>"#);
+
+        assert!(html.contains("<b>Bold</b>"));
+        assert!(html.contains(r#"href="https://example.com""#));
+    }
+
+    #[test]
+    fn javascript_links_are_removed() {
+        let sanitizer = HtmlSanitizer::from_settings(&HtmlSanitizerSettings::default());
+
+        let html = sanitizer.clean(r#"<a href="javascript:alert(1)">link</a>"#);
+
+        assert!(!html.contains("javascript"));
+    }
+
+    #[test]
+    fn tags_out_of_configured_allowlist_are_stripped() {
+        let sanitizer = HtmlSanitizer::from_settings(&HtmlSanitizerSettings {
+            allowed_tags: Some(vec!["P".to_string()]),
+            allowed_url_schemes: None,
+        });
+
+        let html = sanitizer.clean("<p><b>Bold</b></p>");
+
+        assert_eq!(html, "<p>Bold</p>");
+    }
+}
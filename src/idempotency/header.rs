@@ -0,0 +1,84 @@
+use crate::idempotency::IdempotencyKey;
+use actix_web::http::header::HeaderMap;
+use anyhow::Context;
+use sha2::{Digest, Sha256};
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+// API clients conventionally send idempotency key in `Idempotency-Key` header
+// While HTML form sends it as a form field
+// Prefer header when present, fall back to form field
+pub fn get_idempotency_key(
+    headers: &HeaderMap,
+    form_value: Option<String>,
+) -> Result<IdempotencyKey, anyhow::Error> {
+    let header_value = headers
+        .get(IDEMPOTENCY_KEY_HEADER)
+        .map(|value| {
+            value
+                .to_str()
+                .context("`Idempotency-Key` header's value is not valid UTF8")
+                .map(|value| value.to_string())
+        })
+        .transpose()?;
+
+    let idempotency_key = match (header_value, form_value) {
+        (Some(header_value), Some(form_value)) if header_value != form_value => {
+            anyhow::bail!("Idempotency key in header and form are different")
+        }
+        (Some(header_value), _) => header_value,
+        (None, Some(form_value)) => form_value,
+        (None, None) => anyhow::bail!("Idempotency key is missing"),
+    };
+
+    idempotency_key.try_into()
+}
+
+// Browser forms submitted without idempotency key get a key derived from their fields
+// Newsletters form carries a random nonce per render, so double-submits of it get the same key
+// And are deduped as long as idempotency record lives
+pub fn derive_form_idempotency_key(fields: &[Option<&str>]) -> IdempotencyKey {
+    let mut hasher = Sha256::new();
+    for field in fields {
+        // Length prefix keeps ("ab", "c") and ("a", "bc") apart, missing field differs from empty one
+        match field {
+            Some(field) => {
+                hasher.update((field.len() as u64 + 1).to_be_bytes());
+                hasher.update(field.as_bytes());
+            }
+            None => hasher.update(0u64.to_be_bytes()),
+        }
+    }
+    let digest = hasher.finalize();
+    format!("form-{}", hex::encode(&digest[..16]))
+        .try_into()
+        .expect("Derived idempotency key is always valid")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::idempotency::derive_form_idempotency_key;
+
+    #[test]
+    fn same_form_fields_derive_same_key() {
+        let fields = [Some("nonce"), Some("Title"), None];
+
+        assert_eq!(
+            derive_form_idempotency_key(&fields).as_ref(),
+            derive_form_idempotency_key(&fields).as_ref()
+        );
+    }
+
+    #[test]
+    fn different_form_fields_derive_different_keys() {
+        let key = derive_form_idempotency_key(&[Some("ab"), Some("c")]);
+
+        for fields in [
+            [Some("a"), Some("bc")],
+            [Some("ab"), Some("")],
+            [Some("ab"), None],
+        ] {
+            assert_ne!(derive_form_idempotency_key(&fields).as_ref(), key.as_ref());
+        }
+    }
+}
@@ -1,5 +1,7 @@
+mod header;
 mod key;
 mod persistence;
 
+pub use header::*;
 pub use key::IdempotencyKey;
 pub use persistence::*;
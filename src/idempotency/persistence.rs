@@ -1,9 +1,21 @@
 use crate::idempotency::IdempotencyKey;
+use crate::utils::error_chain_fmt;
 use actix_web::body::to_bytes;
 use actix_web::http::StatusCode;
-use actix_web::HttpResponse;
+use actix_web::{HttpResponse, ResponseError};
+use sqlx::postgres::types::PgInterval;
 use sqlx::postgres::{PgHasArrayType, PgTypeInfo};
-use sqlx::{Postgres, Transaction};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::fmt::Debug;
+use std::time::Duration;
+
+// Replayed response carries it when its body was too large to be stored
+// Status and headers are still replayed, so retries don't run the request again
+pub const IDEMPOTENCY_BODY_OMITTED_HEADER: &str = "Idempotency-Body-Omitted";
+
+// Record without response belongs to a request in progress
+// It is only taken over when its request died without completing for longer than this lease
+const IN_PROGRESS_RECORD_LEASE: Duration = Duration::from_secs(5 * 60);
 
 #[derive(Debug, sqlx::Type)]
 #[sqlx(type_name = "header_value")]
@@ -18,6 +30,46 @@ impl PgHasArrayType for ResponseHeaderRecord {
     }
 }
 
+#[derive(thiserror::Error)]
+pub enum IdempotencyError {
+    #[error("Idempotency key is already used for another request: {0}")]
+    RequestMismatch(String),
+    // Another request with the same key is being processed and hasn't stored its response yet
+    #[error("Request with the same idempotency key is still in progress, retry later")]
+    RequestInProgress,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl Debug for IdempotencyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for IdempotencyError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            IdempotencyError::RequestMismatch(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            IdempotencyError::RequestInProgress => StatusCode::CONFLICT,
+            IdempotencyError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+// Stored responses older than this are reprocessed instead of replayed, even records are kept longer
+// `None` replays records until they're deleted
+pub struct IdempotencyReplayMaxAge(pub Option<Duration>);
+
+// Responses are replayed from database, so large bodies must not be stored on every request
+pub struct IdempotencyMaxStoredBodySize(pub usize);
+
+// Method and path of request that an idempotency key is used for
+pub struct IdempotentRequest<'a> {
+    pub method: &'a str,
+    pub path: &'a str,
+}
+
 pub enum ProcessState {
     StartProcessing(Transaction<'static, Postgres>),
     Completed(HttpResponse),
@@ -26,36 +78,68 @@ pub enum ProcessState {
 async fn get_idempotency_response_record_from_database(
     transaction: &mut Transaction<'_, Postgres>,
     idempotency_key: &IdempotencyKey,
-    user_id: &uuid::Uuid,
-) -> Result<Option<HttpResponse>, anyhow::Error> {
+    user_id: Option<&uuid::Uuid>,
+    request: &IdempotentRequest<'_>,
+) -> Result<Option<HttpResponse>, IdempotencyError> {
     struct Row {
-        response_status_code: i16,
-        response_headers: Vec<ResponseHeaderRecord>,
-        response_body: Vec<u8>,
+        request_method: Option<String>,
+        request_path: Option<String>,
+        // Response columns are NULL until the request holding the key stores its response
+        response_status_code: Option<i16>,
+        response_headers: Option<Vec<ResponseHeaderRecord>>,
+        response_body: Option<Vec<u8>>,
     }
     let record = sqlx::query_as!(
         Row,
         r#"
         SELECT 
-            response_status_code as "response_status_code!",
-            response_headers as "response_headers!: Vec<ResponseHeaderRecord>",
-            response_body as "response_body!"
+            request_method,
+            request_path,
+            response_status_code,
+            response_headers as "response_headers: Vec<ResponseHeaderRecord>",
+            response_body
         FROM idempotency
-        WHERE user_id = $1 AND idempotency_key = $2
+        WHERE
+            COALESCE(user_id, '00000000-0000-0000-0000-000000000000') =
+                COALESCE($1::uuid, '00000000-0000-0000-0000-000000000000') AND
+            idempotency_key = $2
         "#,
         user_id,
         idempotency_key.as_ref()
     )
     .fetch_optional(transaction)
-    .await?;
+    .await
+    .map_err(|e| IdempotencyError::UnexpectedError(e.into()))?;
 
     match record {
         Some(Row {
+            request_method,
+            request_path,
             response_status_code,
             response_headers,
             response_body,
         }) => {
-            let status_code = StatusCode::from_u16(response_status_code.try_into()?)?;
+            // Records created before request is recorded are considered matched
+            let is_method_matched = request_method.map_or(true, |m| m == request.method);
+            let is_path_matched = request_path.map_or(true, |p| p == request.path);
+            if !is_method_matched || !is_path_matched {
+                return Err(IdempotencyError::RequestMismatch(format!(
+                    "{} {}",
+                    request.method, request.path
+                )));
+            }
+
+            let (response_status_code, response_headers, response_body) =
+                match (response_status_code, response_headers, response_body) {
+                    (Some(status_code), Some(headers), Some(body)) => (status_code, headers, body),
+                    _ => return Err(IdempotencyError::RequestInProgress),
+                };
+            let status_code = StatusCode::from_u16(
+                response_status_code
+                    .try_into()
+                    .map_err(|e| IdempotencyError::UnexpectedError(anyhow::anyhow!("{}", e)))?,
+            )
+            .map_err(|e| IdempotencyError::UnexpectedError(e.into()))?;
             let mut response = HttpResponse::build(status_code);
             for ResponseHeaderRecord { key, value } in response_headers {
                 response.append_header((key, value));
@@ -66,32 +150,58 @@ async fn get_idempotency_response_record_from_database(
     }
 }
 
+// Records of anonymous requests, e.g. subscribe, have no user
+// Their keys must be scoped by something else, e.g. subscriber email, to not collide between clients
 pub async fn try_insert_idempotency_response_record_into_database(
     mut transaction: Transaction<'static, Postgres>,
     idempotency_key: &IdempotencyKey,
-    user_id: &uuid::Uuid,
-) -> Result<ProcessState, anyhow::Error> {
+    user_id: Option<&uuid::Uuid>,
+    request: &IdempotentRequest<'_>,
+    replay_max_age: Option<Duration>,
+) -> Result<ProcessState, IdempotencyError> {
     let n_row_affected = sqlx::query!(
         r#"
         INSERT INTO idempotency (
             user_id,
             idempotency_key,
+            request_method,
+            request_path,
             created_at
         )
         VALUES (
             $1,
             $2,
+            $3,
+            $4,
             now()
         )
         ON CONFLICT DO NOTHING
         "#,
         user_id,
-        idempotency_key.as_ref()
+        idempotency_key.as_ref(),
+        request.method,
+        request.path
     )
     .execute(&mut transaction)
-    .await?
+    .await
+    .map_err(|e| IdempotencyError::UnexpectedError(e.into()))?
     .rows_affected();
 
+    // Stale completed record is taken over as if it's a new one, its row stays locked until transaction ends
+    let n_row_affected = match (n_row_affected, replay_max_age) {
+        (0, Some(replay_max_age)) => {
+            reset_stale_idempotency_record(
+                &mut transaction,
+                idempotency_key,
+                user_id,
+                request,
+                replay_max_age,
+            )
+            .await?
+        }
+        _ => n_row_affected,
+    };
+
     match n_row_affected {
         // If there is no row affected, query is rejected when trying to insert a new idempotency key
         // Means the idempotency key already exists in the database
@@ -100,6 +210,7 @@ pub async fn try_insert_idempotency_response_record_into_database(
                 &mut transaction,
                 idempotency_key,
                 user_id,
+                request,
             )
             .await?
             .ok_or_else(|| {
@@ -107,7 +218,10 @@ pub async fn try_insert_idempotency_response_record_into_database(
             })?;
 
             // Consume the transaction if idempotency response record is already in database
-            transaction.commit().await?;
+            transaction
+                .commit()
+                .await
+                .map_err(|e| IdempotencyError::UnexpectedError(e.into()))?;
             Ok(ProcessState::Completed(response))
         }
         // Return transaction back to main process to update idempotency response record
@@ -115,12 +229,59 @@ pub async fn try_insert_idempotency_response_record_into_database(
     }
 }
 
+#[tracing::instrument(name = "Reset stale idempotency record", skip_all)]
+async fn reset_stale_idempotency_record(
+    transaction: &mut Transaction<'static, Postgres>,
+    idempotency_key: &IdempotencyKey,
+    user_id: Option<&uuid::Uuid>,
+    request: &IdempotentRequest<'_>,
+    replay_max_age: Duration,
+) -> Result<u64, IdempotencyError> {
+    let replay_max_age = PgInterval::try_from(replay_max_age)
+        .map_err(|e| IdempotencyError::UnexpectedError(anyhow::anyhow!(e)))?;
+    let in_progress_lease = PgInterval::try_from(IN_PROGRESS_RECORD_LEASE)
+        .map_err(|e| IdempotencyError::UnexpectedError(anyhow::anyhow!(e)))?;
+    let n_row_affected = sqlx::query!(
+        r#"
+        UPDATE idempotency
+        SET
+            request_method = $3,
+            request_path = $4,
+            response_status_code = NULL,
+            response_headers = NULL,
+            response_body = NULL,
+            created_at = now()
+        WHERE
+            COALESCE(user_id, '00000000-0000-0000-0000-000000000000') =
+                COALESCE($1::uuid, '00000000-0000-0000-0000-000000000000') AND
+            idempotency_key = $2 AND
+            (
+                (response_status_code IS NOT NULL AND now() - created_at > $5) OR
+                (response_status_code IS NULL AND now() - created_at > $6)
+            )
+        "#,
+        user_id,
+        idempotency_key.as_ref(),
+        request.method,
+        request.path,
+        replay_max_age,
+        in_progress_lease
+    )
+    .execute(transaction)
+    .await
+    .map_err(|e| IdempotencyError::UnexpectedError(e.into()))?
+    .rows_affected();
+
+    Ok(n_row_affected)
+}
+
 #[tracing::instrument(name = "Update idempotency response record into database", skip_all)]
 pub async fn update_idempotency_response_record(
     transaction: &mut Transaction<'_, Postgres>,
     idempotency_key: &IdempotencyKey,
-    user_id: &uuid::Uuid,
+    user_id: Option<&uuid::Uuid>,
     response: HttpResponse,
+    max_stored_body_size: usize,
 ) -> Result<HttpResponse, anyhow::Error> {
     // HttpResponse can't be clone, so split it into parts and gather back the parts before return
     // HttpResponse<B> with B is type of body
@@ -129,16 +290,31 @@ pub async fn update_idempotency_response_record(
     // -> [response_without_body(headers, error, extensions), , response_body]
     let (response_without_body, body) = response.into_parts();
     let status_code: i16 = response_without_body.status().as_u16().try_into()?;
+    let body = to_bytes(body).await.map_err(|e| anyhow::anyhow!("{}", e))?;
+    let is_body_omitted = body.len() > max_stored_body_size;
     let headers = {
-        let mut headers = Vec::with_capacity(response_without_body.headers().len());
+        let mut headers = Vec::with_capacity(response_without_body.headers().len() + 1);
         for (key, value) in response_without_body.headers().iter() {
             let key = key.as_str().to_owned();
             let value = value.as_bytes().to_owned();
             headers.push(ResponseHeaderRecord { key, value });
         }
+        if is_body_omitted {
+            tracing::warn!(
+                body_size = body.len(),
+                "Response body is too large to be stored for idempotency, it's omitted on replay"
+            );
+            headers.push(ResponseHeaderRecord {
+                key: IDEMPOTENCY_BODY_OMITTED_HEADER.to_lowercase(),
+                value: b"true".to_vec(),
+            });
+        }
         headers
     };
-    let body = to_bytes(body).await.map_err(|e| anyhow::anyhow!("{}", e))?;
+    let stored_body: &[u8] = match is_body_omitted {
+        true => &[],
+        false => body.as_ref(),
+    };
 
     sqlx::query!(
         r#"
@@ -148,11 +324,13 @@ pub async fn update_idempotency_response_record(
             response_headers = $2,
             response_body = $3
         WHERE
-            user_id = $4 AND idempotency_key = $5
+            COALESCE(user_id, '00000000-0000-0000-0000-000000000000') =
+                COALESCE($4::uuid, '00000000-0000-0000-0000-000000000000') AND
+            idempotency_key = $5
         "#,
         status_code,
         headers as _,
-        body.as_ref(),
+        stored_body,
         user_id,
         idempotency_key.as_ref()
     )
@@ -162,3 +340,55 @@ pub async fn update_idempotency_response_record(
     let response = response_without_body.set_body(body).map_into_boxed_body();
     Ok(response)
 }
+
+pub struct IdempotencyRecord {
+    pub idempotency_key: String,
+    pub request_method: Option<String>,
+    pub request_path: Option<String>,
+    // `None` while request holding the key hasn't stored its response
+    pub response_status_code: Option<i16>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[tracing::instrument(name = "Get idempotency records of user from database", skip(pg_pool))]
+pub async fn get_user_idempotency_records(
+    pg_pool: &PgPool,
+    user_id: &uuid::Uuid,
+) -> Result<Vec<IdempotencyRecord>, sqlx::Error> {
+    sqlx::query_as!(
+        IdempotencyRecord,
+        r#"
+        SELECT idempotency_key, request_method, request_path, response_status_code, created_at
+        FROM idempotency
+        WHERE user_id = $1
+        ORDER BY created_at DESC
+        "#,
+        user_id
+    )
+    .fetch_all(pg_pool)
+    .await
+}
+
+// Return false if user has no record with the key
+#[tracing::instrument(
+    name = "Delete idempotency record of user from database",
+    skip(pg_pool)
+)]
+pub async fn delete_user_idempotency_record(
+    pg_pool: &PgPool,
+    user_id: &uuid::Uuid,
+    idempotency_key: &str,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        DELETE FROM idempotency
+        WHERE user_id = $1 AND idempotency_key = $2
+        "#,
+        user_id,
+        idempotency_key
+    )
+    .execute(pg_pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
@@ -1,9 +1,20 @@
 mod authentication;
+pub mod cli;
 pub mod configuration;
+pub mod confirmation_emails;
+pub mod content_store;
 pub mod email_client;
+pub mod html_sanitizer;
 pub mod idempotency;
+pub mod middleware;
+pub mod mx_check;
 pub mod newsletters_issues;
+pub mod queue_metrics;
 mod routes;
 pub mod startup;
 pub mod telemetry;
 pub mod utils;
+pub mod worker_status;
+
+// Validated domain types, reusable by services embedding this crate
+pub use routes::{SubscriberEmail, SubscriberName};
@@ -1,40 +1,78 @@
+use clap::Parser;
 use std::fmt::{Debug, Display};
 use std::sync::Arc;
 use tokio::sync::Notify;
 use tokio::task::JoinError;
+use zero2prod::cli::{check_config_exit_code, Cli};
 use zero2prod::configuration::Settings;
+use zero2prod::confirmation_emails::{
+    ConfirmationEmailsDeliveryWorker, DeleteExpiredSubscriptionTokensWorker,
+};
 use zero2prod::newsletters_issues::{
     DeleteExpiredIdempotencyWorker, NewslettersIssuesDeliveryWorker,
 };
-use zero2prod::startup::Application;
+use zero2prod::queue_metrics::QueueMetricsLogger;
+use zero2prod::startup::{build_send_rate_limiter, Application};
 use zero2prod::telemetry::config_tracing;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
     let settings = Settings::get_configuration().expect("Failed to read configuration");
+    if cli.check_config {
+        std::process::exit(check_config_exit_code(&settings).await);
+    }
+    if let Some(command) = cli.command {
+        return command.run(&settings).await;
+    }
+
+    // Fail fast before spawning any task
+    settings.validate()?;
 
     config_tracing(&settings.application);
 
     let notify = Arc::new(Notify::new());
+    // API and workers send emails through the same provider, so they share its sending rate
+    let send_rate_limiter = build_send_rate_limiter(&settings.email_client);
 
     let app = tokio::spawn(
         Application::builder(settings.clone(), notify.clone())
+            .set_send_rate_limiter(send_rate_limiter.clone())
             .build()
             .await?
             .run_until_terminated(),
     );
 
     let newsletters_issue_worker = tokio::spawn(
-        NewslettersIssuesDeliveryWorker::builder(settings.clone(), notify).run_until_terminated(),
+        NewslettersIssuesDeliveryWorker::builder(settings.clone(), notify)
+            .set_send_rate_limiter(send_rate_limiter.clone())
+            .run_until_terminated(),
+    );
+
+    let delete_expired_idempotency_worker = tokio::spawn(
+        DeleteExpiredIdempotencyWorker::builder(settings.clone()).run_until_terminated(),
+    );
+
+    let delete_expired_subscription_tokens_worker = tokio::spawn(
+        DeleteExpiredSubscriptionTokensWorker::builder(settings.clone()).run_until_terminated(),
+    );
+
+    let confirmation_emails_worker = tokio::spawn(
+        ConfirmationEmailsDeliveryWorker::builder(settings.clone())
+            .set_send_rate_limiter(send_rate_limiter)
+            .run_until_terminated(),
     );
 
-    let delete_expired_idempotency_worker =
-        tokio::spawn(DeleteExpiredIdempotencyWorker::builder(settings).run_until_terminated());
+    let queue_metrics_logger =
+        tokio::spawn(QueueMetricsLogger::builder(settings).run_until_terminated());
 
     tokio::select! {
         o = app => report_exit("API", o),
         o = newsletters_issue_worker => report_exit("Newsletter Issue Delivery Worker", o),
         o = delete_expired_idempotency_worker => report_exit("Delete Expired Idempotency Worker", o),
+        o = delete_expired_subscription_tokens_worker => report_exit("Delete Expired Subscription Tokens Worker", o),
+        o = confirmation_emails_worker => report_exit("Confirmation Emails Delivery Worker", o),
+        o = queue_metrics_logger => report_exit("Queue Metrics Logger", o),
     }
 
     Ok(())
@@ -0,0 +1,190 @@
+use crate::authentication::UserId;
+use crate::middleware::RequestId;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::{Error, HttpMessage};
+use actix_web_lab::middleware::Next;
+use std::time::Instant;
+
+// Log systems can filter access log events by this target
+pub const ACCESS_LOG_TARGET: &str = "access_log";
+
+// Query parameters carrying credentials, e.g. `subscription_token` of confirmation links
+// A parameter is redacted when its name contains any of these
+const SENSITIVE_QUERY_PARAMS: [&str; 6] =
+    ["token", "code", "password", "secret", "key", "signature"];
+const REDACTED: &str = "REDACTED";
+
+// Keep names of parameters so logs still tell which were sent, only values are redacted
+pub fn redact_query(query: &str) -> String {
+    query
+        .split('&')
+        .map(|param| match param.split_once('=') {
+            Some((name, _))
+                if SENSITIVE_QUERY_PARAMS
+                    .iter()
+                    .any(|sensitive| name.to_lowercase().contains(sensitive)) =>
+            {
+                format!("{}={}", name, REDACTED)
+            }
+            _ => param.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+// Emit one compact structured event per request
+// Need to be wrapped inside of `propagate_request_id` to read request id
+pub async fn log_access(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let start = Instant::now();
+    let method = req.method().to_string();
+    let path = req.path().to_string();
+    let query = redact_query(req.query_string());
+    let request_id = req
+        .extensions()
+        .get::<RequestId>()
+        .map(|id| id.to_string())
+        .unwrap_or_default();
+
+    let outcome = next.call(req).await;
+
+    let status = match &outcome {
+        Ok(response) => response.status(),
+        Err(e) => e.as_response_error().status_code(),
+    };
+    // User id is inserted into request extensions by `reject_anonymous_users` when user is logged in
+    let user_id = outcome
+        .as_ref()
+        .ok()
+        .and_then(|response| response.request().extensions().get::<UserId>().copied())
+        .map(|user_id| user_id.to_string())
+        .unwrap_or_default();
+
+    tracing::info!(
+        target: ACCESS_LOG_TARGET,
+        method = %method,
+        path = %path,
+        query = %query,
+        status = status.as_u16(),
+        latency_ms = start.elapsed().as_millis() as u64,
+        request_id = %request_id,
+        user_id = %user_id,
+        "Access log"
+    );
+
+    outcome
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::middleware::{log_access, propagate_request_id, redact_query, ACCESS_LOG_TARGET};
+    use actix_web::{test, web, App, HttpResponse};
+    use actix_web_lab::middleware::from_fn;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use tracing::field::{Field, Visit};
+    use tracing::{Event, Subscriber};
+    use tracing_subscriber::layer::{Context, SubscriberExt};
+    use tracing_subscriber::Layer;
+
+    type CapturedEvents = Arc<Mutex<Vec<HashMap<String, String>>>>;
+
+    struct CaptureAccessLogLayer(CapturedEvents);
+
+    impl<S: Subscriber> Layer<S> for CaptureAccessLogLayer {
+        fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+            if event.metadata().target() != ACCESS_LOG_TARGET {
+                return;
+            }
+            let mut visitor = FieldsVisitor::default();
+            event.record(&mut visitor);
+            self.0.lock().unwrap().push(visitor.0);
+        }
+    }
+
+    #[derive(Default)]
+    struct FieldsVisitor(HashMap<String, String>);
+
+    impl Visit for FieldsVisitor {
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.0.insert(field.name().to_string(), value.to_string());
+        }
+
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            self.0
+                .insert(field.name().to_string(), format!("{:?}", value));
+        }
+    }
+
+    #[actix_web::test]
+    async fn access_log_event_is_emitted_with_request_fields() {
+        // Arrange
+        let events = CapturedEvents::default();
+        let subscriber = tracing_subscriber::registry().with(CaptureAccessLogLayer(events.clone()));
+        let _guard = tracing::subscriber::set_default(subscriber);
+        let app = test::init_service(
+            App::new()
+                .route("/health", web::get().to(HttpResponse::Ok))
+                .wrap(from_fn(log_access))
+                .wrap(from_fn(propagate_request_id)),
+        )
+        .await;
+
+        // Act
+        let request = test::TestRequest::get()
+            .uri("/health")
+            .insert_header(("x-request-id", "access-log-test"))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 200);
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 1);
+        let event = &events[0];
+        assert_eq!(event["method"], "GET");
+        assert_eq!(event["path"], "/health");
+        assert_eq!(event["status"], "200");
+        assert_eq!(event["request_id"], "access-log-test");
+        assert_eq!(event["user_id"], "");
+        assert!(event.contains_key("latency_ms"));
+    }
+
+    #[actix_web::test]
+    async fn tokens_in_query_are_redacted_from_access_log() {
+        // Arrange
+        let events = CapturedEvents::default();
+        let subscriber = tracing_subscriber::registry().with(CaptureAccessLogLayer(events.clone()));
+        let _guard = tracing::subscriber::set_default(subscriber);
+        let app = test::init_service(
+            App::new()
+                .route("/subscriptions/confirm", web::get().to(HttpResponse::Ok))
+                .wrap(from_fn(log_access))
+                .wrap(from_fn(propagate_request_id)),
+        )
+        .await;
+
+        // Act
+        let request = test::TestRequest::get()
+            .uri("/subscriptions/confirm?subscription_token=secret-token")
+            .to_request();
+        test::call_service(&app, request).await;
+
+        // Assert
+        let events = events.lock().unwrap();
+        assert_eq!(events[0]["query"], "subscription_token=REDACTED");
+        assert!(!format!("{:?}", events[0]).contains("secret-token"));
+    }
+
+    #[test]
+    fn only_sensitive_query_params_are_redacted() {
+        assert_eq!(
+            redact_query("status=confirmed&token=abc&Idempotency_Key=def&flag"),
+            "status=confirmed&token=REDACTED&Idempotency_Key=REDACTED&flag"
+        );
+        assert_eq!(redact_query(""), "");
+    }
+}
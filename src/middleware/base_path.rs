@@ -0,0 +1,45 @@
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderValue, LOCATION};
+use actix_web::web::Data;
+use actix_web::Error;
+use actix_web_lab::middleware::Next;
+
+// Prefix that all routes are mounted under, e.g. "/newsletter" behind a reverse proxy
+#[derive(Clone, Debug)]
+pub struct BasePath(pub String);
+
+impl BasePath {
+    // Links and form actions in HTML aren't rewritten like redirects, so they're rendered with base path
+    // Escaped to be put in an HTML attribute
+    pub fn href(&self, path: &str) -> String {
+        htmlescape::encode_minimal(&format!("{}{}", self.0, path))
+    }
+}
+
+// Handlers redirect to absolute paths, e.g. "/login"
+// Prefix them with base path so redirects stay under where the app is mounted
+pub async fn prefix_redirect_locations(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let base_path = req.app_data::<Data<BasePath>>().cloned();
+    let mut response = next.call(req).await?.map_into_boxed_body();
+
+    if let Some(base_path) = base_path {
+        let location = response
+            .headers()
+            .get(LOCATION)
+            .and_then(|value| value.to_str().ok())
+            // "//host" is a protocol relative URL, not a path
+            .filter(|location| location.starts_with('/') && !location.starts_with("//"))
+            .map(|location| format!("{}{}", base_path.0, location));
+        if let Some(location) = location {
+            if let Ok(value) = HeaderValue::from_str(&location) {
+                response.headers_mut().insert(LOCATION, value);
+            }
+        }
+    }
+
+    Ok(response)
+}
@@ -0,0 +1,30 @@
+use actix_cors::CorsError;
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::{Error, HttpResponse};
+use actix_web_lab::middleware::Next;
+
+// `Cors` answers origins it doesn't allow with 400, rewrite them into a bare 403
+// so cross-origin callers get neither a body nor any CORS headers back
+pub async fn forbid_disallowed_cors_origins(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let response = next.call(req).await?;
+
+    let origin_not_allowed = response
+        .response()
+        .error()
+        .and_then(|e| e.as_error::<CorsError>())
+        .map_or(false, |e| matches!(e, CorsError::OriginNotAllowed));
+
+    if origin_not_allowed {
+        let (req, _) = response.into_parts();
+        return Ok(ServiceResponse::new(
+            req,
+            HttpResponse::Forbidden().finish(),
+        ));
+    }
+
+    Ok(response.map_into_boxed_body())
+}
@@ -0,0 +1,41 @@
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::web::Data;
+use actix_web::{Error, HttpResponse};
+use actix_web_lab::middleware::Next;
+
+#[derive(Clone, Copy, Debug)]
+pub struct RequestHeaderLimits {
+    pub max_count: usize,
+    pub max_size_bytes: usize,
+}
+
+// Reject suspiciously large header sets before they reach basic auth and session parsing
+pub async fn reject_oversized_headers(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let limits = req
+        .app_data::<Data<RequestHeaderLimits>>()
+        .map(|limits| **limits);
+
+    if let Some(limits) = limits {
+        let headers = req.headers();
+        let headers_size: usize = headers
+            .iter()
+            .map(|(key, value)| key.as_str().len() + value.len())
+            .sum();
+
+        if headers.len() > limits.max_count || headers_size > limits.max_size_bytes {
+            tracing::warn!(
+                headers_count = headers.len(),
+                headers_size,
+                "Reject request with oversized headers"
+            );
+            let response = HttpResponse::RequestHeaderFieldsTooLarge().finish();
+            return Ok(req.into_response(response));
+        }
+    }
+
+    Ok(next.call(req).await?.map_into_boxed_body())
+}
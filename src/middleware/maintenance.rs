@@ -0,0 +1,51 @@
+use crate::configuration::MaintenanceSettings;
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::RETRY_AFTER;
+use actix_web::web::Data;
+use actix_web::{Error, HttpResponse};
+use actix_web_lab::middleware::Next;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Mode is kept in memory, so toggling it only affects this instance
+// and it's reset to configured mode on restart
+pub struct MaintenanceMode {
+    enabled: AtomicBool,
+    retry_after_secs: u64,
+}
+
+impl MaintenanceMode {
+    pub fn from_settings(settings: &MaintenanceSettings) -> Self {
+        Self {
+            enabled: AtomicBool::new(settings.enabled),
+            retry_after_secs: settings.retry_after_secs,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+}
+
+// Requests pass through when no `MaintenanceMode` is registered in app data
+pub async fn reject_during_maintenance(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let maintenance = req.app_data::<Data<MaintenanceMode>>().cloned();
+
+    if let Some(maintenance) = maintenance {
+        if maintenance.is_enabled() {
+            let response = HttpResponse::ServiceUnavailable()
+                .insert_header((RETRY_AFTER, maintenance.retry_after_secs.to_string()))
+                .finish();
+            return Ok(req.into_response(response));
+        }
+    }
+
+    Ok(next.call(req).await?.map_into_boxed_body())
+}
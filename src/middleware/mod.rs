@@ -0,0 +1,21 @@
+mod access_log;
+mod base_path;
+mod cors;
+mod header_limits;
+mod maintenance;
+mod rate_limit;
+mod remember_me;
+mod request_id;
+mod security_headers;
+mod session_store;
+
+pub use access_log::*;
+pub use base_path::*;
+pub use cors::*;
+pub use header_limits::*;
+pub use maintenance::*;
+pub use rate_limit::*;
+pub use remember_me::*;
+pub use request_id::*;
+pub use security_headers::*;
+pub use session_store::*;
@@ -0,0 +1,228 @@
+use crate::configuration::RateLimitSettings;
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{RETRY_AFTER, X_FORWARDED_FOR};
+use actix_web::web::Data;
+use actix_web::{Error, HttpResponse};
+use actix_web_lab::middleware::Next;
+use anyhow::Context;
+use redis::aio::ConnectionManager;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::OnceCell;
+use uuid::Uuid;
+
+const RATE_LIMIT_KEY_PREFIX: &str = "rate_limit";
+
+// Timestamps of accepted requests in the window are kept in a sorted set per client
+// Checking and recording are done in one script so concurrent requests can't exceed the limit
+// Return 0 when request is accepted, otherwise milliseconds until a slot is freed
+const SLIDING_WINDOW_SCRIPT: &str = r#"
+local now = tonumber(ARGV[1])
+local window = tonumber(ARGV[2])
+redis.call('ZREMRANGEBYSCORE', KEYS[1], '-inf', now - window)
+if redis.call('ZCARD', KEYS[1]) < tonumber(ARGV[3]) then
+    redis.call('ZADD', KEYS[1], now, ARGV[4])
+    redis.call('PEXPIRE', KEYS[1], window)
+    return 0
+end
+local oldest = redis.call('ZRANGE', KEYS[1], 0, 0, 'WITHSCORES')
+return math.max(tonumber(oldest[2]) + window - now, 1)
+"#;
+
+#[derive(Clone)]
+pub struct RateLimiter {
+    name: String,
+    redis_client: redis::Client,
+    // Connected on first request, so Redis being down at startup doesn't stop the app
+    redis: Arc<OnceCell<ConnectionManager>>,
+    script: redis::Script,
+    max_requests: u32,
+    window: Duration,
+    trusted_proxies: Vec<IpAddr>,
+}
+
+impl RateLimiter {
+    // `name` separates counters of rate limiters sharing the same Redis
+    pub fn new(
+        name: &str,
+        redis_url: &str,
+        settings: &RateLimitSettings,
+    ) -> Result<Self, anyhow::Error> {
+        let redis_client = redis::Client::open(redis_url).context("Invalid Redis url")?;
+        Ok(Self {
+            name: name.to_string(),
+            redis_client,
+            redis: Arc::new(OnceCell::new()),
+            script: redis::Script::new(SLIDING_WINDOW_SCRIPT),
+            max_requests: settings.max_requests,
+            window: Duration::from_secs(settings.window_secs),
+            trusted_proxies: settings.trusted_proxies.clone(),
+        })
+    }
+
+    fn client_ip(&self, req: &ServiceRequest) -> Option<IpAddr> {
+        let peer_ip = req.peer_addr()?.ip();
+        let forwarded_for = req
+            .headers()
+            .get_all(X_FORWARDED_FOR)
+            .filter_map(|value| value.to_str().ok());
+        Some(resolve_client_ip(
+            peer_ip,
+            forwarded_for,
+            &self.trusted_proxies,
+        ))
+    }
+
+    // Failed connection isn't cached, so it's retried by next request
+    async fn connection(&self) -> Result<ConnectionManager, redis::RedisError> {
+        self.redis
+            .get_or_try_init(|| ConnectionManager::new(self.redis_client.clone()))
+            .await
+            .cloned()
+    }
+
+    // Return how long client has to wait when request exceeds the limit
+    async fn check(&self, client_ip: &IpAddr) -> Result<Option<Duration>, redis::RedisError> {
+        let mut redis = self.connection().await?;
+        let now_millis = chrono::Utc::now().timestamp_millis();
+        let retry_after_millis: u64 = self
+            .script
+            .key(format!(
+                "{}:{}:{}",
+                RATE_LIMIT_KEY_PREFIX, self.name, client_ip
+            ))
+            .arg(now_millis)
+            .arg(self.window.as_millis() as u64)
+            .arg(self.max_requests)
+            .arg(format!("{}-{}", now_millis, Uuid::new_v4()))
+            .invoke_async(&mut redis)
+            .await?;
+
+        Ok(match retry_after_millis {
+            0 => None,
+            millis => Some(Duration::from_millis(millis)),
+        })
+    }
+}
+
+// Each proxy appends the address it received request from to `X-Forwarded-For`
+// So only hops added by trusted proxies can be believed, anything left of them can be spoofed by client
+// Client is the rightmost hop that isn't a trusted proxy, or peer when it isn't a trusted proxy itself
+fn resolve_client_ip<'a>(
+    peer_ip: IpAddr,
+    forwarded_for: impl Iterator<Item = &'a str>,
+    trusted_proxies: &[IpAddr],
+) -> IpAddr {
+    if !trusted_proxies.contains(&peer_ip) {
+        return peer_ip;
+    }
+    let hops: Vec<&str> = forwarded_for.flat_map(|value| value.split(',')).collect();
+    let mut client_ip = peer_ip;
+    for hop in hops.into_iter().rev() {
+        match hop.trim().parse::<IpAddr>() {
+            Ok(ip) => {
+                client_ip = ip;
+                if !trusted_proxies.contains(&ip) {
+                    break;
+                }
+            }
+            // Malformed hop can't be attributed, so nearest trusted hop is used
+            Err(_) => break,
+        }
+    }
+    client_ip
+}
+
+// Requests pass through when no `RateLimiter` is registered in app data
+pub async fn rate_limit_by_client_ip(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let rate_limiter = req.app_data::<Data<RateLimiter>>().cloned();
+
+    if let Some(rate_limiter) = rate_limiter {
+        if let Some(client_ip) = rate_limiter.client_ip(&req) {
+            match rate_limiter.check(&client_ip).await {
+                Ok(Some(retry_after)) => {
+                    tracing::warn!(
+                        client_ip = %client_ip,
+                        rate_limiter = %rate_limiter.name,
+                        "Reject request exceeding rate limit"
+                    );
+                    // Round up so client doesn't retry before a slot is freed
+                    let retry_after_secs = (retry_after.as_millis() as u64 + 999) / 1000;
+                    let response = HttpResponse::TooManyRequests()
+                        .insert_header((RETRY_AFTER, retry_after_secs.to_string()))
+                        .finish();
+                    return Ok(req.into_response(response));
+                }
+                Ok(None) => {}
+                // Fail open, Redis outage shouldn't take down the endpoint
+                Err(e) => {
+                    tracing::error!(
+                        error.cause_chain = ?e,
+                        error.message = %e,
+                        "Failed to check rate limit"
+                    );
+                }
+            }
+        }
+    }
+
+    Ok(next.call(req).await?.map_into_boxed_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::resolve_client_ip;
+    use std::net::IpAddr;
+
+    fn ip(addr: &str) -> IpAddr {
+        addr.parse().unwrap()
+    }
+
+    #[test]
+    fn forwarded_for_is_ignored_without_trusted_proxies() {
+        let client_ip = resolve_client_ip(ip("10.0.0.1"), ["1.2.3.4"].into_iter(), &[]);
+        assert_eq!(client_ip, ip("10.0.0.1"));
+    }
+
+    #[test]
+    fn forwarded_for_is_ignored_when_peer_is_not_trusted() {
+        let client_ip =
+            resolve_client_ip(ip("10.0.0.2"), ["1.2.3.4"].into_iter(), &[ip("10.0.0.1")]);
+        assert_eq!(client_ip, ip("10.0.0.2"));
+    }
+
+    #[test]
+    fn spoofed_leftmost_hops_are_skipped() {
+        let client_ip = resolve_client_ip(
+            ip("10.0.0.1"),
+            ["6.6.6.6, 1.2.3.4"].into_iter(),
+            &[ip("10.0.0.1")],
+        );
+        assert_eq!(client_ip, ip("1.2.3.4"));
+    }
+
+    #[test]
+    fn hops_of_chained_trusted_proxies_are_skipped() {
+        let client_ip = resolve_client_ip(
+            ip("10.0.0.1"),
+            ["6.6.6.6, 1.2.3.4", "10.0.0.2"].into_iter(),
+            &[ip("10.0.0.1"), ip("10.0.0.2")],
+        );
+        assert_eq!(client_ip, ip("1.2.3.4"));
+    }
+
+    #[test]
+    fn malformed_hop_falls_back_to_nearest_trusted_hop() {
+        let client_ip = resolve_client_ip(
+            ip("10.0.0.1"),
+            ["1.2.3.4, unknown"].into_iter(),
+            &[ip("10.0.0.1")],
+        );
+        assert_eq!(client_ip, ip("10.0.0.1"));
+    }
+}
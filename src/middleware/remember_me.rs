@@ -0,0 +1,72 @@
+use crate::authentication::{SessionLifetime, UserSession};
+use actix_web::body::MessageBody;
+use actix_web::cookie::time;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::web::Data;
+use actix_web::{Error, FromRequest, HttpMessage};
+use actix_web_lab::middleware::Next;
+use std::time::Duration;
+
+pub const SESSION_COOKIE_NAME: &str = "id";
+
+// Max age of session cookie of a logged in user who chose "remember me"
+#[derive(Clone, Copy, Debug)]
+struct RememberedSession(Duration);
+
+// SessionMiddleware makes every session cookie a browser session cookie
+// Mark requests of remembered sessions, so `persist_remembered_session_cookie` can make their cookie persistent
+// Need to be wrapped inside of SessionMiddleware to read session state after handler updated it
+pub async fn mark_remembered_session(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let session = {
+        let (http_req, payload) = req.parts_mut();
+        UserSession::from_request(http_req, payload).await?
+    };
+    let remember_me_ttl = req
+        .app_data::<Data<SessionLifetime>>()
+        .map(|lifetime| lifetime.remember_me_ttl);
+
+    let response = next.call(req).await?;
+
+    let is_remembered_user =
+        matches!(session.get_user_id(), Ok(Some(_))) && session.is_remembered().unwrap_or_default();
+    if let (true, Some(remember_me_ttl)) = (is_remembered_user, remember_me_ttl) {
+        response
+            .request()
+            .extensions_mut()
+            .insert(RememberedSession(remember_me_ttl));
+    }
+
+    Ok(response)
+}
+
+// Need to be wrapped outside of SessionMiddleware to see the session cookie it sets
+pub async fn persist_remembered_session_cookie(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let mut response = next.call(req).await?;
+
+    let max_age = match response.request().extensions().get::<RememberedSession>() {
+        Some(RememberedSession(max_age)) => *max_age,
+        None => return Ok(response),
+    };
+
+    let session_cookie = response
+        .response()
+        .cookies()
+        .find(|cookie| cookie.name() == SESSION_COOKIE_NAME)
+        .map(|cookie| cookie.into_owned());
+    if let Some(mut session_cookie) = session_cookie {
+        session_cookie.set_max_age(time::Duration::milliseconds(
+            i64::try_from(max_age.as_millis()).unwrap_or(i64::MAX),
+        ));
+        let http_response = response.response_mut();
+        http_response.del_cookie(SESSION_COOKIE_NAME);
+        http_response.add_cookie(&session_cookie)?;
+    }
+
+    Ok(response)
+}
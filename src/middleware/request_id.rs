@@ -0,0 +1,156 @@
+use crate::middleware::redact_query;
+use crate::utils::ApiError;
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::{Error, HttpMessage};
+use actix_web_lab::middleware::Next;
+use std::fmt::Display;
+use std::ops::Deref;
+use tracing::Span;
+use tracing_actix_web::{DefaultRootSpanBuilder, RootSpanBuilder};
+use uuid::Uuid;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+const MAX_REQUEST_ID_LENGTH: usize = 64;
+
+// Correlation id of a request, provided by client via `X-Request-Id` header or generated by server
+#[derive(Clone, Debug)]
+pub struct RequestId(String);
+
+impl RequestId {
+    fn from_header_value(value: &HeaderValue) -> Option<Self> {
+        // Only keep characters that are safe to write into logs and headers
+        // Prevent client from injecting new lines or control characters into logs
+        let sanitized: String = value
+            .to_str()
+            .ok()?
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric() || ['-', '_', '.'].contains(c))
+            .take(MAX_REQUEST_ID_LENGTH)
+            .collect();
+
+        match sanitized.is_empty() {
+            true => None,
+            false => Some(Self(sanitized)),
+        }
+    }
+
+    fn generate() -> Self {
+        Self(Uuid::new_v4().to_string())
+    }
+}
+
+impl Display for RequestId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl Deref for RequestId {
+    type Target = str;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+pub async fn propagate_request_id(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let request_id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(RequestId::from_header_value)
+        .unwrap_or_else(RequestId::generate);
+    req.extensions_mut().insert(request_id.clone());
+
+    // Keep a handle of request to build a response from error returned by inner services
+    let http_req = req.request().clone();
+    let mut response = match next.call(req).await {
+        Ok(response) => response.map_into_boxed_body(),
+        Err(e) => ServiceResponse::from_err(e, http_req),
+    };
+
+    // Errors built by `e400`/`e500` can't access request, so fill request id into their body here
+    let error_body = response
+        .response()
+        .error()
+        .and_then(|e| e.as_error::<ApiError>())
+        .map(|e| e.json_body(Some(&request_id)));
+    if let Some(error_body) = error_body {
+        response = response
+            .map_body(|_, _| BoxBody::new(error_body))
+            .map_into_boxed_body();
+    }
+
+    // Sanitized request id only contains visible ASCII characters, so it is always a valid header value
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response
+            .headers_mut()
+            .insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+    }
+
+    Ok(response)
+}
+
+// TracingLogger generates its own root span
+// Customize root span to attach request id from `propagate_request_id` middleware
+// `propagate_request_id` need to be wrapped outside of TracingLogger to run before root span is created
+pub struct RequestIdRootSpanBuilder;
+
+impl RootSpanBuilder for RequestIdRootSpanBuilder {
+    fn on_request_start(request: &ServiceRequest) -> Span {
+        let correlation_id = request
+            .extensions()
+            .get::<RequestId>()
+            .map(|id| id.to_string())
+            .unwrap_or_default();
+        let span = tracing_actix_web::root_span!(request, correlation_id = %correlation_id);
+        // Root span records full target by default, which may carry tokens in its query
+        let target = match request.query_string() {
+            "" => request.path().to_string(),
+            query => format!("{}?{}", request.path(), redact_query(query)),
+        };
+        span.record("http.target", &target.as_str());
+        span
+    }
+
+    fn on_request_end<B: MessageBody>(span: Span, outcome: &Result<ServiceResponse<B>, Error>) {
+        DefaultRootSpanBuilder::on_request_end(span, outcome);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::middleware::RequestId;
+    use actix_web::http::header::HeaderValue;
+    use claims::{assert_none, assert_some};
+
+    #[test]
+    fn valid_request_id_is_kept() {
+        let value = HeaderValue::from_static("abc-123_DEF.456");
+        let request_id = assert_some!(RequestId::from_header_value(&value));
+        assert_eq!(&*request_id, "abc-123_DEF.456");
+    }
+
+    #[test]
+    fn unsafe_characters_are_stripped_from_request_id() {
+        let value = HeaderValue::from_static("abc\" level=\"error");
+        let request_id = assert_some!(RequestId::from_header_value(&value));
+        assert_eq!(&*request_id, "abclevelerror");
+    }
+
+    #[test]
+    fn long_request_id_is_truncated() {
+        let value = HeaderValue::from_str(&"a".repeat(200)).unwrap();
+        let request_id = assert_some!(RequestId::from_header_value(&value));
+        assert_eq!(request_id.len(), 64);
+    }
+
+    #[test]
+    fn request_id_without_safe_characters_is_rejected() {
+        let value = HeaderValue::from_static("\"<>{}");
+        assert_none!(RequestId::from_header_value(&value));
+    }
+}
@@ -0,0 +1,126 @@
+use crate::configuration::SecurityHeadersSettings;
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{
+    HeaderName, HeaderValue, CONTENT_SECURITY_POLICY, REFERRER_POLICY, X_CONTENT_TYPE_OPTIONS,
+    X_FRAME_OPTIONS,
+};
+use actix_web::web::Data;
+use actix_web::Error;
+use actix_web_lab::middleware::Next;
+
+#[derive(Clone, Debug)]
+pub struct SecurityHeaders(Vec<(HeaderName, HeaderValue)>);
+
+impl SecurityHeaders {
+    // Empty Content-Security-Policy in settings means the header isn't sent
+    pub fn from_settings(
+        settings: &SecurityHeadersSettings,
+    ) -> Result<Self, actix_web::http::header::InvalidHeaderValue> {
+        let mut headers = vec![
+            (X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff")),
+            (X_FRAME_OPTIONS, HeaderValue::from_static("DENY")),
+            (
+                REFERRER_POLICY,
+                HeaderValue::from_str(&settings.referrer_policy)?,
+            ),
+        ];
+        if !settings.content_security_policy.is_empty() {
+            headers.push((
+                CONTENT_SECURITY_POLICY,
+                HeaderValue::from_str(&settings.content_security_policy)?,
+            ));
+        }
+        Ok(Self(headers))
+    }
+}
+
+// Headers already set by handlers are kept, so a route can loosen its own policy
+pub async fn add_security_headers(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let security_headers = req.app_data::<Data<SecurityHeaders>>().cloned();
+    let mut response = next.call(req).await?.map_into_boxed_body();
+
+    if let Some(security_headers) = security_headers {
+        let headers = response.headers_mut();
+        for (name, value) in &security_headers.0 {
+            if !headers.contains_key(name) {
+                headers.insert(name.clone(), value.clone());
+            }
+        }
+    }
+
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::configuration::SecurityHeadersSettings;
+    use crate::middleware::{add_security_headers, SecurityHeaders};
+    use actix_web::http::header::CONTENT_SECURITY_POLICY;
+    use actix_web::{test, web, App, HttpResponse};
+    use actix_web_lab::middleware::from_fn;
+
+    fn settings(content_security_policy: &str) -> SecurityHeadersSettings {
+        SecurityHeadersSettings {
+            content_security_policy: content_security_policy.to_string(),
+            referrer_policy: "no-referrer".to_string(),
+        }
+    }
+
+    #[actix_web::test]
+    async fn csp_set_by_handler_is_kept() {
+        // Arrange
+        let security_headers =
+            SecurityHeaders::from_settings(&settings("default-src 'none'")).unwrap();
+        let app = test::init_service(
+            App::new()
+                .route(
+                    "/",
+                    web::get().to(|| async {
+                        HttpResponse::Ok()
+                            .insert_header((CONTENT_SECURITY_POLICY, "default-src 'self'"))
+                            .finish()
+                    }),
+                )
+                .wrap(from_fn(add_security_headers))
+                .app_data(web::Data::new(security_headers)),
+        )
+        .await;
+
+        // Act
+        let response = test::call_service(&app, test::TestRequest::get().to_request()).await;
+
+        // Assert
+        assert_eq!(
+            response.headers().get(CONTENT_SECURITY_POLICY).unwrap(),
+            "default-src 'self'"
+        );
+        assert_eq!(response.headers().get("X-Frame-Options").unwrap(), "DENY");
+    }
+
+    #[actix_web::test]
+    async fn empty_csp_is_not_sent() {
+        // Arrange
+        let security_headers = SecurityHeaders::from_settings(&settings("")).unwrap();
+        let app = test::init_service(
+            App::new()
+                .route("/", web::get().to(HttpResponse::Ok))
+                .wrap(from_fn(add_security_headers))
+                .app_data(web::Data::new(security_headers)),
+        )
+        .await;
+
+        // Act
+        let response = test::call_service(&app, test::TestRequest::get().to_request()).await;
+
+        // Assert
+        assert!(response.headers().get(CONTENT_SECURITY_POLICY).is_none());
+        assert_eq!(
+            response.headers().get("X-Content-Type-Options").unwrap(),
+            "nosniff"
+        );
+    }
+}
@@ -0,0 +1,107 @@
+use crate::utils::error_chain_fmt;
+use actix_session::storage::{LoadError, SaveError, UpdateError};
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::InternalError;
+use actix_web::http::header::{ContentType, ACCEPT, RETRY_AFTER};
+use actix_web::http::StatusCode;
+use actix_web::{Error, HttpRequest, HttpResponse, ResponseError};
+use actix_web_lab::middleware::Next;
+use std::fmt::Debug;
+
+const RETRY_AFTER_SECS: u64 = 5;
+
+#[derive(thiserror::Error)]
+#[error("Session store is unavailable")]
+pub struct SessionStoreUnavailableError(#[source] anyhow::Error);
+
+impl Debug for SessionStoreUnavailableError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for SessionStoreUnavailableError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::SERVICE_UNAVAILABLE
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::ServiceUnavailable()
+            .insert_header((RETRY_AFTER, RETRY_AFTER_SECS.to_string()))
+            .finish()
+    }
+}
+
+impl SessionStoreUnavailableError {
+    // SessionMiddleware wraps errors of session store into InternalError and respond 500
+    // Session store is an infrastructure that can be down temporarily, so recover it as 503
+    fn try_from_session_middleware_error(e: &Error) -> Option<Self> {
+        if let Some(e) = e.as_error::<InternalError<LoadError>>() {
+            return Some(Self(anyhow::anyhow!("Failed to load session state: {}", e)));
+        }
+        if let Some(e) = e.as_error::<InternalError<SaveError>>() {
+            return Some(Self(anyhow::anyhow!("Failed to save session state: {}", e)));
+        }
+        if let Some(e) = e.as_error::<InternalError<UpdateError>>() {
+            return Some(Self(anyhow::anyhow!(
+                "Failed to update session state: {}",
+                e
+            )));
+        }
+        None
+    }
+
+    fn browser_response(&self) -> HttpResponse {
+        HttpResponse::ServiceUnavailable()
+            .insert_header((RETRY_AFTER, RETRY_AFTER_SECS.to_string()))
+            .content_type(ContentType::html())
+            .body(format!(
+                r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <meta http-equiv="refresh" content="{RETRY_AFTER_SECS}">
+    <title>Service Unavailable</title>
+</head>
+<body>
+<p>We are having a temporary problem. Please try again in a few seconds.</p>
+</body>
+</html>"#
+            ))
+    }
+}
+
+fn accepts_html(req: &HttpRequest) -> bool {
+    req.headers()
+        .get(ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.contains("text/html"))
+        .unwrap_or(false)
+}
+
+// Need to be wrapped outside of SessionMiddleware to catch its errors
+pub async fn reject_when_session_store_unavailable(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let http_req = req.request().clone();
+    match next.call(req).await {
+        Ok(response) => Ok(response.map_into_boxed_body()),
+        Err(e) => match SessionStoreUnavailableError::try_from_session_middleware_error(&e) {
+            Some(error) => {
+                tracing::error!(
+                    error.cause_chain = ?error,
+                    error.message = %error,
+                    "Session store is unavailable"
+                );
+                let response = match accepts_html(&http_req) {
+                    true => error.browser_response(),
+                    false => error.error_response(),
+                };
+                Ok(ServiceResponse::new(http_req, response))
+            }
+            None => Err(e),
+        },
+    }
+}
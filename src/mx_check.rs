@@ -0,0 +1,140 @@
+use crate::configuration::MxCheckSettings;
+use futures::future::BoxFuture;
+use std::sync::Arc;
+use std::time::Duration;
+
+// Resolver is abstracted so tests can stub DNS instead of hitting real name servers
+pub trait MxResolver: Send + Sync {
+    fn has_mx_records<'a>(&'a self, domain: &'a str) -> BoxFuture<'a, Result<bool, anyhow::Error>>;
+}
+
+// Reject email domains without MX records, e.g. typos like `gmial.con`, before they bounce
+pub struct MxChecker {
+    resolver: Arc<dyn MxResolver>,
+    timeout: Duration,
+}
+
+impl MxChecker {
+    pub fn new(resolver: Arc<dyn MxResolver>, settings: &MxCheckSettings) -> Self {
+        Self {
+            resolver,
+            timeout: Duration::from_millis(settings.timeout_millis),
+        }
+    }
+
+    // Fail open, slow or broken resolver must not stop people from subscribing
+    // Only domains that are known to have no MX records are rejected
+    #[tracing::instrument(name = "Check MX records of email domain", skip(self))]
+    pub async fn check(&self, domain: &str) -> Result<(), String> {
+        match tokio::time::timeout(self.timeout, self.resolver.has_mx_records(domain)).await {
+            Ok(Ok(true)) => Ok(()),
+            Ok(Ok(false)) => Err(format!(
+                "Email domain `{}` can't receive emails, please check it for typos",
+                domain
+            )),
+            Ok(Err(e)) => {
+                tracing::warn!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to look up MX records, accept email domain"
+                );
+                Ok(())
+            }
+            Err(_) => {
+                tracing::warn!("MX lookup timed out, accept email domain");
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(feature = "mx-check")]
+pub struct DnsMxResolver(trust_dns_resolver::TokioAsyncResolver);
+
+#[cfg(feature = "mx-check")]
+impl DnsMxResolver {
+    // Use name servers of host, e.g. from `/etc/resolv.conf`
+    pub fn from_system_conf() -> Result<Self, anyhow::Error> {
+        use anyhow::Context;
+        let resolver = trust_dns_resolver::TokioAsyncResolver::tokio_from_system_conf()
+            .context("Failed to build DNS resolver from system configuration")?;
+        Ok(Self(resolver))
+    }
+}
+
+#[cfg(feature = "mx-check")]
+impl MxResolver for DnsMxResolver {
+    fn has_mx_records<'a>(&'a self, domain: &'a str) -> BoxFuture<'a, Result<bool, anyhow::Error>> {
+        Box::pin(async move {
+            // Trailing dot makes domain fully qualified, so search domains of host aren't appended
+            match self.0.mx_lookup(format!("{}.", domain)).await {
+                Ok(lookup) => Ok(lookup.iter().next().is_some()),
+                Err(e) => match e.kind() {
+                    trust_dns_resolver::error::ResolveErrorKind::NoRecordsFound { .. } => Ok(false),
+                    _ => Err(e.into()),
+                },
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::configuration::MxCheckSettings;
+    use crate::mx_check::{MxChecker, MxResolver};
+    use claims::{assert_err, assert_ok};
+    use futures::future::BoxFuture;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    enum StubResolver {
+        HasMx(bool),
+        Fails,
+        Hangs,
+    }
+
+    impl MxResolver for StubResolver {
+        fn has_mx_records<'a>(
+            &'a self,
+            _domain: &'a str,
+        ) -> BoxFuture<'a, Result<bool, anyhow::Error>> {
+            Box::pin(async move {
+                match self {
+                    StubResolver::HasMx(has_mx) => Ok(*has_mx),
+                    StubResolver::Fails => Err(anyhow::anyhow!("Name server is unreachable")),
+                    StubResolver::Hangs => {
+                        tokio::time::sleep(Duration::from_secs(60)).await;
+                        Ok(false)
+                    }
+                }
+            })
+        }
+    }
+
+    fn checker(resolver: StubResolver) -> MxChecker {
+        MxChecker::new(
+            Arc::new(resolver),
+            &MxCheckSettings {
+                enabled: true,
+                timeout_millis: 100,
+            },
+        )
+    }
+
+    #[tokio::test]
+    async fn domain_without_mx_records_is_rejected() {
+        let error = assert_err!(checker(StubResolver::HasMx(false)).check("gmial.con").await);
+        assert!(error.contains("gmial.con"));
+    }
+
+    #[tokio::test]
+    async fn domain_with_mx_records_is_accepted() {
+        assert_ok!(checker(StubResolver::HasMx(true)).check("gmail.com").await);
+    }
+
+    #[tokio::test]
+    async fn domain_is_accepted_when_resolver_fails_or_times_out() {
+        assert_ok!(checker(StubResolver::Fails).check("gmail.com").await);
+        assert_ok!(checker(StubResolver::Hangs).check("gmail.com").await);
+    }
+}
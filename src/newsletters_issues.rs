@@ -1,17 +1,41 @@
-use crate::configuration::Settings;
-use crate::email_client::EmailClient;
+use crate::configuration::{ApplicationSettings, Settings};
+use crate::content_store::{ContentStore, UncommittedContents};
+use crate::email_client::{suggested_retry_delay, EmailClient, SendRateLimiter};
 use crate::routes::{SubscriberEmail, SubscriptionStatus};
-use crate::startup::{build_email_client, get_pg_pool};
+use crate::startup::{build_email_client, WorkerPgPool};
+use crate::telemetry::redact_pii;
+use crate::utils::error_chain_fmt;
+use crate::worker_status::{try_record_worker_heartbeat, WorkerHeartbeat, WorkerName};
+use anyhow::Context;
 use sqlx::postgres::types::PgInterval;
-use sqlx::PgPool;
+use sqlx::postgres::{PgListener, PgPoolOptions};
+use sqlx::{PgExecutor, PgPool};
+use std::collections::HashSet;
+use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::Notify;
 
+// Postgres channel notified when issues become available to deliver
+// In-process `Notify` only reaches a worker running in the same process as the publisher
+pub const NEWSLETTERS_ISSUES_CHANNEL: &str = "newsletters_issues_available";
+
+// Notification sent within a transaction is only delivered after it's committed
+pub async fn notify_newsletters_issues_available<'c>(
+    executor: impl PgExecutor<'c>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("SELECT pg_notify($1, '')")
+        .bind(NEWSLETTERS_ISSUES_CHANNEL)
+        .execute(executor)
+        .await?;
+    Ok(())
+}
+
 pub struct NewslettersIssuesDeliveryWorker {
     settings: Settings,
     notify: Arc<Notify>,
-    pg_pool: Option<PgPool>,
+    pg_pool: WorkerPgPool,
+    send_rate_limiter: Option<Arc<SendRateLimiter>>,
 }
 
 impl NewslettersIssuesDeliveryWorker {
@@ -19,41 +43,356 @@ impl NewslettersIssuesDeliveryWorker {
         Self {
             settings,
             notify,
-            pg_pool: None,
+            pg_pool: WorkerPgPool::default(),
+            send_rate_limiter: None,
         }
     }
 
     pub fn set_pg_pool(mut self, pg_pool: PgPool) -> Self {
-        self.pg_pool = Some(pg_pool);
+        self.pg_pool.set_pg_pool(pg_pool);
+        self
+    }
+
+    pub fn set_pg_pool_max_connections(mut self, max_connections: u32) -> Self {
+        self.pg_pool.set_max_connections(max_connections);
+        self
+    }
+
+    // Share limiter with API and other workers sending emails in the same process
+    pub fn set_send_rate_limiter(
+        mut self,
+        send_rate_limiter: Option<Arc<SendRateLimiter>>,
+    ) -> Self {
+        self.send_rate_limiter = send_rate_limiter;
         self
     }
 
+    fn get_or_build_pg_pool(&self) -> PgPool {
+        self.pg_pool.get_or_build(&self.settings.database)
+    }
+
     pub async fn run_until_terminated(self) -> Result<(), anyhow::Error> {
-        let pg_pool = self
-            .pg_pool
-            .unwrap_or_else(|| get_pg_pool(&self.settings.database));
-        let email_client = build_email_client(self.settings.email_client.clone())?;
-        worker_loop(pg_pool, email_client, self.notify).await;
+        let pg_pool = self.get_or_build_pg_pool();
+        let email_client = build_email_client(
+            self.settings.email_client.clone(),
+            self.send_rate_limiter.clone(),
+        )?;
+        let content_store = ContentStore::from_settings(&self.settings.content_store);
+        let heartbeat_interval =
+            Duration::from_millis(self.settings.application.worker_heartbeat_interval_millis);
+        let poll_interval =
+            Duration::from_millis(self.settings.application.worker_poll_interval_millis);
+        let backoff = WorkerBackoff::from_settings(&self.settings.application);
+        let listener = listen_newsletters_issues_channel(&pg_pool, &backoff).await;
+        worker_loop(
+            pg_pool,
+            email_client,
+            content_store,
+            self.notify,
+            listener,
+            heartbeat_interval,
+            poll_interval,
+            backoff,
+        )
+        .await;
         Ok(())
     }
 }
 
-async fn worker_loop(pg_pool: PgPool, email_client: EmailClient, notify: Arc<Notify>) {
+// Worker still wakes up by in-process notification and poll interval when it can't listen
+// Listener holds its connection for worker's whole lifetime, so it connects with its own pool
+// instead of taking one of the worker pool's connections
+// Database may still be starting along with worker, so connecting is retried a few times first
+async fn listen_newsletters_issues_channel(
+    pg_pool: &PgPool,
+    backoff: &WorkerBackoff,
+) -> Option<PgListener> {
+    const MAX_LISTEN_RETRIES: u32 = 5;
+    let listener_pg_pool = PgPoolOptions::new()
+        .max_connections(1)
+        .connect_lazy_with(pg_pool.connect_options().clone());
+    let mut n_retries = 0;
     loop {
-        match try_execute_task(&pg_pool, &email_client).await {
-            Ok(ExecutionResult::EmptyQueue) => notify.notified().await,
+        let listener = async {
+            let mut listener = PgListener::connect_with(&listener_pg_pool).await?;
+            listener.listen(NEWSLETTERS_ISSUES_CHANNEL).await?;
+            Ok::<_, sqlx::Error>(listener)
+        };
+        match listener.await {
+            Ok(listener) => return Some(listener),
+            Err(e) if n_retries < MAX_LISTEN_RETRIES => {
+                let delay = backoff.delay(n_retries + 1);
+                tracing::warn!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to listen for newsletters issues notifications, retrying in {:?}",
+                    delay
+                );
+                tokio::time::sleep(delay).await;
+                n_retries += 1;
+            }
+            Err(e) => {
+                tracing::warn!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to listen for newsletters issues notifications after {} retries",
+                    MAX_LISTEN_RETRIES
+                );
+                return None;
+            }
+        }
+    }
+}
+
+// Wake up on whichever of in-process or Postgres notification comes first
+async fn wait_for_notification(notify: &Notify, listener: &mut Option<PgListener>) {
+    match listener {
+        Some(listener) => tokio::select! {
+            _ = notify.notified() => {}
+            result = listener.recv() => {
+                // Listener reconnects by itself, only failure to reconnect is returned
+                if let Err(e) = result {
+                    tracing::warn!(
+                        error.cause_chain = ?e,
+                        error.message = %e,
+                        "Failed to receive newsletters issues notification"
+                    );
+                }
+            }
+        },
+        None => notify.notified().await,
+    }
+}
+
+async fn worker_loop(
+    pg_pool: PgPool,
+    email_client: EmailClient,
+    content_store: ContentStore,
+    notify: Arc<Notify>,
+    mut listener: Option<PgListener>,
+    heartbeat_interval: Duration,
+    poll_interval: Duration,
+    backoff: WorkerBackoff,
+) {
+    let mut loop_backoff = WorkerLoopBackoff::new(backoff);
+    let mut heartbeat =
+        WorkerHeartbeat::new(WorkerName::NewslettersIssuesDelivery, heartbeat_interval);
+    loop {
+        let outcome = try_execute_task(&pg_pool, &email_client, &content_store).await;
+        // Throttling is the email service pacing us, not a failure of the worker
+        let (succeeded_count, failed_count) = match outcome {
+            Ok(ExecutionResult::TaskCompleted) => (1, 0),
+            Ok(ExecutionResult::EmptyQueue) | Err(ExecutionError::Throttled(_)) => (0, 0),
+            Err(ExecutionError::UnexpectedError(_)) => (0, 1),
+        };
+        heartbeat
+            .beat(&pg_pool, succeeded_count, failed_count)
+            .await;
+
+        match loop_backoff.next_delay(&outcome) {
             // Sleep for a while to improve future chances of success
-            // Reference: https://aws.amazon.com/blogs/architecture/exponential-backoff-and-jitter/
-            Err(_) => tokio::time::sleep(Duration::from_secs(1)).await,
-            Ok(ExecutionResult::TaskCompleted) => {}
+            Some(delay) => tokio::time::sleep(delay).await,
+            None if matches!(outcome, Ok(ExecutionResult::EmptyQueue)) => {
+                wait_for_notification_or_poll(
+                    &pg_pool,
+                    &notify,
+                    &mut listener,
+                    &mut heartbeat,
+                    heartbeat_interval,
+                    poll_interval,
+                )
+                .await
+            }
+            None => {}
+        }
+    }
+}
+
+// Heartbeat is kept alive while waiting, but queue is only re-checked when notified
+// Or after poll interval, to pick up issues whose notification was lost, e.g. publisher crashed after commit
+async fn wait_for_notification_or_poll(
+    pg_pool: &PgPool,
+    notify: &Notify,
+    listener: &mut Option<PgListener>,
+    heartbeat: &mut WorkerHeartbeat,
+    heartbeat_interval: Duration,
+    poll_interval: Duration,
+) {
+    let poll_at = tokio::time::Instant::now() + poll_interval;
+    loop {
+        let until_poll = poll_at.saturating_duration_since(tokio::time::Instant::now());
+        let notified = tokio::time::timeout(
+            heartbeat_interval.min(until_poll),
+            wait_for_notification(notify, listener),
+        )
+        .await
+        .is_ok();
+        if notified || tokio::time::Instant::now() >= poll_at {
+            return;
+        }
+        heartbeat.beat(pg_pool, 0, 0).await;
+    }
+}
+
+// Delay before retrying after consecutive failures of a worker
+// Reference: https://aws.amazon.com/blogs/architecture/exponential-backoff-and-jitter/
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct WorkerBackoff {
+    base: Duration,
+    max: Duration,
+}
+
+impl WorkerBackoff {
+    pub(crate) fn new(base: Duration, max: Duration) -> Self {
+        Self { base, max }
+    }
+
+    pub(crate) fn from_settings(settings: &ApplicationSettings) -> Self {
+        Self::new(
+            Duration::from_millis(settings.worker_backoff_base_millis),
+            Duration::from_millis(settings.worker_backoff_max_millis),
+        )
+    }
+
+    // `min(base * 2^(n - 1), max)` with equal jitter
+    pub(crate) fn delay(&self, n_consecutive_failures: u32) -> Duration {
+        let exponent = n_consecutive_failures.saturating_sub(1).min(16);
+        let delay = self.base.saturating_mul(2u32.pow(exponent)).min(self.max);
+        // Keep half of delay so retries don't hammer a failing dependency, randomize the rest
+        // to spread retries of multiple workers
+        let half_delay = delay / 2;
+        half_delay + half_delay.mul_f64(rand::random::<f64>())
+    }
+}
+
+// Decides how long delivery worker loop waits before its next run
+// Honor delay suggested by email service, otherwise back off exponentially with jitter
+// Throttling neither counts as a failure nor resets consecutive failures
+struct WorkerLoopBackoff {
+    backoff: WorkerBackoff,
+    n_consecutive_failures: u32,
+}
+
+impl WorkerLoopBackoff {
+    fn new(backoff: WorkerBackoff) -> Self {
+        Self {
+            backoff,
+            n_consecutive_failures: 0,
+        }
+    }
+
+    fn next_delay(
+        &mut self,
+        outcome: &Result<ExecutionResult, ExecutionError>,
+    ) -> Option<Duration> {
+        match outcome {
+            Ok(_) => {
+                self.n_consecutive_failures = 0;
+                None
+            }
+            Err(ExecutionError::Throttled(retry_after)) => Some(*retry_after),
+            Err(ExecutionError::UnexpectedError(_)) => {
+                self.n_consecutive_failures = self.n_consecutive_failures.saturating_add(1);
+                Some(self.backoff.delay(self.n_consecutive_failures))
+            }
         }
     }
 }
 
 pub struct NewslettersIssue {
     pub title: String,
-    pub text_content: String,
-    pub html_content: String,
+    pub text_content: Option<String>,
+    pub html_content: Option<String>,
+}
+
+impl NewslettersIssue {
+    // Issue can contain only text, only HTML or both, but can't be empty
+    pub fn parse(
+        title: String,
+        text_content: Option<String>,
+        html_content: Option<String>,
+    ) -> Result<Self, String> {
+        let text_content = text_content.filter(|content| !content.trim().is_empty());
+        let html_content = html_content.filter(|content| !content.trim().is_empty());
+        if text_content.is_none() && html_content.is_none() {
+            return Err("Newsletters issue must contain text or HTML content".into());
+        }
+        Ok(Self {
+            title,
+            text_content,
+            html_content,
+        })
+    }
+
+    // Substitute `{{name}}` and `{{email}}` placeholders with subscriber's details
+    // Values are escaped in HTML content, so subscriber's name can't inject markup
+    pub fn personalize(&self, subscriber_name: &str, subscriber_email: &str) -> Self {
+        let plain_values = [("name", subscriber_name), ("email", subscriber_email)];
+        let escaped_name = htmlescape::encode_minimal(subscriber_name);
+        let escaped_email = htmlescape::encode_minimal(subscriber_email);
+        let html_values = [
+            ("name", escaped_name.as_str()),
+            ("email", escaped_email.as_str()),
+        ];
+        Self {
+            title: render_placeholders(&self.title, &plain_values),
+            text_content: self
+                .text_content
+                .as_deref()
+                .map(|content| render_placeholders(content, &plain_values)),
+            html_content: self
+                .html_content
+                .as_deref()
+                .map(|content| render_placeholders(content, &html_values)),
+        }
+    }
+}
+
+// Unknown placeholders are left untouched, so content that uses `{{` for other purposes isn't mangled
+// Template is scanned once, so substituted values are never rendered again
+fn render_placeholders(template: &str, values: &[(&str, &str)]) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        rendered.push_str(&rest[..start]);
+        let after_open = &rest[start + 2..];
+        let end = match after_open.find("}}") {
+            Some(end) => end,
+            None => break,
+        };
+        let key = after_open[..end].trim();
+        match values.iter().find(|(name, _)| *name == key) {
+            Some((_, value)) => rendered.push_str(value),
+            None => rendered.push_str(&rest[start..start + 2 + end + 2]),
+        }
+        rest = &after_open[end + 2..];
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+// Comma separated subscription statuses that receive a newsletters issue, e.g. "pending,confirmed"
+// Only confirmed subscribers receive it when not provided
+pub fn parse_target_statuses(
+    target_statuses: Option<String>,
+) -> Result<Vec<SubscriptionStatus>, String> {
+    let target_statuses = match target_statuses {
+        Some(target_statuses) if !target_statuses.trim().is_empty() => target_statuses,
+        _ => return Ok(vec![SubscriptionStatus::Confirmed]),
+    };
+
+    let mut statuses: Vec<SubscriptionStatus> = vec![];
+    for status in target_statuses.split(',').map(str::trim) {
+        let status = SubscriptionStatus::from_str(status)
+            .map_err(|_| format!("Invalid subscription status: {}", status))?;
+        if status == SubscriptionStatus::Unsubscribed || status == SubscriptionStatus::Bounced {
+            return Err(format!("{} subscribers can't be targeted", status.as_ref()));
+        }
+        if !statuses.contains(&status) {
+            statuses.push(status);
+        }
+    }
+    Ok(statuses)
 }
 
 type PgTransaction = sqlx::Transaction<'static, sqlx::Postgres>;
@@ -63,6 +402,27 @@ pub enum ExecutionResult {
     TaskCompleted,
 }
 
+#[derive(thiserror::Error)]
+pub enum ExecutionError {
+    // Email service asks to slow down, rest of dequeued emails are left in queue
+    #[error("Email service is throttling sending, retry after {0:?}")]
+    Throttled(Duration),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ExecutionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl From<sqlx::Error> for ExecutionError {
+    fn from(e: sqlx::Error) -> Self {
+        Self::UnexpectedError(e.into())
+    }
+}
+
 #[tracing::instrument(
     name = "Execute newsletter issue task",
     skip_all,
@@ -73,15 +433,30 @@ pub enum ExecutionResult {
 pub async fn try_execute_task(
     pg_pool: &PgPool,
     email_client: &EmailClient,
-) -> anyhow::Result<ExecutionResult> {
-    let pending_newsletters_issues = get_available_newsletters_issues(pg_pool).await?;
+    content_store: &ContentStore,
+) -> Result<ExecutionResult, ExecutionError> {
+    // Pending copies are looked up on their own, so they're retried even after issue is completed
+    if let Some(archive_email) = email_client.archive_email() {
+        if let Err(e) =
+            try_send_pending_archive_copies(pg_pool, email_client, content_store, archive_email)
+                .await
+        {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to send archive copy of newsletter issue"
+            );
+        }
+    }
+    let pending_newsletters_issues =
+        get_available_newsletters_issues(pg_pool, content_store).await?;
     if pending_newsletters_issues.is_none() {
         return Ok(ExecutionResult::EmptyQueue);
     }
     let (newsletters_issue_id, issue_content) = pending_newsletters_issues.unwrap();
-    let (mut transaction, remaining_emails) =
+    let (mut transaction, remaining_tasks) =
         dequeue_tasks(pg_pool, &newsletters_issue_id, 50).await?;
-    if remaining_emails.is_empty() {
+    if remaining_tasks.is_empty() {
         return Ok(ExecutionResult::EmptyQueue);
     }
 
@@ -90,87 +465,307 @@ pub async fn try_execute_task(
         &tracing::field::display(newsletters_issue_id),
     );
 
+    // Tasks sent before by a run whose transaction failed to commit are only finished now
+    let sent_emails = get_sent_emails(&mut transaction, newsletters_issue_id, &remaining_tasks)
+        .await
+        .context("Failed to get already sent emails of dequeued tasks")?;
+
     let mut finished_emails = vec![];
-    for subscriber_email in remaining_emails {
-        if try_send_newsletter_issue_to_subscriber_email(
+    let mut failed_emails = vec![];
+    let mut failed_errors = vec![];
+    let mut n_invalid_emails = 0;
+    let mut throttled_for = None;
+    for DeliveryTask {
+        subscriber_email,
+        subscriber_name,
+    } in remaining_tasks
+    {
+        if sent_emails.contains(&subscriber_email) {
+            finished_emails.push(subscriber_email);
+            continue;
+        }
+        match try_send_newsletter_issue_to_subscriber_email(
             &subscriber_email,
+            subscriber_name.as_deref(),
             email_client,
             &issue_content,
         )
         .await
-        .is_ok()
         {
-            finished_emails.push(subscriber_email);
+            Ok(_) => {
+                // Recorded outside of transaction, so it's kept even if transaction isn't committed
+                // Failing to record only risks sending again, delivery is at least once anyway
+                if let Err(e) =
+                    record_sent_email(pg_pool, newsletters_issue_id, &subscriber_email).await
+                {
+                    tracing::warn!(
+                        error.cause_chain = ?e,
+                        error.message = %e,
+                        "Failed to record sent newsletter issue email"
+                    );
+                }
+                finished_emails.push(subscriber_email);
+            }
+            // Invalid email will never be delivered, so remove it from queue instead of retrying
+            Err(DeliveryError::InvalidRecipient(_)) => {
+                n_invalid_emails += 1;
+                finished_emails.push(subscriber_email);
+            }
+            Err(DeliveryError::SendFailed(e)) => {
+                // Throttled email is left in queue with the rest, it isn't a failed attempt
+                // Sending the rest now would be throttled as well
+                if let Some(retry_after) = suggested_retry_delay(&e) {
+                    throttled_for = Some(retry_after);
+                    break;
+                }
+                failed_emails.push(subscriber_email);
+                failed_errors.push(truncate_error(&e));
+            }
         }
     }
 
     const RETRY_INTERVAL: Duration = Duration::from_secs(1);
     const MAX_RETRIES: u32 = 5;
+    let mut transaction = Some(transaction);
     let mut n_retries = 0;
     loop {
-        match delete_tasks(&mut transaction, newsletters_issue_id, &finished_emails).await {
+        // Failed transaction is rolled back, so retry starts over in a new one
+        // Tasks aren't locked in between, but they're recorded as sent so no one sends them again
+        let transaction = match transaction.take() {
+            Some(transaction) => transaction,
+            None => pg_pool.begin().await?,
+        };
+        match finish_tasks(
+            transaction,
+            newsletters_issue_id,
+            &finished_emails,
+            n_invalid_emails,
+            &failed_emails,
+            &failed_errors,
+        )
+        .await
+        {
             Ok(_) => break,
             Err(e) => match e {
                 sqlx::Error::ColumnDecode { .. }
                 | sqlx::Error::ColumnNotFound(_)
-                | sqlx::Error::TypeNotFound { .. } => return Err(anyhow::anyhow!(e)),
+                | sqlx::Error::TypeNotFound { .. } => return Err(anyhow::anyhow!(e).into()),
                 // TODO: need to research more about Postgres error codes that can be retryable
                 // sqlx::Error::Database(e) if matches!(e.try_downcast_ref::<PgDatabaseError>(), Some(e) if ["57014", "58030"].contains(&e.code())) => {}
-                _ => {}
+                _ if n_retries < MAX_RETRIES => {
+                    tracing::warn!(
+                        error.cause_chain = ?e,
+                        error.message = %e,
+                        "Failed to finish newsletter issue tasks, retrying"
+                    );
+                }
+                _ => {
+                    return Err(anyhow::anyhow!(e)
+                        .context("Failed to finish newsletter issue tasks")
+                        .into())
+                }
             },
         }
         n_retries += 1;
-        if n_retries > MAX_RETRIES {
-            break;
-        }
         tokio::time::sleep(RETRY_INTERVAL).await;
     }
-    transaction.commit().await?;
 
-    let done_tasks_count: i32 = finished_emails.len() as i32;
-    update_newsletters_issue_status(pg_pool, &newsletters_issue_id, done_tasks_count).await?;
-    Ok(ExecutionResult::TaskCompleted)
+    update_newsletters_issue_status(pg_pool, &newsletters_issue_id).await?;
+    match throttled_for {
+        Some(retry_after) => Err(ExecutionError::Throttled(retry_after)),
+        None => Ok(ExecutionResult::TaskCompleted),
+    }
+}
+
+// One copy per issue instead of one per recipient
+// Oldest issues are archived first, sending stops at the first failure and resumes on next run
+#[tracing::instrument(name = "Send pending archive copies of newsletters issues", skip_all)]
+async fn try_send_pending_archive_copies(
+    pg_pool: &PgPool,
+    email_client: &EmailClient,
+    content_store: &ContentStore,
+    archive_email: &SubscriberEmail,
+) -> Result<(), anyhow::Error> {
+    while let Some(newsletters_issue_id) = claim_pending_archive_copy(pg_pool).await? {
+        if let Err(e) = try_send_archive_copy(
+            pg_pool,
+            email_client,
+            content_store,
+            archive_email,
+            newsletters_issue_id,
+        )
+        .await
+        {
+            // Copy is only retried when claim is released, a crash before this leaves it unsent
+            release_archive_copy_claim(pg_pool, newsletters_issue_id)
+                .await
+                .context("Failed to release claim of archive copy")?;
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+// Claim is committed before sending, so no row lock is held while talking to email service
+// Workers skip claimed issues instead of sending the same copy again
+async fn claim_pending_archive_copy(pg_pool: &PgPool) -> Result<Option<uuid::Uuid>, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE newsletters_issues
+        SET archived_at = now()
+        WHERE id = (
+            SELECT id
+            FROM newsletters_issues
+            WHERE archived_at IS NULL
+            ORDER BY published_at, id
+            LIMIT 1
+            FOR UPDATE SKIP LOCKED
+        )
+        RETURNING id
+        "#
+    )
+    .fetch_optional(pg_pool)
+    .await?;
+    Ok(result.map(|r| r.id))
+}
+
+async fn release_archive_copy_claim(
+    pg_pool: &PgPool,
+    newsletters_issue_id: uuid::Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE newsletters_issues
+        SET archived_at = NULL
+        WHERE id = $1
+        "#,
+        newsletters_issue_id
+    )
+    .execute(pg_pool)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Send archive copy of newsletters issue",
+    skip(pg_pool, email_client, content_store, archive_email)
+)]
+async fn try_send_archive_copy(
+    pg_pool: &PgPool,
+    email_client: &EmailClient,
+    content_store: &ContentStore,
+    archive_email: &SubscriberEmail,
+    newsletters_issue_id: uuid::Uuid,
+) -> Result<(), anyhow::Error> {
+    let r = sqlx::query!(
+        r#"
+        SELECT title, text_content, html_content, text_content_uri, html_content_uri
+        FROM newsletters_issues
+        WHERE id = $1
+        "#,
+        newsletters_issue_id
+    )
+    .fetch_one(pg_pool)
+    .await?;
+    let issue_content = NewslettersIssue {
+        title: r.title,
+        text_content: content_store
+            .get_optional(r.text_content, r.text_content_uri)
+            .await?,
+        html_content: content_store
+            .get_optional(r.html_content, r.html_content_uri)
+            .await?,
+    };
+
+    // Archive has no subscriber name, placeholders are filled as for a subscriber without one
+    let issue_content = issue_content.personalize("", archive_email.as_ref());
+    email_client
+        .send_newsletter_email(
+            archive_email,
+            &issue_content.title,
+            issue_content.text_content.as_deref(),
+            issue_content.html_content.as_deref(),
+        )
+        .await?;
+
+    Ok(())
+}
+
+// Outcome of sent tasks is only persisted when this commits
+async fn finish_tasks(
+    mut transaction: PgTransaction,
+    newsletters_issue_id: uuid::Uuid,
+    finished_emails: &Vec<String>,
+    n_invalid_emails: i32,
+    failed_emails: &Vec<String>,
+    failed_errors: &Vec<String>,
+) -> Result<(), sqlx::Error> {
+    delete_tasks(&mut transaction, newsletters_issue_id, finished_emails).await?;
+    record_invalid_recipients(&mut transaction, newsletters_issue_id, n_invalid_emails).await?;
+    record_failed_attempts(
+        &mut transaction,
+        newsletters_issue_id,
+        failed_emails,
+        failed_errors,
+    )
+    .await?;
+    transaction.commit().await
+}
+
+#[derive(thiserror::Error, Debug)]
+enum DeliveryError {
+    // Permanent failure, retrying won't help
+    #[error("{0}")]
+    InvalidRecipient(String),
+    // Transient failure, delivery is retried later
+    #[error(transparent)]
+    SendFailed(anyhow::Error),
 }
 
 #[tracing::instrument(
     name = "Send newsletter issue to subscriber's email",
-    skip(email_client, issue_content),
+    skip_all,
     fields(
-        subcriber_email = %subscriber_email,
+        subscriber_email = %redact_pii(subscriber_email),
     )
 )]
 async fn try_send_newsletter_issue_to_subscriber_email(
     subscriber_email: &str,
+    subscriber_name: Option<&str>,
     email_client: &EmailClient,
     issue_content: &NewslettersIssue,
-) -> Result<(), anyhow::Error> {
-    match SubscriberEmail::parse(subscriber_email.into()).map_err(|e| anyhow::anyhow!(e)) {
-        Ok(subscriber_email) => {
-            if let Err(e) = email_client
-                .send_multipart_email(
-                    &subscriber_email,
-                    &issue_content.title,
-                    &issue_content.text_content,
-                    &issue_content.html_content,
-                )
-                .await
-            {
-                tracing::error!(
-                    error.cause_chain = ?e,
-                    error.message = %e,
-                    "Failed to send newsletter issue email to subscriber"
-                );
-                return Err(e);
-            }
-        }
+) -> Result<(), DeliveryError> {
+    let subscriber_email = match SubscriberEmail::parse(subscriber_email.into()) {
+        Ok(subscriber_email) => subscriber_email,
         Err(e) => {
             tracing::error!(
-                error.cause_chain = ?e,
                 error.message = %e,
                 "Skip sending newsletter issue to invalid subscriber email"
             );
-            return Err(e);
+            return Err(DeliveryError::InvalidRecipient(e));
         }
+    };
+
+    // Subscription may have been deleted after the issue was enqueued, its name is blanked then
+    let issue_content = issue_content.personalize(
+        subscriber_name.unwrap_or_default(),
+        subscriber_email.as_ref(),
+    );
+    if let Err(e) = email_client
+        .send_newsletter_email(
+            &subscriber_email,
+            &issue_content.title,
+            issue_content.text_content.as_deref(),
+            issue_content.html_content.as_deref(),
+        )
+        .await
+    {
+        tracing::error!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            "Failed to send newsletter issue email to subscriber"
+        );
+        return Err(DeliveryError::SendFailed(e));
     }
 
     Ok(())
@@ -178,51 +773,90 @@ async fn try_send_newsletter_issue_to_subscriber_email(
 
 #[tracing::instrument(
     name = "Insert newsletters issue into database",
-    skip(newsletters, transaction)
+    skip(newsletters, transaction, content_store)
 )]
 pub async fn insert_newsletters_issue(
     transaction: &mut PgTransaction,
+    content_store: &ContentStore,
     newsletters_issue_id: uuid::Uuid,
     newsletters: NewslettersIssue,
-) -> Result<(), sqlx::Error> {
+    priority: i32,
+) -> Result<UncommittedContents, anyhow::Error> {
     let NewslettersIssue {
         title,
         text_content,
         html_content,
     } = newsletters;
+    // Contents are kept inline when content store is inline
+    // Otherwise only URIs of contents are stored in database
+    // Stored contents are removed if this or anything else fails before transaction is committed
+    let contents = content_store.track_uncommitted(&newsletters_issue_id.to_string());
+    let (text_content, text_content_uri) = content_store
+        .put_optional(&format!("{}/text.txt", newsletters_issue_id), text_content)
+        .await?;
+    let (html_content, html_content_uri) = content_store
+        .put_optional(&format!("{}/html.html", newsletters_issue_id), html_content)
+        .await?;
     sqlx::query!(
         r#"
-        INSERT INTO newsletters_issues (id, title, text_content, html_content, status, published_at, finished_n_tasks, required_n_tasks)
-        VALUES ($1, $2, $3, $4, $5, now(), 0, 0)
+        INSERT INTO newsletters_issues (
+            id,
+            title,
+            text_content,
+            html_content,
+            text_content_uri,
+            html_content_uri,
+            status,
+            published_at,
+            finished_n_tasks,
+            required_n_tasks,
+            priority
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, now(), 0, 0, $8)
         "#,
         newsletters_issue_id,
         title,
         text_content,
         html_content,
-        NewsletterIssueStatus::Available.as_ref()
+        text_content_uri,
+        html_content_uri,
+        NewsletterIssueStatus::Available.as_ref(),
+        priority
     )
     .execute(transaction)
     .await?;
 
-    Ok(())
+    Ok(contents)
 }
 
+// Subscribers are copied into queue within Postgres, so they're never loaded into memory at once,
+// worker then dequeues them in bounded batches
+// Subscribers stored before plus tags were split off are deduplicated by canonical email,
+// so one mailbox receives an issue only once
 #[tracing::instrument(
     name = "Enqueue delivery newsletters issue into database",
-    skip(newsletters_issue_id, transaction)
+    skip(newsletters_issue_id, transaction, target_statuses)
 )]
 pub async fn enqueue_task(
     transaction: &mut PgTransaction,
     newsletters_issue_id: uuid::Uuid,
+    target_statuses: &[SubscriptionStatus],
 ) -> Result<(), sqlx::Error> {
+    let target_statuses: Vec<String> = target_statuses
+        .iter()
+        .map(|status| status.as_ref().to_string())
+        .collect();
     sqlx::query!(
         r#"
         INSERT INTO newsletters_issues_delivery_queue (id, subscriber_email)
-        SELECT $1,
-        email FROM subscriptions WHERE status = $2
+        SELECT DISTINCT ON (regexp_replace(email, '^([^+@]+)\+[^@]+@', '\1@'))
+            $1, delivery_email
+        FROM subscriptions
+        WHERE status = ANY($2)
+        ORDER BY regexp_replace(email, '^([^+@]+)\+[^@]+@', '\1@'), subscribed_at
         "#,
         newsletters_issue_id,
-        SubscriptionStatus::Confirmed.as_ref()
+        &target_statuses
     )
     .execute(transaction)
     .await?;
@@ -272,24 +906,31 @@ pub async fn update_newsletters_issue_require_n_tasks(
     Ok(())
 }
 
+struct DeliveryTask {
+    subscriber_email: String,
+    subscriber_name: Option<String>,
+}
+
 #[tracing::instrument(name = "Dequeue delivery newsletters issue into database", skip_all)]
 async fn dequeue_tasks(
     pg_pool: &PgPool,
     newsletters_issue_id: &uuid::Uuid,
     batch_size: i64,
-) -> Result<(PgTransaction, Vec<String>), sqlx::Error> {
+) -> Result<(PgTransaction, Vec<DeliveryTask>), sqlx::Error> {
     let mut transaction = pg_pool.begin().await?;
     // Retrieve numbers of rows depending on service server supports sending batch data
     // And skip locking row that currently in process (SKIP LOCKED)
     // Lock this row if success to retrieve (FOR UPDATE)
+    // Only queue rows are locked, subscriptions are only read for personalization
     let result = sqlx::query!(
         r#"
-        SELECT subscriber_email
-        FROM newsletters_issues_delivery_queue
-        WHERE id = $1
-        FOR UPDATE
-        SKIP LOCKED
+        SELECT q.subscriber_email, s.name AS "subscriber_name?"
+        FROM newsletters_issues_delivery_queue q
+        LEFT JOIN subscriptions s ON s.delivery_email = q.subscriber_email
+        WHERE q.id = $1
         LIMIT $2
+        FOR UPDATE OF q
+        SKIP LOCKED
         "#,
         newsletters_issue_id,
         batch_size
@@ -297,7 +938,13 @@ async fn dequeue_tasks(
     .fetch_all(&mut transaction)
     .await?;
 
-    let result: Vec<_> = result.into_iter().map(|r| r.subscriber_email).collect();
+    let result: Vec<_> = result
+        .into_iter()
+        .map(|r| DeliveryTask {
+            subscriber_email: r.subscriber_email,
+            subscriber_name: r.subscriber_name,
+        })
+        .collect();
     Ok((transaction, result))
 }
 
@@ -318,52 +965,300 @@ async fn delete_tasks(
         newsletters_issue_id,
         subscriber_emails
     )
+    .execute(&mut *transaction)
+    .await?;
+    // Sent records are only needed while their tasks are in queue
+    sqlx::query!(
+        r#"
+        DELETE FROM newsletters_issues_sent_emails
+        WHERE id = $1 AND subscriber_email = ANY($2)
+        "#,
+        newsletters_issue_id,
+        subscriber_emails
+    )
     .execute(transaction)
     .await?;
 
     Ok(())
 }
 
+#[tracing::instrument(
+    name = "Get sent emails of delivery tasks from database",
+    skip(transaction, newsletters_issue_id, tasks)
+)]
+async fn get_sent_emails(
+    transaction: &mut PgTransaction,
+    newsletters_issue_id: uuid::Uuid,
+    tasks: &[DeliveryTask],
+) -> Result<HashSet<String>, sqlx::Error> {
+    let subscriber_emails: Vec<String> = tasks
+        .iter()
+        .map(|task| task.subscriber_email.clone())
+        .collect();
+    let result = sqlx::query!(
+        r#"
+        SELECT subscriber_email
+        FROM newsletters_issues_sent_emails
+        WHERE id = $1 AND subscriber_email = ANY($2)
+        "#,
+        newsletters_issue_id,
+        &subscriber_emails
+    )
+    .fetch_all(transaction)
+    .await?;
+
+    Ok(result.into_iter().map(|r| r.subscriber_email).collect())
+}
+
+#[tracing::instrument(
+    name = "Record sent email of newsletters issue into database",
+    skip(pg_pool, newsletters_issue_id, subscriber_email)
+)]
+async fn record_sent_email(
+    pg_pool: &PgPool,
+    newsletters_issue_id: uuid::Uuid,
+    subscriber_email: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletters_issues_sent_emails (id, subscriber_email)
+        VALUES ($1, $2)
+        ON CONFLICT DO NOTHING
+        "#,
+        newsletters_issue_id,
+        subscriber_email
+    )
+    .execute(pg_pool)
+    .await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Record invalid recipients of newsletters issue into database",
+    skip(transaction, newsletters_issue_id)
+)]
+async fn record_invalid_recipients(
+    transaction: &mut PgTransaction,
+    newsletters_issue_id: uuid::Uuid,
+    n_invalid_emails: i32,
+) -> Result<(), sqlx::Error> {
+    if n_invalid_emails == 0 {
+        return Ok(());
+    }
+    sqlx::query!(
+        r#"
+        UPDATE newsletters_issues
+        SET failed_n_tasks = failed_n_tasks + $1
+        WHERE id = $2
+        "#,
+        n_invalid_emails,
+        newsletters_issue_id
+    )
+    .execute(transaction)
+    .await?;
+
+    Ok(())
+}
+
+// Keep stored errors short, the full error is still in the logs
+const MAX_LAST_ERROR_LENGTH: usize = 500;
+
+fn truncate_error(error: &anyhow::Error) -> String {
+    format!("{:#}", error)
+        .chars()
+        .take(MAX_LAST_ERROR_LENGTH)
+        .collect()
+}
+
+#[tracing::instrument(
+    name = "Record failed delivery attempts into database",
+    skip(transaction, newsletters_issue_id, subscriber_emails, errors)
+)]
+async fn record_failed_attempts(
+    transaction: &mut PgTransaction,
+    newsletters_issue_id: uuid::Uuid,
+    subscriber_emails: &Vec<String>,
+    errors: &Vec<String>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE newsletters_issues_delivery_queue AS q
+        SET
+            n_attempts = q.n_attempts + 1,
+            last_attempted_at = now(),
+            last_error = failed.error
+        FROM UNNEST($2::text[], $3::text[]) AS failed(subscriber_email, error)
+        WHERE q.id = $1 AND q.subscriber_email = failed.subscriber_email
+        "#,
+        newsletters_issue_id,
+        subscriber_emails,
+        errors
+    )
+    .execute(transaction)
+    .await?;
+
+    Ok(())
+}
+
+#[derive(serde::Serialize, Debug)]
+pub struct DeliveryFailure {
+    pub subscriber_email: String,
+    pub n_attempts: i32,
+    pub last_attempted_at: Option<String>,
+    pub last_error: Option<String>,
+}
+
+#[tracing::instrument(name = "Get failed deliveries of newsletters issue", skip(pg_pool))]
+pub async fn get_delivery_failures(
+    pg_pool: &PgPool,
+    newsletters_issue_id: &uuid::Uuid,
+) -> Result<Vec<DeliveryFailure>, sqlx::Error> {
+    let records = sqlx::query!(
+        r#"
+        SELECT subscriber_email, n_attempts, last_attempted_at, last_error
+        FROM newsletters_issues_delivery_queue
+        WHERE id = $1 AND last_error IS NOT NULL
+        ORDER BY subscriber_email
+        "#,
+        newsletters_issue_id
+    )
+    .fetch_all(pg_pool)
+    .await?;
+
+    Ok(records
+        .into_iter()
+        .map(|r| DeliveryFailure {
+            subscriber_email: r.subscriber_email,
+            n_attempts: r.n_attempts,
+            last_attempted_at: r.last_attempted_at.map(|t| t.to_rfc3339()),
+            last_error: r.last_error,
+        })
+        .collect())
+}
+
+pub struct NewslettersIssueSummary {
+    pub id: uuid::Uuid,
+    pub title: String,
+    pub status: String,
+    pub published_at: String,
+    pub finished_n_tasks: i32,
+    pub required_n_tasks: i32,
+}
+
+#[tracing::instrument(name = "Get recent newsletters issues", skip(pg_pool))]
+pub async fn get_recent_newsletters_issues(
+    pg_pool: &PgPool,
+    limit: i64,
+) -> Result<Vec<NewslettersIssueSummary>, sqlx::Error> {
+    let records = sqlx::query!(
+        r#"
+        SELECT id, title, status, published_at, finished_n_tasks, required_n_tasks
+        FROM newsletters_issues
+        ORDER BY published_at DESC
+        LIMIT $1
+        "#,
+        limit
+    )
+    .fetch_all(pg_pool)
+    .await?;
+
+    Ok(records
+        .into_iter()
+        .map(|r| NewslettersIssueSummary {
+            id: r.id,
+            title: r.title,
+            status: r.status,
+            published_at: r.published_at.to_rfc3339(),
+            finished_n_tasks: r.finished_n_tasks,
+            required_n_tasks: r.required_n_tasks,
+        })
+        .collect())
+}
+
 pub struct DeleteExpiredIdempotencyWorker {
     settings: Settings,
-    pg_pool: Option<PgPool>,
+    pg_pool: WorkerPgPool,
 }
 
 impl DeleteExpiredIdempotencyWorker {
     pub fn builder(settings: Settings) -> Self {
         Self {
             settings,
-            pg_pool: None,
+            pg_pool: WorkerPgPool::default(),
         }
     }
 
     pub fn set_pg_pool(mut self, pg_pool: PgPool) -> Self {
-        self.pg_pool = Some(pg_pool);
+        self.pg_pool.set_pg_pool(pg_pool);
+        self
+    }
+
+    pub fn set_pg_pool_max_connections(mut self, max_connections: u32) -> Self {
+        self.pg_pool.set_max_connections(max_connections);
         self
     }
 
+    fn get_or_build_pg_pool(&self) -> PgPool {
+        self.pg_pool.get_or_build(&self.settings.database)
+    }
+
     pub async fn run_until_terminated(self) -> Result<(), std::io::Error> {
         let expiration_time_millis: Duration =
             Duration::from_millis(self.settings.application.idempotency_expiration_millis);
-        let pg_pool = self
-            .pg_pool
-            .unwrap_or_else(|| get_pg_pool(&self.settings.database));
-        remove_expired_idempotency_worker_loop(pg_pool, expiration_time_millis).await;
+        let sweep_interval =
+            Duration::from_millis(self.settings.application.idempotency_sweep_interval_millis);
+        let pg_pool = self.get_or_build_pg_pool();
+        remove_expired_idempotency_worker_loop(
+            pg_pool,
+            expiration_time_millis,
+            sweep_interval,
+            WorkerBackoff::from_settings(&self.settings.application),
+        )
+        .await;
         Ok(())
     }
 }
 
-async fn remove_expired_idempotency_worker_loop(pg_pool: PgPool, expired_time_millis: Duration) {
+async fn remove_expired_idempotency_worker_loop(
+    pg_pool: PgPool,
+    expired_time_millis: Duration,
+    sweep_interval: Duration,
+    backoff: WorkerBackoff,
+) {
+    let mut n_consecutive_failures = 0;
     loop {
         match delete_expired_idempotency_keys(&pg_pool, expired_time_millis).await {
-            Ok(_) => tokio::time::sleep(expired_time_millis).await,
+            Ok(_) => {
+                n_consecutive_failures = 0;
+                try_record_worker_heartbeat(
+                    &pg_pool,
+                    WorkerName::DeleteExpiredIdempotency,
+                    sweep_interval,
+                    1,
+                    0,
+                )
+                .await;
+                tokio::time::sleep(sweep_interval).await
+            }
             Err(e) => {
                 tracing::error!(
                     error.cause_chain = ?e,
                     error.message = %e,
                     "Failed to delete expired idempotency keys"
                 );
-                tokio::time::sleep(Duration::from_secs(1)).await;
+                n_consecutive_failures += 1;
+                let delay = backoff.delay(n_consecutive_failures);
+                // Worker is expected to be back after backoff delay
+                try_record_worker_heartbeat(
+                    &pg_pool,
+                    WorkerName::DeleteExpiredIdempotency,
+                    delay,
+                    0,
+                    1,
+                )
+                .await;
+                tokio::time::sleep(delay).await;
             }
         }
     }
@@ -390,7 +1285,7 @@ async fn delete_expired_idempotency_keys(
     Ok(())
 }
 
-#[derive(strum::AsRefStr)]
+#[derive(strum::AsRefStr, strum::EnumString, PartialEq, Eq, Debug)]
 pub enum NewsletterIssueStatus {
     #[strum(serialize = "AVAILABLE")]
     Available,
@@ -398,24 +1293,28 @@ pub enum NewsletterIssueStatus {
     Completed,
 }
 
+// Finished tasks are derived from remaining tasks in delivery queue instead of being accumulated
+// So updating status more than once for the same batch doesn't count finished tasks twice
 #[tracing::instrument(
     name = "Check and update newsletters issue status in database",
-    skip(pg_pool, newsletters_issue_id, done_tasks_count)
+    skip(pg_pool, newsletters_issue_id)
 )]
-async fn update_newsletters_issue_status(
+pub(crate) async fn update_newsletters_issue_status(
     pg_pool: &PgPool,
     newsletters_issue_id: &uuid::Uuid,
-    done_tasks_count: i32,
 ) -> Result<(), sqlx::Error> {
     let mut transaction = pg_pool.begin().await?;
 
     sqlx::query!(
         r#"
         UPDATE newsletters_issues
-        SET finished_n_tasks = finished_n_tasks + $1
-        WHERE id = $2 AND status = $3
+        SET finished_n_tasks = required_n_tasks - (
+            SELECT COUNT(*)::int
+            FROM newsletters_issues_delivery_queue
+            WHERE id = $1
+        )
+        WHERE id = $1 AND status = $2
         "#,
-        done_tasks_count,
         newsletters_issue_id,
         NewsletterIssueStatus::Available.as_ref(),
     )
@@ -426,10 +1325,10 @@ async fn update_newsletters_issue_status(
         r#"
         UPDATE newsletters_issues
         SET status = $1
-        WHERE 
+        WHERE
             id = $2 AND
             status = $3 AND
-            finished_n_tasks = required_n_tasks
+            finished_n_tasks >= required_n_tasks
         "#,
         NewsletterIssueStatus::Completed.as_ref(),
         newsletters_issue_id,
@@ -442,36 +1341,307 @@ async fn update_newsletters_issue_status(
     Ok(())
 }
 
+// Issue wrongly marked completed while its delivery queue still has tasks is made available again
+// Only completed issues are reopened, so issues in any other status are never resurrected
+// Return status of issue after reopening, `None` if issue doesn't exist
+#[tracing::instrument(name = "Reopen newsletters issue with pending tasks", skip(pg_pool))]
+pub async fn reopen_newsletters_issue_with_pending_tasks(
+    pg_pool: &PgPool,
+    newsletters_issue_id: &uuid::Uuid,
+) -> Result<Option<String>, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE newsletters_issues
+        SET status = $1
+        WHERE
+            id = $2 AND
+            status = $3 AND
+            EXISTS (
+                SELECT 1
+                FROM newsletters_issues_delivery_queue
+                WHERE id = $2
+            )
+        "#,
+        NewsletterIssueStatus::Available.as_ref(),
+        newsletters_issue_id,
+        NewsletterIssueStatus::Completed.as_ref(),
+    )
+    .execute(pg_pool)
+    .await?;
+    if result.rows_affected() > 0 {
+        tracing::warn!("Completed newsletters issue still had pending tasks, it's reopened");
+    }
+
+    let record = sqlx::query!(
+        r#"
+        SELECT status
+        FROM newsletters_issues
+        WHERE id = $1
+        "#,
+        newsletters_issue_id
+    )
+    .fetch_optional(pg_pool)
+    .await?;
+
+    Ok(record.map(|r| r.status))
+}
+
+// Issues are delivered by highest priority first, then oldest first among the same priority
+// An issue is drained before the next one is started, unless an issue with higher priority is published
+// So newer issues wait for older ones instead of every issue being delivered partially
 #[tracing::instrument(
     name = "Get unfinished newsletters issues from database",
-    skip(pg_pool)
+    skip(pg_pool, content_store)
 )]
 async fn get_available_newsletters_issues(
     pg_pool: &PgPool,
-) -> Result<Option<(uuid::Uuid, NewslettersIssue)>, sqlx::Error> {
+    content_store: &ContentStore,
+) -> Result<Option<(uuid::Uuid, NewslettersIssue)>, anyhow::Error> {
     let result = sqlx::query!(
         r#"
-        SELECT id, title, text_content, html_content 
+        SELECT id, title, text_content, html_content, text_content_uri, html_content_uri
         FROM newsletters_issues
         WHERE status = $1
+        ORDER BY priority DESC, published_at, id
+        LIMIT 1
         "#,
         NewsletterIssueStatus::Available.as_ref(),
     )
     .fetch_optional(pg_pool)
     .await?;
 
-    Ok(result.map(|r| {
-        (
-            r.id,
-            NewslettersIssue {
-                title: r.title,
-                text_content: r.text_content,
-                html_content: r.html_content,
-            },
-        )
-    }))
+    match result {
+        Some(r) => {
+            let text_content = content_store
+                .get_optional(r.text_content, r.text_content_uri)
+                .await?;
+            let html_content = content_store
+                .get_optional(r.html_content, r.html_content_uri)
+                .await?;
+            Ok(Some((
+                r.id,
+                NewslettersIssue {
+                    title: r.title,
+                    text_content,
+                    html_content,
+                },
+            )))
+        }
+        None => Ok(None),
+    }
 }
 
 // TODO: e.g. adding a n_retries and
 // execute_after columns to keep track of how many attempts have already taken place and how long
 // we should wait before trying again. Try implementing it as an exercise
+
+#[cfg(test)]
+mod tests {
+    use crate::configuration::Settings;
+    use crate::newsletters_issues::{
+        parse_target_statuses, DeleteExpiredIdempotencyWorker, ExecutionError, ExecutionResult,
+        NewslettersIssue, NewslettersIssuesDeliveryWorker, WorkerBackoff, WorkerLoopBackoff,
+    };
+    use crate::routes::SubscriptionStatus;
+    use claims::{assert_err, assert_ok};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::sync::Notify;
+
+    fn settings_with_worker_max_connections(worker_max_connections: u32) -> Settings {
+        let mut settings = Settings::get_configuration().expect("Failed to read configuration");
+        settings.database.max_connections = 10;
+        settings.database.worker_max_connections = worker_max_connections;
+        settings
+    }
+
+    #[tokio::test]
+    async fn delivery_worker_pool_uses_worker_max_connections_from_settings() {
+        let worker = NewslettersIssuesDeliveryWorker::builder(
+            settings_with_worker_max_connections(3),
+            Arc::new(Notify::new()),
+        );
+
+        let pg_pool = worker.get_or_build_pg_pool();
+
+        assert_eq!(pg_pool.options().get_max_connections(), 3);
+    }
+
+    #[tokio::test]
+    async fn delivery_worker_pool_honors_max_connections_override() {
+        let worker = NewslettersIssuesDeliveryWorker::builder(
+            settings_with_worker_max_connections(3),
+            Arc::new(Notify::new()),
+        )
+        .set_pg_pool_max_connections(1);
+
+        let pg_pool = worker.get_or_build_pg_pool();
+
+        assert_eq!(pg_pool.options().get_max_connections(), 1);
+    }
+
+    #[tokio::test]
+    async fn idempotency_worker_pool_honors_max_connections_override() {
+        let worker =
+            DeleteExpiredIdempotencyWorker::builder(settings_with_worker_max_connections(3))
+                .set_pg_pool_max_connections(2);
+
+        let pg_pool = worker.get_or_build_pg_pool();
+
+        assert_eq!(pg_pool.options().get_max_connections(), 2);
+    }
+
+    #[test]
+    fn missing_target_statuses_default_to_confirmed() {
+        let statuses = assert_ok!(parse_target_statuses(None));
+        assert_eq!(statuses, vec![SubscriptionStatus::Confirmed]);
+    }
+
+    #[test]
+    fn target_statuses_are_parsed_and_deduplicated() {
+        let statuses = assert_ok!(parse_target_statuses(Some(
+            "pending, confirmed,pending".to_string()
+        )));
+        assert_eq!(
+            statuses,
+            vec![SubscriptionStatus::Pending, SubscriptionStatus::Confirmed]
+        );
+    }
+
+    #[test]
+    fn unknown_target_status_is_rejected() {
+        assert_err!(parse_target_statuses(Some("everyone".to_string())));
+    }
+
+    #[test]
+    fn unsubscribed_target_status_is_rejected() {
+        assert_err!(parse_target_statuses(Some(
+            "confirmed,unsubscribed".to_string()
+        )));
+    }
+
+    #[test]
+    fn bounced_target_status_is_rejected() {
+        assert_err!(parse_target_statuses(Some("bounced".to_string())));
+    }
+
+    fn backoff(base_millis: u64, max_millis: u64) -> WorkerBackoff {
+        WorkerBackoff {
+            base: Duration::from_millis(base_millis),
+            max: Duration::from_millis(max_millis),
+        }
+    }
+
+    fn unexpected_error() -> Result<ExecutionResult, ExecutionError> {
+        Err(ExecutionError::UnexpectedError(anyhow::anyhow!(
+            "Database is down"
+        )))
+    }
+
+    fn assert_delay_within(delay: Option<Duration>, max_delay_secs: u64) {
+        let max_delay = Duration::from_secs(max_delay_secs);
+        let delay = delay.expect("Worker loop must wait after error");
+        assert!(delay >= max_delay / 2 && delay <= max_delay, "{:?}", delay);
+    }
+
+    #[test]
+    fn worker_loop_backs_off_exponentially_on_consecutive_errors() {
+        let mut loop_backoff = WorkerLoopBackoff::new(backoff(1000, 60000));
+
+        for max_delay_secs in [1, 2, 4, 8] {
+            assert_delay_within(loop_backoff.next_delay(&unexpected_error()), max_delay_secs);
+        }
+    }
+
+    #[test]
+    fn worker_loop_waits_suggested_delay_when_throttled_without_counting_failure() {
+        let mut loop_backoff = WorkerLoopBackoff::new(backoff(1000, 60000));
+        let throttled = Err(ExecutionError::Throttled(Duration::from_secs(7)));
+
+        assert_delay_within(loop_backoff.next_delay(&unexpected_error()), 1);
+        assert_eq!(
+            loop_backoff.next_delay(&throttled),
+            Some(Duration::from_secs(7))
+        );
+        assert_eq!(
+            loop_backoff.next_delay(&throttled),
+            Some(Duration::from_secs(7))
+        );
+
+        // Throttling neither grew nor reset the backoff
+        assert_delay_within(loop_backoff.next_delay(&unexpected_error()), 2);
+    }
+
+    #[test]
+    fn worker_loop_runs_again_without_delay_after_success() {
+        let mut loop_backoff = WorkerLoopBackoff::new(backoff(1000, 60000));
+        loop_backoff.next_delay(&unexpected_error());
+        loop_backoff.next_delay(&unexpected_error());
+
+        assert_eq!(
+            loop_backoff.next_delay(&Ok(ExecutionResult::TaskCompleted)),
+            None
+        );
+
+        // Backoff starts over after success
+        assert_delay_within(loop_backoff.next_delay(&unexpected_error()), 1);
+    }
+
+    #[test]
+    fn repeated_failures_increase_delay_up_to_configured_cap() {
+        let backoff = backoff(100, 1000);
+
+        let delays: Vec<u128> = (1..=20)
+            .map(|n_consecutive_failures| backoff.delay(n_consecutive_failures).as_millis())
+            .collect();
+
+        // Jitter keeps each delay within upper half of its cap, so delays grow while they double
+        for (delay, (min_millis, max_millis)) in
+            delays
+                .iter()
+                .zip([(50, 100), (100, 200), (200, 400), (400, 800)])
+        {
+            assert!(*delay >= min_millis && *delay <= max_millis, "{}", delay);
+        }
+        assert!(delays[..4].windows(2).all(|pair| pair[0] <= pair[1]));
+        // Then they stay capped however many times worker fails
+        for delay in &delays[4..] {
+            assert!(*delay >= 500 && *delay <= 1000, "{}", delay);
+        }
+        assert!(backoff.delay(u32::MAX).as_millis() <= 1000);
+    }
+
+    #[test]
+    fn placeholders_are_substituted_and_unknown_ones_are_left_untouched() {
+        let issue = NewslettersIssue {
+            title: "News for {{name}}".to_string(),
+            text_content: Some("Hi {{ name }} <{{email}}>, {{unknown}} {{".to_string()),
+            html_content: None,
+        };
+
+        let issue = issue.personalize("Ursula", "ursula@example.com");
+
+        assert_eq!(issue.title, "News for Ursula");
+        assert_eq!(
+            issue.text_content.as_deref(),
+            Some("Hi Ursula <ursula@example.com>, {{unknown}} {{")
+        );
+    }
+
+    #[test]
+    fn substituted_values_are_escaped_in_html_and_not_rendered_again() {
+        let issue = NewslettersIssue {
+            title: "Title".to_string(),
+            text_content: Some("Hi {{name}}".to_string()),
+            html_content: Some("<p>Hi {{name}}</p>".to_string()),
+        };
+
+        let issue = issue.personalize("<b>{{email}}</b>", "ursula@example.com");
+
+        assert_eq!(issue.text_content.as_deref(), Some("Hi <b>{{email}}</b>"));
+        assert_eq!(
+            issue.html_content.as_deref(),
+            Some("<p>Hi &lt;b&gt;{{email}}&lt;/b&gt;</p>")
+        );
+    }
+}
@@ -0,0 +1,93 @@
+use crate::configuration::Settings;
+use crate::newsletters_issues::NewsletterIssueStatus;
+use crate::startup::WorkerPgPool;
+use sqlx::PgPool;
+use std::time::Duration;
+
+// Periodically log a summary of queue health, so it can be followed in plain logs without metrics stack
+pub struct QueueMetricsLogger {
+    settings: Settings,
+    pg_pool: WorkerPgPool,
+}
+
+impl QueueMetricsLogger {
+    pub fn builder(settings: Settings) -> Self {
+        Self {
+            settings,
+            pg_pool: WorkerPgPool::default(),
+        }
+    }
+
+    pub fn set_pg_pool(mut self, pg_pool: PgPool) -> Self {
+        self.pg_pool.set_pg_pool(pg_pool);
+        self
+    }
+
+    pub fn set_pg_pool_max_connections(mut self, max_connections: u32) -> Self {
+        self.pg_pool.set_max_connections(max_connections);
+        self
+    }
+
+    fn get_or_build_pg_pool(&self) -> PgPool {
+        self.pg_pool.get_or_build(&self.settings.database)
+    }
+
+    pub async fn run_until_terminated(self) -> Result<(), std::io::Error> {
+        let interval =
+            Duration::from_millis(self.settings.application.queue_metrics_interval_millis);
+        let pg_pool = self.get_or_build_pg_pool();
+        loop {
+            // Failing to summarize only skips one summary, it's retried on next interval
+            match get_queue_health_summary(&pg_pool).await {
+                Ok(summary) => tracing::info!(
+                    available_newsletters_issues = summary.available_newsletters_issues,
+                    pending_delivery_tasks = summary.pending_delivery_tasks,
+                    oldest_available_issue_age_secs = summary.oldest_available_issue_age_secs,
+                    idempotency_records = summary.idempotency_records,
+                    "Queue health summary"
+                ),
+                Err(e) => tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to get queue health summary"
+                ),
+            }
+            tokio::time::sleep(interval).await;
+        }
+    }
+}
+
+pub struct QueueHealthSummary {
+    pub available_newsletters_issues: i64,
+    pub pending_delivery_tasks: i64,
+    // 0 when there is no available issue
+    pub oldest_available_issue_age_secs: f64,
+    pub idempotency_records: i64,
+}
+
+#[tracing::instrument(name = "Get queue health summary from database", skip_all)]
+pub async fn get_queue_health_summary(pg_pool: &PgPool) -> Result<QueueHealthSummary, sqlx::Error> {
+    let record = sqlx::query!(
+        r#"
+        SELECT
+            (SELECT COUNT(*) FROM newsletters_issues WHERE status = $1) AS "available_newsletters_issues!",
+            (SELECT COUNT(*) FROM newsletters_issues_delivery_queue) AS "pending_delivery_tasks!",
+            (
+                SELECT EXTRACT(EPOCH FROM now() - MIN(published_at))::float8
+                FROM newsletters_issues
+                WHERE status = $1
+            ) AS oldest_available_issue_age_secs,
+            (SELECT COUNT(*) FROM idempotency) AS "idempotency_records!"
+        "#,
+        NewsletterIssueStatus::Available.as_ref()
+    )
+    .fetch_one(pg_pool)
+    .await?;
+
+    Ok(QueueHealthSummary {
+        available_newsletters_issues: record.available_newsletters_issues,
+        pending_delivery_tasks: record.pending_delivery_tasks,
+        oldest_available_issue_age_secs: record.oldest_available_issue_age_secs.unwrap_or(0.0),
+        idempotency_records: record.idempotency_records,
+    })
+}
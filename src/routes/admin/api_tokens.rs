@@ -0,0 +1,45 @@
+use crate::authentication::{create_api_token, revoke_api_token, UserId};
+use crate::utils::e500;
+use actix_web::{web, HttpResponse};
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Serialize)]
+struct ApiTokenResponse<'a> {
+    token_id: Uuid,
+    // Only returned once when token is created
+    token: &'a str,
+}
+
+#[tracing::instrument(name = "Mint an API token", skip_all, fields(user_id = %*user_id))]
+pub async fn mint_api_token(
+    user_id: web::ReqData<UserId>,
+    pg_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (token_id, token) = create_api_token(&pg_pool, &user_id).await.map_err(e500)?;
+
+    Ok(HttpResponse::Created().json(ApiTokenResponse {
+        token_id,
+        token: token.expose_secret(),
+    }))
+}
+
+#[tracing::instrument(
+    name = "Delete an API token",
+    skip(user_id, pg_pool),
+    fields(user_id = %*user_id)
+)]
+pub async fn delete_api_token(
+    user_id: web::ReqData<UserId>,
+    token_id: web::Path<Uuid>,
+    pg_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    match revoke_api_token(&pg_pool, &user_id, &token_id)
+        .await
+        .map_err(e500)?
+    {
+        true => Ok(HttpResponse::NoContent().finish()),
+        false => Ok(HttpResponse::NotFound().finish()),
+    }
+}
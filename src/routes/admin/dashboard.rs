@@ -1,21 +1,38 @@
 use crate::authentication::UserId;
-use crate::utils::{e500, get_username_from_database};
-use actix_web::http::header::ContentType;
+use crate::middleware::BasePath;
+use crate::routes::admin::get_user_email;
+use crate::utils::{e500, get_username_from_database, html_response};
 use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
 use sqlx::PgPool;
+use std::fmt::Write;
 
 pub async fn admin_dashboard(
     user_id: web::ReqData<UserId>,
     pg_pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    base_path: web::Data<BasePath>,
 ) -> Result<HttpResponse, actix_web::Error> {
-    let username = get_username_from_database(&pg_pool, &user_id.into_inner())
+    let user_id = user_id.into_inner();
+    let username = get_username_from_database(&pg_pool, &user_id)
         .await
         .map_err(e500)?;
+    let email = get_user_email(&pg_pool, &user_id)
+        .await
+        .map_err(e500)?
+        .map(|email| htmlescape::encode_attribute(email.as_ref()))
+        .unwrap_or_default();
+    let mut msg_html = "".to_string();
+    for msg in flash_messages.iter() {
+        let _ = writeln!(
+            msg_html,
+            "<p><i>{}</i></p>",
+            htmlescape::encode_minimal(msg.content())
+        );
+    }
 
-    Ok(HttpResponse::Ok()
-        .content_type(ContentType::html())
-        .body(format!(
-            r#"
+    Ok(html_response(format!(
+        r#"
 <!DOCTYPE html>
 <html lang="en">
 <head>
@@ -23,16 +40,39 @@ pub async fn admin_dashboard(
     <title>Dashboard</title>
 </head>
 <body>
+{}
 <p>Hello {}</p>
 <br>
-<a href="/admin/newsletters">Publish Newsletter</a>
+<a href="{publish_href}">Publish Newsletter</a>
+<br>
+<a href="{history_href}">Newsletters History</a>
+<br>
+<a href="{password_href}">Change Password</a>
+<br>
+<a href="{two_factor_href}">Two-factor Authentication</a>
 <br>
-<a href="/admin/password">Change Password</a>
+<a href="{logout_href}">Logout</a>
 <br>
-<a href="/admin/logout">Logout</a>
+<form action="{email_href}" method="post">
+    <label>Your email:
+        <input type="email" placeholder="Enter your email" name="email" value="{email}">
+    </label>
+    <button type="submit">Save</button>
+</form>
+<form action="{test_email_href}" method="post">
+    <button type="submit">Send test email to your email</button>
+</form>
 </body>
 </html>
            "#,
-            username
-        )))
+        msg_html,
+        username,
+        publish_href = base_path.href("/admin/newsletters"),
+        history_href = base_path.href("/admin/newsletters/history"),
+        password_href = base_path.href("/admin/password"),
+        two_factor_href = base_path.href("/admin/2fa"),
+        logout_href = base_path.href("/admin/logout"),
+        email_href = base_path.href("/admin/email"),
+        test_email_href = base_path.href("/admin/test-email"),
+    )))
 }
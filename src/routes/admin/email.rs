@@ -0,0 +1,82 @@
+use crate::authentication::UserId;
+use crate::routes::SubscriberEmail;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct UserEmailForm {
+    email: String,
+}
+
+#[tracing::instrument(name = "Update email of admin user", skip_all, fields(user_id = %*user_id))]
+pub async fn update_user_email(
+    user_id: web::ReqData<UserId>,
+    web::Form(UserEmailForm { email }): web::Form<UserEmailForm>,
+    pg_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = match SubscriberEmail::parse(email) {
+        Ok(email) => email,
+        Err(e) => {
+            FlashMessage::error(format!("Invalid email: {}", e)).send();
+            return Ok(see_other("/admin/dashboard"));
+        }
+    };
+
+    update_user_email_to_database(&pg_pool, &user_id, &email)
+        .await
+        .context("Failed to update user email in database")
+        .map_err(e500)?;
+
+    FlashMessage::success("Your email has been updated").send();
+    Ok(see_other("/admin/dashboard"))
+}
+
+#[tracing::instrument(name = "Get user's email from database", skip(pg_pool))]
+pub async fn get_user_email(
+    pg_pool: &PgPool,
+    user_id: &Uuid,
+) -> Result<Option<SubscriberEmail>, anyhow::Error> {
+    let result = sqlx::query!(
+        r#"
+        SELECT email
+        FROM users
+        WHERE user_id = $1
+        "#,
+        user_id
+    )
+    .fetch_one(pg_pool)
+    .await
+    .context("Failed to fetch user email from database")?;
+
+    // Only validated emails are stored
+    result
+        .email
+        .map(SubscriberEmail::parse)
+        .transpose()
+        .map_err(anyhow::Error::msg)
+}
+
+#[tracing::instrument(name = "Update user's email to database", skip(pg_pool, email))]
+async fn update_user_email_to_database(
+    pg_pool: &PgPool,
+    user_id: &Uuid,
+    email: &SubscriberEmail,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET email = $2
+        WHERE user_id = $1
+        "#,
+        user_id,
+        email.as_ref()
+    )
+    .execute(pg_pool)
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,64 @@
+use crate::authentication::UserId;
+use crate::idempotency::{delete_user_idempotency_record, get_user_idempotency_records};
+use crate::utils::e500;
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+
+#[derive(serde::Serialize)]
+struct IdempotencyKeysResponse {
+    keys: Vec<IdempotencyKeyResponse>,
+}
+
+// Stored responses are never returned, they may carry data of the request that produced them
+#[derive(serde::Serialize)]
+struct IdempotencyKeyResponse {
+    idempotency_key: String,
+    request_method: Option<String>,
+    request_path: Option<String>,
+    // `null` for requests that are in progress, or never completed because server crashed
+    response_status_code: Option<i16>,
+    created_at: String,
+}
+
+#[tracing::instrument(name = "List idempotency keys", skip_all, fields(user_id = %*user_id))]
+pub async fn list_idempotency_keys(
+    user_id: web::ReqData<UserId>,
+    pg_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let records = get_user_idempotency_records(&pg_pool, &user_id)
+        .await
+        .map_err(e500)?;
+
+    Ok(HttpResponse::Ok().json(IdempotencyKeysResponse {
+        keys: records
+            .into_iter()
+            .map(|r| IdempotencyKeyResponse {
+                idempotency_key: r.idempotency_key,
+                request_method: r.request_method,
+                request_path: r.request_path,
+                response_status_code: r.response_status_code,
+                created_at: r.created_at.to_rfc3339(),
+            })
+            .collect(),
+    }))
+}
+
+// Only keys of logged in user can be cleared, so the key is free to be reused right away
+#[tracing::instrument(
+    name = "Clear an idempotency key",
+    skip(user_id, pg_pool),
+    fields(user_id = %*user_id)
+)]
+pub async fn clear_idempotency_key(
+    user_id: web::ReqData<UserId>,
+    idempotency_key: web::Path<String>,
+    pg_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    match delete_user_idempotency_record(&pg_pool, &user_id, &idempotency_key)
+        .await
+        .map_err(e500)?
+    {
+        true => Ok(HttpResponse::NoContent().finish()),
+        false => Ok(HttpResponse::NotFound().finish()),
+    }
+}
@@ -1,11 +1,25 @@
 use crate::authentication::UserSession;
-use crate::utils::{e500, see_other};
-use actix_web::HttpResponse;
+use crate::middleware::RequestId;
+use crate::utils::{e500, record_audit, see_other, AuditAction};
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
 
-pub async fn logout(session: UserSession) -> Result<HttpResponse, actix_web::Error> {
-    if session.get_user_id().map_err(e500)?.is_some() {
+pub async fn logout(
+    request: HttpRequest,
+    session: UserSession,
+    pg_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if let Some(user_id) = session.get_user_id().map_err(e500)? {
         session.logout();
+        record_audit(
+            &pg_pool,
+            &user_id,
+            AuditAction::Logout,
+            None,
+            request.extensions().get::<RequestId>(),
+        )
+        .await;
         FlashMessage::info("You have been logged out").send();
     }
     Ok(see_other("/login"))
@@ -0,0 +1,41 @@
+use crate::authentication::UserId;
+use crate::middleware::{MaintenanceMode, RequestId};
+use crate::utils::{record_audit, AuditAction};
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize, serde::Serialize)]
+pub struct MaintenanceState {
+    enabled: bool,
+}
+
+pub async fn get_maintenance_mode(maintenance: web::Data<MaintenanceMode>) -> HttpResponse {
+    HttpResponse::Ok().json(MaintenanceState {
+        enabled: maintenance.is_enabled(),
+    })
+}
+
+#[tracing::instrument(
+    name = "Set maintenance mode",
+    skip(request, maintenance, pg_pool, user_id),
+    fields(enabled = %state.enabled)
+)]
+pub async fn set_maintenance_mode(
+    request: HttpRequest,
+    web::Json(state): web::Json<MaintenanceState>,
+    maintenance: web::Data<MaintenanceMode>,
+    pg_pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> HttpResponse {
+    maintenance.set_enabled(state.enabled);
+
+    record_audit(
+        &pg_pool,
+        &user_id.into_inner(),
+        AuditAction::SetMaintenanceMode,
+        Some(if state.enabled { "enabled" } else { "disabled" }),
+        request.extensions().get::<RequestId>(),
+    )
+    .await;
+    HttpResponse::Ok().json(state)
+}
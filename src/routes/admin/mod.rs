@@ -1,9 +1,25 @@
+mod api_tokens;
 mod dashboard;
+mod email;
+mod idempotency;
 mod logout;
+mod maintenance;
 mod newsletters;
 mod password;
+mod subscribers;
+mod test_email;
+mod two_factor;
+mod workers;
 
+pub use api_tokens::*;
 pub use dashboard::*;
+pub use email::*;
+pub use idempotency::*;
 pub use logout::*;
+pub use maintenance::*;
 pub use newsletters::*;
 pub use password::*;
+pub use subscribers::*;
+pub use test_email::*;
+pub use two_factor::*;
+pub use workers::*;
@@ -0,0 +1,78 @@
+use crate::middleware::BasePath;
+use crate::newsletters_issues::{get_delivery_failures, DeliveryFailure};
+use crate::utils::{e500, html_response};
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+#[derive(serde::Serialize)]
+struct DeliveryFailuresResponse {
+    failures: Vec<DeliveryFailure>,
+}
+
+// Recipients still waiting in delivery queue after at least one failed attempt
+#[tracing::instrument(name = "Get newsletters issue delivery failures", skip(pg_pool))]
+pub async fn newsletters_issue_failures(
+    newsletters_issue_id: web::Path<Uuid>,
+    pg_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let failures = get_delivery_failures(&pg_pool, &newsletters_issue_id)
+        .await
+        .map_err(e500)?;
+    Ok(HttpResponse::Ok().json(DeliveryFailuresResponse { failures }))
+}
+
+// Same failures as above for admins browsing from newsletters history
+#[tracing::instrument(
+    name = "Show newsletters issue delivery failures page",
+    skip(pg_pool, base_path)
+)]
+pub async fn newsletters_issue_failures_page(
+    newsletters_issue_id: web::Path<Uuid>,
+    pg_pool: web::Data<PgPool>,
+    base_path: web::Data<BasePath>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let failures = get_delivery_failures(&pg_pool, &newsletters_issue_id)
+        .await
+        .map_err(e500)?;
+
+    let mut rows_html = "".to_string();
+    for failure in failures {
+        let _ = writeln!(
+            rows_html,
+            r#"        <tr>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+        </tr>"#,
+            htmlescape::encode_minimal(&failure.subscriber_email),
+            failure.n_attempts,
+            failure.last_attempted_at.unwrap_or_default(),
+            htmlescape::encode_minimal(&failure.last_error.unwrap_or_default()),
+        );
+    }
+
+    let history_href = base_path.href("/admin/newsletters/history");
+    Ok(html_response(format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Delivery Failures</title>
+</head>
+<body>
+    <table>
+        <tr>
+            <th>Subscriber email</th>
+            <th>Attempts</th>
+            <th>Last attempted at</th>
+            <th>Last error</th>
+        </tr>
+{rows_html}    </table>
+    <p><a href="{history_href}">&lt;- Back</a></p>
+</body>
+</html>"#,
+    )))
+}
@@ -1,21 +1,24 @@
-use actix_web::http::header::ContentType;
-use actix_web::HttpResponse;
+use crate::middleware::BasePath;
+use crate::utils::html_response;
+use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::IncomingFlashMessages;
 use std::fmt::Write;
 use uuid::Uuid;
 
 pub async fn get_newsletters_form(
     flash_messages: IncomingFlashMessages,
+    base_path: web::Data<BasePath>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let mut msg_html = "".to_string();
     for msg in flash_messages.iter() {
         let _ = writeln!(msg_html, "<p><i>{}</i></p>", msg.content());
     }
-    let idempotency_key = Uuid::new_v4().to_string();
-    Ok(HttpResponse::Ok()
-        .content_type(ContentType::html())
-        .body(format!(
-            r#"<!DOCTYPE html>
+    // Fresh nonce per render, so resubmitting the same form is deduped but a new form is not
+    let form_nonce = Uuid::new_v4().to_string();
+    let publish_href = base_path.href("/admin/newsletters");
+    let dashboard_href = base_path.href("/admin/dashboard");
+    Ok(html_response(format!(
+        r#"<!DOCTYPE html>
 <html lang="en">
 <head>
     <meta http-equiv="content-type" content="text/html; charset=utf-8">
@@ -23,7 +26,7 @@ pub async fn get_newsletters_form(
 </head>
 <body>
     {msg_html}
-    <form action="/admin/newsletters" method="post">
+    <form action="{publish_href}" method="post">
         <label>Title:<br>
             <input
                 type="text"
@@ -50,11 +53,20 @@ pub async fn get_newsletters_form(
             ></textarea>
         </label>
         <br>
-        <input hidden type="text" name="idempotency_key" value="{idempotency_key}">
+        <label>Priority, higher is delivered first:<br>
+            <input type="number" name="priority" value="0">
+        </label>
+        <br>
+        <label>
+            <input type="checkbox" name="confirm_large_send" value="true">
+            Confirm sending to more subscribers than the configured limit
+        </label>
+        <br>
+        <input hidden type="text" name="form_nonce" value="{form_nonce}">
         <button type="submit">Publish</button>
     </form>
-    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+    <p><a href="{dashboard_href}">&lt;- Back</a></p>
 </body>
 </html>"#,
-        )))
+    )))
 }
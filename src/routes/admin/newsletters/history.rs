@@ -0,0 +1,69 @@
+use crate::middleware::BasePath;
+use crate::newsletters_issues::get_recent_newsletters_issues;
+use crate::utils::{e500, html_response};
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+use std::fmt::Write;
+
+const DEFAULT_HISTORY_LIMIT: i64 = 20;
+const MAX_HISTORY_LIMIT: i64 = 100;
+
+#[derive(serde::Deserialize)]
+pub struct HistoryQuery {
+    limit: Option<i64>,
+}
+
+#[tracing::instrument(name = "Get newsletters issues history", skip(pg_pool, base_path))]
+pub async fn newsletters_history(
+    web::Query(HistoryQuery { limit }): web::Query<HistoryQuery>,
+    pg_pool: web::Data<PgPool>,
+    base_path: web::Data<BasePath>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let limit = limit
+        .unwrap_or(DEFAULT_HISTORY_LIMIT)
+        .clamp(1, MAX_HISTORY_LIMIT);
+    let issues = get_recent_newsletters_issues(&pg_pool, limit)
+        .await
+        .map_err(e500)?;
+
+    let mut rows_html = "".to_string();
+    for issue in issues {
+        let _ = writeln!(
+            rows_html,
+            r#"        <tr>
+            <td><a href="{}">{}</a></td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}/{}</td>
+        </tr>"#,
+            base_path.href(&format!("/admin/newsletters/{}", issue.id)),
+            htmlescape::encode_minimal(&issue.title),
+            issue.status,
+            issue.published_at,
+            issue.finished_n_tasks,
+            issue.required_n_tasks,
+        );
+    }
+
+    let dashboard_href = base_path.href("/admin/dashboard");
+    Ok(html_response(format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Newsletters History</title>
+</head>
+<body>
+    <table>
+        <tr>
+            <th>Title</th>
+            <th>Status</th>
+            <th>Published at</th>
+            <th>Delivered</th>
+        </tr>
+{rows_html}    </table>
+    <p><a href="{dashboard_href}">&lt;- Back</a></p>
+</body>
+</html>"#,
+    )))
+}
@@ -1,5 +1,11 @@
+mod failures;
 mod get;
+mod history;
 mod post;
+mod redrive;
 
+pub use failures::*;
 pub use get::*;
+pub use history::*;
 pub use post::*;
+pub use redrive::*;
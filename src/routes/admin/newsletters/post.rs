@@ -1,25 +1,53 @@
-use crate::authentication::UserId;
+use crate::authentication::{is_api_token_request, UserId};
+use crate::content_store::ContentStore;
+use crate::html_sanitizer::HtmlSanitizer;
 use crate::idempotency::{
+    derive_form_idempotency_key, get_idempotency_key,
     try_insert_idempotency_response_record_into_database, update_idempotency_response_record,
-    ProcessState,
+    IdempotencyMaxStoredBodySize, IdempotencyReplayMaxAge, IdempotentRequest, ProcessState,
+    IDEMPOTENCY_KEY_HEADER,
 };
+use crate::middleware::RequestId;
 use crate::newsletters_issues::{
     enqueue_task, get_tasks_count_in_queue, insert_newsletters_issue,
+    notify_newsletters_issues_available, parse_target_statuses,
     update_newsletters_issue_require_n_tasks, NewslettersIssue,
 };
-use crate::utils::{e400, e500, see_other};
-use actix_web::{web, HttpResponse};
+use crate::utils::{e400, e500, e503_if_pool_exhausted, record_audit, see_other, AuditAction};
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
 use anyhow::Context;
 use sqlx::PgPool;
 use tokio::sync::Notify;
 
+pub struct MaxNewslettersHtmlSize(pub usize);
+
+// Safety valve against misconfigured target statuses blasting the whole list
+pub struct MaxRecipientsPerIssue(pub u64);
+
+#[derive(serde::Serialize)]
+struct LargeSendNotConfirmedResponse {
+    error: String,
+    n_recipients: u64,
+    max_recipients: u64,
+}
+
 #[derive(serde::Deserialize)]
 pub struct NewsletterForm {
     title: String,
-    text_content: String,
-    html_content: String,
-    idempotency_key: String,
+    text_content: Option<String>,
+    html_content: Option<String>,
+    // Optional when `Idempotency-Key` header is provided
+    // Browser forms may omit both, then key is derived from `form_nonce` and content
+    idempotency_key: Option<String>,
+    // Random value rendered in newsletters form
+    form_nonce: Option<String>,
+    // Comma separated subscription statuses, only confirmed subscribers by default
+    target_statuses: Option<String>,
+    // Required to publish issue to more recipients than `MaxRecipientsPerIssue`
+    confirm_large_send: Option<bool>,
+    // Issues with higher priority are delivered first, 0 by default
+    priority: Option<i32>,
 }
 
 #[tracing::instrument(
@@ -31,46 +59,95 @@ pub struct NewsletterForm {
     )
 )]
 pub async fn publish_newsletters(
+    request: HttpRequest,
     web::Form(NewsletterForm {
         title,
         text_content,
         html_content,
         idempotency_key,
+        form_nonce,
+        target_statuses,
+        confirm_large_send,
+        priority,
     }): web::Form<NewsletterForm>,
     pg_pool: web::Data<PgPool>,
     user_id: web::ReqData<UserId>,
     notify: web::Data<Notify>,
+    content_store: web::Data<ContentStore>,
+    html_sanitizer: web::Data<HtmlSanitizer>,
+    max_html_size: web::Data<MaxNewslettersHtmlSize>,
+    max_recipients: web::Data<MaxRecipientsPerIssue>,
+    idempotency_replay_max_age: web::Data<IdempotencyReplayMaxAge>,
+    idempotency_max_stored_body_size: web::Data<IdempotencyMaxStoredBodySize>,
 ) -> Result<HttpResponse, actix_web::Error> {
-    let idempotency_key = idempotency_key.try_into().map_err(e400)?;
+    // Scripts must still provide an explicit key
+    let idempotency_key = if idempotency_key.is_none()
+        && !request.headers().contains_key(IDEMPOTENCY_KEY_HEADER)
+        && !is_api_token_request(request.headers())
+    {
+        derive_form_idempotency_key(&[
+            form_nonce.as_deref(),
+            Some(&title),
+            text_content.as_deref(),
+            html_content.as_deref(),
+            target_statuses.as_deref(),
+            priority.map(|priority| priority.to_string()).as_deref(),
+        ])
+    } else {
+        get_idempotency_key(request.headers(), idempotency_key)
+            .map_err(|e| e400("Invalid idempotency key", e))?
+    };
+    // Sanitize before parsing, so HTML that is empty after sanitized is treated as missing
+    let html_content = html_content.map(|html| html_sanitizer.clean(&html));
+    // Size is checked on sanitized HTML, which is what subscribers receive
+    if let Some(html) = &html_content {
+        if html.len() > max_html_size.0 {
+            let message = format!(
+                "HTML content must not be larger than {} bytes",
+                max_html_size.0
+            );
+            return Err(e400(message.clone(), message));
+        }
+    }
+    let n_images_without_alt = html_content
+        .as_deref()
+        .map(count_images_without_alt)
+        .unwrap_or_default();
+    let newsletters_issue = NewslettersIssue::parse(title, text_content, html_content)
+        .map_err(|e| e400(e.clone(), e))?;
+    let target_statuses = parse_target_statuses(target_statuses).map_err(|e| e400(e.clone(), e))?;
     let user_id = user_id.into_inner();
-    let transaction = pg_pool.begin().await.map_err(e500)?;
+    let transaction = pg_pool.begin().await.map_err(e503_if_pool_exhausted)?;
 
+    let idempotent_request = IdempotentRequest {
+        method: request.method().as_str(),
+        path: request.path(),
+    };
     let mut transaction = match try_insert_idempotency_response_record_into_database(
         transaction,
         &idempotency_key,
-        &user_id,
+        Some(&*user_id),
+        &idempotent_request,
+        idempotency_replay_max_age.0,
     )
-    .await
-    .map_err(e500)?
+    .await?
     {
         ProcessState::Completed(response) => return Ok(response),
         ProcessState::StartProcessing(transaction) => transaction,
     };
 
     let newsletters_issue_id = uuid::Uuid::new_v4();
-    insert_newsletters_issue(
+    let contents = insert_newsletters_issue(
         &mut transaction,
+        &content_store,
         newsletters_issue_id,
-        NewslettersIssue {
-            title,
-            text_content,
-            html_content,
-        },
+        newsletters_issue,
+        priority.unwrap_or_default(),
     )
     .await
     .map_err(e500)?;
 
-    enqueue_task(&mut transaction, newsletters_issue_id)
+    enqueue_task(&mut transaction, newsletters_issue_id, &target_statuses)
         .await
         .map_err(e500)?;
 
@@ -80,6 +157,28 @@ pub async fn publish_newsletters(
         .context("Tasks count in newsletters issue delivery queue is None")
         .map_err(e500)? as i32;
 
+    // Dropping transaction rolls back issue, enqueued tasks and idempotency key
+    // So the same form can be submitted again with confirmation
+    if required_n_tasks as u64 > max_recipients.0 && confirm_large_send != Some(true) {
+        let message = format!(
+            "Newsletter would be sent to {} subscribers, more than {}. \
+            Confirm large send to publish it",
+            required_n_tasks, max_recipients.0
+        );
+        // Scripts can't follow flash messages, so they get the reason in response body
+        if is_api_token_request(request.headers()) {
+            return Ok(
+                HttpResponse::UnprocessableEntity().json(LargeSendNotConfirmedResponse {
+                    error: message,
+                    n_recipients: required_n_tasks as u64,
+                    max_recipients: max_recipients.0,
+                }),
+            );
+        }
+        FlashMessage::error(message).send();
+        return Ok(see_other("/admin/newsletters"));
+    }
+
     update_newsletters_issue_require_n_tasks(
         &mut transaction,
         &newsletters_issue_id,
@@ -89,12 +188,68 @@ pub async fn publish_newsletters(
     .map_err(e500)?;
 
     FlashMessage::success("Published newsletter successfully!").send();
+    // Not blocking, issue is still published
+    if n_images_without_alt > 0 {
+        FlashMessage::warning(format!(
+            "{} image(s) have no alt text, they are not described when images are blocked",
+            n_images_without_alt
+        ))
+        .send();
+    }
     let response = see_other("/admin/newsletters");
-    let response =
-        update_idempotency_response_record(&mut transaction, &idempotency_key, &user_id, response)
-            .await
-            .map_err(e500)?;
+    let response = update_idempotency_response_record(
+        &mut transaction,
+        &idempotency_key,
+        Some(&*user_id),
+        response,
+        idempotency_max_stored_body_size.0,
+    )
+    .await
+    .map_err(e500)?;
+    notify_newsletters_issues_available(&mut transaction)
+        .await
+        .map_err(e500)?;
     transaction.commit().await.map_err(e500)?;
+    contents.keep();
+    // Saves a round trip through Postgres when worker runs in this process
     notify.notify_one();
+
+    record_audit(
+        &pg_pool,
+        &user_id,
+        AuditAction::PublishNewsletters,
+        Some(&newsletters_issue_id.to_string()),
+        request.extensions().get::<RequestId>(),
+    )
+    .await;
     Ok(response)
 }
+
+// Sanitized HTML is serialized with quoted attributes and `"` escaped in values,
+// so ` alt="` can only appear as an attribute of the tag
+fn count_images_without_alt(html: &str) -> usize {
+    html.match_indices("<img")
+        .filter(|(start, _)| {
+            let tag = &html[*start..];
+            let tag = &tag[..tag.find('>').unwrap_or(tag.len())];
+            !tag.contains(r#" alt=""#)
+        })
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::routes::admin::newsletters::post::count_images_without_alt;
+
+    #[test]
+    fn images_without_alt_are_counted() {
+        let html = r#"<p><img src="a.png" alt="A"><img src="b.png"><img src="c.png" alt=""></p><img src="d.png" title="alt=">"#;
+
+        assert_eq!(count_images_without_alt(html), 2);
+    }
+
+    #[test]
+    fn html_without_images_has_no_images_without_alt() {
+        assert_eq!(count_images_without_alt("<p>Hello</p>"), 0);
+    }
+}
@@ -0,0 +1,66 @@
+use crate::authentication::UserId;
+use crate::middleware::RequestId;
+use crate::newsletters_issues::{
+    notify_newsletters_issues_available, reopen_newsletters_issue_with_pending_tasks,
+    NewsletterIssueStatus,
+};
+use crate::utils::{e500, record_audit, AuditAction};
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
+use sqlx::PgPool;
+use std::str::FromStr;
+use tokio::sync::Notify;
+use uuid::Uuid;
+
+#[derive(serde::Serialize)]
+struct RedriveResponse {
+    status: String,
+}
+
+// Recover an issue stuck after worker crashed mid-batch, e.g. its notification was already consumed
+// Or it's marked completed while tasks are still left in delivery queue
+#[tracing::instrument(name = "Redrive newsletters issue", skip_all, fields(newsletters_issue_id = %newsletters_issue_id))]
+pub async fn redrive_newsletters_issue(
+    request: HttpRequest,
+    newsletters_issue_id: web::Path<Uuid>,
+    pg_pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    notify: web::Data<Notify>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let newsletters_issue_id = newsletters_issue_id.into_inner();
+    let status = match reopen_newsletters_issue_with_pending_tasks(&pg_pool, &newsletters_issue_id)
+        .await
+        .map_err(e500)?
+    {
+        Some(status) => status,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    match NewsletterIssueStatus::from_str(&status) {
+        Ok(NewsletterIssueStatus::Available) => {
+            notify.notify_one();
+            // Worker in other process still picks it up by polling when notification fails
+            if let Err(e) = notify_newsletters_issues_available(&**pg_pool).await {
+                tracing::warn!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to notify newsletters issues channel"
+                );
+            }
+        }
+        // Nothing is left to deliver
+        Ok(NewsletterIssueStatus::Completed) => {}
+        Err(_) => {
+            return Ok(HttpResponse::Conflict().json(RedriveResponse { status }));
+        }
+    }
+
+    record_audit(
+        &pg_pool,
+        &user_id.into_inner(),
+        AuditAction::RedriveNewsletters,
+        Some(&newsletters_issue_id.to_string()),
+        request.extensions().get::<RequestId>(),
+    )
+    .await;
+    Ok(HttpResponse::Ok().json(RedriveResponse { status }))
+}
@@ -1,20 +1,33 @@
-use actix_web::http::header::ContentType;
-use actix_web::HttpResponse;
+use crate::middleware::BasePath;
+use crate::utils::{cacheable_html, uncacheable_html};
+use actix_web::{web, HttpRequest, HttpResponse};
 use actix_web_flash_messages::IncomingFlashMessages;
 use std::fmt::Write;
 
+// Form is static unless it carries flash messages, so it's cacheable without them
 pub async fn change_password_form(
+    request: HttpRequest,
     messages: IncomingFlashMessages,
+    base_path: web::Data<BasePath>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let mut flash_msg = "".to_string();
     for msg in messages.iter() {
         let _ = writeln!(flash_msg, "<p><i>{}</i></p>", msg.content());
     }
 
-    Ok(HttpResponse::Ok()
-        .insert_header(ContentType::html())
-        .body(format!(
-            r#"
+    let html = render_change_password_form(&flash_msg, &base_path);
+    Ok(if flash_msg.is_empty() {
+        cacheable_html(&request, html)
+    } else {
+        uncacheable_html(html)
+    })
+}
+
+fn render_change_password_form(flash_msg: &str, base_path: &BasePath) -> String {
+    let change_password_href = base_path.href("/admin/password");
+    let dashboard_href = base_path.href("/admin/dashboard");
+    format!(
+        r#"
                <!DOCTYPE html>
 <html lang="en">
 <head>
@@ -22,7 +35,7 @@ pub async fn change_password_form(
     <title>Login</title>
 </head>
 <body>
-<form action="/admin/password" method="POST">
+<form action="{change_password_href}" method="POST">
     {flash_msg}
     <label>Current password
         <input
@@ -50,10 +63,10 @@ pub async fn change_password_form(
     <br>
     <button type="submit">Confirm</button>
     <br>
-    <a href="/admin/dashboard">Back</a> 
+    <a href="{dashboard_href}">Back</a> 
 </form>
 </body>
 </html>
             "#
-        )))
+    )
 }
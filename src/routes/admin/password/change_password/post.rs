@@ -1,9 +1,10 @@
 use crate::authentication::{
     hash_password, update_user_password_to_database, validate_credentials, Credentials, UserId,
 };
+use crate::middleware::RequestId;
 use crate::utils;
-use crate::utils::{e500, get_username_from_database, see_other};
-use actix_web::{web, HttpResponse};
+use crate::utils::{e500, get_username_from_database, record_audit, see_other, AuditAction};
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
 use anyhow::Context;
 use secrecy::{ExposeSecret, Secret};
@@ -17,6 +18,7 @@ pub struct ChangePasswordForm {
 }
 
 pub async fn change_password(
+    request: HttpRequest,
     user_id: web::ReqData<UserId>,
     pg_pool: web::Data<PgPool>,
     web::Form(change_pwd_form): web::Form<ChangePasswordForm>,
@@ -70,6 +72,15 @@ pub async fn change_password(
         .context("Failed to update user password in database")
         .map_err(e500)?;
 
+    record_audit(
+        &pg_pool,
+        &user_id,
+        AuditAction::ChangePassword,
+        None,
+        request.extensions().get::<RequestId>(),
+    )
+    .await;
+
     FlashMessage::success("Password changed").send();
     Ok(see_other("/admin/password"))
 }
@@ -0,0 +1,176 @@
+use crate::authentication::UserId;
+use crate::middleware::RequestId;
+use crate::newsletters_issues::update_newsletters_issue_status;
+use crate::routes::SubscriberEmail;
+use crate::utils::{e400, e500, record_audit, AuditAction};
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+// Key of HMAC that tombstones are hashed with
+// Plain digests of erased emails could be reversed by hashing a list of known addresses
+pub struct ErasedEmailHashKey(pub Secret<String>);
+
+#[derive(serde::Deserialize)]
+pub struct EraseSubscriberBody {
+    email: String,
+}
+
+#[derive(serde::Serialize, Default)]
+struct EraseSummary {
+    subscriptions: u64,
+    subscription_tokens: u64,
+    confirmation_emails: u64,
+    newsletters_deliveries: u64,
+}
+
+// Email is normalized before hashing, so the same address is always mapped to the same tombstone
+pub fn hash_erased_email(hash_key: &ErasedEmailHashKey, email: &SubscriberEmail) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(hash_key.0.expose_secret().as_bytes())
+        .expect("HMAC can take key of any size");
+    mac.update(email.as_ref().as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+// Erasure under GDPR, every row carrying subscriber email is deleted instead of being marked
+// Only a hashed tombstone is kept, so erased subscribers can't be imported again
+#[tracing::instrument(name = "Erase subscriber", skip_all)]
+pub async fn erase_subscriber(
+    request: HttpRequest,
+    web::Json(EraseSubscriberBody { email }): web::Json<EraseSubscriberBody>,
+    pg_pool: web::Data<PgPool>,
+    hash_key: web::Data<ErasedEmailHashKey>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    // Subscribers are stored by canonical email, erasing a plus-addressed one must still find them
+    let email = SubscriberEmail::parse_canonical(email).map_err(|e| e400(e.clone(), e))?;
+    let email_hash = hash_erased_email(&hash_key, &email);
+
+    let mut transaction = pg_pool.begin().await.map_err(e500)?;
+    let (summary, newsletters_issue_ids) = delete_subscriber_data(&mut transaction, &email)
+        .await
+        .map_err(e500)?;
+    insert_erased_subscriber_tombstone(&mut transaction, &email_hash)
+        .await
+        .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+
+    // Dropped deliveries may have been the last ones left of an issue
+    for newsletters_issue_id in &newsletters_issue_ids {
+        update_newsletters_issue_status(&pg_pool, newsletters_issue_id)
+            .await
+            .map_err(e500)?;
+    }
+
+    record_audit(
+        &pg_pool,
+        &user_id.into_inner(),
+        AuditAction::EraseSubscriber,
+        Some(&email_hash),
+        request.extensions().get::<RequestId>(),
+    )
+    .await;
+    Ok(HttpResponse::Ok().json(summary))
+}
+
+// Return ids of newsletters issues whose delivery queue had subscriber
+#[tracing::instrument(name = "Delete subscriber data from database", skip_all)]
+async fn delete_subscriber_data(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &SubscriberEmail,
+) -> Result<(EraseSummary, Vec<Uuid>), sqlx::Error> {
+    let mut summary = EraseSummary::default();
+
+    let newsletters_issue_ids = sqlx::query!(
+        r#"
+        DELETE FROM newsletters_issues_delivery_queue
+        WHERE subscriber_email = $1
+            OR subscriber_email IN (SELECT delivery_email FROM subscriptions WHERE email = $1)
+        RETURNING id
+        "#,
+        email.as_ref()
+    )
+    .fetch_all(&mut *transaction)
+    .await?
+    .into_iter()
+    .map(|r| r.id)
+    .collect::<Vec<_>>();
+    summary.newsletters_deliveries = newsletters_issue_ids.len() as u64;
+    sqlx::query!(
+        r#"
+        DELETE FROM newsletters_issues_sent_emails
+        WHERE subscriber_email = $1
+            OR subscriber_email IN (SELECT delivery_email FROM subscriptions WHERE email = $1)
+        "#,
+        email.as_ref()
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    // Confirmation emails reference subscription tokens, so they are deleted first
+    summary.confirmation_emails = sqlx::query!(
+        r#"
+        DELETE FROM confirmation_emails_delivery_queue
+        WHERE subscription_token IN (
+            SELECT subscription_token
+            FROM subscription_tokens
+            JOIN subscriptions ON subscriptions.id = subscription_tokens.subscription_id
+            WHERE subscriptions.email = $1
+        ) OR subscriber_email = $1
+            OR subscriber_email IN (SELECT delivery_email FROM subscriptions WHERE email = $1)
+        "#,
+        email.as_ref()
+    )
+    .execute(&mut *transaction)
+    .await?
+    .rows_affected();
+
+    summary.subscription_tokens = sqlx::query!(
+        r#"
+        DELETE FROM subscription_tokens
+        WHERE subscription_id IN (
+            SELECT id
+            FROM subscriptions
+            WHERE email = $1
+        )
+        "#,
+        email.as_ref()
+    )
+    .execute(&mut *transaction)
+    .await?
+    .rows_affected();
+
+    summary.subscriptions = sqlx::query!(
+        r#"
+        DELETE FROM subscriptions
+        WHERE email = $1
+        "#,
+        email.as_ref()
+    )
+    .execute(&mut *transaction)
+    .await?
+    .rows_affected();
+
+    Ok((summary, newsletters_issue_ids))
+}
+
+#[tracing::instrument(name = "Insert erased subscriber tombstone", skip(transaction))]
+async fn insert_erased_subscriber_tombstone(
+    transaction: &mut Transaction<'_, Postgres>,
+    email_hash: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO erased_subscribers (email_hash)
+        VALUES ($1)
+        ON CONFLICT (email_hash) DO NOTHING
+        "#,
+        email_hash
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,106 @@
+use crate::routes::SubscriptionStatus;
+use crate::utils::e400;
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{web, HttpResponse};
+use futures::TryStreamExt;
+use sqlx::PgPool;
+use std::str::FromStr;
+use tokio::sync::mpsc;
+
+#[derive(serde::Deserialize)]
+pub struct ExportQuery {
+    status: Option<String>,
+}
+
+type CsvChunk = Result<web::Bytes, std::io::Error>;
+
+#[tracing::instrument(name = "Export subscribers as CSV", skip(pg_pool))]
+pub async fn export_subscribers(
+    web::Query(ExportQuery { status }): web::Query<ExportQuery>,
+    pg_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let status = match status {
+        Some(status) => Some(
+            SubscriptionStatus::from_str(&status)
+                .map_err(|e| e400("Invalid subscription status", e))?,
+        ),
+        None => None,
+    };
+
+    // Stream rows from database to response body through a bounded channel
+    // So whole subscriptions table is never buffered in memory
+    let (sender, mut receiver) = mpsc::channel::<CsvChunk>(64);
+    let pg_pool = pg_pool.get_ref().clone();
+    tokio::spawn(async move {
+        if let Err(e) = stream_subscribers_as_csv(&pg_pool, status, &sender).await {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to export subscribers"
+            );
+            let _ = sender.send(Err(e)).await;
+        }
+    });
+    let body = futures::stream::poll_fn(move |cx| receiver.poll_recv(cx));
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv; charset=utf-8")
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename("subscribers.csv".into())],
+        })
+        .streaming(body))
+}
+
+async fn stream_subscribers_as_csv(
+    pg_pool: &PgPool,
+    status: Option<SubscriptionStatus>,
+    sender: &mpsc::Sender<CsvChunk>,
+) -> Result<(), std::io::Error> {
+    let to_io_error = |e: sqlx::Error| std::io::Error::new(std::io::ErrorKind::Other, e);
+
+    if sender
+        .send(Ok(csv_line(["email", "name", "status", "subscribed_at"])?))
+        .await
+        .is_err()
+    {
+        // Client is disconnected
+        return Ok(());
+    }
+
+    let status = status.as_ref().map(|s| s.as_ref());
+    let mut rows = sqlx::query!(
+        r#"
+        SELECT delivery_email AS email, name, status, subscribed_at
+        FROM subscriptions
+        WHERE $1::TEXT IS NULL OR status = $1
+        ORDER BY subscribed_at
+        "#,
+        status
+    )
+    .fetch(pg_pool);
+
+    while let Some(row) = rows.try_next().await.map_err(to_io_error)? {
+        let line = csv_line([
+            row.email.as_str(),
+            row.name.as_str(),
+            row.status.as_str(),
+            row.subscribed_at.to_rfc3339().as_str(),
+        ])?;
+        if sender.send(Ok(line)).await.is_err() {
+            return Ok(());
+        }
+    }
+
+    Ok(())
+}
+
+// Let csv writer take care of quoting and escaping fields
+fn csv_line<const N: usize>(fields: [&str; N]) -> Result<web::Bytes, std::io::Error> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer.write_record(fields)?;
+    let line = writer
+        .into_inner()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))?;
+    Ok(web::Bytes::from(line))
+}
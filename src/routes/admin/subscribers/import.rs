@@ -0,0 +1,109 @@
+use crate::routes::admin::{hash_erased_email, ErasedEmailHashKey};
+use crate::routes::{NewSubscriber, SubscriberEmail, SubscriberName, SubscriptionStatus};
+use crate::utils::e500;
+use actix_web::{web, HttpResponse};
+use chrono::Utc;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+struct SubscriberRecord {
+    email: String,
+    name: String,
+}
+
+#[derive(serde::Serialize, Default)]
+struct ImportSummary {
+    inserted: u64,
+    skipped: u64,
+    invalid: u64,
+}
+
+// Subscribers in CSV are already consented with another provider
+// So insert them directly as confirmed, skip double opt-in
+#[tracing::instrument(name = "Import subscribers from CSV", skip_all)]
+pub async fn import_subscribers(
+    body: web::Bytes,
+    pg_pool: web::Data<PgPool>,
+    hash_key: web::Data<ErasedEmailHashKey>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(body.as_ref());
+
+    let mut summary = ImportSummary::default();
+    let mut transaction = pg_pool.begin().await.map_err(e500)?;
+    for record in reader.deserialize::<SubscriberRecord>() {
+        // Invalid rows must not abort the whole import
+        let subscriber =
+            match record
+                .map_err(|e| e.to_string())
+                .and_then(|SubscriberRecord { email, name }| {
+                    let delivery_email = SubscriberEmail::parse(email)?;
+                    let (email, plus_tag) = delivery_email.clone().split_plus_tag();
+                    Ok(NewSubscriber {
+                        name: SubscriberName::parse(name)?,
+                        email,
+                        plus_tag,
+                        delivery_email,
+                        source: None,
+                        utm_campaign: None,
+                    })
+                }) {
+                Ok(subscriber) => subscriber,
+                Err(e) => {
+                    tracing::warn!(error.message = %e, "Skip invalid subscriber record");
+                    summary.invalid += 1;
+                    continue;
+                }
+            };
+
+        match insert_confirmed_subscriber(&subscriber, &hash_key, &mut transaction)
+            .await
+            .map_err(e500)?
+        {
+            true => summary.inserted += 1,
+            false => summary.skipped += 1,
+        }
+    }
+    transaction.commit().await.map_err(e500)?;
+
+    Ok(HttpResponse::Ok().json(summary))
+}
+
+// Return false if subscriber email already exists or was erased on request
+#[tracing::instrument(
+    name = "Insert a new subscriber to database with confirmed status",
+    skip(subscriber, hash_key, transaction)
+)]
+async fn insert_confirmed_subscriber(
+    subscriber: &NewSubscriber,
+    hash_key: &ErasedEmailHashKey,
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<bool, sqlx::Error> {
+    let n_rows_affected = sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status, plus_tag, delivery_email)
+        SELECT $1, $2, $3, $4, $5, $7, $8
+        WHERE NOT EXISTS (
+            SELECT 1
+            FROM erased_subscribers
+            WHERE email_hash = $6
+        )
+        ON CONFLICT (email) DO NOTHING
+        "#,
+        Uuid::new_v4(),
+        subscriber.email.as_ref(),
+        subscriber.name.as_ref(),
+        Utc::now(),
+        SubscriptionStatus::Confirmed.as_ref(),
+        hash_erased_email(hash_key, &subscriber.email),
+        subscriber.plus_tag.as_deref(),
+        subscriber.delivery_email.as_ref()
+    )
+    .execute(transaction)
+    .await?
+    .rows_affected();
+
+    Ok(n_rows_affected > 0)
+}
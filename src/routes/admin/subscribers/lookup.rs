@@ -0,0 +1,53 @@
+use crate::routes::SubscriberEmail;
+use crate::utils::{e400, e500};
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct LookupQuery {
+    email: String,
+}
+
+// Subscription tokens are never returned, they would let staff confirm or unsubscribe on behalf of subscriber
+#[derive(serde::Serialize)]
+struct SubscriberLookupResponse {
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: String,
+    source: Option<String>,
+    utm_campaign: Option<String>,
+}
+
+#[tracing::instrument(name = "Look up subscriber by email", skip(pg_pool))]
+pub async fn lookup_subscriber(
+    web::Query(LookupQuery { email }): web::Query<LookupQuery>,
+    pg_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    // Normalize email the same way it's stored when subscribing
+    let email = SubscriberEmail::parse_canonical(email).map_err(|e| e400(e.clone(), e))?;
+
+    let record = sqlx::query!(
+        r#"
+        SELECT email, name, status, subscribed_at, source, utm_campaign
+        FROM subscriptions
+        WHERE email = $1
+        "#,
+        email.as_ref()
+    )
+    .fetch_optional(pg_pool.get_ref())
+    .await
+    .map_err(e500)?;
+
+    Ok(match record {
+        Some(r) => HttpResponse::Ok().json(SubscriberLookupResponse {
+            email: r.email,
+            name: r.name,
+            status: r.status,
+            subscribed_at: r.subscribed_at.to_rfc3339(),
+            source: r.source,
+            utm_campaign: r.utm_campaign,
+        }),
+        None => HttpResponse::NotFound().finish(),
+    })
+}
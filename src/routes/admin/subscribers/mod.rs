@@ -0,0 +1,11 @@
+mod erase;
+mod export;
+mod import;
+mod lookup;
+mod stats;
+
+pub use erase::*;
+pub use export::*;
+pub use import::*;
+pub use lookup::*;
+pub use stats::*;
@@ -0,0 +1,35 @@
+use crate::utils::e500;
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+
+#[derive(serde::Serialize)]
+struct SourceCount {
+    // `None` groups subscribers without source
+    source: Option<String>,
+    count: i64,
+}
+
+#[derive(serde::Serialize)]
+struct SubscribersStatsResponse {
+    sources: Vec<SourceCount>,
+}
+
+#[tracing::instrument(name = "Get subscribers stats", skip(pg_pool))]
+pub async fn subscribers_stats(
+    pg_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let sources = sqlx::query_as!(
+        SourceCount,
+        r#"
+        SELECT source, COUNT(*) as "count!"
+        FROM subscriptions
+        GROUP BY source
+        ORDER BY COUNT(*) DESC, source
+        "#
+    )
+    .fetch_all(pg_pool.get_ref())
+    .await
+    .map_err(e500)?;
+
+    Ok(HttpResponse::Ok().json(SubscribersStatsResponse { sources }))
+}
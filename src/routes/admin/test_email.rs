@@ -0,0 +1,50 @@
+use crate::authentication::UserId;
+use crate::email_client::EmailClient;
+use crate::routes::admin::get_user_email;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+
+const TEST_EMAIL_SUBJECT: &str = "Zero2Prod test email";
+
+// Sent to logged in admin only, so dashboard can't be used to send emails to arbitrary addresses
+// Failures are reported back as flash message instead of error response
+// So SMTP connection or authentication problems can be read right on the dashboard
+#[tracing::instrument(name = "Send test email", skip_all, fields(user_id = %*user_id))]
+pub async fn send_test_email(
+    user_id: web::ReqData<UserId>,
+    pg_pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let recipient_email = match get_user_email(&pg_pool, &user_id).await.map_err(e500)? {
+        Some(recipient_email) => recipient_email,
+        None => {
+            FlashMessage::error("Set your email before sending a test email").send();
+            return Ok(see_other("/admin/dashboard"));
+        }
+    };
+
+    match email_client
+        .send_multipart_email(
+            &recipient_email,
+            TEST_EMAIL_SUBJECT,
+            Some("Email settings work, this message was sent from the admin dashboard."),
+            Some("<p>Email settings work, this message was sent from the admin dashboard.</p>"),
+        )
+        .await
+    {
+        Ok(_) => {
+            FlashMessage::success(format!("Test email was sent to {}", recipient_email)).send();
+        }
+        Err(e) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to send test email"
+            );
+            FlashMessage::error(format!("Failed to send test email: {:#}", e)).send();
+        }
+    }
+    Ok(see_other("/admin/dashboard"))
+}
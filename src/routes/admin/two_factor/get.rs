@@ -0,0 +1,89 @@
+use crate::authentication::{
+    generate_totp_secret, get_totp_uri, get_user_totp_secret, UserId, UserSession,
+};
+use crate::middleware::BasePath;
+use crate::utils::{e500, get_username_from_database, html_response};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+pub async fn two_factor_enrollment_form(
+    user_id: web::ReqData<UserId>,
+    pg_pool: web::Data<PgPool>,
+    session: UserSession,
+    messages: IncomingFlashMessages,
+    base_path: web::Data<BasePath>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let mut flash_msg = "".to_string();
+    for msg in messages.iter() {
+        let _ = writeln!(flash_msg, "<p><i>{}</i></p>", msg.content());
+    }
+
+    let content = if get_user_totp_secret(&pg_pool, &user_id)
+        .await
+        .map_err(e500)?
+        .is_some()
+    {
+        "<p>Two-factor authentication is enabled</p>".to_string()
+    } else {
+        // Reuse pending secret, so reloading page doesn't invalidate the one user already scanned
+        let totp_secret = match session.get_pending_totp_secret().map_err(e500)? {
+            Some(totp_secret) => totp_secret,
+            None => {
+                let totp_secret = generate_totp_secret();
+                session
+                    .insert_pending_totp_secret(&totp_secret)
+                    .map_err(e500)?;
+                totp_secret
+            }
+        };
+        let username = get_username_from_database(&pg_pool, &user_id)
+            .await
+            .map_err(e500)?;
+        let totp_uri = get_totp_uri(&totp_secret, &username).map_err(e500)?;
+        format!(
+            r#"
+<p>Add this URI to your authenticator app: <code>{}</code></p>
+<p>Or enter this secret manually: <code>{}</code></p>
+<form action="{two_factor_href}" method="POST">
+    <label>Authentication code
+        <input
+                type="text"
+                inputmode="numeric"
+                autocomplete="one-time-code"
+                placeholder="6-digit code"
+                name="code"
+        >
+    </label>
+    <br>
+    <button type="submit">Enable</button>
+</form>
+            "#,
+            htmlescape::encode_minimal(&totp_uri),
+            totp_secret.expose_secret(),
+            two_factor_href = base_path.href("/admin/2fa"),
+        )
+    };
+
+    let dashboard_href = base_path.href("/admin/dashboard");
+    Ok(html_response(format!(
+        r#"
+<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Two-factor authentication</title>
+</head>
+<body>
+{flash_msg}
+{content}
+<br>
+<a href="{dashboard_href}">Back</a>
+</body>
+</html>
+            "#
+    )))
+}
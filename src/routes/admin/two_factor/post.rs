@@ -0,0 +1,50 @@
+use crate::authentication::{update_user_totp_secret, verify_totp_code, UserId, UserSession};
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct TwoFactorEnrollmentForm {
+    code: String,
+}
+
+#[tracing::instrument(
+    name = "Enroll two-factor authentication",
+    skip_all,
+    fields(user_id = %*user_id)
+)]
+pub async fn enroll_two_factor(
+    user_id: web::ReqData<UserId>,
+    web::Form(form): web::Form<TwoFactorEnrollmentForm>,
+    pg_pool: web::Data<PgPool>,
+    session: UserSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    let totp_secret = match session.get_pending_totp_secret().map_err(e500)? {
+        Some(totp_secret) => totp_secret,
+        None => {
+            FlashMessage::error("Two-factor authentication enrollment expired, please try again")
+                .send();
+            return Ok(see_other("/admin/2fa"));
+        }
+    };
+
+    // Make sure user's authenticator app is set up correctly before enabling it
+    let verified_step = match verify_totp_code(&totp_secret, &form.code).map_err(e500)? {
+        Some(step) => step,
+        None => {
+            FlashMessage::error("Invalid authentication code").send();
+            return Ok(see_other("/admin/2fa"));
+        }
+    };
+
+    update_user_totp_secret(&pg_pool, &user_id, &totp_secret, verified_step)
+        .await
+        .context("Failed to update user TOTP secret in database")
+        .map_err(e500)?;
+    session.remove_pending_totp_secret();
+
+    FlashMessage::success("Two-factor authentication enabled").send();
+    Ok(see_other("/admin/2fa"))
+}
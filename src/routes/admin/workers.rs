@@ -0,0 +1,14 @@
+use crate::utils::e500;
+use crate::worker_status::{get_workers_status, WorkerStatus};
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+
+#[derive(serde::Serialize)]
+struct WorkersStatusResponse {
+    workers: Vec<WorkerStatus>,
+}
+
+pub async fn workers_status(pg_pool: web::Data<PgPool>) -> Result<HttpResponse, actix_web::Error> {
+    let workers = get_workers_status(&pg_pool).await.map_err(e500)?;
+    Ok(HttpResponse::Ok().json(WorkersStatusResponse { workers }))
+}
@@ -1,7 +1,9 @@
 mod new_subscriber;
 mod subscriber_email;
 mod subscriber_name;
+mod subscription_source;
 
 pub use new_subscriber::{NewSubscriber, SubscriptionStatus};
 pub use subscriber_email::SubscriberEmail;
 pub use subscriber_name::SubscriberName;
+pub use subscription_source::SubscriptionSource;
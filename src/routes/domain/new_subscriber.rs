@@ -1,14 +1,56 @@
-use crate::routes::{SubscriberEmail, SubscriberName};
+use crate::routes::{SubscriberEmail, SubscriberName, SubscriptionSource};
 
 pub struct NewSubscriber {
     pub name: SubscriberName,
+    // Canonical form, without plus tag
     pub email: SubscriberEmail,
+    pub plus_tag: Option<String>,
+    // Email as subscriber entered it, which mail is sent to
+    pub delivery_email: SubscriberEmail,
+    pub source: Option<SubscriptionSource>,
+    pub utm_campaign: Option<SubscriptionSource>,
 }
 
-#[derive(strum::AsRefStr)]
+// `EnumString` derives `FromStr` and `TryFrom<&str>`, so values read from database can be matched on
+// `IntoStaticStr` lets responses carry status without allocating
+#[derive(
+    strum::AsRefStr, strum::EnumString, strum::IntoStaticStr, Clone, Copy, PartialEq, Eq, Debug,
+)]
 pub enum SubscriptionStatus {
     #[strum(serialize = "pending")]
     Pending,
     #[strum(serialize = "confirmed")]
     Confirmed,
+    #[strum(serialize = "unsubscribed")]
+    Unsubscribed,
+    // Email provider reported a hard bounce or spam complaint, address is never mailed again
+    #[strum(serialize = "bounced")]
+    Bounced,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::routes::SubscriptionStatus;
+    use claims::assert_err;
+    use std::str::FromStr;
+
+    #[test]
+    fn every_status_round_trips_through_its_string() {
+        for status in [
+            SubscriptionStatus::Pending,
+            SubscriptionStatus::Confirmed,
+            SubscriptionStatus::Unsubscribed,
+            SubscriptionStatus::Bounced,
+        ] {
+            assert_eq!(SubscriptionStatus::try_from(status.as_ref()), Ok(status));
+            assert_eq!(SubscriptionStatus::from_str(status.as_ref()), Ok(status));
+        }
+    }
+
+    #[test]
+    fn unknown_status_is_rejected() {
+        for status in ["pending_confirmation", "Pending", ""] {
+            assert_err!(SubscriptionStatus::try_from(status));
+        }
+    }
 }
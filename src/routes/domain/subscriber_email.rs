@@ -1,15 +1,104 @@
 use std::fmt::Display;
 use validator::validate_email;
 
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct SubscriberEmail(String);
 
+// RFC 5321 limits forward-path to 256 characters, including surrounding angle brackets
+const MAX_EMAIL_LENGTH: usize = 254;
+// RFC 5321 limit, longer local parts are rejected by many SMTP servers
+const MAX_LOCAL_PART_LENGTH: usize = 64;
+
 impl SubscriberEmail {
     pub fn parse(email: String) -> Result<Self, String> {
+        let email = to_ascii_domain(extract_address(email.trim()))?;
+        if email.chars().count() > MAX_EMAIL_LENGTH {
+            return Err(format!(
+                "Email address must not be longer than {} characters",
+                MAX_EMAIL_LENGTH
+            ));
+        }
+        if let Some((local_part, _)) = email.rsplit_once('@') {
+            validate_local_part(local_part)?;
+        }
         match validate_email(&email) {
             true => Ok(Self(email)),
             false => Err("Invalid email address".into()),
         }
     }
+
+    pub fn into_inner(self) -> String {
+        self.0
+    }
+
+    // `foo+blog@example.com` is split into canonical `foo@example.com` and tag `blog`
+    // Plus-addressed emails land in the same mailbox, so subscribers are deduplicated by canonical form
+    pub fn split_plus_tag(self) -> (Self, Option<String>) {
+        let split = self.0.rsplit_once('@').and_then(|(local_part, domain)| {
+            match local_part.split_once('+') {
+                Some((user, tag)) if !user.is_empty() && !tag.is_empty() => {
+                    Some((format!("{}@{}", user, domain), tag.to_string()))
+                }
+                _ => None,
+            }
+        });
+        match split {
+            Some((canonical, tag)) => (Self(canonical), Some(tag)),
+            None => (self, None),
+        }
+    }
+
+    // Canonical form subscribers are stored by, to find them by any of their plus-addressed emails
+    pub fn parse_canonical(email: String) -> Result<Self, String> {
+        Ok(Self::parse(email)?.split_plus_tag().0)
+    }
+
+    // Domain in punycode form, as it's stored
+    pub fn domain(&self) -> &str {
+        self.0.rsplit_once('@').map_or("", |(_, domain)| domain)
+    }
+}
+
+// `validate_email` accepts dot placements that common SMTP servers reject
+fn validate_local_part(local_part: &str) -> Result<(), String> {
+    if local_part.chars().count() > MAX_LOCAL_PART_LENGTH {
+        return Err(format!(
+            "Local part of email address must not be longer than {} characters",
+            MAX_LOCAL_PART_LENGTH
+        ));
+    }
+    if local_part.starts_with('.') {
+        return Err("Local part of email address must not start with a dot".into());
+    }
+    if local_part.ends_with('.') {
+        return Err("Local part of email address must not end with a dot".into());
+    }
+    if local_part.contains("..") {
+        return Err("Local part of email address must not contain consecutive dots".into());
+    }
+    Ok(())
+}
+
+// Take the bare address out of `Display Name <address>` form
+fn extract_address(email: &str) -> &str {
+    match (email.rfind('<'), email.strip_suffix('>')) {
+        (Some(start), Some(email_without_suffix)) => email_without_suffix[start + 1..].trim(),
+        _ => email,
+    }
+}
+
+// SMTP servers may not support internationalized domains, so domain is stored in punycode form
+// e.g. `user@münchen.de` is stored as `user@xn--mnchen-3ya.de`
+fn to_ascii_domain(email: &str) -> Result<String, String> {
+    match email.rsplit_once('@') {
+        Some((local_part, domain)) => {
+            let domain = idna::domain_to_ascii_strict(domain)
+                .map_err(|_| format!("Invalid email domain: {}", domain))?;
+            Ok(format!("{}@{}", local_part, domain))
+        }
+        // Left for `validate_email` to reject
+        None => Ok(email.to_string()),
+    }
 }
 
 impl Display for SubscriberEmail {
@@ -33,6 +122,7 @@ impl AsMut<str> for SubscriberEmail {
 #[cfg(test)]
 mod tests {
     use crate::routes::SubscriberEmail;
+    use claims::{assert_err, assert_ok};
     use fake::faker::internet::en::SafeEmail;
     use fake::Fake;
     use rand::prelude::StdRng;
@@ -53,4 +143,143 @@ mod tests {
     fn valid_email_are_accepted(email: ValidEmailFixture) -> bool {
         SubscriberEmail::parse(email.0).is_ok()
     }
+
+    #[test]
+    fn cloned_email_equals_original() {
+        let email = assert_ok!(SubscriberEmail::parse("ursula@domain.com".to_string()));
+        assert_eq!(email.clone(), email);
+    }
+
+    #[test]
+    fn into_inner_round_trips_through_parse() {
+        let email = assert_ok!(SubscriberEmail::parse(
+            "Ursula <ursula@domain.com>".to_string()
+        ));
+        let inner = email.clone().into_inner();
+        assert_eq!(inner, "ursula@domain.com");
+        assert_eq!(assert_ok!(SubscriberEmail::parse(inner)), email);
+    }
+
+    #[test]
+    fn display_name_form_is_parsed_into_bare_address() {
+        let email = SubscriberEmail::parse("Ursula Le Guin <ursula@domain.com>".to_string());
+        assert_eq!(assert_ok!(email).as_ref(), "ursula@domain.com");
+    }
+
+    #[test]
+    fn surrounding_whitespace_is_trimmed() {
+        let email = SubscriberEmail::parse("  ursula@domain.com \n".to_string());
+        assert_eq!(assert_ok!(email).as_ref(), "ursula@domain.com");
+    }
+
+    #[test]
+    fn email_longer_than_254_characters_is_rejected() {
+        let email = format!("{}@{}.com", "a".repeat(64), "b".repeat(186));
+        assert_eq!(email.len(), 255);
+        assert_err!(SubscriberEmail::parse(email));
+    }
+
+    #[test]
+    fn local_part_of_64_characters_with_inner_dots_is_accepted() {
+        let email = format!("{}.{}@domain.com", "a".repeat(31), "b".repeat(32));
+        assert_ok!(SubscriberEmail::parse(email));
+    }
+
+    #[test]
+    fn local_part_longer_than_64_characters_is_rejected() {
+        let email = format!("{}@domain.com", "a".repeat(65));
+        assert_eq!(
+            assert_err!(SubscriberEmail::parse(email)),
+            "Local part of email address must not be longer than 64 characters"
+        );
+    }
+
+    #[test]
+    fn local_part_starting_with_dot_is_rejected() {
+        assert_eq!(
+            assert_err!(SubscriberEmail::parse(".ursula@domain.com".to_string())),
+            "Local part of email address must not start with a dot"
+        );
+    }
+
+    #[test]
+    fn local_part_ending_with_dot_is_rejected() {
+        assert_eq!(
+            assert_err!(SubscriberEmail::parse("ursula.@domain.com".to_string())),
+            "Local part of email address must not end with a dot"
+        );
+    }
+
+    #[test]
+    fn local_part_with_consecutive_dots_is_rejected() {
+        assert_eq!(
+            assert_err!(SubscriberEmail::parse(
+                "ursula..le.guin@domain.com".to_string()
+            )),
+            "Local part of email address must not contain consecutive dots"
+        );
+    }
+
+    #[test]
+    fn display_name_without_address_is_rejected() {
+        assert_err!(SubscriberEmail::parse("Ursula Le Guin <>".to_string()));
+    }
+
+    #[test]
+    fn unicode_domain_is_encoded_to_punycode() {
+        let email = assert_ok!(SubscriberEmail::parse("user@münchen.de".to_string()));
+        assert_eq!(email.as_ref(), "user@xn--mnchen-3ya.de");
+    }
+
+    #[test]
+    fn plus_addressed_email_is_split_into_canonical_email_and_tag() {
+        let email = assert_ok!(SubscriberEmail::parse("ursula+blog@domain.com".to_string()));
+
+        let (canonical, tag) = email.split_plus_tag();
+
+        assert_eq!(canonical.as_ref(), "ursula@domain.com");
+        assert_eq!(tag.as_deref(), Some("blog"));
+    }
+
+    #[test]
+    fn only_first_plus_separates_tag() {
+        let email = assert_ok!(SubscriberEmail::parse(
+            "ursula+blog+2023@domain.com".to_string()
+        ));
+
+        let (canonical, tag) = email.split_plus_tag();
+
+        assert_eq!(canonical.as_ref(), "ursula@domain.com");
+        assert_eq!(tag.as_deref(), Some("blog+2023"));
+    }
+
+    #[test]
+    fn email_without_plus_tag_is_kept_as_is() {
+        for email in [
+            "ursula@domain.com",
+            "ursula+@domain.com",
+            "+blog@domain.com",
+        ] {
+            let parsed = assert_ok!(SubscriberEmail::parse(email.to_string()));
+
+            let (canonical, tag) = parsed.split_plus_tag();
+
+            assert_eq!(canonical.as_ref(), email);
+            assert_eq!(tag, None);
+        }
+    }
+
+    #[test]
+    fn canonical_email_is_parsed_without_plus_tag() {
+        let email = assert_ok!(SubscriberEmail::parse_canonical(
+            "Ursula <ursula+blog@domain.com>".to_string()
+        ));
+        assert_eq!(email.as_ref(), "ursula@domain.com");
+    }
+
+    #[test]
+    fn malformed_unicode_domain_is_rejected() {
+        assert_err!(SubscriberEmail::parse("user@xn--münchen.de".to_string()));
+        assert_err!(SubscriberEmail::parse("user@mün chen.de".to_string()));
+    }
 }
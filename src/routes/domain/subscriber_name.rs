@@ -1,11 +1,15 @@
+use std::fmt::Display;
 use unicode_segmentation::UnicodeSegmentation;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct SubscriberName(String);
 
 impl SubscriberName {
+    // Name is trimmed and internal runs of whitespace are collapsed to single spaces
+    // Length bound is measured on the normalized name, which is the one that's stored
     pub fn parse(name: String) -> Result<Self, String> {
-        if name.trim().is_empty() {
+        let name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+        if name.is_empty() {
             return Err("SubscriberName cannot be empty".into());
         }
 
@@ -20,6 +24,16 @@ impl SubscriberName {
 
         Ok(Self(name))
     }
+
+    pub fn into_inner(self) -> String {
+        self.0
+    }
+}
+
+impl Display for SubscriberName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
 }
 
 impl AsRef<str> for SubscriberName {
@@ -78,6 +92,45 @@ mod tests {
         }
     }
 
+    #[test]
+    fn leading_and_trailing_whitespace_is_trimmed() {
+        let name = assert_ok!(SubscriberName::parse("  Ursula  ".to_string()));
+        assert_eq!(name.as_ref(), "Ursula");
+    }
+
+    #[test]
+    fn internal_runs_of_whitespace_are_collapsed() {
+        let name = assert_ok!(SubscriberName::parse("Ursula  Le\t\n Guin".to_string()));
+        assert_eq!(name.as_ref(), "Ursula Le Guin");
+    }
+
+    #[test]
+    fn name_only_long_enough_because_of_padding_is_rejected() {
+        let name = format!("  {}  ", "a".repeat(2));
+        assert_err!(SubscriberName::parse(name));
+    }
+
+    #[test]
+    fn name_only_too_long_because_of_padding_is_accepted() {
+        let name = format!("   {}   ", "a".repeat(30));
+        assert_ok!(SubscriberName::parse(name));
+    }
+
+    #[test]
+    fn cloned_name_equals_original() {
+        let name = assert_ok!(SubscriberName::parse("Ursula Le Guin".to_string()));
+        assert_eq!(name.clone(), name);
+        assert_eq!(name.to_string(), "Ursula Le Guin");
+    }
+
+    #[test]
+    fn into_inner_returns_normalized_name() {
+        let name = assert_ok!(SubscriberName::parse(" Ursula  Le Guin ".to_string()));
+        let inner = name.clone().into_inner();
+        assert_eq!(inner, "Ursula Le Guin");
+        assert_eq!(assert_ok!(SubscriberName::parse(inner)), name);
+    }
+
     #[test]
     fn a_valid_name_is_parsed_successfully() {
         let name = "Ursula Le Guin".to_string();
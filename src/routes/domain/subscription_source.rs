@@ -0,0 +1,81 @@
+// Where a subscriber came from, e.g. `source` or `utm_campaign` of subscribe form
+// Only allow characters which are commonly used in UTM values, so they're safe to render and group by
+#[derive(Debug)]
+pub struct SubscriptionSource(String);
+
+impl SubscriptionSource {
+    const MAX_LENGTH: usize = 64;
+
+    pub fn parse(source: String) -> Result<Self, String> {
+        let source = source.trim().to_string();
+        if source.is_empty() {
+            return Err("Subscription source cannot be empty".into());
+        }
+
+        if source.len() > Self::MAX_LENGTH {
+            return Err(format!(
+                "Subscription source must not be longer than {} characters",
+                Self::MAX_LENGTH
+            ));
+        }
+
+        if !source
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || ['-', '_', '.'].contains(&c))
+        {
+            return Err(
+                "Subscription source can only contain letters, digits, `-`, `_` and `.`".into(),
+            );
+        }
+
+        Ok(Self(source))
+    }
+
+    // Forms send empty fields when they're left blank, treat them as absent
+    pub fn parse_optional(source: Option<String>) -> Result<Option<Self>, String> {
+        match source {
+            Some(source) if !source.trim().is_empty() => Self::parse(source).map(Some),
+            _ => Ok(None),
+        }
+    }
+}
+
+impl AsRef<str> for SubscriptionSource {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::routes::SubscriptionSource;
+    use claims::{assert_err, assert_none, assert_ok};
+
+    #[test]
+    fn utm_like_values_are_accepted() {
+        for source in ["twitter", "spring_sale-2023", "news.example.com"] {
+            assert_ok!(SubscriptionSource::parse(source.to_string()));
+        }
+    }
+
+    #[test]
+    fn values_with_unsafe_characters_are_rejected() {
+        for source in ["<script>", "a b", "a/b", "ả"] {
+            assert_err!(SubscriptionSource::parse(source.to_string()));
+        }
+    }
+
+    #[test]
+    fn too_long_value_is_rejected() {
+        assert_ok!(SubscriptionSource::parse("a".repeat(64)));
+        assert_err!(SubscriptionSource::parse("a".repeat(65)));
+    }
+
+    #[test]
+    fn blank_optional_value_is_treated_as_absent() {
+        assert_none!(assert_ok!(SubscriptionSource::parse_optional(None)));
+        assert_none!(assert_ok!(SubscriptionSource::parse_optional(Some(
+            " ".to_string()
+        ))));
+    }
+}
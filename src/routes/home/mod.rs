@@ -1,8 +1,11 @@
-use actix_web::http::header::ContentType;
-use actix_web::HttpResponse;
+use crate::middleware::BasePath;
+use crate::utils::html_response;
+use actix_web::{web, HttpResponse};
 
-pub async fn home() -> HttpResponse {
-    HttpResponse::Ok()
-        .content_type(ContentType::html())
-        .body(include_str!("home.html"))
+pub async fn home(base_path: web::Data<BasePath>) -> HttpResponse {
+    html_response(
+        include_str!("home.html")
+            .replace("{{subscribe_href}}", &base_path.href("/subscriptions"))
+            .replace("{{login_href}}", &base_path.href("/login")),
+    )
 }
@@ -1,18 +1,42 @@
-use actix_web::http::header::ContentType;
-use actix_web::HttpResponse;
+use crate::middleware::BasePath;
+use crate::routes::login::redirect::{parse_login_redirect, LoginRedirectQuery};
+use crate::utils::{cacheable_html, uncacheable_html};
+use actix_web::{web, HttpRequest, HttpResponse};
 use actix_web_flash_messages::IncomingFlashMessages;
 use std::fmt::Write;
 
-pub async fn login_form(messages: IncomingFlashMessages) -> HttpResponse {
+// Form is static unless it carries flash messages, so it's cacheable without them
+pub async fn login_form(
+    request: HttpRequest,
+    web::Query(query): web::Query<LoginRedirectQuery>,
+    messages: IncomingFlashMessages,
+    base_path: web::Data<BasePath>,
+) -> HttpResponse {
     let mut flash_msg = "".to_string();
     for msg in messages.iter() {
         let _ = writeln!(flash_msg, "<p><i>{}</i></p>", msg.content());
     }
 
-    HttpResponse::Ok()
-        .content_type(ContentType::html())
-        .body(format!(
-            r#"
+    // Invalid target is dropped, so it's never rendered into the page
+    let next_input = match parse_login_redirect(query.next) {
+        Some(next) => format!(
+            r#"<input hidden type="text" name="next" value="{}">"#,
+            htmlescape::encode_minimal(&next)
+        ),
+        None => "".to_string(),
+    };
+    let html = render_login_form(&flash_msg, &next_input, &base_path);
+    if flash_msg.is_empty() {
+        cacheable_html(&request, html)
+    } else {
+        uncacheable_html(html)
+    }
+}
+
+fn render_login_form(flash_msg: &str, next_input: &str, base_path: &BasePath) -> String {
+    let login_href = base_path.href("/login");
+    format!(
+        r#"
                <!DOCTYPE html>
 <html lang="en">
 <head>
@@ -20,7 +44,7 @@ pub async fn login_form(messages: IncomingFlashMessages) -> HttpResponse {
     <title>Login</title>
 </head>
 <body>
-<form action="/login" method="POST">
+<form action="{login_href}" method="POST">
     {flash_msg}
     <label>Username
         <input
@@ -38,10 +62,19 @@ pub async fn login_form(messages: IncomingFlashMessages) -> HttpResponse {
         >
     </label>
     <br>
+    <label>
+        <input
+                type="checkbox"
+                name="remember_me"
+        >
+        Remember me
+    </label>
+    <br>
+    {next_input}
     <button type="submit">Login</button>
 </form>
 </body>
 </html>
             "#
-        ))
+    )
 }
@@ -1,4 +1,10 @@
-use crate::authentication::{validate_credentials, AuthError, Credentials, UserSession};
+use crate::authentication::{
+    get_user_totp_secret, validate_credentials, AuthError, Credentials, UserSession,
+};
+use crate::routes::login::redirect::{
+    parse_login_redirect, LoginRedirectQuery, DEFAULT_LOGIN_REDIRECT,
+};
+use crate::telemetry::redact_pii;
 use crate::utils::error_chain_fmt;
 use actix_web::http::header::LOCATION;
 use actix_web::http::StatusCode;
@@ -38,11 +44,17 @@ impl ResponseError for LoginError {
 pub struct UserLoginForm {
     username: String,
     password: Secret<String>,
+    // Checkbox is only sent when it is checked
+    #[serde(default)]
+    remember_me: Option<String>,
+    // Path to go after login, login form passes it on from its query
+    #[serde(default)]
+    next: Option<String>,
 }
 
 #[tracing::instrument(
     name = "Login a user input", 
-    skip(login_form, pg_pool, session),
+    skip(login_form, query, pg_pool, session),
     fields(
     username=tracing::field::Empty,
     user_id=tracing::field::Empty
@@ -50,25 +62,54 @@ pub struct UserLoginForm {
 )]
 pub async fn login(
     web::Form(login_form): web::Form<UserLoginForm>,
+    web::Query(query): web::Query<LoginRedirectQuery>,
     pg_pool: web::Data<PgPool>,
     session: UserSession,
 ) -> Result<HttpResponse, LoginError> {
+    // Invalid target falls back to default instead of failing login
+    let next = parse_login_redirect(login_form.next.or(query.next));
     let credentials = Credentials {
         username: login_form.username,
         password: login_form.password,
     };
-    tracing::Span::current().record("username", tracing::field::display(&credentials.username));
+    tracing::Span::current().record(
+        "username",
+        tracing::field::display(redact_pii(&credentials.username)),
+    );
 
     match validate_credentials(&pg_pool, credentials).await {
         Ok(user_id) => {
             tracing::Span::current().record("user_id", tracing::field::display(&user_id));
 
+            let totp_secret = get_user_totp_secret(&pg_pool, &user_id)
+                .await
+                .map_err(LoginError::UnexpectedError)?;
+
             session.renew();
+            // Keep choice across two-factor authentication step
+            session
+                .insert_remember_me(login_form.remember_me.is_some())
+                .map_err(|e| LoginError::UnexpectedError(anyhow::anyhow!(e)))?;
+            // Keep admin pages blocked until user verifies TOTP code
+            if totp_secret.is_some() {
+                session
+                    .insert_awaiting_2fa_user_id(user_id)
+                    .map_err(|e| LoginError::UnexpectedError(anyhow::anyhow!(e)))?;
+                if let Some(next) = &next {
+                    session
+                        .insert_login_redirect(next)
+                        .map_err(|e| LoginError::UnexpectedError(anyhow::anyhow!(e)))?;
+                }
+                return Ok(HttpResponse::SeeOther()
+                    .insert_header((LOCATION, "/login/2fa"))
+                    .finish());
+            }
+
             session
                 .insert_user_id(user_id)
                 .map_err(|e| LoginError::UnexpectedError(anyhow::anyhow!(e)))?;
             Ok(HttpResponse::SeeOther()
-                .insert_header((LOCATION, "/admin/dashboard"))
+                .insert_header((LOCATION, next.as_deref().unwrap_or(DEFAULT_LOGIN_REDIRECT)))
                 .finish())
         }
         Err(error) => {
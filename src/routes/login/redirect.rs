@@ -0,0 +1,57 @@
+// Where user lands after login when no valid `next` is given
+pub const DEFAULT_LOGIN_REDIRECT: &str = "/admin/dashboard";
+
+#[derive(serde::Deserialize)]
+pub struct LoginRedirectQuery {
+    pub next: Option<String>,
+}
+
+// Only local paths are followed, otherwise login links could redirect users to phishing sites
+// `//host` and `/\host` are treated as absolute URLs by browsers, so they are rejected too
+pub fn parse_login_redirect(next: Option<String>) -> Option<String> {
+    next.filter(|next| {
+        next.starts_with('/')
+            && !next.starts_with("//")
+            && !next.contains('\\')
+            && !next.chars().any(|c| c.is_control() || c.is_whitespace())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::routes::login::redirect::parse_login_redirect;
+
+    #[test]
+    fn local_paths_are_accepted() {
+        for next in [
+            "/admin/newsletters",
+            "/admin/subscribers/stats?status=confirmed",
+        ] {
+            assert_eq!(
+                parse_login_redirect(Some(next.to_string())).as_deref(),
+                Some(next)
+            );
+        }
+    }
+
+    #[test]
+    fn absolute_urls_and_protocol_relative_paths_are_rejected() {
+        for next in [
+            "https://evil.example.com",
+            "//evil.example.com",
+            "/\\evil.example.com",
+            "\\\\evil.example.com",
+            "javascript:alert(1)",
+            "admin/dashboard",
+            "/\t/evil.example.com",
+            "",
+        ] {
+            assert_eq!(
+                parse_login_redirect(Some(next.to_string())),
+                None,
+                "{}",
+                next
+            );
+        }
+    }
+}
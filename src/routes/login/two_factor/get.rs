@@ -0,0 +1,51 @@
+use crate::authentication::UserSession;
+use crate::middleware::BasePath;
+use crate::utils::{e500, html_response, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use std::fmt::Write;
+
+pub async fn two_factor_login_form(
+    session: UserSession,
+    messages: IncomingFlashMessages,
+    base_path: web::Data<BasePath>,
+) -> Result<HttpResponse, actix_web::Error> {
+    // Only users who passed password validation can verify TOTP code
+    if session.get_awaiting_2fa_user_id().map_err(e500)?.is_none() {
+        return Ok(see_other("/login"));
+    }
+
+    let mut flash_msg = "".to_string();
+    for msg in messages.iter() {
+        let _ = writeln!(flash_msg, "<p><i>{}</i></p>", msg.content());
+    }
+
+    let two_factor_href = base_path.href("/login/2fa");
+    Ok(html_response(format!(
+        r#"
+               <!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Two-factor authentication</title>
+</head>
+<body>
+<form action="{two_factor_href}" method="POST">
+    {flash_msg}
+    <label>Authentication code
+        <input
+                type="text"
+                inputmode="numeric"
+                autocomplete="one-time-code"
+                placeholder="6-digit code"
+                name="code"
+        >
+    </label>
+    <br>
+    <button type="submit">Verify</button>
+</form>
+</body>
+</html>
+            "#
+    )))
+}
@@ -0,0 +1,68 @@
+use crate::authentication::{
+    get_user_totp_secret, record_totp_step, verify_totp_code, UserSession,
+};
+use crate::routes::login::redirect::DEFAULT_LOGIN_REDIRECT;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+
+// Force user to login with password again after too many wrong codes
+// To avoid brute forcing 6-digit codes within a single session
+const MAX_FAILED_2FA_ATTEMPTS: u32 = 5;
+
+#[derive(serde::Deserialize)]
+pub struct TwoFactorLoginForm {
+    code: String,
+}
+
+#[tracing::instrument(
+    name = "Verify two-factor authentication code",
+    skip(form, pg_pool, session),
+    fields(user_id = tracing::field::Empty)
+)]
+pub async fn two_factor_login(
+    web::Form(form): web::Form<TwoFactorLoginForm>,
+    pg_pool: web::Data<PgPool>,
+    session: UserSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = match session.get_awaiting_2fa_user_id().map_err(e500)? {
+        Some(user_id) => user_id,
+        None => return Ok(see_other("/login")),
+    };
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+
+    let totp_secret = match get_user_totp_secret(&pg_pool, &user_id)
+        .await
+        .map_err(e500)?
+    {
+        Some(totp_secret) => totp_secret,
+        // Two-factor authentication was disabled in the meantime
+        None => {
+            session.logout();
+            return Ok(see_other("/login"));
+        }
+    };
+
+    // Replayed code is treated like a wrong one, so it counts towards failed attempts
+    let is_accepted = match verify_totp_code(&totp_secret, &form.code).map_err(e500)? {
+        Some(step) => record_totp_step(&pg_pool, &user_id, step)
+            .await
+            .map_err(e500)?,
+        None => false,
+    };
+    if !is_accepted {
+        if session.increase_failed_2fa_attempts().map_err(e500)? >= MAX_FAILED_2FA_ATTEMPTS {
+            session.logout();
+            FlashMessage::error("Too many invalid authentication codes, please login again").send();
+            return Ok(see_other("/login"));
+        }
+        FlashMessage::error("Invalid authentication code").send();
+        return Ok(see_other("/login/2fa"));
+    }
+
+    // Target was validated when it was stored at login
+    let next = session.take_login_redirect().map_err(e500)?;
+    session.complete_2fa(user_id).map_err(e500)?;
+    Ok(see_other(next.as_deref().unwrap_or(DEFAULT_LOGIN_REDIRECT)))
+}
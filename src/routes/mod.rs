@@ -4,8 +4,11 @@ mod domain;
 mod home;
 mod login;
 pub mod subscriptions;
+mod version;
+pub mod webhooks;
 
 pub use check_health::*;
 pub use domain::*;
 pub use home::*;
 pub use login::*;
+pub use version::*;
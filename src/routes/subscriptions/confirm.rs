@@ -1,6 +1,17 @@
-use crate::routes::SubscriptionStatus;
-use actix_web::{web, HttpResponse, Responder};
+use crate::confirmation_emails::WelcomeEmailTemplate;
+use crate::email_client::EmailClient;
+use crate::middleware::BasePath;
+use crate::routes::subscriptions::SubscriptionTokenExpiration;
+use crate::routes::{SubscriberEmail, SubscriptionStatus};
+use crate::telemetry::redact_pii;
+use crate::utils::{error_chain_fmt, html_error_response};
+use actix_web::http::header::ContentType;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, ResponseError};
+use anyhow::Context;
 use sqlx::PgPool;
+use std::fmt::{Debug, Formatter};
+use std::time::Duration;
 use uuid::Uuid;
 
 #[derive(serde::Deserialize)]
@@ -8,72 +19,180 @@ pub struct ConfirmTokenParam {
     pub subscription_token: String,
 }
 
+#[derive(thiserror::Error)]
+pub enum ConfirmError {
+    #[error("Unknown subscription token")]
+    UnknownToken,
+    // Subscriber is pointed to where a new confirmation email can be requested
+    #[error("Confirmation link is expired")]
+    ExpiredToken { resend_href: String },
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl Debug for ConfirmError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ConfirmError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ConfirmError::UnknownToken => StatusCode::NOT_FOUND,
+            ConfirmError::ExpiredToken { .. } => StatusCode::GONE,
+            ConfirmError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            ConfirmError::ExpiredToken { resend_href } => html_error_response(
+                self.status_code(),
+                format!(
+                    r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Confirmation link expired</title>
+</head>
+<body>
+    <p>This confirmation link has expired.</p>
+    <p><a href="{}">Send me a new confirmation email</a></p>
+</body>
+</html>"#,
+                    resend_href
+                ),
+            ),
+            _ => HttpResponse::build(self.status_code())
+                .content_type(ContentType::plaintext())
+                .body(self.to_string()),
+        }
+    }
+}
+
 #[tracing::instrument(
     name = "Confirm a pending subscriber",
-    skip(subscription_token, pg_pool)
+    skip(
+        subscription_token,
+        pg_pool,
+        expiration,
+        email_client,
+        welcome_email_template,
+        base_path
+    )
 )]
 pub async fn confirm(
     web::Query(ConfirmTokenParam { subscription_token }): web::Query<ConfirmTokenParam>,
     pg_pool: web::Data<PgPool>,
-) -> impl Responder {
-    let subscription_id =
-        match get_subscription_id_from_subscription_tokens(&subscription_token, &pg_pool).await {
-            Ok(id) => id,
-            Err(_) => return HttpResponse::InternalServerError().finish(),
-        };
-
-    match get_subscription_status(&subscription_id, &pg_pool).await {
-        Ok(status) => {
-            if status == SubscriptionStatus::Pending.as_ref()
-                && update_subscriber_status_to_confirmed(&subscription_id, &pg_pool)
-                    .await
-                    .is_err()
-            {
-                return HttpResponse::InternalServerError().finish();
-            }
-            HttpResponse::Ok().finish()
+    expiration: web::Data<SubscriptionTokenExpiration>,
+    email_client: web::Data<EmailClient>,
+    welcome_email_template: web::Data<WelcomeEmailTemplate>,
+    base_path: web::Data<BasePath>,
+) -> Result<HttpResponse, ConfirmError> {
+    let (subscription_id, expired) =
+        get_subscription_id_from_subscription_tokens(&subscription_token, expiration.0, &pg_pool)
+            .await
+            .context("Failed to get subscription id from subscription tokens")?
+            .ok_or(ConfirmError::UnknownToken)?;
+
+    if expired {
+        return Err(ConfirmError::ExpiredToken {
+            resend_href: base_path.href("/subscriptions/confirm/resend"),
+        });
+    }
+
+    let status = get_subscription_status(&subscription_id, &pg_pool)
+        .await
+        .context("Failed to get subscription status")?;
+    if status == SubscriptionStatus::Pending {
+        let confirmed_subscriber =
+            update_subscriber_status_to_confirmed(&subscription_id, &pg_pool)
+                .await
+                .context("Failed to update subscriber status to confirmed")?;
+        if let Some(subscriber) = confirmed_subscriber {
+            send_welcome_email(&email_client, &welcome_email_template, &subscriber).await;
+        }
+    }
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+pub struct ConfirmedSubscriber {
+    pub email: String,
+    pub name: String,
+}
+
+// Confirmation is already committed, so failing to welcome subscriber is only logged
+#[tracing::instrument(
+    name = "Send welcome email to confirmed subscriber",
+    skip_all,
+    fields(subscriber_email = %redact_pii(&subscriber.email))
+)]
+pub async fn send_welcome_email(
+    email_client: &EmailClient,
+    template: &WelcomeEmailTemplate,
+    subscriber: &ConfirmedSubscriber,
+) {
+    let result = match SubscriberEmail::parse(subscriber.email.clone()) {
+        Ok(subscriber_email) => {
+            let email = template.render(&subscriber.name);
+            email_client
+                .send_multipart_email(
+                    &subscriber_email,
+                    email.subject,
+                    Some(&email.text_body),
+                    Some(&email.html_body),
+                )
+                .await
+                .map(|_| ())
         }
-        Err(_) => HttpResponse::InternalServerError().finish(),
+        Err(e) => Err(anyhow::anyhow!(e)),
+    };
+    if let Err(e) = result {
+        tracing::error!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            "Failed to send welcome email"
+        );
     }
 }
 
+// Return subscription id and whether subscription token is older than expiration
 #[tracing::instrument(
     name = "Get subscription_id from the subscription_tokens by subscription_token"
     skip(subscription_token, pg_pool)
 )]
-async fn get_subscription_id_from_subscription_tokens(
+pub async fn get_subscription_id_from_subscription_tokens(
     subscription_token: &str,
+    expiration: Duration,
     pg_pool: &PgPool,
-) -> Result<Uuid, sqlx::Error> {
+) -> Result<Option<(Uuid, bool)>, sqlx::Error> {
     let result = sqlx::query!(
         r#"
-        SELECT subscription_id
+        SELECT
+            subscription_id,
+            created_at + make_interval(secs => $2) < now() as "expired!"
         FROM subscription_tokens
         WHERE subscription_token = $1
         "#,
-        subscription_token
+        subscription_token,
+        expiration.as_secs_f64()
     )
-    .fetch_one(pg_pool)
-    .await
-    .map_err(|e| {
-        tracing::error!(
-            "Failed to get subscription id from subscription tokens: {}",
-            e
-        );
-        e
-    })?;
+    .fetch_optional(pg_pool)
+    .await?;
 
-    Ok(result.subscription_id)
+    Ok(result.map(|r| (r.subscription_id, r.expired)))
 }
 
 #[tracing::instrument(
-name = "Get the subscription status from the subscriptions by subscription id"
-skip(subscription_id, pg_pool)
+    name = "Get the subscription status from the subscriptions by subscription id"
+    skip(subscription_id, pg_pool)
 )]
-async fn get_subscription_status(
+pub async fn get_subscription_status(
     subscription_id: &Uuid,
     pg_pool: &PgPool,
-) -> Result<String, sqlx::Error> {
+) -> Result<SubscriptionStatus, anyhow::Error> {
     let result = sqlx::query!(
         r#"
         SELECT status
@@ -83,38 +202,47 @@ async fn get_subscription_status(
         subscription_id
     )
     .fetch_one(pg_pool)
-    .await
-    .map_err(|e| {
-        tracing::error!("Failed to get subscription status: {}", e);
-        e
-    })?;
+    .await?;
+
+    parse_subscription_status(&result.status)
+}
 
-    Ok(result.status)
+// Status column is only written from `SubscriptionStatus`, unknown values mean database is tampered
+pub fn parse_subscription_status(status: &str) -> Result<SubscriptionStatus, anyhow::Error> {
+    SubscriptionStatus::try_from(status)
+        .with_context(|| format!("Unknown subscription status `{}` in database", status))
 }
 
+// Return confirmed subscriber only when it was pending,
+// so concurrent confirmations of the same subscriber welcome it once
 #[tracing::instrument(
     name = "Update subscriber status to confirmed",
     skip(subscription_id, pg_pool)
 )]
-async fn update_subscriber_status_to_confirmed(
+pub async fn update_subscriber_status_to_confirmed(
     subscription_id: &Uuid,
     pg_pool: &PgPool,
-) -> Result<(), sqlx::Error> {
-    sqlx::query!(
+) -> Result<Option<ConfirmedSubscriber>, sqlx::Error> {
+    let result = sqlx::query!(
         r#"
         UPDATE subscriptions
         SET status = $1
-        WHERE id = $2
+        WHERE id = $2 AND status = $3
+        RETURNING delivery_email, name
         "#,
         SubscriptionStatus::Confirmed.as_ref(),
-        subscription_id
+        subscription_id,
+        SubscriptionStatus::Pending.as_ref()
     )
-    .execute(pg_pool)
+    .fetch_optional(pg_pool)
     .await
     .map_err(|e| {
         tracing::error!("Failed to update subscriber status to confirmed: {}", e);
         e
     })?;
 
-    Ok(())
+    Ok(result.map(|r| ConfirmedSubscriber {
+        email: r.delivery_email,
+        name: r.name,
+    }))
 }
@@ -0,0 +1,206 @@
+use crate::authentication::{verify_password_hash, AuthError};
+use crate::confirmation_emails::WelcomeEmailTemplate;
+use crate::email_client::EmailClient;
+use crate::routes::subscriptions::{
+    parse_subscription_status, send_welcome_email, update_subscriber_status_to_confirmed,
+};
+use crate::routes::{SubscriberEmail, SubscriptionStatus};
+use crate::telemetry::redact_pii;
+use crate::utils::{error_chain_fmt, spawn_blocking_task_with_tracing};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, ResponseError};
+use anyhow::Context;
+use secrecy::Secret;
+use sqlx::PgPool;
+use std::fmt::{Debug, Formatter};
+use std::time::Duration;
+use uuid::Uuid;
+
+// Code only has 10^6 values, so guesses are limited per code rather than relying on rate limit by IP
+const MAX_FAILED_CONFIRMATION_CODE_ATTEMPTS: i32 = 5;
+
+// How long subscription token and confirmation code are valid after subscribing
+pub struct SubscriptionTokenExpiration(pub Duration);
+
+#[derive(serde::Deserialize)]
+pub struct ConfirmCodeForm {
+    email: String,
+    code: Secret<String>,
+}
+
+#[derive(thiserror::Error)]
+pub enum ConfirmCodeError {
+    // Same error for unknown email and wrong code, to not leak which emails are subscribed
+    #[error("Invalid email or confirmation code")]
+    InvalidCode(#[source] anyhow::Error),
+    #[error("Confirmation code is expired")]
+    ExpiredCode,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl Debug for ConfirmCodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ConfirmCodeError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ConfirmCodeError::InvalidCode(_) => StatusCode::UNAUTHORIZED,
+            ConfirmCodeError::ExpiredCode => StatusCode::GONE,
+            ConfirmCodeError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[tracing::instrument(
+    name = "Confirm a pending subscriber by confirmation code",
+    skip(code, pg_pool, expiration, email_client, welcome_email_template),
+    fields(email = %redact_pii(&email))
+)]
+pub async fn confirm_code(
+    web::Form(ConfirmCodeForm { email, code }): web::Form<ConfirmCodeForm>,
+    pg_pool: web::Data<PgPool>,
+    expiration: web::Data<SubscriptionTokenExpiration>,
+    email_client: web::Data<EmailClient>,
+    welcome_email_template: web::Data<WelcomeEmailTemplate>,
+) -> Result<HttpResponse, ConfirmCodeError> {
+    // Subscribers are stored by canonical email, so the code can be confirmed with any plus tag
+    let email = SubscriberEmail::parse_canonical(email)
+        .map_err(|e| ConfirmCodeError::InvalidCode(anyhow::anyhow!(e)))?;
+    let record = get_confirmation_code_record(&email, expiration.0, &pg_pool)
+        .await
+        .context("Failed to get confirmation code from database")?
+        .ok_or_else(|| {
+            ConfirmCodeError::InvalidCode(anyhow::anyhow!("No confirmation code for this email"))
+        })?;
+
+    // Attempt is counted before verifying, so concurrent guesses can't go over the limit
+    let attempt_reserved = reserve_confirmation_code_attempt(&record.subscription_token, &pg_pool)
+        .await
+        .context("Failed to count confirmation code attempt")?;
+    if !attempt_reserved {
+        return Err(ConfirmCodeError::InvalidCode(anyhow::anyhow!(
+            "Too many failed confirmation code attempts"
+        )));
+    }
+
+    // Verify code before checking expiration, so expiration is only revealed to code owner
+    spawn_blocking_task_with_tracing(move || {
+        verify_password_hash(code, Secret::new(record.confirmation_code_hash))
+    })
+    .await
+    .context("Failed to spawn blocking task")?
+    .map_err(|e| match e {
+        AuthError::InvalidCredentials(_) => ConfirmCodeError::InvalidCode(e.into()),
+        AuthError::UnexpectedError(_) => ConfirmCodeError::UnexpectedError(e.into()),
+    })?;
+    // Only failed attempts count towards the limit
+    release_confirmation_code_attempt(&record.subscription_token, &pg_pool)
+        .await
+        .context("Failed to release confirmation code attempt")?;
+
+    if record.expired {
+        return Err(ConfirmCodeError::ExpiredCode);
+    }
+
+    if parse_subscription_status(&record.status)? == SubscriptionStatus::Pending {
+        let confirmed_subscriber =
+            update_subscriber_status_to_confirmed(&record.subscription_id, &pg_pool)
+                .await
+                .context("Failed to update subscriber status to confirmed")?;
+        if let Some(subscriber) = confirmed_subscriber {
+            send_welcome_email(&email_client, &welcome_email_template, &subscriber).await;
+        }
+    }
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+struct ConfirmationCodeRecord {
+    subscription_id: Uuid,
+    subscription_token: String,
+    status: String,
+    confirmation_code_hash: String,
+    expired: bool,
+}
+
+#[tracing::instrument(
+    name = "Get confirmation code of subscriber from database",
+    skip(pg_pool)
+)]
+async fn get_confirmation_code_record(
+    email: &SubscriberEmail,
+    expiration: Duration,
+    pg_pool: &PgPool,
+) -> Result<Option<ConfirmationCodeRecord>, sqlx::Error> {
+    let record = sqlx::query!(
+        r#"
+        SELECT
+            s.id,
+            s.status,
+            t.subscription_token,
+            t.confirmation_code_hash as "confirmation_code_hash!",
+            t.created_at + make_interval(secs => $2) < now() as "expired!"
+        FROM subscriptions s
+        JOIN subscription_tokens t ON t.subscription_id = s.id
+        WHERE s.email = $1 AND t.confirmation_code_hash IS NOT NULL
+        ORDER BY t.created_at DESC
+        LIMIT 1
+        "#,
+        email.as_ref(),
+        expiration.as_secs_f64()
+    )
+    .fetch_optional(pg_pool)
+    .await?;
+
+    Ok(record.map(|r| ConfirmationCodeRecord {
+        subscription_id: r.id,
+        subscription_token: r.subscription_token,
+        status: r.status,
+        confirmation_code_hash: r.confirmation_code_hash,
+        expired: r.expired,
+    }))
+}
+
+// Returns false when code has already been guessed wrong too many times
+#[tracing::instrument(name = "Reserve confirmation code attempt", skip_all)]
+async fn reserve_confirmation_code_attempt(
+    subscription_token: &str,
+    pg_pool: &PgPool,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE subscription_tokens
+        SET failed_confirmation_code_attempts = failed_confirmation_code_attempts + 1
+        WHERE subscription_token = $1 AND failed_confirmation_code_attempts < $2
+        "#,
+        subscription_token,
+        MAX_FAILED_CONFIRMATION_CODE_ATTEMPTS
+    )
+    .execute(pg_pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+#[tracing::instrument(name = "Release confirmation code attempt", skip_all)]
+async fn release_confirmation_code_attempt(
+    subscription_token: &str,
+    pg_pool: &PgPool,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE subscription_tokens
+        SET failed_confirmation_code_attempts = failed_confirmation_code_attempts - 1
+        WHERE subscription_token = $1
+        "#,
+        subscription_token
+    )
+    .execute(pg_pool)
+    .await?;
+
+    Ok(())
+}
@@ -1,5 +1,13 @@
 mod confirm;
+mod confirm_code;
+mod resend;
+mod status;
 mod subscribe;
+mod unsubscribe;
 
 pub use confirm::*;
+pub use confirm_code::*;
+pub use resend::*;
+pub use status::*;
 pub use subscribe::*;
+pub use unsubscribe::*;
@@ -0,0 +1,159 @@
+use crate::confirmation_emails::{enqueue_confirmation_email, ConfirmationEmailTemplate};
+use crate::middleware::BasePath;
+use crate::routes::subscriptions::{
+    create_subscription_token, delete_subscription_tokens, SubscriptionTokenLength,
+};
+use crate::routes::{SubscriberEmail, SubscriptionStatus};
+use crate::telemetry::redact_pii;
+use crate::utils::{error_chain_fmt, html_response};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, ResponseError};
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, Transaction};
+use std::fmt::{Debug, Formatter};
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct ResendConfirmationForm {
+    email: String,
+}
+
+#[derive(thiserror::Error)]
+pub enum ResendConfirmationError {
+    #[error("{0}")]
+    InvalidEmail(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl Debug for ResendConfirmationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ResendConfirmationError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ResendConfirmationError::InvalidEmail(_) => StatusCode::BAD_REQUEST,
+            ResendConfirmationError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[tracing::instrument(name = "Show resend confirmation email page", skip_all)]
+pub async fn resend_confirmation_form(base_path: web::Data<BasePath>) -> HttpResponse {
+    html_response(format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Resend confirmation email</title>
+</head>
+<body>
+    <form action="{}" method="post">
+        <label>Email
+            <input type="email" placeholder="Enter your email" name="email">
+        </label>
+        <button type="submit">Send me a new confirmation email</button>
+    </form>
+</body>
+</html>"#,
+        base_path.href("/subscriptions/confirm/resend")
+    ))
+}
+
+// Old tokens of subscriber are replaced, so only the newest link and code confirm the subscription
+// Response is the same whether email is waiting for confirmation or not, to not leak subscribed emails
+#[tracing::instrument(
+    name = "Resend confirmation email to a pending subscriber",
+    skip(pg_pool, token_length, template),
+    fields(email = %redact_pii(&email))
+)]
+pub async fn resend_confirmation(
+    web::Form(ResendConfirmationForm { email }): web::Form<ResendConfirmationForm>,
+    pg_pool: web::Data<PgPool>,
+    token_length: web::Data<SubscriptionTokenLength>,
+    template: web::Data<ConfirmationEmailTemplate>,
+) -> Result<HttpResponse, ResendConfirmationError> {
+    // Subscribers are stored by canonical email, so any plus tag of the mailbox finds them
+    let email =
+        SubscriberEmail::parse_canonical(email).map_err(ResendConfirmationError::InvalidEmail)?;
+
+    let mut transaction = pg_pool
+        .begin()
+        .await
+        .context("Failed to begin a database transaction")?;
+    if let Some((subscription_id, delivery_email)) =
+        get_pending_subscriber(&email, &mut transaction)
+            .await
+            .context("Failed to get pending subscriber")?
+    {
+        delete_subscription_tokens(&subscription_id, &mut transaction)
+            .await
+            .context("Failed to delete old subscription tokens")?;
+        let (subscription_token, confirmation_code) = create_subscription_token(
+            &subscription_id,
+            token_length.0,
+            &template,
+            &mut transaction,
+        )
+        .await?;
+        // Delivered by background worker, which retries when sending fails
+        enqueue_confirmation_email(
+            &mut transaction,
+            &subscription_token,
+            confirmation_code.as_deref(),
+            &delivery_email,
+        )
+        .await
+        .context("Failed to enqueue confirmation email")?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit a database transaction")?;
+
+    Ok(html_response(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Resend confirmation email</title>
+</head>
+<body>
+    <p>If this email is waiting for confirmation, a new confirmation email is on its way.</p>
+</body>
+</html>"#
+            .to_string(),
+    ))
+}
+
+// Return subscription id and the address confirmation email is delivered to
+#[tracing::instrument(name = "Get pending subscriber by email", skip_all)]
+async fn get_pending_subscriber(
+    email: &SubscriberEmail,
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<Option<(Uuid, SubscriberEmail)>, anyhow::Error> {
+    let record = sqlx::query!(
+        r#"
+        SELECT id, delivery_email
+        FROM subscriptions
+        WHERE email = $1 AND status = $2
+        FOR UPDATE
+        "#,
+        email.as_ref(),
+        SubscriptionStatus::Pending.as_ref()
+    )
+    .fetch_optional(transaction)
+    .await?;
+
+    match record {
+        Some(record) => {
+            let delivery_email =
+                SubscriberEmail::parse(record.delivery_email).map_err(anyhow::Error::msg)?;
+            Ok(Some((record.id, delivery_email)))
+        }
+        None => Ok(None),
+    }
+}
@@ -0,0 +1,42 @@
+use crate::routes::subscriptions::{
+    get_subscription_id_from_subscription_tokens, get_subscription_status,
+    SubscriptionTokenExpiration,
+};
+use crate::utils::e500;
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct SubscriptionStatusQuery {
+    token: String,
+}
+
+// Email is not returned, so token can't be used to find out who is subscribed
+#[derive(serde::Serialize)]
+struct SubscriptionStatusResponse {
+    status: &'static str,
+}
+
+#[tracing::instrument(name = "Get subscription status by subscription token", skip_all)]
+pub async fn subscription_status(
+    web::Query(SubscriptionStatusQuery { token }): web::Query<SubscriptionStatusQuery>,
+    pg_pool: web::Data<PgPool>,
+    expiration: web::Data<SubscriptionTokenExpiration>,
+) -> Result<HttpResponse, actix_web::Error> {
+    // Status can still be checked with an expired token
+    let subscription_id =
+        match get_subscription_id_from_subscription_tokens(&token, expiration.0, &pg_pool)
+            .await
+            .map_err(e500)?
+        {
+            Some((subscription_id, _)) => subscription_id,
+            None => return Ok(HttpResponse::NotFound().finish()),
+        };
+
+    let status = get_subscription_status(&subscription_id, &pg_pool)
+        .await
+        .map_err(e500)?;
+    Ok(HttpResponse::Ok().json(SubscriptionStatusResponse {
+        status: status.into(),
+    }))
+}
@@ -1,28 +1,58 @@
+use crate::authentication::hash_password;
+use crate::confirmation_emails::{
+    enqueue_confirmation_email, ConfirmationEmailRetryPolicy, ConfirmationEmailTemplate,
+};
 use crate::email_client::EmailClient;
-use crate::routes::domain::{NewSubscriber, SubscriberEmail, SubscriberName, SubscriptionStatus};
-use crate::utils::error_chain_fmt;
-use actix_web::{web, HttpResponse, ResponseError};
+use crate::idempotency::{
+    derive_form_idempotency_key, get_idempotency_key,
+    try_insert_idempotency_response_record_into_database, update_idempotency_response_record,
+    IdempotencyError, IdempotencyKey, IdempotencyMaxStoredBodySize, IdempotencyReplayMaxAge,
+    IdempotentRequest, ProcessState, IDEMPOTENCY_KEY_HEADER,
+};
+use crate::mx_check::MxChecker;
+use crate::routes::domain::{
+    NewSubscriber, SubscriberEmail, SubscriberName, SubscriptionSource, SubscriptionStatus,
+};
+use crate::telemetry::redact_pii;
+use crate::utils::{
+    error_chain_fmt, generate_secure_token, is_pool_exhausted, spawn_blocking_task_with_tracing,
+    POOL_EXHAUSTED_RETRY_AFTER_SECS,
+};
+use actix_web::http::header::{ContentType, RETRY_AFTER};
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
 use anyhow::Context;
 use chrono::Utc;
-use rand::distributions::Alphanumeric;
 use rand::Rng;
 use serde::Deserialize;
 use sqlx::{PgPool, Postgres, Transaction};
 use std::fmt::{Debug, Display, Formatter};
 use uuid::Uuid;
 
+// Number of characters in subscription tokens sent in confirmation links
+pub struct SubscriptionTokenLength(pub usize);
+
 #[derive(Deserialize)]
 pub struct NewSubscriberForm {
     name: String,
     email: String,
+    source: Option<String>,
+    utm_campaign: Option<String>,
+    // Optional, `Idempotency-Key` header is used when present
+    idempotency_key: Option<String>,
 }
 
 impl TryInto<NewSubscriber> for NewSubscriberForm {
     type Error = String;
     fn try_into(self) -> Result<NewSubscriber, Self::Error> {
+        let delivery_email = SubscriberEmail::parse(self.email)?;
+        let (email, plus_tag) = delivery_email.clone().split_plus_tag();
         Ok(NewSubscriber {
             name: SubscriberName::parse(self.name)?,
-            email: SubscriberEmail::parse(self.email)?,
+            email,
+            plus_tag,
+            delivery_email,
+            source: SubscriptionSource::parse_optional(self.source)?,
+            utm_campaign: SubscriptionSource::parse_optional(self.utm_campaign)?,
         })
     }
 }
@@ -31,6 +61,10 @@ impl TryInto<NewSubscriber> for NewSubscriberForm {
 pub enum SubscribeError {
     #[error("{0}")]
     InvalidSubscriptionForm(String),
+    #[error("Service is overloaded, retry later")]
+    PoolExhausted(#[source] sqlx::Error),
+    #[error(transparent)]
+    IdempotencyError(#[from] IdempotencyError),
     #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error),
 }
@@ -39,11 +73,34 @@ impl ResponseError for SubscribeError {
     fn status_code(&self) -> actix_web::http::StatusCode {
         match self {
             SubscribeError::InvalidSubscriptionForm(_) => actix_web::http::StatusCode::BAD_REQUEST,
+            SubscribeError::PoolExhausted(_) => actix_web::http::StatusCode::SERVICE_UNAVAILABLE,
+            SubscribeError::IdempotencyError(e) => e.status_code(),
             SubscribeError::UnexpectedError(_) => {
                 actix_web::http::StatusCode::INTERNAL_SERVER_ERROR
             }
         }
     }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+        if let SubscribeError::PoolExhausted(_) = self {
+            response.insert_header((RETRY_AFTER, POOL_EXHAUSTED_RETRY_AFTER_SECS.to_string()));
+        }
+        response
+            .content_type(ContentType::plaintext())
+            .body(self.to_string())
+    }
+}
+
+impl SubscribeError {
+    fn from_begin_transaction_error(e: sqlx::Error) -> Self {
+        if is_pool_exhausted(&e) {
+            return SubscribeError::PoolExhausted(e);
+        }
+        SubscribeError::UnexpectedError(
+            anyhow::Error::new(e).context("Failed to begin a database transaction"),
+        )
+    }
 }
 
 impl Debug for SubscribeError {
@@ -56,35 +113,140 @@ impl Debug for SubscribeError {
 // Instrument can capture arguments of function, but CAN'T capture local variables
 #[tracing::instrument(
     name = "Add a new subscriber",
-    skip(subscriber, pg_pool, email_client, app_base_url),
+    skip(
+        request,
+        subscriber,
+        pg_pool,
+        email_client,
+        app_base_url,
+        confirmation_email_template,
+        retry_policy,
+        token_length,
+        mx_checker,
+        idempotency_replay_max_age,
+        idempotency_max_stored_body_size
+    ),
     fields(
-        name = %subscriber.name,
-        email = %subscriber.email,
+        name = %redact_pii(&subscriber.name),
+        email = %redact_pii(&subscriber.email),
     )
 )]
 pub async fn subscribe(
-    web::Form(subscriber): web::Form<NewSubscriberForm>,
+    request: HttpRequest,
+    web::Form(mut subscriber): web::Form<NewSubscriberForm>,
     pg_pool: web::Data<PgPool>,
     email_client: web::Data<EmailClient>,
     app_base_url: web::Data<String>,
+    confirmation_email_template: web::Data<ConfirmationEmailTemplate>,
+    retry_policy: web::Data<ConfirmationEmailRetryPolicy>,
+    token_length: web::Data<SubscriptionTokenLength>,
+    mx_checker: Option<web::Data<MxChecker>>,
+    idempotency_replay_max_age: web::Data<IdempotencyReplayMaxAge>,
+    idempotency_max_stored_body_size: web::Data<IdempotencyMaxStoredBodySize>,
 ) -> Result<HttpResponse, SubscribeError> {
-    let mut transaction = pg_pool
-        .begin()
-        .await
-        .context("Failed to begin a database transaction")?;
-
+    let idempotency_key = subscriber.idempotency_key.take();
     let subscriber: NewSubscriber = subscriber
         .try_into()
         .map_err(SubscribeError::InvalidSubscriptionForm)?;
 
-    let subscription_id = insert_pending_subscriber(&subscriber, &mut transaction)
+    // Checked before beginning transaction, so slow DNS doesn't hold a database connection
+    if let Some(mx_checker) = mx_checker {
+        mx_checker
+            .check(subscriber.email.domain())
+            .await
+            .map_err(SubscribeError::InvalidSubscriptionForm)?;
+    }
+
+    let idempotency_key = get_subscribe_idempotency_key(&request, idempotency_key, &subscriber)?;
+
+    let transaction = pg_pool
+        .begin()
         .await
-        .context("Failed to insert new subscriber")?;
+        .map_err(SubscribeError::from_begin_transaction_error)?;
+    let mut transaction = match &idempotency_key {
+        Some(idempotency_key) => {
+            let idempotent_request = IdempotentRequest {
+                method: request.method().as_str(),
+                path: request.path(),
+            };
+            match try_insert_idempotency_response_record_into_database(
+                transaction,
+                idempotency_key,
+                None,
+                &idempotent_request,
+                idempotency_replay_max_age.0,
+            )
+            .await?
+            {
+                ProcessState::Completed(response) => return Ok(response),
+                ProcessState::StartProcessing(transaction) => transaction,
+            }
+        }
+        None => transaction,
+    };
 
-    let subscription_token = generate_subscription_token();
-    insert_subscription_token(&subscription_id, &subscription_token, &mut transaction)
+    let subscription_id = match insert_pending_subscriber(&subscriber, &mut transaction)
         .await
-        .context("Failed to insert subscription token into database")?;
+        .context("Failed to insert new subscriber")?
+    {
+        Some(subscription_id) => subscription_id,
+        // Mailbox is already subscribed, e.g. with another plus tag
+        // Respond the same as for new subscribers, so subscribed emails aren't leaked
+        None => {
+            if let Some(idempotency_key) = &idempotency_key {
+                update_idempotency_response_record(
+                    &mut transaction,
+                    idempotency_key,
+                    None,
+                    HttpResponse::Ok().finish(),
+                    idempotency_max_stored_body_size.0,
+                )
+                .await
+                .context("Failed to store idempotency response")?;
+            }
+            transaction
+                .commit()
+                .await
+                .context("Failed to commit a database transaction")?;
+            return Ok(HttpResponse::Ok().finish());
+        }
+    };
+
+    let (subscription_token, confirmation_code) = create_subscription_token(
+        &subscription_id,
+        token_length.0,
+        &confirmation_email_template,
+        &mut transaction,
+    )
+    .await?;
+
+    // Don't fail the subscriber when sending rate is exceeded
+    // Enqueue confirmation email to be delivered later by background worker instead
+    let send_now = !email_client.is_send_rate_exceeded();
+    if !send_now {
+        enqueue_confirmation_email(
+            &mut transaction,
+            &subscription_token,
+            confirmation_code.as_deref(),
+            &subscriber.delivery_email,
+        )
+        .await
+        .context("Failed to enqueue confirmation email")?;
+    }
+
+    // Response is stored before commit, otherwise retries would see the request in progress forever
+    // Retries get success even when sending confirmation email below fails, subscriber is persisted anyway
+    if let Some(idempotency_key) = &idempotency_key {
+        update_idempotency_response_record(
+            &mut transaction,
+            idempotency_key,
+            None,
+            HttpResponse::Ok().finish(),
+            idempotency_max_stored_body_size.0,
+        )
+        .await
+        .context("Failed to store idempotency response")?;
+    }
 
     // Use Transaction to guarantee all database queries in one request is failed or success all together
     // To avoid fault states in database
@@ -95,20 +257,70 @@ pub async fn subscribe(
         .context("Failed to commit a database transaction")?;
 
     // Need to insert subscription token into database before sending confirmation email
-    send_confirmation_email(
-        &app_base_url,
-        email_client,
-        &subscriber.email,
-        &subscription_token,
-    )
-    .await
-    .context("Failed to send confirmation email")?;
+    if send_now {
+        if let Err(e) = send_confirmation_email(
+            &app_base_url,
+            &email_client,
+            &confirmation_email_template,
+            &subscriber.delivery_email,
+            &subscription_token,
+            confirmation_code.as_deref().unwrap_or_default(),
+        )
+        .await
+        {
+            if !retry_policy.is_enabled() {
+                return Err(e.context("Failed to send confirmation email").into());
+            }
+            // Subscriber is already persisted, so let background worker retry sending
+            // confirmation email instead of failing the subscriber
+            tracing::warn!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to send confirmation email, enqueue it to be retried later"
+            );
+            let mut transaction = pg_pool
+                .begin()
+                .await
+                .context("Failed to begin a database transaction")?;
+            enqueue_confirmation_email(
+                &mut transaction,
+                &subscription_token,
+                confirmation_code.as_deref(),
+                &subscriber.delivery_email,
+            )
+            .await
+            .context("Failed to enqueue confirmation email")?;
+            transaction
+                .commit()
+                .await
+                .context("Failed to commit a database transaction")?;
+        }
+    }
 
     Ok(HttpResponse::Ok().finish())
 }
 
+// Subscribe is anonymous, so client's key is scoped by subscriber email
+// Then the same key sent for different emails never replays another subscriber's response
+fn get_subscribe_idempotency_key(
+    request: &HttpRequest,
+    form_value: Option<String>,
+    subscriber: &NewSubscriber,
+) -> Result<Option<IdempotencyKey>, SubscribeError> {
+    if form_value.is_none() && !request.headers().contains_key(IDEMPOTENCY_KEY_HEADER) {
+        return Ok(None);
+    }
+    let idempotency_key = get_idempotency_key(request.headers(), form_value)
+        .map_err(|e| SubscribeError::InvalidSubscriptionForm(e.to_string()))?;
+    Ok(Some(derive_form_idempotency_key(&[
+        Some(subscriber.email.as_ref()),
+        Some(idempotency_key.as_ref()),
+    ])))
+}
+
 // Separate sql query into separate function (separation of concerns)
 // This function not dependent on actix-web framework
+// Return None if subscriber email already exists
 #[tracing::instrument(
     name = "Insert a new subscriber to database with pending status",
     skip(subscriber, transaction)
@@ -116,23 +328,31 @@ pub async fn subscribe(
 async fn insert_pending_subscriber(
     subscriber: &NewSubscriber,
     transaction: &mut Transaction<'_, Postgres>,
-) -> sqlx::Result<Uuid> {
+) -> sqlx::Result<Option<Uuid>> {
     let id = Uuid::new_v4();
-    sqlx::query!(
+    let n_rows_affected = sqlx::query!(
         r#"
-        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
-        VALUES ($1, $2, $3, $4, $5)
+        INSERT INTO subscriptions (
+            id, email, name, subscribed_at, status, source, utm_campaign, plus_tag, delivery_email
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+        ON CONFLICT (email) DO NOTHING
         "#,
         id,
         subscriber.email.as_ref(),
         subscriber.name.as_ref(),
         Utc::now(),
-        SubscriptionStatus::Pending.as_ref()
+        SubscriptionStatus::Pending.as_ref(),
+        subscriber.source.as_ref().map(|s| s.as_ref()),
+        subscriber.utm_campaign.as_ref().map(|s| s.as_ref()),
+        subscriber.plus_tag.as_deref(),
+        subscriber.delivery_email.as_ref()
     )
     .execute(transaction)
-    .await?;
+    .await?
+    .rows_affected();
 
-    Ok(id)
+    Ok((n_rows_affected > 0).then_some(id))
 }
 
 pub struct InsertSubscriptionError(sqlx::Error);
@@ -159,20 +379,27 @@ impl Debug for InsertSubscriptionError {
 
 #[tracing::instrument(
     name = "Insert new subscription token map to a subscription id into database",
-    skip(subscription_id, subscription_token, transaction)
+    skip(
+        subscription_id,
+        subscription_token,
+        confirmation_code_hash,
+        transaction
+    )
 )]
 async fn insert_subscription_token(
     subscription_id: &Uuid,
     subscription_token: &str,
+    confirmation_code_hash: Option<&str>,
     transaction: &mut Transaction<'_, Postgres>,
 ) -> Result<(), InsertSubscriptionError> {
     sqlx::query!(
         r#"
-        INSERT INTO subscription_tokens (subscription_id, subscription_token)
-        VALUES ($1, $2)
+        INSERT INTO subscription_tokens (subscription_id, subscription_token, confirmation_code_hash, created_at)
+        VALUES ($1, $2, $3, now())
         "#,
         subscription_id,
-        subscription_token
+        subscription_token,
+        confirmation_code_hash
     )
     .execute(transaction)
     .await
@@ -181,46 +408,75 @@ async fn insert_subscription_token(
     Ok(())
 }
 
+// Return subscription token and confirmation code, which is only generated when template offers it
+// Only hash of confirmation code is stored
+pub async fn create_subscription_token(
+    subscription_id: &Uuid,
+    token_length: usize,
+    template: &ConfirmationEmailTemplate,
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<(String, Option<String>), anyhow::Error> {
+    let subscription_token = generate_secure_token(token_length);
+    let confirmation_code = template.offers_code().then(generate_confirmation_code);
+    let confirmation_code_hash = match confirmation_code.clone() {
+        Some(confirmation_code) => Some(
+            spawn_blocking_task_with_tracing(move || hash_password(&confirmation_code))
+                .await
+                .context("Failed to spawn blocking task")?
+                .context("Failed to hash confirmation code")?,
+        ),
+        None => None,
+    };
+    insert_subscription_token(
+        subscription_id,
+        &subscription_token,
+        confirmation_code_hash.as_deref(),
+        transaction,
+    )
+    .await
+    .context("Failed to insert subscription token into database")?;
+    Ok((subscription_token, confirmation_code))
+}
+
 #[tracing::instrument(
     name = "Send a confirmation email to a new subscriber",
-    skip(app_base_url, email_client, subscriber_email, subscription_token)
+    skip(
+        app_base_url,
+        email_client,
+        template,
+        subscriber_email,
+        subscription_token,
+        confirmation_code
+    )
 )]
-async fn send_confirmation_email(
+pub async fn send_confirmation_email(
     app_base_url: &str,
-    email_client: web::Data<EmailClient>,
+    email_client: &EmailClient,
+    template: &ConfirmationEmailTemplate,
     subscriber_email: &SubscriberEmail,
     subscription_token: &str,
+    confirmation_code: &str,
 ) -> Result<(), anyhow::Error> {
     let confirmation_link = format!(
         "{}/subscriptions/confirm?subscription_token={}",
         app_base_url, subscription_token
     );
-    // TODO: make better form
-    let subject = "Confirmation";
-    let html_body = format!(
-        "<p>\
-        Welcome to our newsletter!<br />\
-        Click <a href=\"{}\">here</a> to confirm your subscription.\
-        </p>",
-        confirmation_link,
-    );
-    let text_body = format!(
-        "Welcome to our newsletter!\nGo to this link: {} to confirm your subscription.",
-        confirmation_link
-    );
+    let email = template.render(&confirmation_link, confirmation_code);
 
     email_client
-        .send_multipart_email(subscriber_email, subject, &text_body, &html_body)
+        .send_multipart_email(
+            subscriber_email,
+            email.subject,
+            Some(&email.text_body),
+            Some(&email.html_body),
+        )
         .await?;
 
     Ok(())
 }
 
-// Generate Alphanumeric (A-Z, a-z, 0-9) 25-characters-long case-sensitive subscriptions token
-fn generate_subscription_token() -> String {
+// Generate 6-digits-long numeric confirmation code, which is easy to type
+fn generate_confirmation_code() -> String {
     let mut rng = rand::thread_rng();
-    std::iter::repeat_with(|| rng.sample(Alphanumeric))
-        .map(char::from)
-        .take(25)
-        .collect()
+    format!("{:06}", rng.gen_range(0..1_000_000))
 }
@@ -0,0 +1,180 @@
+use crate::middleware::BasePath;
+use crate::routes::subscriptions::ConfirmTokenParam;
+use crate::routes::SubscriptionStatus;
+use crate::utils::{error_chain_fmt, html_response};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, ResponseError};
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, Transaction};
+use std::fmt::{Debug, Formatter};
+use uuid::Uuid;
+
+#[derive(thiserror::Error)]
+pub enum UnsubscribeError {
+    #[error("Unknown subscription token")]
+    UnknownToken,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl Debug for UnsubscribeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for UnsubscribeError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            UnsubscribeError::UnknownToken => StatusCode::NOT_FOUND,
+            UnsubscribeError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+// GET only renders a confirmation page, so link prefetchers and scanners
+// that follow links in emails can't unsubscribe anyone by accident
+#[tracing::instrument(name = "Show unsubscribe confirmation page", skip_all)]
+pub async fn unsubscribe_form(
+    web::Query(ConfirmTokenParam { subscription_token }): web::Query<ConfirmTokenParam>,
+    pg_pool: web::Data<PgPool>,
+    base_path: web::Data<BasePath>,
+) -> Result<HttpResponse, UnsubscribeError> {
+    get_subscription_id_by_token(&subscription_token, &pg_pool)
+        .await
+        .context("Failed to get subscription id from subscription tokens")?
+        .ok_or(UnsubscribeError::UnknownToken)?;
+
+    // Token is put in query string of form action instead of form body
+    // So the same URL also works for List-Unsubscribe-Post one-click (RFC 8058)
+    Ok(html_response(format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Unsubscribe</title>
+</head>
+<body>
+    <p>Do you really want to stop receiving our newsletters?</p>
+    <form action="{}" method="post">
+        <button type="submit">Unsubscribe</button>
+    </form>
+</body>
+</html>"#,
+        base_path.href(&format!(
+            "/subscriptions/unsubscribe?subscription_token={}",
+            subscription_token
+        ))
+    )))
+}
+
+#[tracing::instrument(name = "Unsubscribe a subscriber", skip_all)]
+pub async fn unsubscribe(
+    web::Query(ConfirmTokenParam { subscription_token }): web::Query<ConfirmTokenParam>,
+    pg_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, UnsubscribeError> {
+    let subscription_id = get_subscription_id_by_token(&subscription_token, &pg_pool)
+        .await
+        .context("Failed to get subscription id from subscription tokens")?
+        .ok_or(UnsubscribeError::UnknownToken)?;
+
+    let mut transaction = pg_pool
+        .begin()
+        .await
+        .context("Failed to begin a database transaction")?;
+    update_subscriber_status_to_unsubscribed(&subscription_id, &mut transaction)
+        .await
+        .context("Failed to update subscriber status to unsubscribed")?;
+    // Tokens are single-use for unsubscribing, a leaked link can't be replayed later
+    delete_subscription_tokens(&subscription_id, &mut transaction)
+        .await
+        .context("Failed to delete subscription tokens")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit a database transaction")?;
+
+    Ok(html_response(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Unsubscribed</title>
+</head>
+<body>
+    <p>You have been unsubscribed.</p>
+</body>
+</html>"#
+            .to_string(),
+    ))
+}
+
+// Expiration of subscription token is ignored, subscriber must be able to unsubscribe any time
+#[tracing::instrument(name = "Get subscription id by subscription token", skip_all)]
+async fn get_subscription_id_by_token(
+    subscription_token: &str,
+    pg_pool: &PgPool,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        SELECT subscription_id
+        FROM subscription_tokens
+        WHERE subscription_token = $1
+        "#,
+        subscription_token
+    )
+    .fetch_optional(pg_pool)
+    .await?;
+
+    Ok(result.map(|r| r.subscription_id))
+}
+
+#[tracing::instrument(name = "Update subscriber status to unsubscribed", skip_all)]
+async fn update_subscriber_status_to_unsubscribed(
+    subscription_id: &Uuid,
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET status = $1
+        WHERE id = $2
+        "#,
+        SubscriptionStatus::Unsubscribed.as_ref(),
+        subscription_id
+    )
+    .execute(transaction)
+    .await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(name = "Delete subscription tokens of a subscriber", skip_all)]
+pub async fn delete_subscription_tokens(
+    subscription_id: &Uuid,
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<(), sqlx::Error> {
+    // Pending confirmation emails reference the tokens, so they're deleted along
+    sqlx::query!(
+        r#"
+        DELETE FROM confirmation_emails_delivery_queue
+        WHERE subscription_token IN (
+            SELECT subscription_token FROM subscription_tokens WHERE subscription_id = $1
+        )
+        "#,
+        subscription_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+        DELETE FROM subscription_tokens
+        WHERE subscription_id = $1
+        "#,
+        subscription_id
+    )
+    .execute(transaction)
+    .await?;
+
+    Ok(())
+}
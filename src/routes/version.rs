@@ -0,0 +1,46 @@
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+
+// Only build info and schema version are exposed, this endpoint is unauthenticated
+#[derive(serde::Serialize)]
+struct VersionResponse {
+    version: &'static str,
+    git_sha: &'static str,
+    migration_version: Option<i64>,
+}
+
+#[tracing::instrument(name = "Get version", skip_all)]
+pub async fn version(pg_pool: web::Data<PgPool>) -> HttpResponse {
+    // Build info is still reported when migration version can't be read
+    let migration_version = match get_latest_migration_version(&pg_pool).await {
+        Ok(migration_version) => migration_version,
+        Err(e) => {
+            tracing::warn!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to get latest migration version"
+            );
+            None
+        }
+    };
+
+    HttpResponse::Ok().json(VersionResponse {
+        version: env!("CARGO_PKG_VERSION"),
+        git_sha: env!("GIT_SHA"),
+        migration_version,
+    })
+}
+
+#[tracing::instrument(name = "Get latest applied migration version", skip_all)]
+async fn get_latest_migration_version(pg_pool: &PgPool) -> Result<Option<i64>, sqlx::Error> {
+    Ok(sqlx::query!(
+        r#"
+        SELECT MAX(version) as version
+        FROM _sqlx_migrations
+        WHERE success
+        "#
+    )
+    .fetch_one(pg_pool)
+    .await?
+    .version)
+}
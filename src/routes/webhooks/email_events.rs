@@ -0,0 +1,246 @@
+use crate::routes::SubscriptionStatus;
+use crate::utils::error_chain_fmt;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
+use anyhow::Context;
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+use sqlx::PgPool;
+use std::fmt::{Debug, Formatter};
+
+// Hex encoded HMAC-SHA256 of `<timestamp>.<raw request body>`, keyed with the shared webhook secret
+pub const EMAIL_EVENTS_SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+// Unix seconds when provider signed the event, signed together with body
+pub const EMAIL_EVENTS_TIMESTAMP_HEADER: &str = "X-Webhook-Timestamp";
+// Captured requests can only be replayed within this window, it also absorbs clock skew
+const EMAIL_EVENTS_TIMESTAMP_TOLERANCE_SECS: i64 = 300;
+
+pub struct EmailEventsWebhookSecret(pub Secret<String>);
+
+#[derive(serde::Deserialize)]
+pub struct EmailEvent {
+    email: String,
+    event: EmailEventKind,
+}
+
+// Deliveries, opens and other events providers may send are accepted but ignored
+#[derive(serde::Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum EmailEventKind {
+    Bounce,
+    Complaint,
+    #[serde(other)]
+    Other,
+}
+
+#[derive(thiserror::Error)]
+pub enum EmailEventsError {
+    #[error("Invalid webhook signature")]
+    InvalidSignature,
+    #[error("{0}")]
+    InvalidPayload(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl Debug for EmailEventsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for EmailEventsError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            EmailEventsError::InvalidSignature => StatusCode::UNAUTHORIZED,
+            EmailEventsError::InvalidPayload(_) => StatusCode::BAD_REQUEST,
+            EmailEventsError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+// Signature is verified over raw body, so payload is only parsed after it's trusted
+#[tracing::instrument(name = "Handle email event from provider", skip_all)]
+pub async fn handle_email_event(
+    req: HttpRequest,
+    body: web::Bytes,
+    secret: web::Data<EmailEventsWebhookSecret>,
+    pg_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, EmailEventsError> {
+    let get_header = |name: &str| {
+        req.headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .ok_or(EmailEventsError::InvalidSignature)
+    };
+    let signature = get_header(EMAIL_EVENTS_SIGNATURE_HEADER)?;
+    let timestamp = get_header(EMAIL_EVENTS_TIMESTAMP_HEADER)?;
+    verify_signature(
+        &secret.0,
+        timestamp,
+        &body,
+        signature,
+        chrono::Utc::now().timestamp(),
+    )?;
+
+    let EmailEvent { email, event } = serde_json::from_slice(&body)
+        .map_err(|e| EmailEventsError::InvalidPayload(format!("Invalid email event: {}", e)))?;
+    if event == EmailEventKind::Other {
+        return Ok(HttpResponse::Ok().finish());
+    }
+
+    // Unknown emails are ignored, provider shouldn't retry events we can't act on
+    let n_bounced = mark_subscriber_as_bounced(&pg_pool, &email)
+        .await
+        .context("Failed to mark subscriber as bounced")?;
+    if n_bounced > 0 {
+        tracing::info!("Subscriber is marked as bounced and won't receive newsletters");
+    }
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+fn verify_signature(
+    secret: &Secret<String>,
+    timestamp: &str,
+    body: &[u8],
+    signature: &str,
+    now: i64,
+) -> Result<(), EmailEventsError> {
+    // Empty secret means webhook isn't configured, anyone could sign with it
+    if secret.expose_secret().is_empty() {
+        return Err(EmailEventsError::InvalidSignature);
+    }
+    let signed_at: i64 = timestamp
+        .parse()
+        .map_err(|_| EmailEventsError::InvalidSignature)?;
+    if (now - signed_at).abs() > EMAIL_EVENTS_TIMESTAMP_TOLERANCE_SECS {
+        return Err(EmailEventsError::InvalidSignature);
+    }
+    let signature = hex::decode(signature).map_err(|_| EmailEventsError::InvalidSignature)?;
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.expose_secret().as_bytes())
+        .context("Failed to build HMAC from webhook secret")?;
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    // `verify_slice` compares in constant time
+    mac.verify_slice(&signature)
+        .map_err(|_| EmailEventsError::InvalidSignature)
+}
+
+#[tracing::instrument(name = "Mark subscriber as bounced", skip(pg_pool))]
+async fn mark_subscriber_as_bounced(pg_pool: &PgPool, email: &str) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET status = $1
+        WHERE email = $2 OR delivery_email = $2
+        "#,
+        SubscriptionStatus::Bounced.as_ref(),
+        email
+    )
+    .execute(pg_pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::routes::webhooks::email_events::verify_signature;
+    use claims::{assert_err, assert_ok};
+    use hmac::{Hmac, Mac};
+    use secrecy::Secret;
+    use sha2::Sha256;
+
+    const NOW: i64 = 1_700_000_000;
+
+    fn sign(secret: &str, timestamp: &str, body: &[u8]) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(timestamp.as_bytes());
+        mac.update(b".");
+        mac.update(body);
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    #[test]
+    fn signature_of_timestamp_and_body_with_shared_secret_is_accepted() {
+        let body = br#"{"email":"ursula@example.com","event":"bounce"}"#;
+        let timestamp = NOW.to_string();
+        let signature = sign("secret", &timestamp, body);
+
+        assert_ok!(verify_signature(
+            &Secret::new("secret".to_string()),
+            &timestamp,
+            body,
+            &signature,
+            NOW
+        ));
+    }
+
+    #[test]
+    fn signature_with_other_secret_body_or_timestamp_is_rejected() {
+        let body = br#"{"email":"ursula@example.com","event":"bounce"}"#;
+        let secret = Secret::new("secret".to_string());
+        let timestamp = NOW.to_string();
+        let signature = sign("secret", &timestamp, body);
+
+        assert_err!(verify_signature(
+            &secret,
+            &timestamp,
+            body,
+            &sign("other", &timestamp, body),
+            NOW
+        ));
+        assert_err!(verify_signature(
+            &secret, &timestamp, b"{}", &signature, NOW
+        ));
+        assert_err!(verify_signature(
+            &secret,
+            &(NOW + 1).to_string(),
+            body,
+            &signature,
+            NOW
+        ));
+        assert_err!(verify_signature(&secret, &timestamp, body, "not hex", NOW));
+        assert_err!(verify_signature(
+            &secret,
+            "not a number",
+            body,
+            &signature,
+            NOW
+        ));
+    }
+
+    #[test]
+    fn signature_outside_tolerance_window_is_rejected() {
+        let body = br#"{"email":"ursula@example.com","event":"bounce"}"#;
+        let secret = Secret::new("secret".to_string());
+
+        for signed_at in [NOW - 300, NOW + 300] {
+            let timestamp = signed_at.to_string();
+            let signature = sign("secret", &timestamp, body);
+            assert_ok!(verify_signature(&secret, &timestamp, body, &signature, NOW));
+        }
+        for signed_at in [NOW - 301, NOW + 301] {
+            let timestamp = signed_at.to_string();
+            let signature = sign("secret", &timestamp, body);
+            assert_err!(verify_signature(&secret, &timestamp, body, &signature, NOW));
+        }
+    }
+
+    #[test]
+    fn any_signature_is_rejected_when_secret_is_empty() {
+        let body = b"{}";
+        let timestamp = NOW.to_string();
+
+        assert_err!(verify_signature(
+            &Secret::new(String::new()),
+            &timestamp,
+            body,
+            &sign("", &timestamp, body),
+            NOW
+        ));
+    }
+}
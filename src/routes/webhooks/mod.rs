@@ -0,0 +1,3 @@
+mod email_events;
+
+pub use email_events::*;
@@ -1,17 +1,49 @@
-use crate::authentication::reject_anonymous_users;
-use crate::configuration::{DatabaseSettings, EmailClientSettings, Settings};
-use crate::email_client::EmailClient;
-use crate::routes::{admin, check_health, home, login, login_form, subscriptions, SubscriberEmail};
+use crate::authentication::{
+    has_basic_auth, has_bearer_token, reject_anonymous_users, reject_invalid_api_tokens,
+    reject_invalid_basic_auth_credentials, SessionLifetime, SessionLifetimeStore,
+};
+use crate::configuration::{
+    CorsSettings, DatabaseSettings, DatabaseStartupCheckSettings, EmailClientSettings,
+    FlashMessageStoreSettings, Settings, SmtpTlsMode,
+};
+use crate::confirmation_emails::{
+    ConfirmationEmailRetryPolicy, ConfirmationEmailTemplate, WelcomeEmailTemplate,
+};
+use crate::content_store::ContentStore;
+use crate::email_client::{EmailClient, SendRateLimiter, SmtpClientCertificate, SmtpTls};
+use crate::html_sanitizer::HtmlSanitizer;
+use crate::idempotency::{IdempotencyMaxStoredBodySize, IdempotencyReplayMaxAge};
+use crate::middleware::{
+    add_security_headers, forbid_disallowed_cors_origins, log_access, mark_remembered_session,
+    persist_remembered_session_cookie, prefix_redirect_locations, propagate_request_id,
+    rate_limit_by_client_ip, reject_during_maintenance, reject_oversized_headers,
+    reject_when_session_store_unavailable, BasePath, MaintenanceMode, RateLimiter,
+    RequestHeaderLimits, RequestIdRootSpanBuilder, SecurityHeaders, SESSION_COOKIE_NAME,
+};
+#[cfg(feature = "mx-check")]
+use crate::mx_check::DnsMxResolver;
+use crate::mx_check::{MxChecker, MxResolver};
+use crate::newsletters_issues::WorkerBackoff;
+use crate::routes::subscriptions::{SubscriptionTokenExpiration, SubscriptionTokenLength};
+use crate::routes::webhooks::EmailEventsWebhookSecret;
+use crate::routes::{
+    admin, check_health, home, login, login_form, subscriptions, two_factor_login,
+    two_factor_login_form, version, webhooks, SubscriberEmail,
+};
+use actix_cors::Cors;
+use actix_session::config::BrowserSession;
 use actix_session::storage::RedisSessionStore;
 use actix_session::SessionMiddleware;
-use actix_web::cookie::Key;
+use actix_web::cookie::{time, Key};
 use actix_web::dev::Server;
+use actix_web::middleware::{Compress, Condition};
 use actix_web::web::Data;
-use actix_web::{web, App, HttpServer};
-use actix_web_flash_messages::storage::CookieMessageStore;
+use actix_web::{guard, web, App, HttpServer};
+use actix_web_flash_messages::storage::{CookieMessageStore, SessionMessageStore};
 use actix_web_flash_messages::FlashMessagesFramework;
 use actix_web_lab::middleware;
-use secrecy::ExposeSecret;
+use anyhow::Context;
+use secrecy::{ExposeSecret, Secret};
 use sqlx::postgres::PgPoolOptions;
 use sqlx::PgPool;
 use std::net::TcpListener;
@@ -23,6 +55,8 @@ pub struct ApplicationBuilder {
     settings: Settings,
     notify: Arc<Notify>,
     pg_pool: Option<PgPool>,
+    mx_resolver: Option<Arc<dyn MxResolver>>,
+    send_rate_limiter: Option<Arc<SendRateLimiter>>,
 }
 
 impl ApplicationBuilder {
@@ -31,6 +65,8 @@ impl ApplicationBuilder {
             settings,
             notify,
             pg_pool: None,
+            mx_resolver: None,
+            send_rate_limiter: None,
         }
     }
 
@@ -39,20 +75,129 @@ impl ApplicationBuilder {
         self
     }
 
+    // Replace DNS resolver used by MX check, it's only used when MX check is enabled
+    pub fn set_mx_resolver(mut self, mx_resolver: Arc<dyn MxResolver>) -> Self {
+        self.mx_resolver = Some(mx_resolver);
+        self
+    }
+
+    // Limiter shared with workers, otherwise one is built from settings for the API alone
+    pub fn set_send_rate_limiter(
+        mut self,
+        send_rate_limiter: Option<Arc<SendRateLimiter>>,
+    ) -> Self {
+        self.send_rate_limiter = send_rate_limiter;
+        self
+    }
+
     pub async fn build(self) -> Result<Application, anyhow::Error> {
+        // Cookie keys are checked here before `Key::from` panics on short keys
+        self.settings.validate()?;
         let listener = TcpListener::bind(self.settings.application.get_url())?;
 
         let port = listener.local_addr().unwrap().port();
 
-        let email_client = build_email_client(self.settings.email_client.clone())?;
+        let email_client =
+            build_email_client(self.settings.email_client.clone(), self.send_rate_limiter)?;
         // So to share data between threads, actix-web provide web::Data<T>(Arc<T>)
         // which is a thread-safe reference counting pointer to a value of type T
         let pg_pool = Data::new(match self.pg_pool {
             Some(pool) => pool,
             None => get_pg_pool(&self.settings.database),
         });
+        if let Some(startup_check) = &self.settings.database.startup_check {
+            wait_until_database_is_ready(&pg_pool, startup_check).await?;
+        }
         let email_client = Data::new(email_client);
-        let app_base_url = Data::new(self.settings.application.base_url.clone());
+        let app_base_url = Data::new(self.settings.application.get_public_url());
+        let base_path = self.settings.application.base_path.clone();
+        let base_path_data = Data::new(BasePath(base_path.clone()));
+        let content_store = Data::new(ContentStore::from_settings(&self.settings.content_store));
+        let html_sanitizer = Data::new(HtmlSanitizer::from_settings(&self.settings.html_sanitizer));
+        let confirmation_email_retry_policy = Data::new(
+            ConfirmationEmailRetryPolicy::from_settings(&self.settings.application),
+        );
+        let confirmation_email_template = Data::new(ConfirmationEmailTemplate::from_settings(
+            &self.settings.confirmation_email,
+        ));
+        let welcome_email_template = Data::new(WelcomeEmailTemplate::from_settings(
+            &self.settings.welcome_email,
+        ));
+        let session_lifetime =
+            Data::new(SessionLifetime::from_settings(&self.settings.application));
+        let request_header_limits = Data::new(RequestHeaderLimits {
+            max_count: self.settings.application.max_request_headers_count,
+            max_size_bytes: self.settings.application.max_request_headers_size_bytes,
+        });
+        let security_headers = Data::new(
+            SecurityHeaders::from_settings(&self.settings.application.security_headers)
+                .context("Invalid security headers")?,
+        );
+        // MX checker is registered only when it is enabled
+        let mx_checker = match &self.settings.application.mx_check {
+            settings if settings.enabled => {
+                let resolver = match self.mx_resolver.clone() {
+                    Some(resolver) => resolver,
+                    None => build_mx_resolver()?,
+                };
+                Some(Data::new(MxChecker::new(resolver, settings)))
+            }
+            _ => None,
+        };
+        let max_request_body_size = self.settings.application.max_request_body_size_bytes;
+        let max_subscribe_body_size = self.settings.application.max_subscribe_body_size_bytes;
+        let max_newsletters_body_size = self.settings.application.max_newsletters_body_size_bytes;
+        let max_recipients_per_issue = Data::new(admin::MaxRecipientsPerIssue(
+            self.settings.application.max_recipients_per_issue,
+        ));
+        let max_newsletters_html_size = Data::new(admin::MaxNewslettersHtmlSize(
+            self.settings.application.max_newsletters_html_size_bytes,
+        ));
+        let max_subscribers_import_body_size = self
+            .settings
+            .application
+            .max_subscribers_import_body_size_bytes;
+        let subscription_token_expiration =
+            Data::new(SubscriptionTokenExpiration(std::time::Duration::from_secs(
+                self.settings.application.subscription_token_expiration_secs,
+            )));
+
+        let maintenance_mode = Data::new(MaintenanceMode::from_settings(
+            &self.settings.application.maintenance,
+        ));
+        let idempotency_replay_max_age = Data::new(IdempotencyReplayMaxAge(
+            self.settings
+                .application
+                .idempotency_replay_max_age_millis
+                .map(std::time::Duration::from_millis),
+        ));
+        let idempotency_max_stored_body_size = Data::new(IdempotencyMaxStoredBodySize(
+            self.settings
+                .application
+                .idempotency_max_stored_body_size_bytes,
+        ));
+        let subscription_token_length = Data::new(SubscriptionTokenLength(
+            self.settings.application.subscription_token_length,
+        ));
+        let email_events_webhook_secret = Data::new(EmailEventsWebhookSecret(
+            self.settings
+                .application
+                .email_events_webhook_secret
+                .clone(),
+        ));
+
+        let erased_email_hash_key = Data::new(admin::ErasedEmailHashKey(
+            self.settings.application.erased_email_hash_key.clone(),
+        ));
+
+        let subscribe_rate_limiter = match &self.settings.application.subscribe_rate_limit {
+            Some(rate_limit) => Some(Data::new(RateLimiter::new(
+                "subscribe",
+                self.settings.application.redis_url.expose_secret(),
+                rate_limit,
+            )?)),
+            None => None,
+        };
 
         let message_key = Key::from(
             self.settings
@@ -61,8 +206,16 @@ impl ApplicationBuilder {
                 .expose_secret()
                 .as_bytes(),
         );
-        let message_store = CookieMessageStore::builder(message_key).build();
-        let message_framework = FlashMessagesFramework::builder(message_store).build();
+        // Session store relies on `SessionMiddleware`, which is wrapped outside of flash messages middleware
+        let message_framework = match self.settings.application.flash_message_store {
+            FlashMessageStoreSettings::Cookie => {
+                FlashMessagesFramework::builder(CookieMessageStore::builder(message_key).build())
+                    .build()
+            }
+            FlashMessageStoreSettings::Session => {
+                FlashMessagesFramework::builder(SessionMessageStore::default()).build()
+            }
+        };
 
         let session_key = Key::from(
             self.settings
@@ -71,47 +224,269 @@ impl ApplicationBuilder {
                 .expose_secret()
                 .as_bytes(),
         );
-        let session_store =
+        let session_store = SessionLifetimeStore::new(
             RedisSessionStore::builder(self.settings.application.redis_url.expose_secret())
                 .build()
                 .await
-                .expect("Failed to build RedisSessionStore");
+                .context("Failed to build Redis session store")?,
+            *session_lifetime.get_ref(),
+        );
+
+        // Session cookie dies with browser unless user chooses "remember me"
+        // TTL of session state in store is decided per session by `SessionLifetimeStore`
+        let session_state_ttl = time::Duration::milliseconds(
+            i64::try_from(session_lifetime.idle_timeout.as_millis()).unwrap_or(i64::MAX),
+        );
 
         let notify = Data::from(self.notify);
+        let request_log = self.settings.application.request_log;
+        let compress_responses = self.settings.application.compress_responses;
+        let allow_newsletters_basic_auth = self.settings.application.allow_newsletters_basic_auth;
+        let cors_settings = self.settings.application.cors.clone();
+        let app_origin = self.settings.application.base_url.clone();
 
         // Actix-web runtime that have multiple threads
         let server = HttpServer::new(move || {
+            // Rate limiter is registered only when it is configured
+            // Mounted in `/subscriptions` scope
+            let subscribe_resource = match subscribe_rate_limiter.clone() {
+                Some(rate_limiter) => web::resource("").app_data(rate_limiter),
+                None => web::resource(""),
+            }
+            .app_data(web::FormConfig::default().limit(max_subscribe_body_size));
+            // Resending sends emails as well, so it counts against the same limit as subscribing
+            let resend_confirmation_resource = match subscribe_rate_limiter.clone() {
+                Some(rate_limiter) => web::resource("/confirm/resend").app_data(rate_limiter),
+                None => web::resource("/confirm/resend"),
+            };
+            let subscribe_resource = match mx_checker.clone() {
+                Some(mx_checker) => subscribe_resource.app_data(mx_checker),
+                None => subscribe_resource,
+            };
             App::new()
-                .wrap(TracingLogger::default()) // logger middleware
+                .wrap(Condition::new(
+                    request_log.is_tracing_logger_enabled(),
+                    TracingLogger::<RequestIdRootSpanBuilder>::new(),
+                )) // logger middleware
                 .wrap(message_framework.clone())
-                .wrap(SessionMiddleware::new(
-                    session_store.clone(),
-                    session_key.clone(),
-                ))
-                .route("/", web::get().to(home))
-                .route("/login", web::get().to(login_form))
-                .route("/login", web::post().to(login))
-                .route("/health", web::get().to(check_health))
-                .route("/subscriptions", web::post().to(subscriptions::subscribe))
-                .route(
-                    "/subscriptions/confirm",
-                    web::get().to(subscriptions::confirm),
+                .wrap(middleware::from_fn(mark_remembered_session))
+                .wrap(
+                    SessionMiddleware::builder(session_store.clone(), session_key.clone())
+                        .cookie_name(SESSION_COOKIE_NAME.to_string())
+                        .session_lifecycle(BrowserSession::default().state_ttl(session_state_ttl))
+                        .build(),
                 )
+                .wrap(middleware::from_fn(persist_remembered_session_cookie))
+                .wrap(middleware::from_fn(reject_when_session_store_unavailable))
+                .wrap(middleware::from_fn(reject_oversized_headers))
+                .wrap(Condition::new(
+                    request_log.is_access_log_enabled(),
+                    middleware::from_fn(log_access),
+                ))
+                // Error responses of inner middlewares get security headers too
+                .wrap(middleware::from_fn(add_security_headers))
+                .wrap(middleware::from_fn(propagate_request_id))
+                // The last wrapped middleware is the first to process the request
+                // Compress is the outermost, so bodies rewritten by other middlewares are compressed once
+                // Responses which already have `Content-Encoding` are passed through untouched
+                .wrap(Condition::new(compress_responses, Compress::default()))
+                // All routes are mounted under base path, empty base path mounts them at root
                 .service(
-                    web::scope("/admin")
-                        .wrap(middleware::from_fn(reject_anonymous_users))
-                        .route("/dashboard", web::get().to(admin::admin_dashboard))
-                        .route("/newsletters", web::get().to(admin::get_newsletters_form))
-                        .route("/newsletters", web::post().to(admin::publish_newsletters))
-                        .route("/logout", web::get().to(admin::logout))
-                        .route("/password", web::get().to(admin::change_password_form))
-                        .route("/password", web::post().to(admin::change_password))
-                        .app_data(notify.clone()),
+                    web::scope(&base_path)
+                        .wrap(Condition::new(
+                            !base_path.is_empty(),
+                            middleware::from_fn(prefix_redirect_locations),
+                        ))
+                        // Scripts publish newsletters with API token instead of session cookie
+                        // Requests without bearer token fall through to session protected `/admin` scope
+                        .service(
+                            web::resource("/admin/newsletters")
+                                .guard(guard::Post())
+                                .guard(guard::fn_guard(has_bearer_token))
+                                .wrap(middleware::from_fn(reject_invalid_api_tokens))
+                                .route(web::post().to(admin::publish_newsletters))
+                                .app_data(
+                                    web::FormConfig::default().limit(max_newsletters_body_size),
+                                )
+                                .app_data(notify.clone()),
+                        )
+                        // Basic credentials are only accepted when enabled, otherwise requests fall
+                        // through to session protected `/admin` scope like before
+                        .service(
+                            web::resource("/admin/newsletters")
+                                .guard(guard::Post())
+                                .guard(guard::fn_guard(move |ctx| {
+                                    allow_newsletters_basic_auth && has_basic_auth(ctx)
+                                }))
+                                .wrap(middleware::from_fn(reject_invalid_basic_auth_credentials))
+                                .route(web::post().to(admin::publish_newsletters))
+                                .app_data(
+                                    web::FormConfig::default().limit(max_newsletters_body_size),
+                                )
+                                .app_data(notify.clone()),
+                        )
+                        .service(
+                            web::scope("/admin")
+                                .wrap(middleware::from_fn(reject_anonymous_users))
+                                .route("/dashboard", web::get().to(admin::admin_dashboard))
+                                .service(
+                                    web::resource("/newsletters")
+                                        .route(web::get().to(admin::get_newsletters_form))
+                                        .route(web::post().to(admin::publish_newsletters))
+                                        .app_data(
+                                            web::FormConfig::default()
+                                                .limit(max_newsletters_body_size),
+                                        ),
+                                )
+                                .route(
+                                    "/newsletters/history",
+                                    web::get().to(admin::newsletters_history),
+                                )
+                                .route(
+                                    "/newsletters/{newsletters_issue_id}",
+                                    web::get().to(admin::newsletters_issue_failures_page),
+                                )
+                                .route(
+                                    "/newsletters/{newsletters_issue_id}/failures",
+                                    web::get().to(admin::newsletters_issue_failures),
+                                )
+                                .route(
+                                    "/newsletters/{newsletters_issue_id}/redrive",
+                                    web::post().to(admin::redrive_newsletters_issue),
+                                )
+                                .route("/logout", web::get().to(admin::logout))
+                                .route("/password", web::get().to(admin::change_password_form))
+                                .route("/password", web::post().to(admin::change_password))
+                                .route("/2fa", web::get().to(admin::two_factor_enrollment_form))
+                                .route("/2fa", web::post().to(admin::enroll_two_factor))
+                                .route("/api_tokens", web::post().to(admin::mint_api_token))
+                                .route(
+                                    "/api_tokens/{token_id}",
+                                    web::delete().to(admin::delete_api_token),
+                                )
+                                .route("/idempotency", web::get().to(admin::list_idempotency_keys))
+                                .route(
+                                    "/idempotency/{idempotency_key}",
+                                    web::delete().to(admin::clear_idempotency_key),
+                                )
+                                .route("/maintenance", web::get().to(admin::get_maintenance_mode))
+                                .route("/maintenance", web::put().to(admin::set_maintenance_mode))
+                                .route("/workers/status", web::get().to(admin::workers_status))
+                                .route("/email", web::post().to(admin::update_user_email))
+                                .route("/test-email", web::post().to(admin::send_test_email))
+                                .service(
+                                    web::resource("/subscribers/import")
+                                        .route(web::post().to(admin::import_subscribers))
+                                        .app_data(web::PayloadConfig::new(
+                                            max_subscribers_import_body_size,
+                                        )),
+                                )
+                                .route(
+                                    "/subscribers/export",
+                                    web::get().to(admin::export_subscribers),
+                                )
+                                .route(
+                                    "/subscribers/stats",
+                                    web::get().to(admin::subscribers_stats),
+                                )
+                                .route(
+                                    "/subscribers/lookup",
+                                    web::get().to(admin::lookup_subscriber),
+                                )
+                                .route(
+                                    "/subscribers/erase",
+                                    web::post().to(admin::erase_subscriber),
+                                )
+                                .app_data(notify.clone()),
+                        )
+                        // Registered after admin routes, so CORS is never applied to them
+                        .service(
+                            web::scope("")
+                                .wrap(Condition::new(
+                                    cors_settings.is_enabled(),
+                                    build_cors(&cors_settings, &app_origin),
+                                ))
+                                // Wraps `Cors`, so it sees its rejections of unknown origins
+                                .wrap(middleware::from_fn(forbid_disallowed_cors_origins))
+                                .route("/", web::get().to(home))
+                                .route("/login", web::get().to(login_form))
+                                .route("/login", web::post().to(login))
+                                .route("/login/2fa", web::get().to(two_factor_login_form))
+                                .route("/login/2fa", web::post().to(two_factor_login))
+                                .route("/health", web::get().to(check_health))
+                                .route("/version", web::get().to(version))
+                                // Public subscription routes are taken offline during maintenance
+                                .service(
+                                    web::scope("/subscriptions")
+                                        .wrap(middleware::from_fn(reject_during_maintenance))
+                                        .service(
+                                            subscribe_resource
+                                                .wrap(middleware::from_fn(rate_limit_by_client_ip))
+                                                .route(web::post().to(subscriptions::subscribe)),
+                                        )
+                                        .route("/confirm", web::get().to(subscriptions::confirm))
+                                        .service(
+                                            resend_confirmation_resource
+                                                .wrap(middleware::from_fn(rate_limit_by_client_ip))
+                                                .route(
+                                                    web::get().to(
+                                                        subscriptions::resend_confirmation_form,
+                                                    ),
+                                                )
+                                                .route(
+                                                    web::post()
+                                                        .to(subscriptions::resend_confirmation),
+                                                ),
+                                        )
+                                        .route(
+                                            "/status",
+                                            web::get().to(subscriptions::subscription_status),
+                                        )
+                                        .route(
+                                            "/confirm_code",
+                                            web::post().to(subscriptions::confirm_code),
+                                        )
+                                        .route(
+                                            "/unsubscribe",
+                                            web::get().to(subscriptions::unsubscribe_form),
+                                        )
+                                        .route(
+                                            "/unsubscribe",
+                                            web::post().to(subscriptions::unsubscribe),
+                                        ),
+                                )
+                                .route(
+                                    "/webhooks/email-events",
+                                    web::post().to(webhooks::handle_email_event),
+                                ),
+                        ),
                 )
+                // Default body size limits, overridden by routes above that need other limits
+                .app_data(web::FormConfig::default().limit(max_request_body_size))
+                .app_data(web::JsonConfig::default().limit(max_request_body_size))
+                .app_data(web::PayloadConfig::new(max_request_body_size))
                 // Application Context, that store state of application
                 .app_data(pg_pool.clone())
                 .app_data(email_client.clone())
                 .app_data(app_base_url.clone())
+                .app_data(content_store.clone())
+                .app_data(html_sanitizer.clone())
+                .app_data(max_newsletters_html_size.clone())
+                .app_data(max_recipients_per_issue.clone())
+                .app_data(email_events_webhook_secret.clone())
+                .app_data(erased_email_hash_key.clone())
+                .app_data(subscription_token_expiration.clone())
+                .app_data(subscription_token_length.clone())
+                .app_data(idempotency_replay_max_age.clone())
+                .app_data(idempotency_max_stored_body_size.clone())
+                .app_data(maintenance_mode.clone())
+                .app_data(confirmation_email_retry_policy.clone())
+                .app_data(confirmation_email_template.clone())
+                .app_data(welcome_email_template.clone())
+                .app_data(request_header_limits.clone())
+                .app_data(security_headers.clone())
+                .app_data(base_path_data.clone())
+                .app_data(session_lifetime.clone())
         })
         .listen(listener)?
         .run();
@@ -120,6 +495,19 @@ impl ApplicationBuilder {
     }
 }
 
+// Own origin is always allowed, otherwise same-origin form posts carrying `Origin` are rejected
+fn build_cors(settings: &CorsSettings, app_origin: &str) -> Cors {
+    let mut cors = Cors::default()
+        .allowed_origin(app_origin.trim_end_matches('/'))
+        .allowed_methods(settings.allowed_methods.iter().map(String::as_str))
+        .allowed_headers(settings.allowed_headers.iter().map(String::as_str))
+        .max_age(settings.max_age_secs);
+    for origin in &settings.allowed_origins {
+        cors = cors.allowed_origin(origin);
+    }
+    cors
+}
+
 pub struct Application {
     port: u16,
     server: Server,
@@ -143,24 +531,321 @@ impl Application {
 // Allow to work with multithreading actix-web runtime
 // Use lazy connect to connect to database when needed
 pub fn get_pg_pool(database_config: &DatabaseSettings) -> PgPool {
+    build_pg_pool(
+        database_config,
+        database_config.max_connections,
+        database_config.min_connections,
+    )
+}
+
+// Background workers only need a few connections, so they get a smaller pool than the API
+// `max_connections` overrides `database.worker_max_connections` when provided
+pub fn get_worker_pg_pool(
+    database_config: &DatabaseSettings,
+    max_connections: Option<u32>,
+) -> PgPool {
+    let max_connections = max_connections.unwrap_or(database_config.worker_max_connections);
+    build_pg_pool(
+        database_config,
+        max_connections,
+        database_config.min_connections.min(max_connections),
+    )
+}
+
+// Pool of a background worker, shared by all workers so they're sized the same way
+// Injected pool is used as is, otherwise one is built from `database.worker_max_connections`
+#[derive(Default)]
+pub struct WorkerPgPool {
+    pg_pool: Option<PgPool>,
+    max_connections: Option<u32>,
+}
+
+impl WorkerPgPool {
+    pub fn set_pg_pool(&mut self, pg_pool: PgPool) {
+        self.pg_pool = Some(pg_pool);
+    }
+
+    pub fn set_max_connections(&mut self, max_connections: u32) {
+        self.max_connections = Some(max_connections);
+    }
+
+    pub fn get_or_build(&self, database_config: &DatabaseSettings) -> PgPool {
+        match &self.pg_pool {
+            Some(pg_pool) => pg_pool.clone(),
+            None => get_worker_pg_pool(database_config, self.max_connections),
+        }
+    }
+}
+
+// Database that is briefly unavailable at boot, e.g. started at the same time as app, is waited for
+#[tracing::instrument(name = "Wait until database is ready", skip_all)]
+pub async fn wait_until_database_is_ready(
+    pg_pool: &PgPool,
+    settings: &DatabaseStartupCheckSettings,
+) -> Result<(), anyhow::Error> {
+    let backoff = startup_check_backoff(settings);
+    let mut n_retries = 0;
+    loop {
+        match sqlx::query("SELECT 1").execute(pg_pool).await {
+            Ok(_) => return Ok(()),
+            Err(e) if n_retries < settings.max_retries => {
+                let delay = backoff.delay(n_retries + 1);
+                tracing::warn!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Database isn't ready, retrying in {:?}",
+                    delay
+                );
+                tokio::time::sleep(delay).await;
+                n_retries += 1;
+            }
+            Err(e) => {
+                return Err(anyhow::anyhow!(e).context(format!(
+                    "Database isn't ready after {} retries",
+                    settings.max_retries
+                )))
+            }
+        }
+    }
+}
+
+// Delay doubles after each retry, up to configured max
+fn startup_check_backoff(settings: &DatabaseStartupCheckSettings) -> WorkerBackoff {
+    WorkerBackoff::new(
+        std::time::Duration::from_millis(settings.backoff_base_millis),
+        std::time::Duration::from_millis(settings.backoff_max_millis),
+    )
+}
+
+fn build_pg_pool(
+    database_config: &DatabaseSettings,
+    max_connections: u32,
+    min_connections: u32,
+) -> PgPool {
     PgPoolOptions::new()
         // Limit connection timeout to avoid long wait times
         .acquire_timeout(std::time::Duration::from_secs(
             database_config.query_timeout_secs,
         ))
+        .max_connections(max_connections)
+        .min_connections(min_connections)
+        .idle_timeout(std::time::Duration::from_secs(
+            database_config.idle_timeout_secs,
+        ))
+        .max_lifetime(std::time::Duration::from_secs(
+            database_config.max_lifetime_secs,
+        ))
+        // Connections are only opened when they are needed
         .connect_lazy_with(database_config.get_pg_database_options())
 }
 
+fn build_smtp_tls(
+    tls_mode: SmtpTlsMode,
+    client_certificate_file: &Option<String>,
+    client_key_file: &Option<String>,
+) -> Result<SmtpTls, anyhow::Error> {
+    let client_certificate = match (client_certificate_file, client_key_file) {
+        (Some(certificate_file), Some(key_file)) => Some(SmtpClientCertificate {
+            certificate_pem: std::fs::read(certificate_file).with_context(|| {
+                format!(
+                    "Failed to read SMTP client certificate `{}`",
+                    certificate_file
+                )
+            })?,
+            key_pem: Secret::new(
+                std::fs::read(key_file)
+                    .with_context(|| format!("Failed to read SMTP client key `{}`", key_file))?,
+            ),
+        }),
+        _ => None,
+    };
+    Ok(SmtpTls {
+        mode: tls_mode,
+        client_certificate,
+    })
+}
+
+#[cfg(feature = "mx-check")]
+fn build_mx_resolver() -> Result<Arc<dyn MxResolver>, anyhow::Error> {
+    Ok(Arc::new(DnsMxResolver::from_system_conf()?))
+}
+
+#[cfg(not(feature = "mx-check"))]
+fn build_mx_resolver() -> Result<Arc<dyn MxResolver>, anyhow::Error> {
+    anyhow::bail!("`application.mx_check.enabled` requires the `mx-check` feature")
+}
+
+// Emails sent by API and workers in the same process should go through one limiter
+pub fn build_send_rate_limiter(
+    email_client_config: &EmailClientSettings,
+) -> Option<Arc<SendRateLimiter>> {
+    email_client_config
+        .send_rate_per_second
+        .map(|rate_per_second| Arc::new(SendRateLimiter::new(rate_per_second)))
+}
+
+// Limiter is built from settings when no shared one is given
 pub fn build_email_client(
     email_client_config: EmailClientSettings,
+    send_rate_limiter: Option<Arc<SendRateLimiter>>,
 ) -> Result<EmailClient, anyhow::Error> {
-    EmailClient::new(
-        email_client_config.host,
-        SubscriberEmail::parse(email_client_config.sender_email).map_err(|e| anyhow::anyhow!(e))?,
-        email_client_config.username,
-        email_client_config.password,
-        email_client_config.port,
-        email_client_config.require_tls,
-        email_client_config.request_timeout_millis,
-    )
+    let send_rate_limiter =
+        send_rate_limiter.or_else(|| build_send_rate_limiter(&email_client_config));
+    let tls = build_smtp_tls(
+        email_client_config.get_tls_mode(),
+        &email_client_config.client_certificate_file,
+        &email_client_config.client_key_file,
+    )?;
+    let mut builder = EmailClient::builder()
+        .host(email_client_config.host)
+        .sender_email(email_client_config.sender_email)
+        .from_name(email_client_config.from_name)
+        .tls(tls)
+        .request_timeout_millis(email_client_config.request_timeout_millis);
+    if let Some(reply_to) = email_client_config.reply_to {
+        builder = builder.reply_to(reply_to);
+    }
+    if let (Some(username), Some(password)) =
+        (email_client_config.username, email_client_config.password)
+    {
+        builder = builder.credentials(username, password);
+    }
+    if let Some(port) = email_client_config.port {
+        builder = builder.port(port);
+    }
+    let email_client = builder.build()?;
+
+    let email_client = email_client_config
+        .failover_providers
+        .into_iter()
+        .try_fold(email_client, |email_client, provider| {
+            let tls = build_smtp_tls(
+                provider.get_tls_mode(),
+                &provider.client_certificate_file,
+                &provider.client_key_file,
+            )?;
+            email_client.add_failover_provider(
+                provider.host,
+                provider.username,
+                provider.password,
+                provider.port,
+                tls,
+            )
+        })?;
+
+    let email_client = match email_client_config.envelope_from {
+        Some(envelope_from) => email_client.set_envelope_from(
+            SubscriberEmail::parse(envelope_from).map_err(|e| anyhow::anyhow!(e))?,
+        ),
+        None => email_client,
+    };
+
+    let email_client = match email_client_config.archive_email {
+        Some(archive_email) => email_client.set_archive_email(
+            SubscriberEmail::parse(archive_email).map_err(|e| anyhow::anyhow!(e))?,
+        ),
+        None => email_client,
+    };
+
+    let email_client = match email_client_config.list_id {
+        Some(list_id) => email_client.set_list_id(list_id),
+        None => email_client,
+    }
+    .set_subject_prefix(email_client_config.subject_prefix);
+
+    let email_client = match email_client_config.max_attachments_size_bytes {
+        Some(max_attachments_size_bytes) => {
+            email_client.set_max_attachments_size_bytes(max_attachments_size_bytes)
+        }
+        None => email_client,
+    };
+
+    Ok(match send_rate_limiter {
+        Some(send_rate_limiter) => email_client.set_send_rate_limiter(send_rate_limiter),
+        None => email_client,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::configuration::{DatabaseStartupCheckSettings, Settings};
+    use crate::startup::{
+        get_pg_pool, get_worker_pg_pool, startup_check_backoff, Application, WorkerPgPool,
+    };
+    use secrecy::Secret;
+    use std::sync::Arc;
+    use tokio::sync::Notify;
+
+    #[tokio::test]
+    async fn pg_pool_honors_configured_max_connections() {
+        let mut settings = Settings::get_configuration().expect("Failed to read configuration");
+        settings.database.max_connections = 3;
+
+        let pg_pool = get_pg_pool(&settings.database);
+
+        assert_eq!(pg_pool.options().get_max_connections(), 3);
+    }
+
+    #[tokio::test]
+    async fn worker_pg_pool_uses_worker_max_connections_instead_of_api_pool_size() {
+        let mut settings = Settings::get_configuration().expect("Failed to read configuration");
+        settings.database.max_connections = 10;
+        settings.database.min_connections = 5;
+        settings.database.worker_max_connections = 2;
+
+        let pg_pool = get_worker_pg_pool(&settings.database, None);
+
+        assert_eq!(pg_pool.options().get_max_connections(), 2);
+        assert_eq!(pg_pool.options().get_min_connections(), 2);
+    }
+
+    #[tokio::test]
+    async fn injected_worker_pg_pool_takes_precedence_over_max_connections() {
+        let settings = Settings::get_configuration().expect("Failed to read configuration");
+        let mut worker_pg_pool = WorkerPgPool::default();
+        worker_pg_pool.set_max_connections(3);
+        worker_pg_pool.set_pg_pool(get_worker_pg_pool(&settings.database, Some(5)));
+
+        let pg_pool = worker_pg_pool.get_or_build(&settings.database);
+
+        assert_eq!(pg_pool.options().get_max_connections(), 5);
+    }
+
+    #[test]
+    fn startup_check_backoff_doubles_up_to_max() {
+        let settings = DatabaseStartupCheckSettings {
+            max_retries: 10,
+            backoff_base_millis: 100,
+            backoff_max_millis: 1000,
+        };
+
+        let backoff = startup_check_backoff(&settings);
+
+        // Delay of each retry is jittered within upper half of its cap
+        for (n_retries, max_delay_millis) in
+            [100, 200, 400, 800, 1000, 1000].into_iter().enumerate()
+        {
+            let delay = backoff.delay(n_retries as u32 + 1).as_millis();
+            assert!(delay >= max_delay_millis / 2 && delay <= max_delay_millis);
+        }
+        // Large retry counts don't overflow
+        assert!(backoff.delay(u32::MAX).as_millis() <= 1000);
+    }
+
+    #[tokio::test]
+    async fn malformed_redis_url_fails_build_with_config_error() {
+        let mut settings = Settings::get_configuration().expect("Failed to read configuration");
+        settings.application.port = 0;
+        settings.application.redis_url = Secret::new("redis://127.0.0.1:not-a-port".to_string());
+
+        let result = Application::builder(settings, Arc::new(Notify::new()))
+            .build()
+            .await;
+
+        // `Application` isn't `Debug`, so `assert_err!` can't be used
+        let error = result
+            .err()
+            .expect("Build should fail on malformed Redis url");
+        assert!(error.to_string().contains("application.redis_url"));
+    }
 }
@@ -1,4 +1,9 @@
-use crate::configuration::ApplicationSettings;
+use crate::configuration::{ApplicationSettings, PiiRedactionSettings};
+use hmac::{Hmac, Mac};
+use secrecy::ExposeSecret;
+use sha2::Sha256;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::RwLock;
 use tracing::subscriber::set_global_default;
 use tracing::Subscriber;
 use tracing_bunyan_formatter::{BunyanFormattingLayer, JsonStorageLayer};
@@ -42,9 +47,140 @@ pub fn init_tracing_subscriber(subscriber: impl Subscriber + Send + Sync) {
 }
 
 pub fn config_tracing(app_config: &ApplicationSettings) {
+    set_pii_redaction(
+        app_config.log_pii_redaction,
+        app_config.log_pii_hash_key.expose_secret(),
+    );
     init_tracing_subscriber(get_tracing_subscriber(
         &app_config.name,
         &app_config.rust_log,
         std::io::stdout,
     ));
 }
+
+// Span fields are evaluated where they're recorded, so redaction mode is global like the subscriber
+static PII_REDACTION: AtomicU8 = AtomicU8::new(PiiRedactionSettings::Off as u8);
+static PII_HASH_KEY: RwLock<Vec<u8>> = RwLock::new(Vec::new());
+
+pub fn set_pii_redaction(redaction: PiiRedactionSettings, hash_key: &str) {
+    *PII_HASH_KEY.write().expect("PII hash key lock is poisoned") = hash_key.as_bytes().to_vec();
+    PII_REDACTION.store(redaction as u8, Ordering::Relaxed);
+}
+
+fn pii_redaction() -> PiiRedactionSettings {
+    match PII_REDACTION.load(Ordering::Relaxed) {
+        x if x == PiiRedactionSettings::Mask as u8 => PiiRedactionSettings::Mask,
+        x if x == PiiRedactionSettings::Hash as u8 => PiiRedactionSettings::Hash,
+        _ => PiiRedactionSettings::Off,
+    }
+}
+
+// Use it for emails, names and usernames recorded in spans
+pub fn redact_pii(value: &str) -> String {
+    let hash_key = PII_HASH_KEY.read().expect("PII hash key lock is poisoned");
+    redact_pii_with(pii_redaction(), &hash_key, value)
+}
+
+fn redact_pii_with(redaction: PiiRedactionSettings, hash_key: &[u8], value: &str) -> String {
+    match redaction {
+        PiiRedactionSettings::Off => value.to_string(),
+        PiiRedactionSettings::Mask => mask_pii(value),
+        PiiRedactionSettings::Hash => hash_pii(hash_key, value),
+    }
+}
+
+fn hash_pii(hash_key: &[u8], value: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(hash_key).expect("HMAC can take key of any size");
+    mac.update(value.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+// `foo@example.com` becomes `f***@example.com`, single character is fully masked
+fn mask_pii(value: &str) -> String {
+    let (local, domain) = match value.rsplit_once('@') {
+        Some((local, domain)) => (local, Some(domain)),
+        None => (value, None),
+    };
+    let mut chars = local.chars();
+    let masked = match (chars.next(), chars.next()) {
+        (Some(first), Some(_)) => format!("{}***", first),
+        _ => "***".to_string(),
+    };
+    match domain {
+        Some(domain) => format!("{}@{}", masked, domain),
+        None => masked,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::configuration::PiiRedactionSettings;
+    use crate::telemetry::redact_pii_with;
+
+    const KEY: &[u8] = b"log-pii-hash-key";
+
+    #[test]
+    fn values_are_passed_through_when_redaction_is_off() {
+        assert_eq!(
+            redact_pii_with(PiiRedactionSettings::Off, KEY, "ursula@example.com"),
+            "ursula@example.com"
+        );
+    }
+
+    #[test]
+    fn email_local_part_is_masked_and_domain_is_kept() {
+        assert_eq!(
+            redact_pii_with(PiiRedactionSettings::Mask, KEY, "ursula@example.com"),
+            "u***@example.com"
+        );
+    }
+
+    #[test]
+    fn short_local_parts_are_fully_masked() {
+        assert_eq!(
+            redact_pii_with(PiiRedactionSettings::Mask, KEY, "u@example.com"),
+            "***@example.com"
+        );
+        assert_eq!(
+            redact_pii_with(PiiRedactionSettings::Mask, KEY, "@example.com"),
+            "***@example.com"
+        );
+        assert_eq!(redact_pii_with(PiiRedactionSettings::Mask, KEY, "u"), "***");
+    }
+
+    #[test]
+    fn names_are_masked_after_first_character() {
+        assert_eq!(
+            redact_pii_with(PiiRedactionSettings::Mask, KEY, "Ursula"),
+            "U***"
+        );
+        assert_eq!(
+            redact_pii_with(PiiRedactionSettings::Mask, KEY, "Ærin"),
+            "Æ***"
+        );
+    }
+
+    #[test]
+    fn hashed_values_are_stable_and_hide_original() {
+        let hashed = redact_pii_with(PiiRedactionSettings::Hash, KEY, "ursula@example.com");
+
+        assert_eq!(hashed.len(), 64);
+        assert!(!hashed.contains("ursula"));
+        assert_eq!(
+            hashed,
+            redact_pii_with(PiiRedactionSettings::Hash, KEY, "ursula@example.com")
+        );
+    }
+
+    #[test]
+    fn hashed_values_depend_on_key() {
+        assert_ne!(
+            redact_pii_with(PiiRedactionSettings::Hash, KEY, "ursula@example.com"),
+            redact_pii_with(
+                PiiRedactionSettings::Hash,
+                b"other-key",
+                "ursula@example.com"
+            )
+        );
+    }
+}
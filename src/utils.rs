@@ -1,5 +1,14 @@
-use actix_web::http::header::LOCATION;
-use actix_web::HttpResponse;
+use crate::middleware::RequestId;
+use actix_web::http::header::{
+    CacheControl, CacheDirective, ContentType, ETag, EntityTag, Header, IfNoneMatch, LOCATION,
+    RETRY_AFTER, VARY,
+};
+use actix_web::http::StatusCode;
+use actix_web::{HttpRequest, HttpResponse, HttpResponseBuilder, ResponseError};
+use base64::Engine;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
 use sqlx::PgPool;
 use std::fmt::Formatter;
 use uuid::Uuid;
@@ -29,18 +38,142 @@ where
     tokio::task::spawn_blocking(move || current_span.in_scope(f))
 }
 
+// Generate `len`-characters-long URL-safe base64 (A-Z, a-z, 0-9, `-`, `_`) token from OS CSPRNG
+// Each character carries 6 bits of entropy, so it can be put in links without escaping
+pub fn generate_secure_token(len: usize) -> String {
+    let mut bytes = vec![0u8; (len * 3 + 3) / 4];
+    OsRng.fill_bytes(&mut bytes);
+    let mut token = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes);
+    token.truncate(len);
+    token
+}
+
+trait ErrorCause: std::fmt::Debug + std::fmt::Display {}
+
+impl<T: std::fmt::Debug + std::fmt::Display> ErrorCause for T {}
+
+// Error returned to client with a JSON body `{ "error": "...", "request_id": "..." }`
+// Only `public_message` is exposed to client, internal cause is only recorded in logs
+pub struct ApiError {
+    status_code: StatusCode,
+    public_message: String,
+    cause: Box<dyn ErrorCause>,
+    retry_after_secs: Option<u64>,
+}
+
+#[derive(serde::Serialize)]
+struct ApiErrorBody<'a> {
+    error: &'a str,
+    request_id: Option<&'a str>,
+}
+
+impl ApiError {
+    pub fn new<T>(status_code: StatusCode, public_message: impl Into<String>, cause: T) -> Self
+    where
+        T: std::fmt::Debug + std::fmt::Display + 'static,
+    {
+        Self {
+            status_code,
+            public_message: public_message.into(),
+            cause: Box::new(cause),
+            retry_after_secs: None,
+        }
+    }
+
+    // Tell client when to retry with `Retry-After` header
+    pub fn retry_after(mut self, secs: u64) -> Self {
+        self.retry_after_secs = Some(secs);
+        self
+    }
+
+    // Request id is only known by `propagate_request_id` middleware, which fills it in later
+    pub fn json_body(&self, request_id: Option<&str>) -> String {
+        serde_json::to_string(&ApiErrorBody {
+            error: &self.public_message,
+            request_id,
+        })
+        .unwrap_or_default()
+    }
+}
+
+// Logged by TracingLogger, so keep full error chain of cause
+// Error types in this crate format their chain with `error_chain_fmt`
+impl std::fmt::Debug for ApiError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&self.cause, f)
+    }
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Display::fmt(&self.cause, f)
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        self.status_code
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code);
+        if let Some(retry_after_secs) = self.retry_after_secs {
+            response.insert_header((RETRY_AFTER, retry_after_secs.to_string()));
+        }
+        response
+            .content_type(ContentType::json())
+            .body(self.json_body(None))
+    }
+}
+
 pub fn e500<T>(e: T) -> actix_web::Error
 where
     T: std::fmt::Debug + std::fmt::Display + 'static,
 {
-    actix_web::error::ErrorInternalServerError(e)
+    // Don't leak internal details (e.g. database errors) to client
+    ApiError::new(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "Internal server error",
+        e,
+    )
+    .into()
+}
+
+// Seconds clients are told to wait when no database connection is available
+pub const POOL_EXHAUSTED_RETRY_AFTER_SECS: u64 = 1;
+
+// Connection pool times out acquiring a connection when app is overloaded rather than broken
+pub fn is_pool_exhausted(e: &sqlx::Error) -> bool {
+    matches!(e, sqlx::Error::PoolTimedOut)
 }
 
-pub fn e400<T>(e: T) -> actix_web::Error
+// Respond 503 with `Retry-After` when database pool is exhausted, so clients and load balancers back off
+// Other database errors are internal errors
+pub fn e503_if_pool_exhausted(e: sqlx::Error) -> actix_web::Error {
+    if !is_pool_exhausted(&e) {
+        return e500(e);
+    }
+    ApiError::new(
+        StatusCode::SERVICE_UNAVAILABLE,
+        "Service is overloaded, retry later",
+        e,
+    )
+    .retry_after(POOL_EXHAUSTED_RETRY_AFTER_SECS)
+    .into()
+}
+
+// Cause may describe internals, e.g. parser errors, so caller decides what client is told
+// Pass a validation message as both when it's written for users
+pub fn e400<T>(public_message: impl Into<String>, e: T) -> actix_web::Error
 where
     T: std::fmt::Debug + std::fmt::Display + 'static,
 {
-    actix_web::error::ErrorBadRequest(e)
+    tracing::warn!(
+        error.cause_chain = ?e,
+        error.message = %e,
+        "Rejected invalid request"
+    );
+    ApiError::new(StatusCode::BAD_REQUEST, public_message, e).into()
 }
 
 #[tracing::instrument(name = "Get username from database with user_id", skip(pg_pool))]
@@ -66,3 +199,157 @@ pub fn see_other(location: &str) -> HttpResponse {
         .insert_header((LOCATION, location))
         .finish()
 }
+
+// Pages differ per session cookie and may be compressed per `Accept-Encoding`,
+// so shared caches must not serve one client's page to another
+const HTML_VARY: &str = "Accept-Encoding, Cookie";
+
+// `ContentType::html()` is `text/html; charset=utf-8`
+fn html_headers(builder: &mut HttpResponseBuilder) -> &mut HttpResponseBuilder {
+    builder
+        .content_type(ContentType::html())
+        .insert_header((VARY, HTML_VARY))
+}
+
+// All HTML pages are built here, so they carry the same charset and `Vary`
+pub fn html_response(html: String) -> HttpResponse {
+    html_headers(&mut HttpResponse::Ok()).body(html)
+}
+
+pub fn html_error_response(status: StatusCode, html: String) -> HttpResponse {
+    html_headers(&mut HttpResponse::build(status)).body(html)
+}
+
+// Page which renders the same HTML every time is revalidated with `ETag` derived from its content
+// Browser gets 304 without body when its cached page is still the same
+pub fn cacheable_html(request: &HttpRequest, html: String) -> HttpResponse {
+    let etag = EntityTag::new_strong(hex::encode(&Sha256::digest(html.as_bytes())[..16]));
+    let is_not_modified = match IfNoneMatch::parse(request) {
+        Ok(IfNoneMatch::Any) => true,
+        Ok(IfNoneMatch::Items(etags)) => etags.iter().any(|e| e.weak_eq(&etag)),
+        Err(_) => false,
+    };
+    // Pages may be served to logged in users, so only browsers may cache them
+    let cache_control = CacheControl(vec![CacheDirective::NoCache, CacheDirective::Private]);
+
+    if is_not_modified {
+        return HttpResponse::NotModified()
+            .insert_header((VARY, HTML_VARY))
+            .insert_header(ETag(etag))
+            .insert_header(cache_control)
+            .finish();
+    }
+    html_headers(&mut HttpResponse::Ok())
+        .insert_header(ETag(etag))
+        .insert_header(cache_control)
+        .body(html)
+}
+
+// Page embedding one-off content, e.g. flash messages, must never be served from cache
+pub fn uncacheable_html(html: String) -> HttpResponse {
+    html_headers(&mut HttpResponse::Ok())
+        .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+        .body(html)
+}
+
+#[derive(strum::AsRefStr, Clone, Copy, Debug)]
+pub enum AuditAction {
+    #[strum(serialize = "change_password")]
+    ChangePassword,
+    #[strum(serialize = "publish_newsletters")]
+    PublishNewsletters,
+    #[strum(serialize = "redrive_newsletters")]
+    RedriveNewsletters,
+    #[strum(serialize = "erase_subscriber")]
+    EraseSubscriber,
+    #[strum(serialize = "logout")]
+    Logout,
+    #[strum(serialize = "set_maintenance_mode")]
+    SetMaintenanceMode,
+}
+
+// Audit trail must not fail the action it records, so errors are only logged
+#[tracing::instrument(name = "Record audit log into database", skip(pg_pool, request_id))]
+pub async fn record_audit(
+    pg_pool: &PgPool,
+    user_id: &Uuid,
+    action: AuditAction,
+    target: Option<&str>,
+    request_id: Option<&RequestId>,
+) {
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO audit_log (id, user_id, action, target, request_id)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        Uuid::new_v4(),
+        user_id,
+        action.as_ref(),
+        target,
+        request_id.map(|id| id.to_string())
+    )
+    .execute(pg_pool)
+    .await;
+
+    if let Err(e) = result {
+        tracing::error!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            "Failed to record audit log"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::{e400, generate_secure_token, html_response};
+    use actix_web::body::to_bytes;
+    use actix_web::http::header::{CONTENT_TYPE, VARY};
+
+    #[test]
+    fn secure_token_has_requested_length_and_is_url_safe() {
+        for len in [22, 25, 43, 64] {
+            let token = generate_secure_token(len);
+
+            assert_eq!(token.len(), len);
+            assert!(token
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+        }
+    }
+
+    #[test]
+    fn secure_tokens_are_unique() {
+        assert_ne!(generate_secure_token(43), generate_secure_token(43));
+    }
+
+    #[test]
+    fn html_response_has_utf8_charset_and_vary() {
+        let response = html_response("<p>Hello</p>".to_string());
+
+        assert_eq!(
+            response.headers().get(CONTENT_TYPE).unwrap(),
+            "text/html; charset=utf-8"
+        );
+        assert_eq!(
+            response.headers().get(VARY).unwrap(),
+            "Accept-Encoding, Cookie"
+        );
+    }
+
+    #[tokio::test]
+    async fn bad_request_exposes_public_message_but_not_its_cause() {
+        let error = e400(
+            "Invalid idempotency key",
+            "key `secret-internal-detail` is not valid UTF-8",
+        );
+
+        let response = error.error_response();
+
+        assert_eq!(response.status().as_u16(), 400);
+        let body = to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"], "Invalid idempotency key");
+        assert!(!body.to_string().contains("secret-internal-detail"));
+    }
+}
@@ -0,0 +1,170 @@
+use sqlx::PgPool;
+use std::time::{Duration, Instant};
+
+// Worker is considered unhealthy if it misses this number of heartbeats in a row
+const MISSED_HEARTBEATS_THRESHOLD: i64 = 3;
+
+#[derive(strum::AsRefStr, Clone, Copy, Debug)]
+pub enum WorkerName {
+    #[strum(serialize = "newsletters_issues_delivery")]
+    NewslettersIssuesDelivery,
+    #[strum(serialize = "delete_expired_idempotency")]
+    DeleteExpiredIdempotency,
+    #[strum(serialize = "confirmation_emails_delivery")]
+    ConfirmationEmailsDelivery,
+    #[strum(serialize = "delete_expired_subscription_tokens")]
+    DeleteExpiredSubscriptionTokens,
+}
+
+#[derive(serde::Serialize, Debug)]
+pub struct WorkerStatus {
+    pub name: String,
+    pub last_heartbeat_at: String,
+    pub healthy: bool,
+    pub succeeded_count: i64,
+    pub failed_count: i64,
+}
+
+#[tracing::instrument(name = "Record worker heartbeat into database", skip(pg_pool))]
+pub async fn record_worker_heartbeat(
+    pg_pool: &PgPool,
+    worker_name: WorkerName,
+    expected_interval: Duration,
+    succeeded_count: i64,
+    failed_count: i64,
+) -> Result<(), sqlx::Error> {
+    let expected_interval_millis = expected_interval.as_millis() as i64;
+    sqlx::query!(
+        r#"
+        INSERT INTO worker_heartbeats (
+            worker_name,
+            last_heartbeat_at,
+            expected_interval_millis,
+            succeeded_count,
+            failed_count
+        )
+        VALUES ($1, now(), $2, $3, $4)
+        ON CONFLICT (worker_name) DO UPDATE
+        SET
+            last_heartbeat_at = now(),
+            expected_interval_millis = EXCLUDED.expected_interval_millis,
+            succeeded_count = worker_heartbeats.succeeded_count + EXCLUDED.succeeded_count,
+            failed_count = worker_heartbeats.failed_count + EXCLUDED.failed_count
+        "#,
+        worker_name.as_ref(),
+        expected_interval_millis,
+        succeeded_count,
+        failed_count
+    )
+    .execute(pg_pool)
+    .await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(name = "Get workers status from database", skip(pg_pool))]
+pub async fn get_workers_status(pg_pool: &PgPool) -> Result<Vec<WorkerStatus>, sqlx::Error> {
+    let records = sqlx::query!(
+        r#"
+        SELECT
+            worker_name,
+            last_heartbeat_at,
+            succeeded_count,
+            failed_count,
+            now() - last_heartbeat_at <= make_interval(secs => expected_interval_millis * $1 / 1000.0) AS "healthy!"
+        FROM worker_heartbeats
+        ORDER BY worker_name
+        "#,
+        MISSED_HEARTBEATS_THRESHOLD
+    )
+    .fetch_all(pg_pool)
+    .await?;
+
+    Ok(records
+        .into_iter()
+        .map(|r| WorkerStatus {
+            name: r.worker_name,
+            last_heartbeat_at: r.last_heartbeat_at.to_rfc3339(),
+            healthy: r.healthy,
+            succeeded_count: r.succeeded_count,
+            failed_count: r.failed_count,
+        })
+        .collect())
+}
+
+// Workers looping faster than heartbeat interval, e.g. busy or polling often, write at most one
+// heartbeat per interval, counters of tasks in between are accumulated into it
+pub struct WorkerHeartbeat {
+    worker_name: WorkerName,
+    interval: Duration,
+    last_recorded_at: Option<Instant>,
+    succeeded_count: i64,
+    failed_count: i64,
+}
+
+impl WorkerHeartbeat {
+    pub fn new(worker_name: WorkerName, interval: Duration) -> Self {
+        Self {
+            worker_name,
+            interval,
+            last_recorded_at: None,
+            succeeded_count: 0,
+            failed_count: 0,
+        }
+    }
+
+    pub async fn beat(&mut self, pg_pool: &PgPool, succeeded_count: i64, failed_count: i64) {
+        self.succeeded_count += succeeded_count;
+        self.failed_count += failed_count;
+        if let Some(last_recorded_at) = self.last_recorded_at {
+            if last_recorded_at.elapsed() < self.interval {
+                return;
+            }
+        }
+        // Counters are kept for next heartbeat when recording fails
+        if try_record_worker_heartbeat(
+            pg_pool,
+            self.worker_name,
+            self.interval,
+            self.succeeded_count,
+            self.failed_count,
+        )
+        .await
+        {
+            self.succeeded_count = 0;
+            self.failed_count = 0;
+        }
+        self.last_recorded_at = Some(Instant::now());
+    }
+}
+
+// Heartbeat failure shouldn't stop the worker from doing its job
+// Return whether heartbeat is recorded
+pub async fn try_record_worker_heartbeat(
+    pg_pool: &PgPool,
+    worker_name: WorkerName,
+    expected_interval: Duration,
+    succeeded_count: i64,
+    failed_count: i64,
+) -> bool {
+    match record_worker_heartbeat(
+        pg_pool,
+        worker_name,
+        expected_interval,
+        succeeded_count,
+        failed_count,
+    )
+    .await
+    {
+        Ok(()) => true,
+        Err(e) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to record {} worker heartbeat",
+                worker_name.as_ref()
+            );
+            false
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use crate::helpers::{assert_redirects_to, create_confirmed_subscriber, TestApp};
+use uuid::Uuid;
+
+fn newsletter_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string()
+    })
+}
+
+#[tokio::test]
+async fn publish_newsletters_with_valid_api_token_succeeds() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    create_confirmed_subscriber(&app).await;
+    app.login().await;
+    let (_, token) = app.mint_api_token().await;
+
+    // Act
+    let response = app
+        .post_newsletters_with_api_token(&newsletter_body(), &token)
+        .await;
+
+    // Assert
+    assert_redirects_to(&response, "/admin/newsletters");
+    let last_used_at = sqlx::query!("SELECT last_used_at FROM api_tokens")
+        .fetch_one(&app.pg_pool)
+        .await
+        .unwrap()
+        .last_used_at;
+    assert!(last_used_at.is_some());
+}
+
+#[tokio::test]
+async fn publish_newsletters_with_revoked_api_token_ret_401() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    app.login().await;
+    let (token_id, token) = app.mint_api_token().await;
+    let response = app.revoke_api_token(&token_id).await;
+    assert_eq!(response.status().as_u16(), 204);
+
+    // Act
+    let response = app
+        .post_newsletters_with_api_token(&newsletter_body(), &token)
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn publish_newsletters_with_tampered_api_token_ret_401() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    app.login().await;
+    let (token_id, _) = app.mint_api_token().await;
+    let tampered_token = format!("{}.{}", token_id.replace('-', ""), "a".repeat(32));
+
+    // Act
+    let response = app
+        .post_newsletters_with_api_token(&newsletter_body(), &tampered_token)
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn api_token_stored_hashed() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    app.login().await;
+
+    // Act
+    let (_, token) = app.mint_api_token().await;
+
+    // Assert
+    let token_hash = sqlx::query!("SELECT token_hash FROM api_tokens")
+        .fetch_one(&app.pg_pool)
+        .await
+        .unwrap()
+        .token_hash;
+    let (_, secret) = token.split_once('.').unwrap();
+    assert!(!token_hash.contains(secret));
+}
+
+#[tokio::test]
+async fn publish_newsletters_with_api_token_without_idempotency_key_ret_400() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    app.login().await;
+    let (_, token) = app.mint_api_token().await;
+    let mut body = newsletter_body();
+    body.as_object_mut().unwrap().remove("idempotency_key");
+
+    // Act
+    let response = app.post_newsletters_with_api_token(&body, &token).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
@@ -0,0 +1,112 @@
+use crate::helpers::{assert_redirects_to, create_confirmed_subscriber, TestApp};
+use uuid::Uuid;
+
+fn newsletter_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string()
+    })
+}
+
+#[tokio::test]
+async fn publish_newsletters_with_valid_basic_auth_succeeds() {
+    // Arrange
+    let app = TestApp::builder()
+        .allow_newsletters_basic_auth()
+        .build()
+        .await
+        .unwrap();
+    create_confirmed_subscriber(&app).await;
+
+    // Act
+    let response = app
+        .post_newsletters_with_basic_auth(
+            &newsletter_body(),
+            &app.test_user.username,
+            &app.test_user.password,
+        )
+        .await;
+
+    // Assert
+    assert_redirects_to(&response, "/admin/newsletters");
+    let n_issues = sqlx::query!("SELECT COUNT(*) FROM newsletters_issues")
+        .fetch_one(&app.pg_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_issues, Some(1));
+}
+
+#[tokio::test]
+async fn publish_newsletters_with_invalid_basic_auth_ret_401() {
+    // Arrange
+    let app = TestApp::builder()
+        .allow_newsletters_basic_auth()
+        .build()
+        .await
+        .unwrap();
+    create_confirmed_subscriber(&app).await;
+
+    for (username, password) in [
+        (app.test_user.username.clone(), Uuid::new_v4().to_string()),
+        (Uuid::new_v4().to_string(), app.test_user.password.clone()),
+    ] {
+        // Act
+        let response = app
+            .post_newsletters_with_basic_auth(&newsletter_body(), &username, &password)
+            .await;
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 401);
+        assert_eq!(
+            response.headers()["WWW-Authenticate"],
+            r#"Basic realm="admin""#
+        );
+    }
+}
+
+#[tokio::test]
+async fn publish_newsletters_with_basic_auth_of_user_with_two_factor_ret_401() {
+    // Arrange
+    let app = TestApp::builder()
+        .allow_newsletters_basic_auth()
+        .build()
+        .await
+        .unwrap();
+    create_confirmed_subscriber(&app).await;
+    app.login().await;
+    app.enroll_two_factor().await;
+
+    // Act
+    let response = app
+        .post_newsletters_with_basic_auth(
+            &newsletter_body(),
+            &app.test_user.username,
+            &app.test_user.password,
+        )
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn basic_auth_is_not_accepted_unless_enabled() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    create_confirmed_subscriber(&app).await;
+
+    // Act
+    let response = app
+        .post_newsletters_with_basic_auth(
+            &newsletter_body(),
+            &app.test_user.username,
+            &app.test_user.password,
+        )
+        .await;
+
+    // Assert
+    assert_redirects_to(&response, "/login");
+}
@@ -1,4 +1,5 @@
 use crate::helpers::{assert_redirects_to, TestApp};
+use zero2prod::configuration::FlashMessageStoreSettings;
 
 #[tokio::test]
 async fn wrong_current_password() {
@@ -121,3 +122,43 @@ async fn change_password_succeed() {
     let html = app.get_html("/admin/password").await;
     assert!(html.contains(r#"<p><i>Password changed</i></p>"#));
 }
+
+#[tokio::test]
+async fn flash_error_survives_redirect_with_session_message_store() {
+    // Arrange
+    let app = TestApp::builder()
+        .flash_message_store(FlashMessageStoreSettings::Session)
+        .build()
+        .await
+        .unwrap();
+    let login_form = serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password
+    });
+    let response = app.post_login(login_form).await;
+    assert_redirects_to(&response, "/admin/dashboard");
+
+    // Act 1 apply wrong current password to change password form
+    let change_pwd_form = serde_json::json!({
+        "current_password": "wrong_password",
+        "new_password": &app.test_user.password,
+        "confirm_password": &app.test_user.password
+    });
+    let response = app.post_form("/admin/password", change_pwd_form).await;
+
+    // Assert 1 flash message is kept in session instead of a cookie
+    assert_redirects_to(&response, "/admin/password");
+    assert!(response.cookies().all(|cookie| cookie.name() != "_flash"));
+
+    // Act 2 follow the redirect
+    let html = app.get_html("/admin/password").await;
+
+    // Assert 2
+    assert!(html.contains(r#"<p><i>Wrong current password</i></p>"#));
+
+    // Act 3 reload the page
+    let html = app.get_html("/admin/password").await;
+
+    // Assert 3 flash message is only shown once
+    assert!(!html.contains("Wrong current password"));
+}
@@ -38,6 +38,31 @@ async fn admin_dashboard_is_accessible_when_logged_in() {
     assert!(response.status().is_success());
 }
 
+#[tokio::test]
+async fn admin_dashboard_is_utf8_html_varying_by_cookie() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    app.login().await;
+
+    // Act
+    let response = app.get("/admin/dashboard").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Type"].to_str().unwrap(),
+        "text/html; charset=utf-8"
+    );
+    let vary: Vec<_> = response
+        .headers()
+        .get_all("Vary")
+        .iter()
+        .map(|value| value.to_str().unwrap().to_lowercase())
+        .collect();
+    assert!(vary.iter().any(|value| value.contains("cookie")));
+    assert!(vary.iter().any(|value| value.contains("accept-encoding")));
+}
+
 #[tokio::test]
 async fn clink_on_change_password_link_in_admin_dashboard_html() {
     // Arrange
@@ -76,3 +101,88 @@ async fn click_on_logout_link_in_admin_dashboard_redirects_to_login() {
     let response = app.get("/admin/logout").await;
     assert_redirects_to(&response, "/login");
 }
+
+#[tokio::test]
+async fn admin_dashboard_ret_503_when_session_store_is_unavailable() {
+    // Arrange
+    let app = TestApp::builder().proxy_redis().build().await.unwrap();
+    let response = app.login().await;
+    assert_redirects_to(&response, "/admin/dashboard");
+
+    // Act
+    app.redis_proxy.as_ref().unwrap().shutdown();
+    let response = app.get("/admin/dashboard").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 503);
+    assert!(response.headers().get("retry-after").is_some());
+}
+
+#[tokio::test]
+async fn dashboard_is_compressed_with_accepted_encoding() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    app.login().await;
+
+    // Act
+    let response = app
+        .get_with_accept_encoding("/admin/dashboard", "gzip")
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers().get("Content-Encoding").unwrap(), "gzip");
+}
+
+#[tokio::test]
+async fn dashboard_is_not_compressed_when_compression_is_disabled() {
+    // Arrange
+    let app = TestApp::builder()
+        .disable_compression()
+        .build()
+        .await
+        .unwrap();
+    app.login().await;
+
+    // Act
+    let response = app
+        .get_with_accept_encoding("/admin/dashboard", "gzip")
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.headers().get("Content-Encoding").is_none());
+}
+
+#[tokio::test]
+async fn dashboard_links_stay_under_configured_base_path() {
+    // Arrange
+    let app = TestApp::builder()
+        .base_path("/newsletter")
+        .build()
+        .await
+        .unwrap();
+    let login_html = app.get_html("/newsletter/login").await;
+    assert!(login_html.contains(r#"<form action="/newsletter/login""#));
+    let response = app
+        .post_form(
+            "/newsletter/login",
+            serde_json::json!({
+                "username": &app.test_user.username,
+                "password": &app.test_user.password
+            }),
+        )
+        .await;
+    assert_redirects_to(&response, "/newsletter/admin/dashboard");
+    let dashboard_html = app.get_html("/newsletter/admin/dashboard").await;
+    let history_href = r#"href="/newsletter/admin/newsletters/history""#;
+    assert!(dashboard_html.contains(history_href));
+
+    // Act
+    let response = app.get("/newsletter/admin/newsletters/history").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let history_html = response.text().await.unwrap();
+    assert!(history_html.contains(r#"href="/newsletter/admin/dashboard""#));
+}
@@ -0,0 +1,116 @@
+use crate::helpers::{assert_redirects_to, TestApp, TestUser};
+use uuid::Uuid;
+
+fn newsletter_body(idempotency_key: &str) -> serde_json::Value {
+    serde_json::json!({
+        "title": Uuid::new_v4().to_string(),
+        "text_content": "Newsletter body as plain text",
+        "idempotency_key": idempotency_key
+    })
+}
+
+#[tokio::test]
+async fn list_idempotency_keys_contains_key_of_published_newsletters() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    app.login().await;
+    let idempotency_key = Uuid::new_v4().to_string();
+    app.post_newsletters(&newsletter_body(&idempotency_key))
+        .await;
+
+    // Act
+    let response = app.get("/admin/idempotency").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    let keys = body["keys"].as_array().unwrap();
+    assert_eq!(keys.len(), 1);
+    assert_eq!(keys[0]["idempotency_key"], idempotency_key.as_str());
+    assert_eq!(keys[0]["request_method"], "POST");
+    assert_eq!(keys[0]["request_path"], "/admin/newsletters");
+    assert_eq!(keys[0]["response_status_code"], 303);
+    assert!(keys[0]["created_at"].is_string());
+}
+
+#[tokio::test]
+async fn cleared_idempotency_key_can_be_reused() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    app.login().await;
+    let idempotency_key = Uuid::new_v4().to_string();
+    app.post_newsletters(&newsletter_body(&idempotency_key))
+        .await;
+
+    // Act 1 clear the key
+    let response = app.clear_idempotency_key(&idempotency_key).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 204);
+
+    // Act 2 publish another issue with the same key
+    let response = app
+        .post_newsletters(&newsletter_body(&idempotency_key))
+        .await;
+
+    // Assert a new issue is published instead of replaying the first response
+    assert_redirects_to(&response, "/admin/newsletters");
+    let n_issues = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM newsletters_issues"#)
+        .fetch_one(&app.pg_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_issues, 2);
+}
+
+#[tokio::test]
+async fn idempotency_key_of_another_user_is_neither_listed_nor_cleared() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    let other_user = TestUser::generate();
+    other_user.create_user(&app.pg_pool).await;
+    let idempotency_key = Uuid::new_v4().to_string();
+    sqlx::query!(
+        r#"
+        INSERT INTO idempotency (user_id, idempotency_key, created_at)
+        VALUES ($1, $2, now())
+        "#,
+        other_user.user_id,
+        idempotency_key
+    )
+    .execute(&app.pg_pool)
+    .await
+    .unwrap();
+    app.login().await;
+
+    // Act 1
+    let body: serde_json::Value = app.get("/admin/idempotency").await.json().await.unwrap();
+
+    // Assert
+    assert!(body["keys"].as_array().unwrap().is_empty());
+
+    // Act 2
+    let response = app.clear_idempotency_key(&idempotency_key).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+    let n_records = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) AS "count!" FROM idempotency WHERE idempotency_key = $1"#,
+        idempotency_key
+    )
+    .fetch_one(&app.pg_pool)
+    .await
+    .unwrap();
+    assert_eq!(n_records, 1);
+}
+
+#[tokio::test]
+async fn list_idempotency_keys_without_login_redirects_to_login() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+
+    // Act
+    let response = app.get("/admin/idempotency").await;
+
+    // Assert
+    assert_redirects_to(&response, "/login");
+}
@@ -0,0 +1,59 @@
+use crate::helpers::{assert_redirects_to, TestApp};
+use fake::faker::internet::en::SafeEmail;
+use fake::faker::name::en::Name;
+use fake::Fake;
+
+fn subscribe_body() -> String {
+    serde_urlencoded::to_string(serde_json::json!({
+        "name": Name().fake::<String>(),
+        "email": SafeEmail().fake::<String>()
+    }))
+    .unwrap()
+}
+
+#[tokio::test]
+async fn public_routes_ret_503_during_maintenance_while_admin_and_health_stay_up() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    app.login().await;
+
+    // Act 1 enable maintenance
+    let response = app.put_maintenance_mode(true).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Assert 1
+    let response = app.post_subscriptions(subscribe_body()).await;
+    assert_eq!(response.status().as_u16(), 503);
+    assert_eq!(response.headers()["Retry-After"], "300");
+    let response = app
+        .get("/subscriptions/confirm?subscription_token=abc")
+        .await;
+    assert_eq!(response.status().as_u16(), 503);
+    assert_eq!(app.get("/health").await.status().as_u16(), 200);
+    let response = app.get("/admin/maintenance").await;
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["enabled"], true);
+
+    // Act 2 disable maintenance
+    let response = app.put_maintenance_mode(false).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Assert 2
+    let response = app.post_subscriptions(subscribe_body()).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn anonymous_user_cannot_toggle_maintenance() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+
+    // Act
+    let response = app.put_maintenance_mode(true).await;
+
+    // Assert
+    assert_redirects_to(&response, "/login");
+    let response = app.post_subscriptions(subscribe_body()).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
@@ -1,3 +1,11 @@
+mod api_tokens;
+mod basic_auth;
 mod change_password;
 mod dashboard;
+mod idempotency;
+mod maintenance;
 mod newsletters;
+mod subscribers;
+mod test_email;
+mod two_factor;
+mod workers;
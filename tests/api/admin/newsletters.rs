@@ -1,8 +1,20 @@
-use crate::helpers::{assert_redirects_to, create_confirmed_subscriber, TestApp};
+use crate::helpers::{
+    assert_redirects_to, create_confirmed_subscriber, test_database_settings, TestApp,
+};
+use actix_web::HttpResponse;
+use fake::faker::internet::en::SafeEmail;
 use fake::faker::lorem::en::{Paragraph, Sentence};
+use fake::faker::name::en::Name;
 use fake::Fake;
 use std::time::Duration;
 use uuid::Uuid;
+use zero2prod::configuration::Settings;
+use zero2prod::idempotency::{
+    try_insert_idempotency_response_record_into_database, update_idempotency_response_record,
+    IdempotencyKey, IdempotentRequest, ProcessState,
+};
+use zero2prod::newsletters_issues::{try_execute_task, ExecutionResult};
+use zero2prod::startup::get_worker_pg_pool;
 
 #[tokio::test]
 async fn publish_newsletters_invalid_form_data_ret_400() {
@@ -270,6 +282,7 @@ async fn idempotency_expired_and_republish_newsletter() {
         .spawn_newsletters_issues_delivery_worker()
         .spawn_delete_expired_idempotency_worker()
         .idempotency_expiration_time_millis(10)
+        .idempotency_sweep_interval_millis(10)
         .build()
         .await
         .unwrap();
@@ -344,3 +357,1799 @@ async fn idempotency_expired_and_republish_newsletter() {
         (n_issues * n_subscribers) as usize
     );
 }
+
+#[tokio::test]
+async fn idempotency_record_older_than_replay_max_age_is_reprocessed() {
+    // Arrange
+    // Records are kept much longer than they are replayed
+    let app = TestApp::builder()
+        .idempotency_expiration_time_millis(60_000)
+        .idempotency_replay_max_age_millis(500)
+        .build()
+        .await
+        .unwrap();
+    app.login().await;
+    create_confirmed_subscriber(&app).await;
+    let newsletter_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string()
+    });
+    let count_issues = || async {
+        sqlx::query!("SELECT COUNT(*) FROM newsletters_issues")
+            .fetch_one(&app.pg_pool)
+            .await
+            .unwrap()
+            .count
+            .unwrap()
+    };
+
+    // Act 1 publish, then retry within replay window
+    let response = app.post_newsletters(&newsletter_body).await;
+    assert_redirects_to(&response, "/admin/newsletters");
+    let response = app.post_newsletters(&newsletter_body).await;
+    assert_redirects_to(&response, "/admin/newsletters");
+
+    // Assert 1 fresh record is replayed
+    assert_eq!(count_issues().await, 1);
+
+    // Act 2 retry after replay window
+    tokio::time::sleep(Duration::from_millis(700)).await;
+    let response = app.post_newsletters(&newsletter_body).await;
+    assert_redirects_to(&response, "/admin/newsletters");
+
+    // Assert 2 stale record is reprocessed and kept as a fresh one
+    assert_eq!(count_issues().await, 2);
+    let response = app.post_newsletters(&newsletter_body).await;
+    assert_redirects_to(&response, "/admin/newsletters");
+    assert_eq!(count_issues().await, 2);
+}
+
+#[tokio::test]
+async fn in_progress_idempotency_record_older_than_replay_max_age_is_not_reprocessed() {
+    // Arrange
+    let app = TestApp::builder()
+        .idempotency_expiration_time_millis(60_000)
+        .idempotency_replay_max_age_millis(500)
+        .build()
+        .await
+        .unwrap();
+    app.login().await;
+    create_confirmed_subscriber(&app).await;
+    let idempotency_key = Uuid::new_v4().to_string();
+    let newsletter_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": idempotency_key
+    });
+    // Request in progress has no stored response yet, and can't be left in progress through API
+    sqlx::query!(
+        r#"
+        INSERT INTO idempotency (user_id, idempotency_key, request_method, request_path, created_at)
+        VALUES ($1, $2, 'POST', '/admin/newsletters', now() - interval '1 second')
+        "#,
+        app.test_user.user_id,
+        idempotency_key
+    )
+    .execute(&app.pg_pool)
+    .await
+    .unwrap();
+
+    // Act
+    let response = app.post_newsletters(&newsletter_body).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 409);
+    let n_issues = sqlx::query!("SELECT COUNT(*) FROM newsletters_issues")
+        .fetch_one(&app.pg_pool)
+        .await
+        .unwrap()
+        .count
+        .unwrap();
+    assert_eq!(n_issues, 0);
+}
+
+async fn publish_single_content_newsletter_and_get_received_message(
+    content_field: &str,
+    content: &str,
+) -> serde_json::Value {
+    // Arrange
+    let app = TestApp::builder()
+        .spawn_newsletters_issues_delivery_worker()
+        .build()
+        .await
+        .unwrap();
+    app.login().await;
+
+    let subscriber_email: String = SafeEmail().fake();
+    app.create_confirmed_subscriber(serde_json::json!({
+        "name": Name().fake::<String>(),
+        "email": subscriber_email
+    }))
+    .await;
+
+    let title: String = Sentence(10..20).fake();
+    let newsletter_body = serde_json::json!({
+        "title": title,
+        content_field: content,
+        "idempotency_key": Uuid::new_v4().to_string()
+    });
+
+    // Act
+    let response = app.post_newsletters(&newsletter_body).await;
+    assert_redirects_to(&response, "/admin/newsletters");
+
+    tokio::time::timeout(
+        Duration::from_secs(10),
+        app.wait_until_completed_newsletters_issue_count_matches(1),
+    )
+    .await
+    .expect("Failed to wait until newsletters issue is completed");
+
+    app.get_email_message_json(&subscriber_email, &title).await
+}
+
+#[tokio::test]
+async fn publish_text_only_newsletter_sends_plain_text_email() {
+    let text: String = Paragraph(5..10).fake();
+
+    let message =
+        publish_single_content_newsletter_and_get_received_message("text_content", &text).await;
+
+    // Assert
+    assert_eq!(message["text"].as_str().unwrap().trim(), text);
+    let raw = message["raw"].as_str().unwrap();
+    assert!(raw.contains("Content-Type: text/plain"));
+    assert!(!raw.contains("multipart/alternative"));
+}
+
+#[tokio::test]
+async fn publish_html_only_newsletter_sends_html_email() {
+    let html = format!("<p>{}</p>", Paragraph(5..10).fake::<String>());
+
+    let message =
+        publish_single_content_newsletter_and_get_received_message("html_content", &html).await;
+
+    // Assert
+    assert_eq!(message["html"].as_str().unwrap().trim(), html);
+    let raw = message["raw"].as_str().unwrap();
+    assert!(raw.contains("Content-Type: text/html"));
+    assert!(!raw.contains("multipart/alternative"));
+}
+
+#[tokio::test]
+async fn newsletter_carries_list_id_and_subject_prefix_but_confirmation_email_does_not() {
+    // Arrange
+    let list_id = "Zero2Prod Newsletter <newsletter.zero2prod.example.com>";
+    let subject_prefix = "[Zero2Prod] ";
+    let app = TestApp::builder()
+        .list_id(list_id)
+        .subject_prefix(subject_prefix)
+        .spawn_newsletters_issues_delivery_worker()
+        .build()
+        .await
+        .unwrap();
+    app.login().await;
+
+    let subscriber_email: String = SafeEmail().fake();
+    app.create_confirmed_subscriber(serde_json::json!({
+        "name": Name().fake::<String>(),
+        "email": subscriber_email
+    }))
+    .await;
+
+    let title: String = Sentence(10..20).fake();
+    let newsletter_body = serde_json::json!({
+        "title": title,
+        "text_content": Paragraph(5..10).fake::<String>(),
+        "idempotency_key": Uuid::new_v4().to_string()
+    });
+
+    // Act
+    let response = app.post_newsletters(&newsletter_body).await;
+    assert_redirects_to(&response, "/admin/newsletters");
+
+    tokio::time::timeout(
+        Duration::from_secs(10),
+        app.wait_until_completed_newsletters_issue_count_matches(1),
+    )
+    .await
+    .expect("Failed to wait until newsletters issue is completed");
+
+    // Assert
+    let newsletter = app
+        .get_email_message_json(&subscriber_email, &format!("{}{}", subject_prefix, title))
+        .await;
+    assert!(newsletter["raw"]
+        .as_str()
+        .unwrap()
+        .contains(&format!("List-Id: {}", list_id)));
+
+    let confirmation = app
+        .get_email_message_json(&subscriber_email, "Confirmation")
+        .await;
+    assert!(!confirmation["raw"].as_str().unwrap().contains("List-Id"));
+}
+
+#[tokio::test]
+async fn publish_newsletters_with_empty_contents_ret_400() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    app.login().await;
+
+    let newsletter_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "",
+        "html_content": "   ",
+        "idempotency_key": Uuid::new_v4().to_string()
+    });
+
+    // Act
+    let response = app.post_newsletters(&newsletter_body).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+async fn count_newsletters_issues(app: &TestApp) -> i64 {
+    sqlx::query!("SELECT COUNT(*) FROM newsletters_issues")
+        .fetch_one(&app.pg_pool)
+        .await
+        .expect("Failed to fetch number of newsletters_issues")
+        .count
+        .expect("Expect number of newsletters_issues")
+}
+
+#[tokio::test]
+async fn publish_newsletters_with_idempotency_key_header_only() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    app.login().await;
+    let idempotency_key = Uuid::new_v4().to_string();
+    let newsletter_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+    });
+
+    // Act
+    for _ in 0..2 {
+        let response = app
+            .post_newsletters_with_idempotency_key_header(&newsletter_body, &idempotency_key)
+            .await;
+        assert_redirects_to(&response, "/admin/newsletters");
+    }
+
+    // Assert
+    assert_eq!(count_newsletters_issues(&app).await, 1);
+}
+
+#[tokio::test]
+async fn publish_newsletters_with_idempotency_key_form_field_only() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    app.login().await;
+    let newsletter_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "idempotency_key": Uuid::new_v4().to_string()
+    });
+
+    // Act
+    for _ in 0..2 {
+        let response = app.post_newsletters(&newsletter_body).await;
+        assert_redirects_to(&response, "/admin/newsletters");
+    }
+
+    // Assert
+    assert_eq!(count_newsletters_issues(&app).await, 1);
+}
+
+#[tokio::test]
+async fn publish_newsletters_with_conflicting_idempotency_keys_ret_400() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    app.login().await;
+    let newsletter_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "idempotency_key": Uuid::new_v4().to_string()
+    });
+
+    // Act
+    let response = app
+        .post_newsletters_with_idempotency_key_header(&newsletter_body, &Uuid::new_v4().to_string())
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(count_newsletters_issues(&app).await, 0);
+}
+
+#[tokio::test]
+async fn reuse_idempotency_key_of_another_route_ret_422() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    app.login().await;
+    let idempotency_key = Uuid::new_v4().to_string();
+
+    // Same key is already used by the user on another route
+    let key = IdempotencyKey::try_from(idempotency_key.clone()).unwrap();
+    let other_request = IdempotentRequest {
+        method: "POST",
+        path: "/admin/password",
+    };
+    let transaction = app.pg_pool.begin().await.unwrap();
+    let mut transaction = match try_insert_idempotency_response_record_into_database(
+        transaction,
+        &key,
+        Some(&app.test_user.user_id),
+        &other_request,
+        None,
+    )
+    .await
+    .unwrap()
+    {
+        ProcessState::StartProcessing(transaction) => transaction,
+        ProcessState::Completed(_) => panic!("Idempotency key must not be used yet"),
+    };
+    update_idempotency_response_record(
+        &mut transaction,
+        &key,
+        Some(&app.test_user.user_id),
+        HttpResponse::SeeOther().finish(),
+        usize::MAX,
+    )
+    .await
+    .unwrap();
+    transaction.commit().await.unwrap();
+
+    let newsletter_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "idempotency_key": idempotency_key
+    });
+
+    // Act
+    let response = app.post_newsletters(&newsletter_body).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 422);
+    assert_eq!(count_newsletters_issues(&app).await, 0);
+}
+
+#[tokio::test]
+async fn failed_delivery_is_listed_in_newsletters_issue_failures_with_last_error() {
+    // Arrange
+    let app = TestApp::builder()
+        .proxy_email_server()
+        .spawn_newsletters_issues_delivery_worker()
+        .build()
+        .await
+        .unwrap();
+    app.login().await;
+
+    let subscriber_email: String = SafeEmail().fake();
+    app.create_confirmed_subscriber(serde_json::json!({
+        "name": Name().fake::<String>(),
+        "email": subscriber_email
+    }))
+    .await;
+
+    // Email provider goes down, so every newsletter delivery attempt fails
+    app.email_server_proxy.as_ref().unwrap().pause();
+
+    let newsletter_body = serde_json::json!({
+        "title": Sentence(10..20).fake::<String>(),
+        "text_content": Paragraph(5..10).fake::<String>(),
+        "idempotency_key": Uuid::new_v4().to_string()
+    });
+    let response = app.post_newsletters(&newsletter_body).await;
+    assert_redirects_to(&response, "/admin/newsletters");
+
+    let newsletters_issue_id = sqlx::query!("SELECT id FROM newsletters_issues")
+        .fetch_one(&app.pg_pool)
+        .await
+        .expect("Failed to fetch newsletters issue")
+        .id;
+
+    // Act
+    let failures = tokio::time::timeout(Duration::from_secs(15), async {
+        loop {
+            let response = app
+                .get(&format!(
+                    "/admin/newsletters/{}/failures",
+                    newsletters_issue_id
+                ))
+                .await;
+            assert_eq!(response.status().as_u16(), 200);
+            let body: serde_json::Value = response.json().await.unwrap();
+            let failures = body["failures"].as_array().unwrap().clone();
+            if !failures.is_empty() {
+                break failures;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await
+    .expect("Failed to wait until delivery failure is recorded");
+
+    // Assert
+    assert_eq!(failures.len(), 1);
+    assert_eq!(failures[0]["subscriber_email"], subscriber_email.as_str());
+    assert!(failures[0]["n_attempts"].as_i64().unwrap() >= 1);
+    assert!(!failures[0]["last_attempted_at"].is_null());
+    assert!(!failures[0]["last_error"].as_str().unwrap().is_empty());
+
+    // Assert failures page linked from history lists the same recipient
+    let history_html = app.get_html("/admin/newsletters/history").await;
+    let issue_href = format!(r#"href="/admin/newsletters/{}""#, newsletters_issue_id);
+    assert!(history_html.contains(&issue_href));
+    let response = app
+        .get(&format!("/admin/newsletters/{}", newsletters_issue_id))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains(subscriber_email.as_str()));
+}
+
+#[tokio::test]
+async fn newsletters_issue_failures_without_login_redirects_to_login() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+
+    // Act
+    let response = app
+        .get(&format!("/admin/newsletters/{}/failures", Uuid::new_v4()))
+        .await;
+
+    // Assert
+    assert_redirects_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn repeated_newsletters_issue_status_updates_still_complete_issue() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    app.login().await;
+    let emails: Vec<String> = (0..2).map(|_| SafeEmail().fake()).collect();
+    for email in &emails {
+        app.create_confirmed_subscriber(serde_json::json!({
+            "name": Name().fake::<String>(),
+            "email": email
+        }))
+        .await;
+    }
+
+    let newsletter_body = serde_json::json!({
+        "title": Sentence(10..20).fake::<String>(),
+        "text_content": Paragraph(5..10).fake::<String>(),
+        "idempotency_key": Uuid::new_v4().to_string()
+    });
+    let response = app.post_newsletters(&newsletter_body).await;
+    assert_redirects_to(&response, "/admin/newsletters");
+
+    let newsletters_issue_id = sqlx::query!("SELECT id FROM newsletters_issues")
+        .fetch_one(&app.pg_pool)
+        .await
+        .expect("Failed to fetch newsletters issue")
+        .id;
+
+    // Act 1 erasing a recipient updates issue status once
+    let response = app
+        .post_subscribers_erase(&serde_json::json!({ "email": emails[0] }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Act 2 delivering the rest updates it again
+    loop {
+        let result = try_execute_task(&app.pg_pool, &app.email_client, &app.content_store)
+            .await
+            .unwrap();
+        if matches!(result, ExecutionResult::EmptyQueue) {
+            break;
+        }
+    }
+
+    // Assert
+    let issue = sqlx::query!(
+        "SELECT status, finished_n_tasks, required_n_tasks FROM newsletters_issues WHERE id = $1",
+        newsletters_issue_id
+    )
+    .fetch_one(&app.pg_pool)
+    .await
+    .expect("Failed to fetch newsletters issue");
+    assert_eq!(issue.status, "COMPLETED");
+    assert_eq!(issue.required_n_tasks, 2);
+    assert_eq!(issue.finished_n_tasks, issue.required_n_tasks);
+}
+
+#[tokio::test]
+async fn newsletters_issue_with_only_invalid_recipients_is_completed() {
+    // Arrange
+    let app = TestApp::builder()
+        .spawn_newsletters_issues_delivery_worker()
+        .build()
+        .await
+        .unwrap();
+    let newsletters_issue_id = Uuid::new_v4();
+
+    // Seed issue and its queue in one transaction, so worker only sees the complete issue
+    let mut transaction = app.pg_pool.begin().await.unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletters_issues (
+            id,
+            title,
+            text_content,
+            status,
+            published_at,
+            finished_n_tasks,
+            required_n_tasks
+        )
+        VALUES ($1, $2, $3, 'AVAILABLE', now(), 0, 1)
+        "#,
+        newsletters_issue_id,
+        Sentence(10..20).fake::<String>(),
+        Paragraph(5..10).fake::<String>(),
+    )
+    .execute(&mut transaction)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletters_issues_delivery_queue (id, subscriber_email)
+        VALUES ($1, 'not-an-email')
+        "#,
+        newsletters_issue_id,
+    )
+    .execute(&mut transaction)
+    .await
+    .unwrap();
+    transaction.commit().await.unwrap();
+
+    // Act
+    tokio::time::timeout(
+        Duration::from_secs(15),
+        app.wait_until_completed_newsletters_issue_count_matches(1),
+    )
+    .await
+    .expect("Worker kept retrying invalid recipient instead of completing the issue");
+
+    // Assert
+    let issue = sqlx::query!(
+        "SELECT finished_n_tasks, failed_n_tasks FROM newsletters_issues WHERE id = $1",
+        newsletters_issue_id
+    )
+    .fetch_one(&app.pg_pool)
+    .await
+    .expect("Failed to fetch newsletters issue");
+    assert_eq!(issue.finished_n_tasks, 1);
+    assert_eq!(issue.failed_n_tasks, 1);
+
+    let remaining_tasks = sqlx::query!(
+        "SELECT COUNT(*) FROM newsletters_issues_delivery_queue WHERE id = $1",
+        newsletters_issue_id
+    )
+    .fetch_one(&app.pg_pool)
+    .await
+    .unwrap()
+    .count;
+    assert_eq!(remaining_tasks, Some(0));
+}
+
+#[tokio::test]
+async fn newsletters_history_lists_published_issues() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    app.login().await;
+    let titles: Vec<String> = (0..2).map(|_| Sentence(3..6).fake()).collect();
+    for title in &titles {
+        let response = app
+            .post_newsletters(&serde_json::json!({
+                "title": title,
+                "text_content": Paragraph(5..10).fake::<String>(),
+                "idempotency_key": Uuid::new_v4().to_string()
+            }))
+            .await;
+        assert_redirects_to(&response, "/admin/newsletters");
+    }
+
+    // Act
+    let html = app.get_html("/admin/newsletters/history").await;
+
+    // Assert
+    for title in &titles {
+        assert!(html.contains(title.as_str()));
+    }
+}
+
+#[tokio::test]
+async fn newsletters_history_honors_limit() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    app.login().await;
+    for _ in 0..3 {
+        app.post_newsletters(&serde_json::json!({
+            "title": Sentence(3..6).fake::<String>(),
+            "text_content": Paragraph(5..10).fake::<String>(),
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+    }
+
+    // Act
+    let html = app.get_html("/admin/newsletters/history?limit=2").await;
+
+    // Assert
+    assert_eq!(html.matches("/failures\"").count(), 2);
+}
+
+#[tokio::test]
+async fn newsletters_history_without_login_redirects_to_login() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+
+    // Act
+    let response = app.get("/admin/newsletters/history").await;
+
+    // Assert
+    assert_redirects_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn publish_newsletters_records_audit_log() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    app.login().await;
+
+    // Act
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": Sentence(3..6).fake::<String>(),
+            "text_content": Paragraph(5..10).fake::<String>(),
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_redirects_to(&response, "/admin/newsletters");
+
+    // Assert
+    let newsletters_issue_id = sqlx::query!("SELECT id FROM newsletters_issues")
+        .fetch_one(&app.pg_pool)
+        .await
+        .expect("Failed to fetch newsletters issue")
+        .id;
+    let audit = sqlx::query!("SELECT user_id, action, target, request_id FROM audit_log")
+        .fetch_one(&app.pg_pool)
+        .await
+        .expect("Failed to fetch audit log");
+    assert_eq!(audit.user_id, app.test_user.user_id);
+    assert_eq!(audit.action, "publish_newsletters");
+    assert_eq!(audit.target, Some(newsletters_issue_id.to_string()));
+    assert!(audit.request_id.is_some());
+}
+
+#[tokio::test]
+async fn expired_idempotency_records_are_deleted_with_sweep_interval_longer_than_expiration() {
+    // Arrange
+    let app = TestApp::builder()
+        .spawn_delete_expired_idempotency_worker()
+        .idempotency_expiration_time_millis(10)
+        .idempotency_sweep_interval_millis(200)
+        .build()
+        .await
+        .unwrap();
+    app.login().await;
+
+    let idempotency_key = Uuid::new_v4().to_string();
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": Sentence(3..6).fake::<String>(),
+            "text_content": Paragraph(5..10).fake::<String>(),
+            "idempotency_key": idempotency_key
+        }))
+        .await;
+    assert_redirects_to(&response, "/admin/newsletters");
+
+    // Act wait for a sweep after record is expired
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    // Assert
+    let result = sqlx::query!(
+        "SELECT user_id FROM idempotency WHERE idempotency_key = $1",
+        idempotency_key
+    )
+    .fetch_optional(&app.pg_pool)
+    .await
+    .expect("Failed to fetch idempotency");
+    assert!(result.is_none());
+}
+
+async fn get_queued_subscriber_emails(app: &TestApp, title: &str) -> Vec<String> {
+    sqlx::query!(
+        r#"
+        SELECT q.subscriber_email
+        FROM newsletters_issues_delivery_queue q
+        JOIN newsletters_issues i ON i.id = q.id
+        WHERE i.title = $1
+        "#,
+        title
+    )
+    .fetch_all(&app.pg_pool)
+    .await
+    .expect("Failed to fetch queued subscriber emails")
+    .into_iter()
+    .map(|r| r.subscriber_email)
+    .collect()
+}
+
+#[tokio::test]
+async fn pending_targeted_newsletters_issue_enqueues_pending_subscribers_but_default_does_not() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    let pending_email: String = SafeEmail().fake();
+    let response = app
+        .post_subscriptions(
+            serde_urlencoded::to_string(serde_json::json!({
+                "name": Name().fake::<String>(),
+                "email": pending_email
+            }))
+            .unwrap(),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    create_confirmed_subscriber(&app).await;
+    app.login().await;
+
+    // Act
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "Default issue",
+            "text_content": "Newsletter body as plain text",
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_redirects_to(&response, "/admin/newsletters");
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "Re-engagement issue",
+            "text_content": "Newsletter body as plain text",
+            "idempotency_key": Uuid::new_v4().to_string(),
+            "target_statuses": "pending"
+        }))
+        .await;
+    assert_redirects_to(&response, "/admin/newsletters");
+
+    // Assert
+    let default_emails = get_queued_subscriber_emails(&app, "Default issue").await;
+    assert_eq!(default_emails.len(), 1);
+    assert!(!default_emails.contains(&pending_email));
+    let pending_emails = get_queued_subscriber_emails(&app, "Re-engagement issue").await;
+    assert_eq!(pending_emails, vec![pending_email]);
+}
+
+#[tokio::test]
+async fn publish_newsletters_targeting_unsubscribed_ret_400() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    app.login().await;
+
+    // Act
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "idempotency_key": Uuid::new_v4().to_string(),
+            "target_statuses": "confirmed,unsubscribed"
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(count_newsletters_issues(&app).await, 0);
+}
+
+#[tokio::test]
+async fn script_in_newsletter_html_is_removed_from_stored_and_sent_content() {
+    // Arrange
+    let app = TestApp::builder()
+        .spawn_newsletters_issues_delivery_worker()
+        .build()
+        .await
+        .unwrap();
+    app.login().await;
+    let subscriber_email: String = SafeEmail().fake();
+    app.create_confirmed_subscriber(serde_json::json!({
+        "name": Name().fake::<String>(),
+        "email": subscriber_email
+    }))
+    .await;
+    let title: String = Sentence(10..20).fake();
+
+    // Act
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": title,
+            "html_content": r#"<p onclick="steal()">Hello</p><script>alert("xss")</script>"#,
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_redirects_to(&response, "/admin/newsletters");
+    tokio::time::timeout(
+        Duration::from_secs(10),
+        app.wait_until_completed_newsletters_issue_count_matches(1),
+    )
+    .await
+    .expect("Failed to wait until newsletters issue is completed");
+
+    // Assert
+    let stored_html = sqlx::query!("SELECT html_content FROM newsletters_issues")
+        .fetch_one(&app.pg_pool)
+        .await
+        .unwrap()
+        .html_content
+        .expect("Missing stored HTML content");
+    assert_eq!(stored_html, "<p>Hello</p>");
+    let message = app.get_email_message_json(&subscriber_email, &title).await;
+    let sent_html = message["html"].as_str().unwrap();
+    assert!(sent_html.contains("<p>Hello</p>"));
+    assert!(!sent_html.contains("script"));
+    assert!(!sent_html.contains("onclick"));
+}
+
+#[tokio::test]
+async fn newsletter_placeholders_are_substituted_with_subscriber_details() {
+    // Arrange
+    let app = TestApp::builder()
+        .spawn_newsletters_issues_delivery_worker()
+        .build()
+        .await
+        .unwrap();
+    app.login().await;
+
+    let subscriber_name = "Ursula Le Guin";
+    let subscriber_email: String = SafeEmail().fake();
+    app.create_confirmed_subscriber(serde_json::json!({
+        "name": subscriber_name,
+        "email": subscriber_email
+    }))
+    .await;
+
+    let title: String = Sentence(10..20).fake();
+    let newsletter_body = serde_json::json!({
+        "title": title,
+        "text_content": "Hi {{name}}, this was sent to {{ email }}. {{unknown}}",
+        "html_content": "<p>Hi {{name}}</p>",
+        "idempotency_key": Uuid::new_v4().to_string()
+    });
+
+    // Act
+    let response = app.post_newsletters(&newsletter_body).await;
+    assert_redirects_to(&response, "/admin/newsletters");
+
+    tokio::time::timeout(
+        Duration::from_secs(10),
+        app.wait_until_completed_newsletters_issue_count_matches(1),
+    )
+    .await
+    .expect("Failed to wait until newsletters issue is completed");
+
+    // Assert
+    let message = app.get_email_message_json(&subscriber_email, &title).await;
+    assert_eq!(
+        message["text"].as_str().unwrap().trim(),
+        format!(
+            "Hi {}, this was sent to {}. {{{{unknown}}}}",
+            subscriber_name, subscriber_email
+        )
+    );
+    assert_eq!(
+        message["html"].as_str().unwrap().trim(),
+        format!("<p>Hi {}</p>", subscriber_name)
+    );
+}
+
+#[tokio::test]
+async fn publish_newsletters_with_oversized_html_ret_400() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    app.login().await;
+    let newsletter_body = serde_json::json!({
+        "title": "Newsletter title",
+        "html_content": format!("<p>{}</p>", "a".repeat(110 * 1024)),
+        "idempotency_key": Uuid::new_v4().to_string()
+    });
+
+    // Act
+    let response = app.post_newsletters(&newsletter_body).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(count_newsletters_issues(&app).await, 0);
+}
+
+#[tokio::test]
+async fn publish_newsletters_with_images_without_alt_warns_but_publishes() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    app.login().await;
+    let newsletter_body = serde_json::json!({
+        "title": "Newsletter title",
+        "html_content": r#"<p><img src="https://example.com/a.png"><img src="https://example.com/b.png" alt="B"></p>"#,
+        "idempotency_key": Uuid::new_v4().to_string()
+    });
+
+    // Act
+    let response = app.post_newsletters(&newsletter_body).await;
+
+    // Assert
+    assert_redirects_to(&response, "/admin/newsletters");
+    assert_eq!(count_newsletters_issues(&app).await, 1);
+    let html = app.get_html("/admin/newsletters").await;
+    assert!(html.contains(r#"<p><i>Published newsletter successfully!</i></p>"#));
+    assert!(html.contains("1 image(s) have no alt text"));
+}
+
+#[tokio::test]
+async fn double_submit_of_form_without_idempotency_key_publishes_one_issue() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    create_confirmed_subscriber(&app).await;
+    app.login().await;
+    let form_html = app.get_html("/admin/newsletters").await;
+    let form_nonce = form_html
+        .split(r#"name="form_nonce" value=""#)
+        .nth(1)
+        .and_then(|s| s.split('"').next())
+        .expect("Newsletters form doesn't render a form nonce")
+        .to_string();
+    let newsletter_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "form_nonce": form_nonce
+    });
+
+    // Act 1 submit the same form twice concurrently
+    let (response1, response2) = tokio::join!(
+        app.post_newsletters(&newsletter_body),
+        app.post_newsletters(&newsletter_body)
+    );
+
+    // Assert 1
+    assert_redirects_to(&response1, "/admin/newsletters");
+    assert_redirects_to(&response2, "/admin/newsletters");
+    assert_eq!(count_newsletters_issues(&app).await, 1);
+
+    // Act 2 submit a form without nonce either, twice
+    let newsletter_body = serde_json::json!({
+        "title": "Another newsletter title",
+        "text_content": "Newsletter body as plain text",
+    });
+    for _ in 0..2 {
+        let response = app.post_newsletters(&newsletter_body).await;
+        assert_redirects_to(&response, "/admin/newsletters");
+    }
+
+    // Assert 2
+    assert_eq!(count_newsletters_issues(&app).await, 2);
+}
+
+#[tokio::test]
+async fn publish_newsletters_with_idempotency_key_still_in_progress_ret_409() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    create_confirmed_subscriber(&app).await;
+    let idempotency_key = Uuid::new_v4().to_string();
+    // Simulate another request holding the key, which hasn't stored its response yet
+    sqlx::query!(
+        r#"
+        INSERT INTO idempotency (user_id, idempotency_key, created_at)
+        VALUES ($1, $2, now())
+        "#,
+        app.test_user.user_id,
+        idempotency_key
+    )
+    .execute(&app.pg_pool)
+    .await
+    .expect("Failed to insert in progress idempotency record");
+
+    let response = app.login().await;
+    assert_redirects_to(&response, "/admin/dashboard");
+
+    // Act
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "idempotency_key": idempotency_key
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 409);
+    assert_eq!(count_newsletters_issues(&app).await, 0);
+}
+
+#[tokio::test]
+async fn hammering_same_idempotency_key_never_ret_500() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    create_confirmed_subscriber(&app).await;
+    let newsletter_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "idempotency_key": Uuid::new_v4().to_string()
+    });
+
+    let response = app.login().await;
+    assert_redirects_to(&response, "/admin/dashboard");
+
+    // Act
+    let responses =
+        futures::future::join_all((0..20).map(|_| app.post_newsletters(&newsletter_body))).await;
+
+    // Assert requests either replay the stored response or are told to retry
+    for response in responses {
+        let status = response.status().as_u16();
+        assert!(
+            status == 303 || status == 409,
+            "Unexpected status code {}",
+            status
+        );
+    }
+    assert_eq!(count_newsletters_issues(&app).await, 1);
+}
+
+#[tokio::test]
+async fn redrive_reopens_completed_issue_with_pending_tasks_and_delivers_them() {
+    // Arrange
+    let app = TestApp::builder()
+        .spawn_newsletters_issues_delivery_worker()
+        .build()
+        .await
+        .unwrap();
+    app.login().await;
+    create_confirmed_subscriber(&app).await;
+
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_redirects_to(&response, "/admin/newsletters");
+    tokio::time::timeout(
+        Duration::from_secs(10),
+        app.wait_until_completed_newsletters_issue_count_matches(1),
+    )
+    .await
+    .expect("Failed to wait until newsletters issue is completed");
+
+    // Leave tasks in queue of completed issue, as if worker crashed before draining them
+    let newsletters_issue_id = sqlx::query!("SELECT id FROM newsletters_issues")
+        .fetch_one(&app.pg_pool)
+        .await
+        .expect("Failed to fetch newsletters issue")
+        .id;
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletters_issues_delivery_queue (id, subscriber_email)
+        SELECT $1, email
+        FROM subscriptions
+        WHERE status = 'confirmed'
+        "#,
+        newsletters_issue_id
+    )
+    .execute(&app.pg_pool)
+    .await
+    .expect("Failed to insert pending tasks");
+    let msg_count_before_redrive = app
+        .get_email_messages_json()
+        .await
+        .as_array()
+        .unwrap()
+        .len();
+
+    // Act
+    let response = app.redrive_newsletters_issue(&newsletters_issue_id).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    tokio::time::timeout(Duration::from_secs(10), async {
+        loop {
+            let n_pending_tasks = sqlx::query!(
+                "SELECT COUNT(*) FROM newsletters_issues_delivery_queue WHERE id = $1",
+                newsletters_issue_id
+            )
+            .fetch_one(&app.pg_pool)
+            .await
+            .expect("Failed to count pending tasks")
+            .count
+            .unwrap_or_default();
+            if n_pending_tasks == 0 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("Failed to wait until pending tasks are delivered");
+    app.wait_until_completed_newsletters_issue_count_matches(1)
+        .await;
+    let msg_count_after_redrive = app
+        .get_email_messages_json()
+        .await
+        .as_array()
+        .unwrap()
+        .len();
+    assert_eq!(msg_count_after_redrive, msg_count_before_redrive + 1);
+}
+
+#[tokio::test]
+async fn redrive_unknown_issue_ret_404() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    app.login().await;
+
+    // Act
+    let response = app.redrive_newsletters_issue(&Uuid::new_v4()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn publish_newsletters_below_max_recipients_proceeds() {
+    // Arrange
+    let app = TestApp::builder()
+        .max_recipients_per_issue(2)
+        .build()
+        .await
+        .unwrap();
+    app.login().await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+
+    // Act
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+
+    // Assert
+    assert_redirects_to(&response, "/admin/newsletters");
+    assert_eq!(count_newsletters_issues(&app).await, 1);
+}
+
+#[tokio::test]
+async fn publish_newsletters_above_max_recipients_without_confirmation_is_rejected() {
+    // Arrange
+    let app = TestApp::builder()
+        .max_recipients_per_issue(1)
+        .build()
+        .await
+        .unwrap();
+    app.login().await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+
+    // Act
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+
+    // Assert
+    assert_redirects_to(&response, "/admin/newsletters");
+    let html = app.get_html("/admin/newsletters").await;
+    assert!(html.contains("Newsletter would be sent to 2 subscribers, more than 1"));
+    assert_eq!(count_newsletters_issues(&app).await, 0);
+    let n_tasks = sqlx::query!("SELECT COUNT(*) FROM newsletters_issues_delivery_queue")
+        .fetch_one(&app.pg_pool)
+        .await
+        .expect("Failed to count enqueued tasks")
+        .count;
+    assert_eq!(n_tasks, Some(0));
+}
+
+#[tokio::test]
+async fn publish_newsletters_above_max_recipients_with_confirmation_proceeds() {
+    // Arrange
+    let app = TestApp::builder()
+        .max_recipients_per_issue(1)
+        .build()
+        .await
+        .unwrap();
+    app.login().await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+
+    // Act
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "idempotency_key": Uuid::new_v4().to_string(),
+            "confirm_large_send": true
+        }))
+        .await;
+
+    // Assert
+    assert_redirects_to(&response, "/admin/newsletters");
+    assert_eq!(count_newsletters_issues(&app).await, 1);
+}
+
+#[tokio::test]
+async fn older_newsletters_issue_is_drained_first() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    let newer_issue_id = Uuid::new_v4();
+    let older_issue_id = Uuid::new_v4();
+    // Newer issue is inserted first, so insertion order can't decide which is picked
+    for (newsletters_issue_id, published_ago_secs) in
+        [(newer_issue_id, 0.0), (older_issue_id, 3600.0)]
+    {
+        sqlx::query!(
+            r#"
+            INSERT INTO newsletters_issues (
+                id,
+                title,
+                text_content,
+                status,
+                published_at,
+                finished_n_tasks,
+                required_n_tasks
+            )
+            VALUES ($1, $2, $3, 'AVAILABLE', now() - make_interval(secs => $4), 0, 1)
+            "#,
+            newsletters_issue_id,
+            Sentence(10..20).fake::<String>(),
+            Paragraph(5..10).fake::<String>(),
+            published_ago_secs,
+        )
+        .execute(&app.pg_pool)
+        .await
+        .unwrap();
+        // Invalid recipient is dropped from queue without sending any email
+        sqlx::query!(
+            r#"
+            INSERT INTO newsletters_issues_delivery_queue (id, subscriber_email)
+            VALUES ($1, 'not-an-email')
+            "#,
+            newsletters_issue_id,
+        )
+        .execute(&app.pg_pool)
+        .await
+        .unwrap();
+    }
+    let get_status = |newsletters_issue_id: Uuid| {
+        let pg_pool = app.pg_pool.clone();
+        async move {
+            sqlx::query!(
+                "SELECT status FROM newsletters_issues WHERE id = $1",
+                newsletters_issue_id
+            )
+            .fetch_one(&pg_pool)
+            .await
+            .unwrap()
+            .status
+        }
+    };
+
+    // Act 1
+    try_execute_task(&app.pg_pool, &app.email_client, &app.content_store)
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(get_status(older_issue_id).await, "COMPLETED");
+    assert_eq!(get_status(newer_issue_id).await, "AVAILABLE");
+
+    // Act 2
+    try_execute_task(&app.pg_pool, &app.email_client, &app.content_store)
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(get_status(newer_issue_id).await, "COMPLETED");
+}
+
+#[tokio::test]
+async fn high_priority_newsletters_issue_is_delivered_before_older_ones() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    create_confirmed_subscriber(&app).await;
+    app.login().await;
+    // Worker isn't spawned, so both issues are left available
+    for (title, priority) in [("Scheduled campaign", 0), ("Urgent announcement", 10)] {
+        let response = app
+            .post_newsletters(&serde_json::json!({
+                "title": title,
+                "text_content": Paragraph(5..10).fake::<String>(),
+                "priority": priority,
+                "idempotency_key": Uuid::new_v4().to_string()
+            }))
+            .await;
+        assert_redirects_to(&response, "/admin/newsletters");
+    }
+
+    // Act
+    try_execute_task(&app.pg_pool, &app.email_client, &app.content_store)
+        .await
+        .unwrap();
+
+    // Assert
+    let issues =
+        sqlx::query!("SELECT title, status, priority FROM newsletters_issues ORDER BY title")
+            .fetch_all(&app.pg_pool)
+            .await
+            .unwrap();
+    assert_eq!(issues[0].title, "Scheduled campaign");
+    assert_eq!(issues[0].priority, 0);
+    assert_eq!(issues[0].status, "AVAILABLE");
+    assert_eq!(issues[1].title, "Urgent announcement");
+    assert_eq!(issues[1].priority, 10);
+    assert_eq!(issues[1].status, "COMPLETED");
+}
+
+async fn enqueue_newsletters_issue_without_notification(app: &TestApp) {
+    let newsletters_issue_id = Uuid::new_v4();
+    let mut transaction = app.pg_pool.begin().await.unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletters_issues (
+            id,
+            title,
+            text_content,
+            status,
+            published_at,
+            finished_n_tasks,
+            required_n_tasks
+        )
+        VALUES ($1, $2, $3, 'AVAILABLE', now(), 0, 1)
+        "#,
+        newsletters_issue_id,
+        Sentence(10..20).fake::<String>(),
+        Paragraph(5..10).fake::<String>(),
+    )
+    .execute(&mut transaction)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletters_issues_delivery_queue (id, subscriber_email)
+        VALUES ($1, 'not-an-email')
+        "#,
+        newsletters_issue_id,
+    )
+    .execute(&mut transaction)
+    .await
+    .unwrap();
+    transaction.commit().await.unwrap();
+}
+
+#[tokio::test]
+async fn enqueued_newsletters_issue_is_delivered_without_notification_after_poll_interval() {
+    // Arrange
+    // Heartbeat is long, so only poll interval can wake worker up in time
+    let app = TestApp::builder()
+        .worker_heartbeat_interval_millis(60_000)
+        .worker_poll_interval_millis(500)
+        .spawn_newsletters_issues_delivery_worker()
+        .build()
+        .await
+        .unwrap();
+    // Let worker find the queue empty and wait for notification
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // Act, issue is enqueued directly, as if publisher crashed before notifying worker
+    enqueue_newsletters_issue_without_notification(&app).await;
+
+    // Assert
+    tokio::time::timeout(
+        Duration::from_secs(10),
+        app.wait_until_completed_newsletters_issue_count_matches(1),
+    )
+    .await
+    .expect("Worker didn't pick up issue without notification");
+}
+
+#[tokio::test]
+async fn heartbeat_keeps_worker_alive_without_re_checking_queue_before_poll_interval() {
+    // Arrange
+    let app = TestApp::builder()
+        .worker_heartbeat_interval_millis(200)
+        .worker_poll_interval_millis(60_000)
+        .spawn_newsletters_issues_delivery_worker()
+        .build()
+        .await
+        .unwrap();
+    // Let worker find the queue empty and wait for notification
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // Act
+    enqueue_newsletters_issue_without_notification(&app).await;
+    tokio::time::sleep(Duration::from_millis(1000)).await;
+
+    // Assert
+    let record = sqlx::query!(
+        r#"
+        SELECT
+            (SELECT COUNT(*) FROM newsletters_issues WHERE status = 'COMPLETED') AS "completed!",
+            (
+                SELECT now() - last_heartbeat_at < interval '1 second'
+                FROM worker_heartbeats
+            ) AS "heartbeat_is_recent!"
+        "#
+    )
+    .fetch_one(&app.pg_pool)
+    .await
+    .unwrap();
+    assert_eq!(record.completed, 0);
+    assert!(record.heartbeat_is_recent);
+}
+
+#[tokio::test]
+async fn worker_in_other_process_is_woken_up_by_postgres_notification() {
+    // Arrange
+    // Both intervals are long and worker doesn't share `Notify` with app,
+    // so only Postgres notification can wake worker up in time
+    let app = TestApp::builder()
+        .worker_heartbeat_interval_millis(60_000)
+        .worker_poll_interval_millis(60_000)
+        .spawn_newsletters_issues_delivery_worker()
+        .isolate_newsletters_issues_delivery_worker()
+        .build()
+        .await
+        .unwrap();
+    create_confirmed_subscriber(&app).await;
+    app.login().await;
+    // Let worker start listening and find the queue empty
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // Act
+    let text: String = Paragraph(5..10).fake();
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": Sentence(10..20).fake::<String>(),
+            "text_content": &text,
+            "html_content": format!("<p>{}</p>", &text),
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_redirects_to(&response, "/admin/newsletters");
+
+    // Assert
+    tokio::time::timeout(
+        Duration::from_secs(10),
+        app.wait_until_completed_newsletters_issue_count_matches(1),
+    )
+    .await
+    .expect("Worker wasn't woken up by Postgres notification");
+}
+
+// Deferred constraint trigger only fires at commit, after emails are sent
+async fn fail_delivery_commits(app: &TestApp) {
+    sqlx::query(
+        r#"
+        CREATE FUNCTION fail_delivery_commit() RETURNS trigger AS $$
+        BEGIN
+            RAISE EXCEPTION 'injected commit failure';
+        END;
+        $$ LANGUAGE plpgsql
+        "#,
+    )
+    .execute(&app.pg_pool)
+    .await
+    .unwrap();
+    sqlx::query(
+        r#"
+        CREATE CONSTRAINT TRIGGER fail_delivery_commit
+        AFTER DELETE ON newsletters_issues_delivery_queue
+        DEFERRABLE INITIALLY DEFERRED
+        FOR EACH ROW EXECUTE FUNCTION fail_delivery_commit()
+        "#,
+    )
+    .execute(&app.pg_pool)
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn emails_sent_before_failed_commit_are_not_sent_again() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    let email: String = SafeEmail().fake();
+    app.create_confirmed_subscriber(serde_json::json!({
+        "name": Name().fake::<String>(),
+        "email": email
+    }))
+    .await;
+    app.login().await;
+    let title: String = Sentence(10..20).fake();
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": title,
+            "text_content": Paragraph(5..10).fake::<String>(),
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_redirects_to(&response, "/admin/newsletters");
+    fail_delivery_commits(&app).await;
+    let count_received_emails = || async {
+        app.get_email_messages_json()
+            .await
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|msg| {
+                msg["to"][0]["email"].as_str() == Some(email.as_str())
+                    && msg["subject"]
+                        .as_str()
+                        .map_or(false, |subject| subject.contains(&title))
+            })
+            .count()
+    };
+
+    // Act 1 every commit fails, so tasks are left in queue
+    let result = try_execute_task(&app.pg_pool, &app.email_client, &app.content_store).await;
+    assert!(result.is_err());
+    assert_eq!(count_received_emails().await, 1);
+
+    // Act 2 commit succeeds
+    sqlx::query("DROP TRIGGER fail_delivery_commit ON newsletters_issues_delivery_queue")
+        .execute(&app.pg_pool)
+        .await
+        .unwrap();
+    try_execute_task(&app.pg_pool, &app.email_client, &app.content_store)
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(count_received_emails().await, 1);
+    let n_queued = sqlx::query!("SELECT COUNT(*) FROM newsletters_issues_delivery_queue")
+        .fetch_one(&app.pg_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_queued, Some(0));
+    let n_sent_records = sqlx::query!("SELECT COUNT(*) FROM newsletters_issues_sent_emails")
+        .fetch_one(&app.pg_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_sent_records, Some(0));
+}
+
+#[tokio::test]
+async fn archive_email_receives_one_copy_of_each_completed_newsletters_issue() {
+    // Arrange
+    let archive_email: String = SafeEmail().fake();
+    let app = TestApp::builder()
+        .archive_email(&archive_email)
+        .spawn_newsletters_issues_delivery_worker()
+        .build()
+        .await
+        .unwrap();
+    for _ in 0..3 {
+        create_confirmed_subscriber(&app).await;
+    }
+    app.login().await;
+
+    let title = Uuid::new_v4().to_string();
+    let newsletter_body = serde_json::json!({
+        "title": &title,
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string()
+    });
+
+    // Act
+    let response = app.post_newsletters(&newsletter_body).await;
+    assert_redirects_to(&response, "/admin/newsletters");
+    tokio::time::timeout(
+        Duration::from_secs(5),
+        app.wait_until_completed_newsletters_issue_count_matches(1),
+    )
+    .await
+    .expect("Failed to wait until newsletters issue is completed");
+
+    // Assert
+    let n_archive_copies = app
+        .get_email_messages_json()
+        .await
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|msg| {
+            msg["to"][0]["email"].as_str() == Some(archive_email.as_str())
+                && msg["subject"]
+                    .as_str()
+                    .map_or(false, |subject| subject.contains(&title))
+        })
+        .count();
+    assert_eq!(n_archive_copies, 1);
+
+    let archived_at = sqlx::query!(r#"SELECT archived_at FROM newsletters_issues"#)
+        .fetch_one(&app.pg_pool)
+        .await
+        .expect("Failed to fetch newsletters issue")
+        .archived_at;
+    assert!(archived_at.is_some());
+}
+
+#[tokio::test]
+async fn pending_archive_copy_is_sent_after_newsletters_issue_is_completed() {
+    // Arrange
+    let archive_email: String = SafeEmail().fake();
+    let app = TestApp::builder()
+        .archive_email(&archive_email)
+        .build()
+        .await
+        .unwrap();
+    create_confirmed_subscriber(&app).await;
+    app.login().await;
+    let title = Uuid::new_v4().to_string();
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": &title,
+            "text_content": "Newsletter body as plain text",
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_redirects_to(&response, "/admin/newsletters");
+    // Subscribers were delivered to while archive copy failed to send
+    sqlx::query!("DELETE FROM newsletters_issues_delivery_queue")
+        .execute(&app.pg_pool)
+        .await
+        .unwrap();
+    sqlx::query!("UPDATE newsletters_issues SET status = 'COMPLETED'")
+        .execute(&app.pg_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let result = try_execute_task(&app.pg_pool, &app.email_client, &app.content_store)
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(matches!(result, ExecutionResult::EmptyQueue));
+    let n_archive_copies = app
+        .get_email_messages_json()
+        .await
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|msg| {
+            msg["to"][0]["email"].as_str() == Some(archive_email.as_str())
+                && msg["subject"]
+                    .as_str()
+                    .map_or(false, |subject| subject.contains(&title))
+        })
+        .count();
+    assert_eq!(n_archive_copies, 1);
+    let archived_at = sqlx::query!(r#"SELECT archived_at FROM newsletters_issues"#)
+        .fetch_one(&app.pg_pool)
+        .await
+        .expect("Failed to fetch newsletters issue")
+        .archived_at;
+    assert!(archived_at.is_some());
+}
+
+#[tokio::test]
+async fn sent_emails_are_recorded_with_default_worker_max_connections() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    create_confirmed_subscriber(&app).await;
+    app.login().await;
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": Sentence(10..20).fake::<String>(),
+            "text_content": Paragraph(5..10).fake::<String>(),
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_redirects_to(&response, "/admin/newsletters");
+    fail_delivery_commits(&app).await;
+    let settings = Settings::get_configuration().expect("Failed to read configuration");
+    // Pool is sized as in production, not as test database pool
+    let worker_pg_pool = get_worker_pg_pool(
+        &test_database_settings(&settings.database, &app.pg_pool).await,
+        None,
+    );
+
+    // Act, commit fails so only records written outside of batch's transaction are kept
+    let result = try_execute_task(&worker_pg_pool, &app.email_client, &app.content_store).await;
+    assert!(result.is_err());
+
+    // Assert
+    let n_sent_records = sqlx::query!("SELECT COUNT(*) FROM newsletters_issues_sent_emails")
+        .fetch_one(&app.pg_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_sent_records, Some(1));
+}
+
+#[tokio::test]
+async fn worker_with_default_pool_size_delivers_without_waiting_for_connections() {
+    // Arrange
+    let app = TestApp::builder()
+        .settings_sized_delivery_worker_pg_pool()
+        .spawn_newsletters_issues_delivery_worker()
+        .build()
+        .await
+        .unwrap();
+    for _ in 0..3 {
+        create_confirmed_subscriber(&app).await;
+    }
+    app.login().await;
+
+    // Act
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": Sentence(10..20).fake::<String>(),
+            "text_content": Paragraph(5..10).fake::<String>(),
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_redirects_to(&response, "/admin/newsletters");
+
+    // Assert
+    // Each send would wait for acquire timeout of 2 seconds if pool was exhausted
+    tokio::time::timeout(
+        Duration::from_secs(4),
+        app.wait_until_completed_newsletters_issue_count_matches(1),
+    )
+    .await
+    .expect("Worker waited for database connections while delivering");
+}
+
+#[tokio::test]
+async fn publish_newsletters_with_api_token_above_max_recipients_without_confirmation_ret_422() {
+    // Arrange
+    let app = TestApp::builder()
+        .max_recipients_per_issue(1)
+        .build()
+        .await
+        .unwrap();
+    app.login().await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    let (_, token) = app.mint_api_token().await;
+
+    // Act
+    let response = app
+        .post_newsletters_with_api_token(
+            &serde_json::json!({
+                "title": "Newsletter title",
+                "text_content": "Newsletter body as plain text",
+                "idempotency_key": Uuid::new_v4().to_string()
+            }),
+            &token,
+        )
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 422);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["n_recipients"], 2);
+    assert_eq!(body["max_recipients"], 1);
+    assert!(body["error"]
+        .as_str()
+        .unwrap()
+        .contains("Newsletter would be sent to 2 subscribers, more than 1"));
+    assert_eq!(count_newsletters_issues(&app).await, 0);
+}
@@ -0,0 +1,378 @@
+use crate::helpers::{assert_redirects_to, TestApp};
+use uuid::Uuid;
+
+#[tokio::test]
+async fn import_subscribers_without_login_redirects_to_login() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+
+    // Act
+    let response = app
+        .post_subscribers_import("email,name\nfoo@example.com,Foo Bar\n".into())
+        .await;
+
+    // Assert
+    assert_redirects_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn import_subscribers_skips_invalid_and_duplicate_rows() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    app.login().await;
+
+    let csv = "email,name\n\
+        ursula@example.com,Ursula Le Guin\n\
+        not-an-email,Invalid Email\n\
+        frank@example.com,Frank Herbert\n\
+        ursula@example.com,Ursula Again\n"
+        .to_string();
+
+    // Act
+    let response = app.post_subscribers_import(csv).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let summary: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(summary["inserted"], 2);
+    assert_eq!(summary["skipped"], 1);
+    assert_eq!(summary["invalid"], 1);
+
+    let saved = sqlx::query!("SELECT email, status FROM subscriptions ORDER BY email")
+        .fetch_all(&app.pg_pool)
+        .await
+        .expect("Failed to fetch saved subscriptions");
+    assert_eq!(saved.len(), 2);
+    assert!(saved.iter().all(|s| s.status == "confirmed"));
+}
+
+#[tokio::test]
+async fn export_subscribers_contains_subscribers_with_requested_status() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    app.login().await;
+
+    let csv = "email,name\n\
+        ursula@example.com,Ursula Le Guin\n\
+        frank@example.com,Frank Herbert\n"
+        .to_string();
+    app.post_subscribers_import(csv).await;
+    app.post_subscriptions("name=Pending%20Person&email=pending%40example.com".into())
+        .await;
+
+    // Act
+    let response = app.get("/admin/subscribers/export?status=confirmed").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let exported = response.text().await.unwrap();
+    let mut lines = exported.lines();
+    assert_eq!(lines.next(), Some("email,name,status,subscribed_at"));
+    assert!(exported.contains("ursula@example.com,Ursula Le Guin,confirmed,"));
+    assert!(exported.contains("frank@example.com,Frank Herbert,confirmed,"));
+    assert!(!exported.contains("pending@example.com"));
+
+    // Act 2 export all subscribers
+    let exported = app.get_html("/admin/subscribers/export").await;
+
+    // Assert
+    assert!(exported.contains("pending@example.com"));
+}
+
+#[tokio::test]
+async fn export_subscribers_with_invalid_status_ret_400() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    app.login().await;
+
+    // Act
+    let response = app.get("/admin/subscribers/export?status=garbage").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn subscribers_stats_groups_subscribers_by_source() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    app.login().await;
+
+    for body in [
+        "name=Ursula%20Le%20Guin&email=ursula%40example.com&source=twitter&utm_campaign=spring",
+        "name=Frank%20Herbert&email=frank%40example.com&source=twitter",
+        "name=Octavia%20Butler&email=octavia%40example.com",
+    ] {
+        let response = app.post_subscriptions(body.into()).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    // Act
+    let response = app.get("/admin/subscribers/stats").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    let sources = body["sources"].as_array().unwrap();
+    let count_of = |source: serde_json::Value| {
+        sources
+            .iter()
+            .find(|s| s["source"] == source)
+            .map(|s| s["count"].clone())
+    };
+    assert_eq!(count_of(serde_json::json!("twitter")), Some(2.into()));
+    assert_eq!(count_of(serde_json::Value::Null), Some(1.into()));
+
+    let saved =
+        sqlx::query!("SELECT utm_campaign FROM subscriptions WHERE email = 'ursula@example.com'")
+            .fetch_one(&app.pg_pool)
+            .await
+            .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.utm_campaign.as_deref(), Some("spring"));
+}
+
+#[tokio::test]
+async fn subscribe_with_unsafe_source_is_rejected() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+
+    // Act
+    let response = app
+        .post_subscriptions(
+            "name=Ursula%20Le%20Guin&email=ursula%40example.com&source=%3Cscript%3E".into(),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn subscribers_stats_without_login_redirects_to_login() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+
+    // Act
+    let response = app.get("/admin/subscribers/stats").await;
+
+    // Assert
+    assert_redirects_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn export_subscribers_is_compressed_only_once() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    app.login().await;
+    app.post_subscribers_import("email,name\nursula@example.com,Ursula Le Guin\n".into())
+        .await;
+
+    // Act
+    let response = app
+        .get_with_accept_encoding("/admin/subscribers/export", "gzip")
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response
+            .headers()
+            .get_all("Content-Encoding")
+            .iter()
+            .count(),
+        1
+    );
+    assert_eq!(response.headers().get("Content-Encoding").unwrap(), "gzip");
+}
+
+#[tokio::test]
+async fn lookup_subscriber_returns_its_status() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    app.login().await;
+    app.post_subscriptions("name=Ursula%20Le%20Guin&email=ursula%40example.com".into())
+        .await;
+
+    // Act, email is normalized before lookup
+    let response = app
+        .get("/admin/subscribers/lookup?email=%20Ursula%20%3Cursula%40example.com%3E")
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["email"], "ursula@example.com");
+    assert_eq!(body["status"], "pending");
+    assert!(body["subscribed_at"].is_string());
+    assert!(!body.to_string().contains("token"));
+}
+
+#[tokio::test]
+async fn lookup_subscriber_by_plus_addressed_email_finds_canonical_subscriber() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    app.login().await;
+    app.post_subscriptions("name=Ursula%20Le%20Guin&email=ursula%2Bblog%40example.com".into())
+        .await;
+
+    // Act
+    let response = app
+        .get("/admin/subscribers/lookup?email=ursula%2Bnews%40example.com")
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["email"], "ursula@example.com");
+}
+
+#[tokio::test]
+async fn lookup_unknown_subscriber_ret_404() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    app.login().await;
+
+    // Act
+    let response = app
+        .get("/admin/subscribers/lookup?email=nobody%40example.com")
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn lookup_subscriber_without_login_redirects_to_login() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+
+    // Act
+    let response = app
+        .get("/admin/subscribers/lookup?email=ursula%40example.com")
+        .await;
+
+    // Assert
+    assert_redirects_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn erase_subscriber_removes_every_trace_of_its_email_but_tombstone() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    app.create_confirmed_subscriber(serde_json::json!({
+        "name": "Ursula Le Guin",
+        "email": "ursula@example.com"
+    }))
+    .await;
+    app.login().await;
+    // Worker isn't spawned, so delivery task of subscriber is left in queue
+    app.post_newsletters(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string()
+    }))
+    .await;
+
+    // Act
+    let response = app
+        .post_subscribers_erase(&serde_json::json!({"email": "ursula@example.com"}))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let summary: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(summary["subscriptions"], 1);
+    assert_eq!(summary["subscription_tokens"], 1);
+    assert_eq!(summary["newsletters_deliveries"], 1);
+
+    let n_rows_with_email = sqlx::query_scalar!(
+        r#"
+        SELECT (
+            (SELECT COUNT(*) FROM subscriptions WHERE email = $1) +
+            (SELECT COUNT(*) FROM confirmation_emails_delivery_queue WHERE subscriber_email = $1) +
+            (SELECT COUNT(*) FROM newsletters_issues_delivery_queue WHERE subscriber_email = $1) +
+            (SELECT COUNT(*) FROM audit_log WHERE target = $1)
+        ) AS "count!"
+        "#,
+        "ursula@example.com"
+    )
+    .fetch_one(&app.pg_pool)
+    .await
+    .unwrap();
+    assert_eq!(n_rows_with_email, 0);
+
+    let tombstones = sqlx::query!("SELECT email_hash FROM erased_subscribers")
+        .fetch_all(&app.pg_pool)
+        .await
+        .unwrap();
+    assert_eq!(tombstones.len(), 1);
+    assert!(!tombstones[0].email_hash.contains("ursula"));
+}
+
+#[tokio::test]
+async fn erased_subscriber_is_not_imported_again() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    app.login().await;
+    app.post_subscribers_import("email,name\nursula@example.com,Ursula Le Guin\n".into())
+        .await;
+    app.post_subscribers_erase(&serde_json::json!({"email": "ursula@example.com"}))
+        .await;
+
+    // Act
+    let response = app
+        .post_subscribers_import("email,name\nursula@Example.COM,Ursula Le Guin\n".into())
+        .await;
+
+    // Assert
+    let summary: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(summary["inserted"], 0);
+    assert_eq!(summary["skipped"], 1);
+}
+
+#[tokio::test]
+async fn erase_subscriber_by_plus_addressed_email_erases_canonical_subscriber() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    app.login().await;
+    app.post_subscriptions("name=Ursula%20Le%20Guin&email=ursula%2Bblog%40example.com".into())
+        .await;
+
+    // Act
+    let response = app
+        .post_subscribers_erase(&serde_json::json!({"email": "ursula+blog@example.com"}))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let summary: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(summary["subscriptions"], 1);
+    let n_subscriptions = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&app.pg_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_subscriptions, 0);
+
+    // Tombstone matches canonical email, which is what import checks
+    let response = app
+        .post_subscribers_import("email,name\nursula@example.com,Ursula Le Guin\n".into())
+        .await;
+    let summary: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(summary["inserted"], 0);
+    assert_eq!(summary["skipped"], 1);
+}
+
+#[tokio::test]
+async fn erase_subscriber_without_login_redirects_to_login() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+
+    // Act
+    let response = app
+        .post_subscribers_erase(&serde_json::json!({"email": "ursula@example.com"}))
+        .await;
+
+    // Assert
+    assert_redirects_to(&response, "/login");
+}
@@ -0,0 +1,140 @@
+use crate::helpers::{assert_redirects_to, TestApp};
+use fake::faker::internet::en::SafeEmail;
+use fake::Fake;
+
+#[tokio::test]
+async fn send_test_email_reports_success_when_email_server_is_up() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    app.login().await;
+    let admin_email: String = SafeEmail().fake();
+    let response = app
+        .post_form("/admin/email", serde_json::json!({ "email": admin_email }))
+        .await;
+    assert_redirects_to(&response, "/admin/dashboard");
+
+    // Act
+    let response = app
+        .post_form("/admin/test-email", serde_json::json!({}))
+        .await;
+
+    // Assert
+    assert_redirects_to(&response, "/admin/dashboard");
+    let html = app.get_html("/admin/dashboard").await;
+    assert!(html.contains(&format!("Test email was sent to {}", admin_email)));
+    let message = app
+        .get_email_message_json(&admin_email, "Zero2Prod test email")
+        .await;
+    assert!(message["text"]
+        .as_str()
+        .unwrap()
+        .contains("Email settings work"));
+}
+
+#[tokio::test]
+async fn send_test_email_ignores_recipient_given_in_form() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    app.login().await;
+    let admin_email: String = SafeEmail().fake();
+    app.post_form("/admin/email", serde_json::json!({ "email": admin_email }))
+        .await;
+    let other_email: String = SafeEmail().fake();
+
+    // Act
+    let response = app
+        .post_form(
+            "/admin/test-email",
+            serde_json::json!({ "recipient_email": other_email }),
+        )
+        .await;
+
+    // Assert
+    assert_redirects_to(&response, "/admin/dashboard");
+    let html = app.get_html("/admin/dashboard").await;
+    assert!(html.contains(&format!("Test email was sent to {}", admin_email)));
+    let messages = app.get_email_messages_json().await;
+    assert!(!messages
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|msg| msg["to"][0]["email"].as_str() == Some(other_email.as_str())));
+}
+
+#[tokio::test]
+async fn send_test_email_without_admin_email_reports_error() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    app.login().await;
+
+    // Act
+    let response = app
+        .post_form("/admin/test-email", serde_json::json!({}))
+        .await;
+
+    // Assert
+    assert_redirects_to(&response, "/admin/dashboard");
+    let html = app.get_html("/admin/dashboard").await;
+    assert!(html.contains("Set your email before sending a test email"));
+}
+
+#[tokio::test]
+async fn send_test_email_reports_error_when_email_server_is_down() {
+    // Arrange
+    let app = TestApp::builder()
+        .proxy_email_server()
+        .build()
+        .await
+        .unwrap();
+    app.login().await;
+    app.post_form(
+        "/admin/email",
+        serde_json::json!({ "email": SafeEmail().fake::<String>() }),
+    )
+    .await;
+    app.email_server_proxy.as_ref().unwrap().pause();
+
+    // Act
+    let response = app
+        .post_form("/admin/test-email", serde_json::json!({}))
+        .await;
+
+    // Assert
+    assert_redirects_to(&response, "/admin/dashboard");
+    let html = app.get_html("/admin/dashboard").await;
+    assert!(html.contains("Failed to send test email"));
+}
+
+#[tokio::test]
+async fn update_admin_email_with_invalid_email_is_rejected() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    app.login().await;
+
+    // Act
+    let response = app
+        .post_form(
+            "/admin/email",
+            serde_json::json!({ "email": "not-an-email" }),
+        )
+        .await;
+
+    // Assert
+    assert_redirects_to(&response, "/admin/dashboard");
+    let html = app.get_html("/admin/dashboard").await;
+    assert!(html.contains("Invalid email"));
+}
+
+#[tokio::test]
+async fn send_test_email_without_login_redirects_to_login() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+
+    // Act
+    let response = app
+        .post_form("/admin/test-email", serde_json::json!({}))
+        .await;
+
+    // Assert
+    assert_redirects_to(&response, "/login");
+}
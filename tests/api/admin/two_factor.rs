@@ -0,0 +1,135 @@
+use crate::helpers::{assert_redirects_to, generate_totp_code, TestApp};
+
+#[tokio::test]
+async fn login_without_two_factor_enrolled_redirects_to_dashboard() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+
+    // Act
+    let response = app.login().await;
+
+    // Assert
+    assert_redirects_to(&response, "/admin/dashboard");
+    let response = app
+        .post_form("/login/2fa", serde_json::json!({ "code": "123456" }))
+        .await;
+    assert_redirects_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn login_with_two_factor_enrolled_requires_code_before_accessing_admin() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    app.login().await;
+    app.enroll_two_factor().await;
+    app.get("/admin/logout").await;
+
+    // Act 1 login with password
+    let response = app.login().await;
+
+    // Assert
+    assert_redirects_to(&response, "/login/2fa");
+    let response = app.get("/admin/dashboard").await;
+    assert_redirects_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn two_factor_login_with_correct_code_redirects_to_dashboard() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    app.login().await;
+    let totp_secret = app.enroll_two_factor().await;
+    app.get("/admin/logout").await;
+    let response = app.login().await;
+    assert_redirects_to(&response, "/login/2fa");
+
+    // Act
+    let response = app
+        .post_form(
+            "/login/2fa",
+            serde_json::json!({ "code": generate_totp_code(&totp_secret) }),
+        )
+        .await;
+
+    // Assert
+    assert_redirects_to(&response, "/admin/dashboard");
+    let html = app.get_html("/admin/dashboard").await;
+    assert!(html.contains(&format!("Hello {}", app.test_user.username)));
+}
+
+#[tokio::test]
+async fn two_factor_login_with_wrong_code_keeps_admin_blocked() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    app.login().await;
+    let totp_secret = app.enroll_two_factor().await;
+    app.get("/admin/logout").await;
+    let response = app.login().await;
+    assert_redirects_to(&response, "/login/2fa");
+    // Shift every digit to get a code that doesn't match any accepted time step
+    let wrong_code: String = generate_totp_code(&totp_secret)
+        .chars()
+        .map(|c| char::from_digit((c.to_digit(10).unwrap() + 5) % 10, 10).unwrap())
+        .collect();
+
+    // Act
+    let response = app
+        .post_form("/login/2fa", serde_json::json!({ "code": wrong_code }))
+        .await;
+
+    // Assert
+    assert_redirects_to(&response, "/login/2fa");
+    let html = app.get_html("/login/2fa").await;
+    assert!(html.contains("<p><i>Invalid authentication code</i></p>"));
+    let response = app.get("/admin/dashboard").await;
+    assert_redirects_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn enroll_two_factor_with_wrong_code_is_rejected() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    app.login().await;
+    app.get_html("/admin/2fa").await;
+
+    // Act
+    let response = app
+        .post_form("/admin/2fa", serde_json::json!({ "code": "not-a-code" }))
+        .await;
+
+    // Assert
+    assert_redirects_to(&response, "/admin/2fa");
+    let html = app.get_html("/admin/2fa").await;
+    assert!(html.contains("<p><i>Invalid authentication code</i></p>"));
+    assert!(!html.contains("Two-factor authentication is enabled"));
+}
+
+#[tokio::test]
+async fn two_factor_code_can_not_be_used_twice() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    app.login().await;
+    let totp_secret = app.enroll_two_factor().await;
+    app.get("/admin/logout").await;
+    app.login().await;
+    let code = generate_totp_code(&totp_secret);
+    let response = app
+        .post_form("/login/2fa", serde_json::json!({ "code": code }))
+        .await;
+    assert_redirects_to(&response, "/admin/dashboard");
+    app.get("/admin/logout").await;
+    let response = app.login().await;
+    assert_redirects_to(&response, "/login/2fa");
+
+    // Act
+    let response = app
+        .post_form("/login/2fa", serde_json::json!({ "code": code }))
+        .await;
+
+    // Assert
+    assert_redirects_to(&response, "/login/2fa");
+    let html = app.get_html("/login/2fa").await;
+    assert!(html.contains("<p><i>Invalid authentication code</i></p>"));
+    let response = app.get("/admin/dashboard").await;
+    assert_redirects_to(&response, "/login");
+}
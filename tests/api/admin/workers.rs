@@ -0,0 +1,83 @@
+use crate::helpers::{assert_redirects_to, TestApp};
+use std::time::Duration;
+
+#[tokio::test]
+async fn workers_status_without_login_redirects_to_login() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+
+    // Act
+    let response = app.get("/admin/workers/status").await;
+
+    // Assert
+    assert_redirects_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn workers_status_reports_running_delivery_worker_as_healthy() {
+    // Arrange
+    let app = TestApp::builder()
+        .spawn_newsletters_issues_delivery_worker()
+        .build()
+        .await
+        .unwrap();
+
+    // Act 1 login
+    let response = app.login().await;
+    assert_redirects_to(&response, "/admin/dashboard");
+
+    // Act 2 wait for the first heartbeat of delivery worker
+    let mut delivery_worker_status = None;
+    for _ in 0..100 {
+        let response = app.get("/admin/workers/status").await;
+        assert_eq!(response.status().as_u16(), 200);
+        let body: serde_json::Value = response.json().await.unwrap();
+        delivery_worker_status = body["workers"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|w| w["name"] == "newsletters_issues_delivery")
+            .cloned();
+        if delivery_worker_status.is_some() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    // Assert
+    let delivery_worker_status =
+        delivery_worker_status.expect("Delivery worker didn't send any heartbeat");
+    assert_eq!(delivery_worker_status["healthy"], true);
+    assert_eq!(delivery_worker_status["failed_count"], 0);
+}
+
+#[tokio::test]
+async fn workers_status_internal_error_ret_500_with_json_body_without_internal_details() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    let response = app.login().await;
+    assert_redirects_to(&response, "/admin/dashboard");
+
+    // Sabotage database to force an internal error
+    sqlx::query!("DROP TABLE worker_heartbeats;")
+        .execute(&app.pg_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = app.get("/admin/workers/status").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 500);
+    let request_id = response
+        .headers()
+        .get("x-request-id")
+        .expect("Missing request id header")
+        .to_str()
+        .unwrap()
+        .to_owned();
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["error"], "Internal server error");
+    assert_eq!(body["request_id"], request_id.as_str());
+    assert!(!body.to_string().contains("worker_heartbeats"));
+}
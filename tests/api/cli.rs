@@ -0,0 +1,120 @@
+use crate::helpers::{assert_redirects_to, TestApp};
+use claims::{assert_err, assert_ok};
+use secrecy::Secret;
+use uuid::Uuid;
+use zero2prod::cli::{check_config, check_config_exit_code, create_user, reset_password, Command};
+use zero2prod::configuration::Settings;
+
+#[tokio::test]
+async fn user_created_from_cli_can_login() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    let username = Uuid::new_v4().to_string();
+    let password = Uuid::new_v4().to_string();
+
+    // Act
+    create_user(&app.pg_pool, &username, Secret::new(password.clone()))
+        .await
+        .expect("Failed to create user");
+
+    // Assert
+    let response = app
+        .post_login(serde_json::json!({
+            "username": &username,
+            "password": &password
+        }))
+        .await;
+    assert_redirects_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn create_user_with_existing_username_fails() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+
+    // Act
+    let outcome = create_user(
+        &app.pg_pool,
+        &app.test_user.username,
+        Secret::new(Uuid::new_v4().to_string()),
+    )
+    .await;
+
+    // Assert
+    assert_err!(outcome);
+}
+
+#[tokio::test]
+async fn password_reset_from_cli_replaces_old_password() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    let new_password = Uuid::new_v4().to_string();
+
+    // Act
+    reset_password(
+        &app.pg_pool,
+        &app.test_user.username,
+        Secret::new(new_password.clone()),
+    )
+    .await
+    .expect("Failed to reset password");
+
+    // Assert
+    let response = app.login().await;
+    assert_redirects_to(&response, "/login");
+    let response = app
+        .post_login(serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &new_password
+        }))
+        .await;
+    assert_redirects_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn check_config_succeeds_against_test_environment() {
+    // Arrange
+    let settings = Settings::get_configuration().expect("Failed to read configuration");
+
+    // Act
+    let outcome = check_config(&settings).await;
+
+    // Assert
+    assert_ok!(outcome);
+    assert_eq!(check_config_exit_code(&settings).await, 0);
+}
+
+#[tokio::test]
+async fn check_config_lists_every_failed_check() {
+    // Arrange
+    let mut settings = Settings::get_configuration().expect("Failed to read configuration");
+    settings.application.base_url = "localhost".to_string();
+    // Nothing listens on port 1 of loopback
+    settings.application.redis_url = Secret::new("redis://127.0.0.1:1".to_string());
+
+    // Act
+    let error = assert_err!(check_config(&settings).await);
+
+    // Assert
+    assert_eq!(
+        error.to_string(),
+        "Configuration check failed: Configuration, Redis"
+    );
+    assert_eq!(check_config_exit_code(&settings).await, 1);
+}
+
+#[tokio::test]
+async fn subcommand_rejects_invalid_database_settings_before_prompting() {
+    // Arrange
+    let mut settings = Settings::get_configuration().expect("Failed to read configuration");
+    settings.database.host = " ".to_string();
+    let command = Command::ResetPassword {
+        username: Uuid::new_v4().to_string(),
+    };
+
+    // Act
+    let error = assert_err!(command.run(&settings).await);
+
+    // Assert
+    assert!(error.to_string().contains("database.host"));
+}
@@ -1,4 +1,5 @@
-use crate::helpers::TestApp;
+use crate::helpers::{assert_redirects_to, TestApp};
+use zero2prod::configuration::DatabaseStartupCheckSettings;
 
 #[tokio::test]
 async fn check_health_check() {
@@ -16,3 +17,140 @@ async fn check_health_check() {
     assert!(response.status().is_success());
     assert_eq!(Some(0), response.content_length());
 } // _app_thread is dropped here after all tests are successful
+
+#[tokio::test]
+async fn app_with_database_startup_check_builds_against_healthy_database() {
+    // Arrange
+    let app = TestApp::builder()
+        .database_startup_check(DatabaseStartupCheckSettings {
+            max_retries: 3,
+            backoff_base_millis: 100,
+            backoff_max_millis: 1000,
+        })
+        .build()
+        .await
+        .unwrap();
+
+    // Act
+    let response = app.get("/health").await;
+
+    // Assert
+    assert!(response.status().is_success());
+}
+
+#[tokio::test]
+async fn response_carries_back_provided_request_id() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    let request_id = "my-request-id-123";
+
+    // Act
+    let response = reqwest::Client::new()
+        .get(&format!("{}/health", app.addr))
+        .header("X-Request-Id", request_id)
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // Assert
+    assert!(response.status().is_success());
+    assert_eq!(response.headers().get("x-request-id").unwrap(), request_id);
+}
+
+#[tokio::test]
+async fn response_carries_generated_request_id_when_absent() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+
+    // Act
+    let response = app.get("/health").await;
+
+    // Assert
+    assert!(response.status().is_success());
+    assert!(response.headers().get("x-request-id").is_some());
+}
+
+#[tokio::test]
+async fn request_with_oversized_headers_ret_431() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    let mut request = reqwest::Client::new().get(&format!("{}/health", app.addr));
+    for i in 0..60 {
+        request = request.header(format!("x-custom-header-{}", i), "a".repeat(200));
+    }
+
+    // Act
+    let response = request.send().await.expect("Failed to execute request");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 431);
+}
+
+#[tokio::test]
+async fn version_reports_build_info_and_migration_version() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+
+    // Act
+    let response = app.get("/version").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert!(!body["version"].as_str().unwrap().is_empty());
+    assert!(!body["git_sha"].as_str().unwrap().is_empty());
+    assert!(body["migration_version"].as_i64().is_some());
+}
+
+#[tokio::test]
+async fn version_without_migrations_table_still_reports_build_info() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    sqlx::query!("DROP TABLE _sqlx_migrations;")
+        .execute(&app.pg_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = app.get("/version").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert!(!body["version"].as_str().unwrap().is_empty());
+    assert!(body["migration_version"].is_null());
+}
+
+#[tokio::test]
+async fn routes_are_mounted_under_configured_base_path() {
+    // Arrange
+    let app = TestApp::builder()
+        .base_path("/newsletter")
+        .build()
+        .await
+        .unwrap();
+
+    // Act
+    let prefixed_response = app.get("/newsletter/health").await;
+    let root_response = app.get("/health").await;
+
+    // Assert
+    assert!(prefixed_response.status().is_success());
+    assert_eq!(root_response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn redirects_stay_under_configured_base_path() {
+    // Arrange
+    let app = TestApp::builder()
+        .base_path("/newsletter")
+        .build()
+        .await
+        .unwrap();
+
+    // Act
+    let response = app.get("/newsletter/admin/dashboard").await;
+
+    // Assert
+    assert_redirects_to(&response, "/newsletter/login");
+}
@@ -3,19 +3,38 @@ use argon2::{Algorithm, Params, PasswordHasher, Version};
 use fake::faker::internet::en::SafeEmail;
 use fake::faker::name::en::Name;
 use fake::Fake;
+use hmac::{Hmac, Mac};
 use once_cell::sync::Lazy;
 use rand::rngs::OsRng;
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
 use sqlx::{Connection, Executor, PgConnection, PgPool};
-use std::sync::Arc;
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
 use tokio::sync::Notify;
+use tokio::task::JoinHandle;
 use uuid::Uuid;
-use zero2prod::configuration::{DatabaseSettings, Settings};
+use zero2prod::configuration::{
+    ConfirmationEmailSettings, DatabaseSettings, DatabaseStartupCheckSettings,
+    FlashMessageStoreSettings, RateLimitSettings, Settings,
+};
+use zero2prod::confirmation_emails::{
+    ConfirmationEmailsDeliveryWorker, DeleteExpiredSubscriptionTokensWorker,
+    DEFAULT_WELCOME_SUBJECT,
+};
+use zero2prod::content_store::ContentStore;
 use zero2prod::email_client::EmailClient;
+use zero2prod::mx_check::MxResolver;
 use zero2prod::newsletters_issues::{
     DeleteExpiredIdempotencyWorker, NewslettersIssuesDeliveryWorker,
 };
-use zero2prod::startup::{build_email_client, Application};
+use zero2prod::queue_metrics::QueueMetricsLogger;
+use zero2prod::startup::{
+    build_email_client, build_send_rate_limiter, get_pg_pool, get_worker_pg_pool, Application,
+};
 use zero2prod::telemetry::{get_tracing_subscriber, init_tracing_subscriber};
 
 #[cfg(not(feature = "pool"))]
@@ -24,8 +43,15 @@ pub struct TestApp {
     pub addr: String,
     pub port: u16,
     pub pg_pool: PgPool,
+    // Pool used by app, it's `pg_pool` unless app's pool size is limited
+    pub app_pg_pool: PgPool,
     pub email_client: EmailClient,
+    // Store of newsletters issue contents, configured the same as app's
+    pub content_store: ContentStore,
     pub test_user: TestUser,
+    pub redis_proxy: Option<TcpProxy>,
+    pub email_server_proxy: Option<TcpProxy>,
+    pub email_events_webhook_secret: Secret<String>,
 }
 
 impl TestApp {
@@ -47,6 +73,63 @@ impl TestApp {
             .expect("Failed to execute request")
     }
 
+    pub async fn post_subscriptions_from(
+        &self,
+        client_ip: &str,
+        body: String,
+    ) -> reqwest::Response {
+        self.client
+            .post(&format!("{}/subscriptions", self.addr))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .header("X-Forwarded-For", client_ip)
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    // Sign body like email provider does, unless signature is given
+    pub async fn post_email_event(
+        &self,
+        body: &serde_json::Value,
+        signature: Option<&str>,
+    ) -> reqwest::Response {
+        self.post_email_event_signed_at(body, chrono::Utc::now().timestamp(), signature)
+            .await
+    }
+
+    pub async fn post_email_event_signed_at(
+        &self,
+        body: &serde_json::Value,
+        timestamp: i64,
+        signature: Option<&str>,
+    ) -> reqwest::Response {
+        let body = serde_json::to_vec(body).unwrap();
+        let timestamp = timestamp.to_string();
+        let signature = match signature {
+            Some(signature) => signature.to_string(),
+            None => {
+                let mut mac = Hmac::<Sha256>::new_from_slice(
+                    self.email_events_webhook_secret.expose_secret().as_bytes(),
+                )
+                .unwrap();
+                mac.update(timestamp.as_bytes());
+                mac.update(b".");
+                mac.update(&body);
+                hex::encode(mac.finalize().into_bytes())
+            }
+        };
+        self.client
+            .post(&format!("{}/webhooks/email-events", self.addr))
+            .header("Content-Type", "application/json")
+            .header("X-Webhook-Signature", signature)
+            .header("X-Webhook-Timestamp", timestamp)
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
     pub async fn get(&self, path: &str) -> reqwest::Response {
         self.client
             .get(&format!("{}{}", self.addr, path))
@@ -55,6 +138,29 @@ impl TestApp {
             .unwrap()
     }
 
+    pub async fn get_with_accept_encoding(&self, path: &str, encoding: &str) -> reqwest::Response {
+        self.client
+            .get(&format!("{}{}", self.addr, path))
+            .header("Accept-Encoding", encoding)
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn redrive_newsletters_issue(
+        &self,
+        newsletters_issue_id: &Uuid,
+    ) -> reqwest::Response {
+        self.client
+            .post(&format!(
+                "{}/admin/newsletters/{}/redrive",
+                self.addr, newsletters_issue_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
     pub async fn get_html(&self, path: &str) -> String {
         self.client
             .get(&format!("{}{}", self.addr, path))
@@ -75,6 +181,119 @@ impl TestApp {
             .expect("Failed to execute request")
     }
 
+    // Return token id and token of a new API token minted by logged in test user
+    pub async fn mint_api_token(&self) -> (String, String) {
+        let response = self
+            .client
+            .post(&format!("{}/admin/api_tokens", self.addr))
+            .send()
+            .await
+            .expect("Failed to execute request");
+        assert_eq!(response.status().as_u16(), 201);
+        let body: serde_json::Value = response.json().await.unwrap();
+        (
+            body["token_id"].as_str().unwrap().to_string(),
+            body["token"].as_str().unwrap().to_string(),
+        )
+    }
+
+    pub async fn revoke_api_token(&self, token_id: &str) -> reqwest::Response {
+        self.client
+            .delete(&format!("{}/admin/api_tokens/{}", self.addr, token_id))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn clear_idempotency_key(&self, idempotency_key: &str) -> reqwest::Response {
+        self.client
+            .delete(&format!(
+                "{}/admin/idempotency/{}",
+                self.addr, idempotency_key
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    // Use a new client without session cookie to only authenticate with API token
+    pub async fn post_newsletters_with_api_token(
+        &self,
+        body: &serde_json::Value,
+        token: &str,
+    ) -> reqwest::Response {
+        reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .unwrap()
+            .post(&format!("{}/admin/newsletters", self.addr))
+            .bearer_auth(token)
+            .form(&body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_newsletters_with_basic_auth(
+        &self,
+        body: &serde_json::Value,
+        username: &str,
+        password: &str,
+    ) -> reqwest::Response {
+        reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .unwrap()
+            .post(&format!("{}/admin/newsletters", self.addr))
+            .basic_auth(username, Some(password))
+            .form(&body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_subscribers_import(&self, csv: String) -> reqwest::Response {
+        self.client
+            .post(&format!("{}/admin/subscribers/import", self.addr))
+            .header("Content-Type", "text/csv")
+            .body(csv)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn put_maintenance_mode(&self, enabled: bool) -> reqwest::Response {
+        self.client
+            .put(&format!("{}/admin/maintenance", self.addr))
+            .json(&serde_json::json!({ "enabled": enabled }))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_subscribers_erase(&self, body: &serde_json::Value) -> reqwest::Response {
+        self.client
+            .post(&format!("{}/admin/subscribers/erase", self.addr))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_newsletters_with_idempotency_key_header(
+        &self,
+        body: &serde_json::Value,
+        idempotency_key: &str,
+    ) -> reqwest::Response {
+        self.client
+            .post(&format!("{}/admin/newsletters", self.addr))
+            .header("Idempotency-Key", idempotency_key)
+            .form(&body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
     pub async fn post_login(&self, login_form: serde_json::Value) -> reqwest::Response {
         self.client
             .post(&format!("{}/login", self.addr))
@@ -93,6 +312,40 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    // Enroll two-factor authentication for logged in test user and return its TOTP secret
+    pub async fn enroll_two_factor(&self) -> String {
+        let html = self.get_html("/admin/2fa").await;
+        let totp_secret = html
+            .split("secret=")
+            .nth(1)
+            .and_then(|s| s.split(&['&', '<'][..]).next())
+            .expect("TOTP secret not found in enrollment page")
+            .to_string();
+
+        let response = self
+            .post_form(
+                "/admin/2fa",
+                serde_json::json!({ "code": generate_previous_totp_code(&totp_secret) }),
+            )
+            .await;
+        assert_redirects_to(&response, "/admin/2fa");
+        assert!(self
+            .get_html("/admin/2fa")
+            .await
+            .contains("Two-factor authentication is enabled"));
+
+        totp_secret
+    }
+
+    pub async fn get_with_if_none_match(&self, path: &str, etag: &str) -> reqwest::Response {
+        self.client
+            .get(&format!("{}{}", self.addr, path))
+            .header("If-None-Match", etag)
+            .send()
+            .await
+            .unwrap()
+    }
+
     pub async fn get_login_html(&self) -> String {
         self.client
             .get(&format!("{}/login", self.addr))
@@ -139,6 +392,40 @@ impl TestApp {
         response.json().await.expect("Fail to parse email messages")
     }
 
+    pub async fn get_email_message_json(
+        &self,
+        recipient_email: &str,
+        subject: &str,
+    ) -> serde_json::Value {
+        let messages = self.get_email_messages_json().await;
+
+        let message_id = messages
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|msg| {
+                msg["to"][0]["email"].as_str() == Some(recipient_email)
+                    && msg["subject"].as_str() == Some(subject)
+            })
+            .unwrap()
+            .get("id")
+            .unwrap()
+            .as_str()
+            .unwrap();
+
+        let response = reqwest::Client::new()
+            .get(format!("http://localhost:1080/api/message/{}", message_id))
+            .send()
+            .await
+            .expect("Fail to get email message");
+        assert_eq!(response.status().as_u16(), 200);
+
+        response
+            .json()
+            .await
+            .expect("Fail to parse email message to json")
+    }
+
     pub async fn get_confirmation_links(&self, email: &str) -> ConfirmationLinks {
         let messages = self.get_email_messages_json().await;
 
@@ -149,6 +436,8 @@ impl TestApp {
             .find(|msg| {
                 msg["from"]["email"].as_str() == Some(self.email_client.sender_email())
                     && msg["to"][0]["email"].as_str() == Some(email)
+                    // Confirmed subscriber is also sent a welcome email
+                    && msg["subject"].as_str() != Some(DEFAULT_WELCOME_SUBJECT)
             })
             .unwrap()
             .get("id")
@@ -171,6 +460,46 @@ impl TestApp {
         ConfirmationLinks::get_confirmation_links(message_json)
     }
 
+    pub async fn get_confirmation_code(&self, email: &str) -> String {
+        let messages = self.get_email_messages_json().await;
+        let subject = messages
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|msg| {
+                msg["from"]["email"].as_str() == Some(self.email_client.sender_email())
+                    && msg["to"][0]["email"].as_str() == Some(email)
+                    // Confirmed subscriber is also sent a welcome email
+                    && msg["subject"].as_str() != Some(DEFAULT_WELCOME_SUBJECT)
+            })
+            .unwrap()["subject"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        let message = self.get_email_message_json(email, &subject).await;
+
+        message["text"]
+            .as_str()
+            .unwrap()
+            .split("confirmation code: ")
+            .nth(1)
+            .expect("Confirmation email doesn't contain confirmation code")
+            .chars()
+            .take_while(|c| c.is_ascii_digit())
+            .collect()
+    }
+
+    pub async fn post_confirm_code(&self, email: &str, code: &str) -> reqwest::Response {
+        self.post_form(
+            "/subscriptions/confirm_code",
+            serde_json::json!({
+                "email": email,
+                "code": code
+            }),
+        )
+        .await
+    }
+
     pub async fn click_confirmation_link(&self, confirmation_links: &ConfirmationLinks) {
         let mut link = reqwest::Url::parse(&confirmation_links.html).unwrap();
         link.set_port(Some(self.port)).unwrap();
@@ -207,8 +536,39 @@ impl TestApp {
 #[derive(Default, Clone)]
 pub struct TestAppBuilder {
     spawn_newsletters_issues_delivery_worker: bool,
+    isolate_newsletters_issues_delivery_worker: bool,
+    settings_sized_delivery_worker_pg_pool: bool,
     spawn_delete_expired_idempotency_worker: bool,
+    spawn_confirmation_emails_delivery_worker: bool,
+    spawn_delete_expired_subscription_tokens_worker: bool,
+    spawn_queue_metrics_logger: bool,
+    queue_metrics_interval_millis: Option<u64>,
     idempotency_expiration_time_millis: Option<u64>,
+    idempotency_sweep_interval_millis: Option<u64>,
+    subscription_token_sweep_interval_millis: Option<u64>,
+    idempotency_replay_max_age_millis: Option<u64>,
+    session_idle_timeout_millis: Option<u64>,
+    session_max_lifetime_millis: Option<u64>,
+    send_rate_per_second: Option<u32>,
+    base_path: Option<String>,
+    public_url: Option<(String, String, Option<u16>)>,
+    subscribe_rate_limit: Option<RateLimitSettings>,
+    cors_allowed_origins: Option<Vec<String>>,
+    flash_message_store: Option<FlashMessageStoreSettings>,
+    list_id: Option<String>,
+    subject_prefix: Option<String>,
+    archive_email: Option<String>,
+    confirmation_email: Option<ConfirmationEmailSettings>,
+    proxy_redis: bool,
+    proxy_email_server: bool,
+    database_max_connections: Option<u32>,
+    database_startup_check: Option<DatabaseStartupCheckSettings>,
+    disable_compression: bool,
+    max_recipients_per_issue: Option<u64>,
+    mx_resolver: Option<Arc<dyn MxResolver>>,
+    worker_heartbeat_interval_millis: Option<u64>,
+    worker_poll_interval_millis: Option<u64>,
+    allow_newsletters_basic_auth: bool,
 }
 
 impl TestAppBuilder {
@@ -217,21 +577,191 @@ impl TestAppBuilder {
         self
     }
 
+    // Worker doesn't share in-process `Notify` with app, as if it runs in another process
+    pub fn isolate_newsletters_issues_delivery_worker(mut self) -> Self {
+        self.isolate_newsletters_issues_delivery_worker = true;
+        self
+    }
+
+    // Worker gets its own pool sized by `database.worker_max_connections`, as it does in production
+    pub fn settings_sized_delivery_worker_pg_pool(mut self) -> Self {
+        self.settings_sized_delivery_worker_pg_pool = true;
+        self
+    }
+
     pub fn spawn_delete_expired_idempotency_worker(mut self) -> Self {
         self.spawn_delete_expired_idempotency_worker = true;
         self
     }
 
+    pub fn spawn_confirmation_emails_delivery_worker(mut self) -> Self {
+        self.spawn_confirmation_emails_delivery_worker = true;
+        self
+    }
+
+    pub fn spawn_delete_expired_subscription_tokens_worker(mut self) -> Self {
+        self.spawn_delete_expired_subscription_tokens_worker = true;
+        self
+    }
+
+    pub fn spawn_queue_metrics_logger(mut self) -> Self {
+        self.spawn_queue_metrics_logger = true;
+        self
+    }
+
+    pub fn queue_metrics_interval_millis(mut self, interval_millis: u64) -> Self {
+        self.queue_metrics_interval_millis = Some(interval_millis);
+        self
+    }
+
+    pub fn send_rate_per_second(mut self, rate_per_second: u32) -> Self {
+        self.send_rate_per_second = Some(rate_per_second);
+        self
+    }
+
+    pub fn base_path(mut self, base_path: &str) -> Self {
+        self.base_path = Some(base_path.to_string());
+        self
+    }
+
+    // Links sent to users point to this instead of the address app is bound to
+    pub fn public_url(mut self, scheme: &str, host: &str, port: Option<u16>) -> Self {
+        self.public_url = Some((scheme.to_string(), host.to_string(), port));
+        self
+    }
+
+    // Client IP is read from `X-Forwarded-For`, so tests running concurrently don't share counters
+    pub fn subscribe_rate_limit(mut self, max_requests: u32, window_secs: u64) -> Self {
+        self.subscribe_rate_limit = Some(RateLimitSettings {
+            max_requests,
+            window_secs,
+            // Test client connects from loopback, acting as reverse proxy
+            trusted_proxies: vec![IpAddr::V4(Ipv4Addr::LOCALHOST)],
+        });
+        self
+    }
+
+    pub fn cors_allowed_origins(mut self, origins: &[&str]) -> Self {
+        self.cors_allowed_origins = Some(origins.iter().map(|o| o.to_string()).collect());
+        self
+    }
+
+    pub fn flash_message_store(mut self, store: FlashMessageStoreSettings) -> Self {
+        self.flash_message_store = Some(store);
+        self
+    }
+
+    pub fn list_id(mut self, list_id: &str) -> Self {
+        self.list_id = Some(list_id.to_string());
+        self
+    }
+
+    pub fn subject_prefix(mut self, subject_prefix: &str) -> Self {
+        self.subject_prefix = Some(subject_prefix.to_string());
+        self
+    }
+
+    pub fn archive_email(mut self, archive_email: &str) -> Self {
+        self.archive_email = Some(archive_email.to_string());
+        self
+    }
+
+    pub fn confirmation_email(mut self, confirmation_email: ConfirmationEmailSettings) -> Self {
+        self.confirmation_email = Some(confirmation_email);
+        self
+    }
+
+    // App gets its own pool of at most `max_connections`, which times out quickly when exhausted
+    pub fn database_max_connections(mut self, max_connections: u32) -> Self {
+        self.database_max_connections = Some(max_connections);
+        self
+    }
+
+    pub fn database_startup_check(mut self, startup_check: DatabaseStartupCheckSettings) -> Self {
+        self.database_startup_check = Some(startup_check);
+        self
+    }
+
+    pub fn max_recipients_per_issue(mut self, max_recipients: u64) -> Self {
+        self.max_recipients_per_issue = Some(max_recipients);
+        self
+    }
+
+    // Enable MX check of subscriber emails with stubbed resolver, so tests don't hit DNS
+    pub fn mx_resolver(mut self, mx_resolver: Arc<dyn MxResolver>) -> Self {
+        self.mx_resolver = Some(mx_resolver);
+        self
+    }
+
+    pub fn worker_heartbeat_interval_millis(mut self, interval_millis: u64) -> Self {
+        self.worker_heartbeat_interval_millis = Some(interval_millis);
+        self
+    }
+
+    pub fn worker_poll_interval_millis(mut self, interval_millis: u64) -> Self {
+        self.worker_poll_interval_millis = Some(interval_millis);
+        self
+    }
+
+    pub fn allow_newsletters_basic_auth(mut self) -> Self {
+        self.allow_newsletters_basic_auth = true;
+        self
+    }
+
+    pub fn disable_compression(mut self) -> Self {
+        self.disable_compression = true;
+        self
+    }
+
+    // Connect to Redis through a proxy that can be shut down to simulate Redis outage
+    pub fn proxy_redis(mut self) -> Self {
+        self.proxy_redis = true;
+        self
+    }
+
+    // Connect to SMTP server through a proxy that can be paused to simulate email provider outage
+    pub fn proxy_email_server(mut self) -> Self {
+        self.proxy_email_server = true;
+        self
+    }
+
     pub fn idempotency_expiration_time_millis(mut self, time_millis: u64) -> Self {
         self.idempotency_expiration_time_millis = Some(time_millis);
         self
     }
 
+    pub fn idempotency_sweep_interval_millis(mut self, interval_millis: u64) -> Self {
+        self.idempotency_sweep_interval_millis = Some(interval_millis);
+        self
+    }
+
+    pub fn subscription_token_sweep_interval_millis(mut self, interval_millis: u64) -> Self {
+        self.subscription_token_sweep_interval_millis = Some(interval_millis);
+        self
+    }
+
+    pub fn idempotency_replay_max_age_millis(mut self, max_age_millis: u64) -> Self {
+        self.idempotency_replay_max_age_millis = Some(max_age_millis);
+        self
+    }
+
+    pub fn session_idle_timeout_millis(mut self, time_millis: u64) -> Self {
+        self.session_idle_timeout_millis = Some(time_millis);
+        self
+    }
+
+    pub fn session_max_lifetime_millis(mut self, time_millis: u64) -> Self {
+        self.session_max_lifetime_millis = Some(time_millis);
+        self
+    }
+
     pub async fn build(self) -> anyhow::Result<TestApp> {
         // Lazy mean only run when it is called
         // once_cell make sure it is only run once on entire program lifetime
         Lazy::force(&TRACING);
 
+        let mut redis_proxy = None;
+        let mut email_server_proxy = None;
         let settings = {
             let mut settings = Settings::get_configuration().expect("Failed to read configuration");
 
@@ -242,20 +772,153 @@ impl TestAppBuilder {
                 settings.application.idempotency_expiration_millis = time_millis;
             }
 
+            if let Some(interval_millis) = self.idempotency_sweep_interval_millis {
+                settings.application.idempotency_sweep_interval_millis = interval_millis;
+            }
+
+            if let Some(interval_millis) = self.subscription_token_sweep_interval_millis {
+                settings
+                    .application
+                    .subscription_token_sweep_interval_millis = interval_millis;
+            }
+
+            if let Some(max_age_millis) = self.idempotency_replay_max_age_millis {
+                settings.application.idempotency_replay_max_age_millis = Some(max_age_millis);
+            }
+
+            if let Some(interval_millis) = self.worker_heartbeat_interval_millis {
+                settings.application.worker_heartbeat_interval_millis = interval_millis;
+            }
+
+            if let Some(interval_millis) = self.worker_poll_interval_millis {
+                settings.application.worker_poll_interval_millis = interval_millis;
+            }
+
+            settings.application.allow_newsletters_basic_auth = self.allow_newsletters_basic_auth;
+
+            if let Some(time_millis) = self.session_idle_timeout_millis {
+                settings.application.session_idle_timeout_millis = time_millis;
+            }
+
+            if let Some(time_millis) = self.session_max_lifetime_millis {
+                settings.application.session_max_lifetime_millis = time_millis;
+            }
+
+            if let Some(interval_millis) = self.queue_metrics_interval_millis {
+                settings.application.queue_metrics_interval_millis = interval_millis;
+            }
+
+            if let Some(startup_check) = self.database_startup_check {
+                settings.database.startup_check = Some(startup_check);
+            }
+
+            if let Some(base_path) = self.base_path {
+                settings.application.base_path = base_path;
+            }
+
+            if let Some((scheme, host, port)) = self.public_url {
+                settings.application.public_scheme = Some(scheme);
+                settings.application.public_host = Some(host);
+                settings.application.public_port = port;
+            }
+
+            // All tests subscribe from the same IP, so rate limit is only enabled when requested
+            settings.application.subscribe_rate_limit = self.subscribe_rate_limit;
+
+            if let Some(max_recipients) = self.max_recipients_per_issue {
+                settings.application.max_recipients_per_issue = max_recipients;
+            }
+
+            settings.application.mx_check.enabled = self.mx_resolver.is_some();
+
+            if self.disable_compression {
+                settings.application.compress_responses = false;
+            }
+
+            if let Some(store) = self.flash_message_store {
+                settings.application.flash_message_store = store;
+            }
+
+            if let Some(origins) = self.cors_allowed_origins {
+                settings.application.cors.allowed_origins = origins;
+            }
+
             // Increase uniqueness of each test case
             settings.email_client.sender_email = SafeEmail().fake();
 
+            if let Some(rate_per_second) = self.send_rate_per_second {
+                settings.email_client.send_rate_per_second = Some(rate_per_second);
+            }
+
+            if let Some(list_id) = self.list_id {
+                settings.email_client.list_id = Some(list_id);
+            }
+
+            if let Some(subject_prefix) = self.subject_prefix {
+                settings.email_client.subject_prefix = subject_prefix;
+            }
+            if let Some(archive_email) = self.archive_email {
+                settings.email_client.archive_email = Some(archive_email);
+            }
+
+            if let Some(confirmation_email) = self.confirmation_email {
+                settings.confirmation_email = confirmation_email;
+            }
+
+            if self.proxy_redis {
+                let redis_addr = settings
+                    .application
+                    .redis_url
+                    .expose_secret()
+                    .trim_start_matches("redis://")
+                    .to_string();
+                let proxy = TcpProxy::start(redis_addr).await;
+                settings.application.redis_url =
+                    Secret::new(format!("redis://127.0.0.1:{}", proxy.port));
+                redis_proxy = Some(proxy);
+            }
+
+            if self.proxy_email_server {
+                let email_server_addr = format!(
+                    "{}:{}",
+                    settings.email_client.host,
+                    settings
+                        .email_client
+                        .port
+                        .expect("Email server port must be set to be proxied")
+                );
+                let proxy = TcpProxy::start(email_server_addr).await;
+                settings.email_client.host = "127.0.0.1".to_string();
+                settings.email_client.port = Some(proxy.port);
+                email_server_proxy = Some(proxy);
+            }
+
             settings
         };
 
         let notify = Arc::new(Notify::new());
-        let email_client = build_email_client(settings.email_client.clone())?;
+        let email_client = build_email_client(settings.email_client.clone(), None)?;
+        let content_store = ContentStore::from_settings(&settings.content_store);
+        let send_rate_limiter = build_send_rate_limiter(&settings.email_client);
+        let email_events_webhook_secret = settings.application.email_events_webhook_secret.clone();
         let pg_pool = get_test_database(&settings.database).await;
-        let app = Application::builder(settings.clone(), notify.clone())
-            .set_pg_pool(pg_pool.clone())
-            .build()
-            .await
-            .expect("Failed to build Server");
+        let app_pg_pool = match self.database_max_connections {
+            Some(max_connections) => {
+                let mut database = test_database_settings(&settings.database, &pg_pool).await;
+                database.max_connections = max_connections;
+                database.min_connections = 0;
+                database.query_timeout_secs = 1;
+                get_pg_pool(&database)
+            }
+            None => pg_pool.clone(),
+        };
+        let mut app_builder = Application::builder(settings.clone(), notify.clone())
+            .set_pg_pool(app_pg_pool.clone())
+            .set_send_rate_limiter(send_rate_limiter.clone());
+        if let Some(mx_resolver) = self.mx_resolver {
+            app_builder = app_builder.set_mx_resolver(mx_resolver);
+        }
+        let app = app_builder.build().await.expect("Failed to build Server");
 
         let port = app.port();
         let addr = format!("http://127.0.0.1:{}", port);
@@ -269,15 +932,49 @@ impl TestAppBuilder {
         tokio::spawn(app.run_until_terminated());
 
         if self.spawn_newsletters_issues_delivery_worker {
+            let notify = match self.isolate_newsletters_issues_delivery_worker {
+                true => Arc::new(Notify::new()),
+                false => notify,
+            };
+            let worker_pg_pool = match self.settings_sized_delivery_worker_pg_pool {
+                true => get_worker_pg_pool(
+                    &test_database_settings(&settings.database, &pg_pool).await,
+                    None,
+                ),
+                false => pg_pool.clone(),
+            };
             tokio::spawn(
                 NewslettersIssuesDeliveryWorker::builder(settings.clone(), notify)
-                    .set_pg_pool(pg_pool.clone())
+                    .set_pg_pool(worker_pg_pool)
+                    .set_send_rate_limiter(send_rate_limiter.clone())
                     .run_until_terminated(),
             );
         }
         if self.spawn_delete_expired_idempotency_worker {
             tokio::spawn(
-                DeleteExpiredIdempotencyWorker::builder(settings)
+                DeleteExpiredIdempotencyWorker::builder(settings.clone())
+                    .set_pg_pool(pg_pool.clone())
+                    .run_until_terminated(),
+            );
+        }
+        if self.spawn_confirmation_emails_delivery_worker {
+            tokio::spawn(
+                ConfirmationEmailsDeliveryWorker::builder(settings.clone())
+                    .set_pg_pool(pg_pool.clone())
+                    .set_send_rate_limiter(send_rate_limiter)
+                    .run_until_terminated(),
+            );
+        }
+        if self.spawn_delete_expired_subscription_tokens_worker {
+            tokio::spawn(
+                DeleteExpiredSubscriptionTokensWorker::builder(settings.clone())
+                    .set_pg_pool(pg_pool.clone())
+                    .run_until_terminated(),
+            );
+        }
+        if self.spawn_queue_metrics_logger {
+            tokio::spawn(
+                QueueMetricsLogger::builder(settings)
                     .set_pg_pool(pg_pool.clone())
                     .run_until_terminated(),
             );
@@ -294,12 +991,81 @@ impl TestAppBuilder {
             addr,
             port,
             pg_pool,
+            app_pg_pool,
             email_client,
+            content_store,
             test_user,
+            redis_proxy,
+            email_server_proxy,
+            email_events_webhook_secret,
         })
     }
 }
 
+pub struct TcpProxy {
+    pub port: u16,
+    listener_handle: JoinHandle<()>,
+    connection_handles: Arc<Mutex<Vec<JoinHandle<()>>>>,
+    paused: Arc<AtomicBool>,
+}
+
+impl TcpProxy {
+    pub async fn start(upstream_addr: String) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Failed to bind proxy listener");
+        let port = listener.local_addr().unwrap().port();
+        let connection_handles = Arc::new(Mutex::new(vec![]));
+
+        let paused = Arc::new(AtomicBool::new(false));
+
+        let handles = connection_handles.clone();
+        let is_paused = paused.clone();
+        let listener_handle = tokio::spawn(async move {
+            while let Ok((mut inbound, _)) = listener.accept().await {
+                // Drop inbound connection right away to act like upstream is down
+                if is_paused.load(Ordering::SeqCst) {
+                    continue;
+                }
+                let upstream_addr = upstream_addr.clone();
+                let handle = tokio::spawn(async move {
+                    if let Ok(mut outbound) = TcpStream::connect(upstream_addr).await {
+                        let _ = tokio::io::copy_bidirectional(&mut inbound, &mut outbound).await;
+                    }
+                });
+                handles.lock().unwrap().push(handle);
+            }
+        });
+
+        Self {
+            port,
+            listener_handle,
+            connection_handles,
+            paused,
+        }
+    }
+
+    // Refuse new connections until resumed, while keeping listening port
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::SeqCst);
+        for handle in self.connection_handles.lock().unwrap().drain(..) {
+            handle.abort();
+        }
+    }
+
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::SeqCst);
+    }
+
+    // Drop listener and all opened connections
+    pub fn shutdown(&self) {
+        self.listener_handle.abort();
+        for handle in self.connection_handles.lock().unwrap().iter() {
+            handle.abort();
+        }
+    }
+}
+
 static TRACING: Lazy<()> = Lazy::new(|| {
     const TEST_NAME: &str = "test_app";
     const DEFAULT_LOG_LEVEL: &str = "debug";
@@ -342,6 +1108,19 @@ impl ConfirmationLinks {
     }
 }
 
+// Settings pointing to test database, to build other pools for it
+pub async fn test_database_settings(
+    database: &DatabaseSettings,
+    pg_pool: &PgPool,
+) -> DatabaseSettings {
+    let mut database = database.clone();
+    database.database_name = sqlx::query_scalar("SELECT current_database()")
+        .fetch_one(pg_pool)
+        .await
+        .expect("Failed to get name of test database");
+    database
+}
+
 // Test will cause unexpected result if do same test multiple times to the same database
 // So we need to create a branch new test database for each test for isolation
 // Need to manually clean up test database
@@ -426,3 +1205,33 @@ pub async fn create_confirmed_subscriber(app: &TestApp) {
 
     app.create_confirmed_subscriber(body).await;
 }
+
+pub fn generate_totp_code(totp_secret: &str) -> String {
+    build_totp(totp_secret)
+        .generate_current()
+        .expect("Failed to generate TOTP code")
+}
+
+// Code of previous time step is still accepted, enrolling with it leaves current code usable to login
+pub fn generate_previous_totp_code(totp_secret: &str) -> String {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    build_totp(totp_secret).generate(now - 30)
+}
+
+fn build_totp(totp_secret: &str) -> totp_rs::TOTP {
+    let secret = totp_rs::Secret::Encoded(totp_secret.to_string())
+        .to_bytes()
+        .expect("Invalid TOTP secret");
+    totp_rs::TOTP::new_unchecked(
+        totp_rs::Algorithm::SHA1,
+        6,
+        1,
+        30,
+        secret,
+        None,
+        "".to_string(),
+    )
+}
@@ -0,0 +1,28 @@
+use crate::helpers::TestApp;
+
+#[tokio::test]
+async fn home_page_serves_subscribe_form_and_login_link() {
+    // Arrange
+    let app = TestApp::builder()
+        .build()
+        .await
+        .expect("Failed to spawn app");
+
+    // Act
+    let response = app.get("/").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .headers()
+        .get("Content-Type")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .starts_with("text/html"));
+    let html = response.text().await.unwrap();
+    assert!(html.contains(r#"<form action="/subscriptions" method="POST">"#));
+    assert!(html.contains(r#"name="name""#));
+    assert!(html.contains(r#"name="email""#));
+    assert!(html.contains(r#"href="/login""#));
+}
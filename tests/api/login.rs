@@ -1,4 +1,5 @@
 use crate::helpers::{assert_redirects_to, TestApp};
+use std::time::Duration;
 use uuid::Uuid;
 
 #[tokio::test]
@@ -48,3 +49,315 @@ async fn login_successfully_redirects_to_home() {
     // Assert
     assert_redirects_to(&response, "/admin/dashboard");
 }
+
+#[tokio::test]
+async fn idle_session_beyond_idle_timeout_redirects_to_login() {
+    // Arrange
+    let app = TestApp::builder()
+        .session_idle_timeout_millis(500)
+        .build()
+        .await
+        .unwrap();
+    let response = app.login().await;
+    assert_redirects_to(&response, "/admin/dashboard");
+
+    // Act
+    tokio::time::sleep(Duration::from_millis(1000)).await;
+    let response = app.get("/admin/dashboard").await;
+
+    // Assert
+    assert_redirects_to(&response, "/login");
+    let login_html = app.get_login_html().await;
+    assert!(login_html.contains("<p><i>Your session has expired, please login again</i></p>"));
+    // Session is purged, so it doesn't come back alive
+    let response = app.get("/admin/dashboard").await;
+    assert_redirects_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn active_session_within_idle_timeout_stays_logged_in() {
+    // Arrange
+    let app = TestApp::builder()
+        .session_idle_timeout_millis(1000)
+        .build()
+        .await
+        .unwrap();
+    app.login().await;
+
+    for _ in 0..4 {
+        // Act
+        tokio::time::sleep(Duration::from_millis(400)).await;
+        let response = app.get("/admin/dashboard").await;
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 200);
+    }
+}
+
+#[tokio::test]
+async fn session_beyond_max_lifetime_redirects_to_login_regardless_of_activity() {
+    // Arrange
+    let app = TestApp::builder()
+        .session_max_lifetime_millis(1000)
+        .build()
+        .await
+        .unwrap();
+    app.login().await;
+    let response = app.get("/admin/dashboard").await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Act
+    tokio::time::sleep(Duration::from_millis(600)).await;
+    let response = app.get("/admin/dashboard").await;
+    assert_eq!(response.status().as_u16(), 200);
+    tokio::time::sleep(Duration::from_millis(600)).await;
+    let response = app.get("/admin/dashboard").await;
+
+    // Assert
+    assert_redirects_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn login_with_remember_me_sets_persistent_session_cookie() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+
+    // Act
+    let response = app
+        .post_login(serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+            "remember_me": "on"
+        }))
+        .await;
+
+    // Assert
+    assert_redirects_to(&response, "/admin/dashboard");
+    let session_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == "id")
+        .expect("Session cookie is not set");
+    assert!(session_cookie.max_age().is_some());
+}
+
+#[tokio::test]
+async fn login_without_remember_me_sets_browser_session_cookie() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+
+    // Act
+    let response = app.login().await;
+
+    // Assert
+    assert_redirects_to(&response, "/admin/dashboard");
+    let session_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == "id")
+        .expect("Session cookie is not set");
+    assert!(session_cookie.max_age().is_none());
+}
+
+#[tokio::test]
+async fn remembered_session_is_not_purged_by_idle_timeout() {
+    // Arrange
+    let app = TestApp::builder()
+        .session_idle_timeout_millis(500)
+        .build()
+        .await
+        .unwrap();
+    app.post_login(serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password,
+        "remember_me": "on"
+    }))
+    .await;
+
+    // Act
+    tokio::time::sleep(Duration::from_millis(1000)).await;
+    let response = app.get("/admin/dashboard").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let session_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == "id")
+        .expect("Session cookie is not set");
+    assert!(session_cookie.max_age().is_some());
+}
+
+#[tokio::test]
+async fn login_page_is_not_modified_when_etag_matches() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    let response = app.get("/login").await;
+    assert_eq!(response.status().as_u16(), 200);
+    let etag = response
+        .headers()
+        .get("ETag")
+        .expect("Login page must have ETag")
+        .to_str()
+        .unwrap()
+        .to_string();
+
+    // Act
+    let response = app.get_with_if_none_match("/login", &etag).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 304);
+    assert_eq!(response.headers().get("ETag").unwrap(), etag.as_str());
+    assert!(response.text().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn login_page_with_flash_message_is_not_cached() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    let etag = app
+        .get("/login")
+        .await
+        .headers()
+        .get("ETag")
+        .expect("Login page must have ETag")
+        .to_str()
+        .unwrap()
+        .to_string();
+    app.post_login(serde_json::json!({
+        "username": Uuid::new_v4().to_string(),
+        "password": Uuid::new_v4().to_string()
+    }))
+    .await;
+
+    // Act
+    let response = app.get_with_if_none_match("/login", &etag).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.headers().get("ETag").is_none());
+    assert_eq!(response.headers().get("Cache-Control").unwrap(), "no-store");
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("Invalid Username or Password"));
+}
+
+#[tokio::test]
+async fn login_page_is_served_with_security_headers() {
+    // Arrange
+    let app = TestApp::builder()
+        .build()
+        .await
+        .expect("Failed to spawn app");
+
+    // Act
+    let response = app.get("/login").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let headers = response.headers();
+    assert_eq!(headers.get("X-Content-Type-Options").unwrap(), "nosniff");
+    assert_eq!(headers.get("X-Frame-Options").unwrap(), "DENY");
+    assert_eq!(headers.get("Referrer-Policy").unwrap(), "no-referrer");
+    let csp = headers
+        .get("Content-Security-Policy")
+        .unwrap()
+        .to_str()
+        .unwrap();
+    assert!(csp.contains("default-src 'self'"));
+    assert!(csp.contains("frame-ancestors 'none'"));
+}
+
+#[tokio::test]
+async fn login_successfully_redirects_to_local_next_path() {
+    // Arrange
+    let app = TestApp::builder()
+        .build()
+        .await
+        .expect("Failed to spawn app");
+
+    let login_form = serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password,
+        "next": "/admin/newsletters"
+    });
+
+    // Act
+    let response = app.post_login(login_form).await;
+
+    // Assert
+    assert_redirects_to(&response, "/admin/newsletters");
+}
+
+#[tokio::test]
+async fn login_accepts_next_path_from_query() {
+    // Arrange
+    let app = TestApp::builder()
+        .build()
+        .await
+        .expect("Failed to spawn app");
+
+    // Act
+    let response = app
+        .client
+        .post(&format!("{}/login?next=/admin/password", app.addr))
+        .form(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_redirects_to(&response, "/admin/password");
+}
+
+#[tokio::test]
+async fn login_ignores_next_pointing_to_other_host() {
+    // Arrange
+    let app = TestApp::builder()
+        .build()
+        .await
+        .expect("Failed to spawn app");
+
+    for next in ["https://evil.example.com", "//evil.example.com"] {
+        let login_form = serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+            "next": next
+        });
+
+        // Act
+        let response = app.post_login(login_form).await;
+
+        // Assert
+        assert_redirects_to(&response, "/admin/dashboard");
+    }
+}
+
+#[tokio::test]
+async fn login_page_passes_on_only_local_next_path() {
+    // Arrange
+    let app = TestApp::builder()
+        .build()
+        .await
+        .expect("Failed to spawn app");
+
+    // Act
+    let local_html = app
+        .get("/login?next=/admin/newsletters")
+        .await
+        .text()
+        .await
+        .unwrap();
+    let external_html = app
+        .get("/login?next=https://evil.example.com")
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(local_html.contains(r#"name="next" value="/admin/newsletters""#));
+    assert!(!external_html.contains("evil.example.com"));
+}
@@ -1,5 +1,9 @@
 mod admin;
+mod cli;
 mod health;
 mod helpers;
+mod home;
 mod login;
+mod queue_metrics;
 mod subscriptions;
+mod webhooks;
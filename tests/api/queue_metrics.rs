@@ -0,0 +1,50 @@
+use crate::helpers::TestApp;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::subscriber::Subscriber;
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::Layer;
+
+// Count events carrying queue health summary fields
+struct SummaryEventCounter(Arc<AtomicUsize>);
+
+impl<S: Subscriber> Layer<S> for SummaryEventCounter {
+    fn on_event(&self, event: &tracing::Event<'_>, _ctx: Context<'_, S>) {
+        if event
+            .metadata()
+            .fields()
+            .field("pending_delivery_tasks")
+            .is_some()
+        {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+}
+
+#[tokio::test]
+async fn queue_metrics_logger_emits_summary_within_interval() {
+    // Arrange
+    // Test runtime is single-threaded, so events of spawned logger reach this thread's subscriber
+    let n_summaries = Arc::new(AtomicUsize::new(0));
+    let _guard = tracing::subscriber::set_default(
+        tracing_subscriber::registry().with(SummaryEventCounter(n_summaries.clone())),
+    );
+
+    // Act
+    let _app = TestApp::builder()
+        .spawn_queue_metrics_logger()
+        .queue_metrics_interval_millis(200)
+        .build()
+        .await
+        .unwrap();
+
+    // Assert
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while n_summaries.load(Ordering::SeqCst) < 2 {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("Failed to wait until queue health summaries are logged");
+}
@@ -2,6 +2,21 @@ use crate::helpers::TestApp;
 use fake::faker::internet::en::SafeEmail;
 use fake::faker::name::en::Name;
 use fake::Fake;
+use futures::future::BoxFuture;
+use std::sync::Arc;
+use std::time::Duration;
+use zero2prod::configuration::{ConfirmationEmailSettings, ConfirmationMethodSettings};
+use zero2prod::confirmation_emails::DEFAULT_WELCOME_SUBJECT;
+use zero2prod::mx_check::MxResolver;
+
+// Domains in list have no MX records, any other domain has
+struct StubMxResolver(Vec<&'static str>);
+
+impl MxResolver for StubMxResolver {
+    fn has_mx_records<'a>(&'a self, domain: &'a str) -> BoxFuture<'a, Result<bool, anyhow::Error>> {
+        Box::pin(async move { Ok(!self.0.contains(&domain)) })
+    }
+}
 
 #[tokio::test]
 async fn post_subscribe_in_urlencoded_valid_format_ret_200() {
@@ -40,6 +55,25 @@ async fn post_subscribe_in_urlencoded_format_with_missing_data_ret_400() {
     }
 }
 
+#[tokio::test]
+async fn post_subscribe_with_oversized_body_ret_413() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    let body = format!("name={}&email=foobar%40example.com", "a".repeat(64 * 1024));
+
+    // Act
+    let response = app.post_subscriptions(body).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 413);
+    let n_subscriptions = sqlx::query!("SELECT COUNT(*) as \"count!\" FROM subscriptions")
+        .fetch_one(&app.pg_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_subscriptions, 0);
+}
+
 #[tokio::test]
 async fn query_pending_confirmation_subscriber_after_user_send_subscription_form_ret_200() {
     // Arrange
@@ -145,3 +179,1066 @@ async fn drop_subscription_token_column_to_cause_internal_error_when_send_subscr
     // Assert
     assert_eq!(500, response.status().as_u16());
 }
+
+#[tokio::test]
+async fn rapid_subscribes_over_send_rate_succeed_and_confirmations_are_delivered_later() {
+    // Arrange
+    let app = TestApp::builder()
+        .send_rate_per_second(1)
+        .spawn_confirmation_emails_delivery_worker()
+        .build()
+        .await
+        .unwrap();
+    let emails: Vec<String> = (0..3).map(|_| SafeEmail().fake()).collect();
+
+    // Act
+    for email in &emails {
+        let body = serde_json::json!({
+            "name": Name().fake::<String>(),
+            "email": email
+        });
+        let response = app
+            .post_subscriptions(serde_urlencoded::to_string(body).unwrap())
+            .await;
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    // Assert all confirmation emails are eventually delivered
+    tokio::time::timeout(Duration::from_secs(10), async {
+        loop {
+            let messages = app.get_email_messages_json().await;
+            let n_delivered = emails
+                .iter()
+                .filter(|email| {
+                    messages.as_array().unwrap().iter().any(|msg| {
+                        msg["from"]["email"].as_str() == Some(app.email_client.sender_email())
+                            && msg["to"][0]["email"].as_str() == Some(email.as_str())
+                    })
+                })
+                .count();
+            if n_delivered == emails.len() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await
+    .expect("Failed to wait until all confirmation emails are delivered");
+}
+
+#[tokio::test]
+async fn subscribe_while_email_provider_is_down_succeeds_and_confirmation_is_delivered_after_recovery(
+) {
+    // Arrange
+    let app = TestApp::builder()
+        .proxy_email_server()
+        .spawn_confirmation_emails_delivery_worker()
+        .build()
+        .await
+        .unwrap();
+    let email: String = SafeEmail().fake();
+    let body = serde_json::json!({
+        "name": Name().fake::<String>(),
+        "email": email
+    });
+    app.email_server_proxy.as_ref().unwrap().pause();
+
+    // Act
+    let response = app
+        .post_subscriptions(serde_urlencoded::to_string(body).unwrap())
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let subscriber = sqlx::query!("SELECT email, status FROM subscriptions")
+        .fetch_one(&app.pg_pool)
+        .await
+        .expect("Failed to fetch saved subscriptions");
+    assert_eq!(subscriber.email, email);
+    assert_eq!(subscriber.status, "pending");
+
+    // Act
+    app.email_server_proxy.as_ref().unwrap().resume();
+
+    // Assert confirmation email is delivered by worker once provider recovers
+    tokio::time::timeout(Duration::from_secs(10), async {
+        loop {
+            let messages = app.get_email_messages_json().await;
+            let is_delivered = messages.as_array().unwrap().iter().any(|msg| {
+                msg["from"]["email"].as_str() == Some(app.email_client.sender_email())
+                    && msg["to"][0]["email"].as_str() == Some(email.as_str())
+            });
+            if is_delivered {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await
+    .expect("Failed to wait until confirmation email is delivered");
+}
+
+async fn subscribe_new_subscriber(app: &TestApp) -> String {
+    let email: String = SafeEmail().fake();
+    let body = serde_json::json!({
+        "name": Name().fake::<String>(),
+        "email": email
+    });
+    let response = app
+        .post_subscriptions(serde_urlencoded::to_string(body).unwrap())
+        .await;
+    assert!(response.status().is_success());
+    email
+}
+
+async fn get_subscription_status(app: &TestApp, email: &str) -> String {
+    sqlx::query!("SELECT status FROM subscriptions WHERE email = $1", email)
+        .fetch_one(&app.pg_pool)
+        .await
+        .expect("Failed to fetch saved subscription")
+        .status
+}
+
+#[tokio::test]
+async fn confirm_with_valid_code_confirms_subscriber() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    let email = subscribe_new_subscriber(&app).await;
+    let code = app.get_confirmation_code(&email).await;
+    assert_eq!(code.len(), 6);
+
+    // Act
+    let response = app.post_confirm_code(&email, &code).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(get_subscription_status(&app, &email).await, "confirmed");
+}
+
+#[tokio::test]
+async fn confirm_with_wrong_code_ret_401() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    let email = subscribe_new_subscriber(&app).await;
+    let code = app.get_confirmation_code(&email).await;
+    let wrong_code = format!("{:06}", (code.parse::<u32>().unwrap() + 1) % 1_000_000);
+
+    // Act
+    let response = app.post_confirm_code(&email, &wrong_code).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(get_subscription_status(&app, &email).await, "pending");
+}
+
+#[tokio::test]
+async fn confirmation_code_stops_working_after_too_many_wrong_guesses() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    let email = subscribe_new_subscriber(&app).await;
+    let code = app.get_confirmation_code(&email).await;
+    let wrong_code = format!("{:06}", (code.parse::<u32>().unwrap() + 1) % 1_000_000);
+    for _ in 0..5 {
+        let response = app.post_confirm_code(&email, &wrong_code).await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    // Act
+    let response = app.post_confirm_code(&email, &code).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(get_subscription_status(&app, &email).await, "pending");
+}
+
+#[tokio::test]
+async fn confirm_with_expired_code_ret_410() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    let email = subscribe_new_subscriber(&app).await;
+    let code = app.get_confirmation_code(&email).await;
+    sqlx::query!("UPDATE subscription_tokens SET created_at = now() - interval '30 days'")
+        .execute(&app.pg_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = app.post_confirm_code(&email, &code).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 410);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("/subscriptions/confirm/resend"));
+    assert_eq!(get_subscription_status(&app, &email).await, "pending");
+}
+
+#[tokio::test]
+async fn resend_confirmation_replaces_expired_token() {
+    // Arrange
+    let app = TestApp::builder()
+        .spawn_confirmation_emails_delivery_worker()
+        .build()
+        .await
+        .unwrap();
+    let email = subscribe_new_subscriber(&app).await;
+    let confirmation_links = app.get_confirmation_links(&email).await;
+    sqlx::query!("UPDATE subscription_tokens SET created_at = now() - interval '30 days'")
+        .execute(&app.pg_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = app
+        .post_form(
+            "/subscriptions/confirm/resend",
+            serde_json::json!({ "email": email }),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let mut old_link = reqwest::Url::parse(&confirmation_links.html).unwrap();
+    old_link.set_port(Some(app.port)).unwrap();
+    assert_eq!(reqwest::get(old_link).await.unwrap().status().as_u16(), 404);
+
+    // Assert new confirmation email is delivered by worker
+    tokio::time::timeout(Duration::from_secs(10), async {
+        loop {
+            let n_queued = sqlx::query!(
+                "SELECT COUNT(*) as \"count!\" FROM confirmation_emails_delivery_queue"
+            )
+            .fetch_one(&app.pg_pool)
+            .await
+            .unwrap()
+            .count;
+            if n_queued == 0 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await
+    .expect("Failed to wait until new confirmation email is delivered");
+
+    // Act confirm with new token
+    let subscription_token = sqlx::query!("SELECT subscription_token FROM subscription_tokens")
+        .fetch_one(&app.pg_pool)
+        .await
+        .unwrap()
+        .subscription_token;
+    let response = app
+        .get(&format!(
+            "/subscriptions/confirm?subscription_token={}",
+            subscription_token
+        ))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(get_subscription_status(&app, &email).await, "confirmed");
+}
+
+#[tokio::test]
+async fn resend_confirmation_to_unknown_email_ret_200_without_sending() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+
+    // Act
+    let response = app
+        .post_form(
+            "/subscriptions/confirm/resend",
+            serde_json::json!({ "email": SafeEmail().fake::<String>() }),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let n_queued =
+        sqlx::query!("SELECT COUNT(*) as \"count!\" FROM confirmation_emails_delivery_queue")
+            .fetch_one(&app.pg_pool)
+            .await
+            .unwrap()
+            .count;
+    assert_eq!(n_queued, 0);
+}
+
+#[tokio::test]
+async fn expired_tokens_of_pending_subscribers_are_deleted() {
+    // Arrange
+    let app = TestApp::builder()
+        .spawn_delete_expired_subscription_tokens_worker()
+        .subscription_token_sweep_interval_millis(100)
+        .build()
+        .await
+        .unwrap();
+    crate::helpers::create_confirmed_subscriber(&app).await;
+    let email = subscribe_new_subscriber(&app).await;
+    sqlx::query!("UPDATE subscription_tokens SET created_at = now() - interval '30 days'")
+        .execute(&app.pg_pool)
+        .await
+        .unwrap();
+
+    // Act wait for a sweep after tokens are expired
+    tokio::time::timeout(Duration::from_secs(10), async {
+        loop {
+            let n_pending_tokens = sqlx::query!(
+                r#"
+                SELECT COUNT(*) as "count!"
+                FROM subscription_tokens t
+                JOIN subscriptions s ON s.id = t.subscription_id
+                WHERE s.email = $1
+                "#,
+                email
+            )
+            .fetch_one(&app.pg_pool)
+            .await
+            .unwrap()
+            .count;
+            if n_pending_tokens == 0 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await
+    .expect("Failed to wait until expired token is deleted");
+
+    // Assert token of confirmed subscriber is kept for unsubscribe links
+    let n_tokens = sqlx::query!("SELECT COUNT(*) as \"count!\" FROM subscription_tokens")
+        .fetch_one(&app.pg_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_tokens, 1);
+    assert_eq!(get_subscription_status(&app, &email).await, "pending");
+}
+
+#[tokio::test]
+async fn confirmation_code_is_neither_sent_nor_accepted_when_only_link_is_offered() {
+    // Arrange
+    let app = TestApp::builder()
+        .confirmation_email(ConfirmationEmailSettings {
+            method: ConfirmationMethodSettings::Link,
+            ..Default::default()
+        })
+        .build()
+        .await
+        .unwrap();
+    let email = subscribe_new_subscriber(&app).await;
+    let message = app.get_email_message_json(&email, "Confirmation").await;
+    assert!(!message["text"]
+        .as_str()
+        .unwrap()
+        .contains("confirmation code"));
+
+    // Act
+    let response = app.post_confirm_code(&email, "000000").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+    let confirmation_links = app.get_confirmation_links(&email).await;
+    app.click_confirmation_link(&confirmation_links).await;
+    assert_eq!(get_subscription_status(&app, &email).await, "confirmed");
+}
+
+#[tokio::test]
+async fn configured_confirmation_email_template_is_rendered_with_confirmation_link() {
+    // Arrange
+    let app = TestApp::builder()
+        .confirmation_email(ConfirmationEmailSettings {
+            subject: Some("Please confirm your subscription".to_string()),
+            html_body: Some(r#"<p><a href="{{confirmation_link}}">Confirm</a></p>"#.to_string()),
+            text_body: Some("Confirm here: {{confirmation_link}}".to_string()),
+            ..Default::default()
+        })
+        .build()
+        .await
+        .unwrap();
+
+    // Act
+    let email = subscribe_new_subscriber(&app).await;
+
+    // Assert
+    let message = app
+        .get_email_message_json(&email, "Please confirm your subscription")
+        .await;
+    let text = message["text"].as_str().unwrap();
+    assert!(text.starts_with("Confirm here: "));
+    assert!(!text.contains("{{confirmation_link}}"));
+
+    let subscription_token = sqlx::query!("SELECT subscription_token FROM subscription_tokens")
+        .fetch_one(&app.pg_pool)
+        .await
+        .expect("Failed to fetch subscription token")
+        .subscription_token;
+    assert!(text.contains(&format!(
+        "/subscriptions/confirm?subscription_token={}",
+        subscription_token
+    )));
+
+    let confirmation_links = app.get_confirmation_links(&email).await;
+    app.click_confirmation_link(&confirmation_links).await;
+    assert_eq!(get_subscription_status(&app, &email).await, "confirmed");
+}
+
+#[tokio::test]
+async fn click_expired_confirmation_link_ret_410() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    let email = subscribe_new_subscriber(&app).await;
+    let confirmation_links = app.get_confirmation_links(&email).await;
+    sqlx::query!("UPDATE subscription_tokens SET created_at = now() - interval '30 days'")
+        .execute(&app.pg_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let mut link = reqwest::Url::parse(&confirmation_links.html).unwrap();
+    link.set_port(Some(app.port)).unwrap();
+    let response = reqwest::get(link).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 410);
+    assert_eq!(get_subscription_status(&app, &email).await, "pending");
+}
+
+#[tokio::test]
+async fn confirm_with_unknown_subscription_token_ret_404() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+
+    // Act
+    let response = app
+        .get("/subscriptions/confirm?subscription_token=unknowntoken")
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn confirm_without_subscription_token_ret_400() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+
+    // Act
+    let response = app.get("/subscriptions/confirm").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn click_confirmation_link_moves_pending_subscriber_to_confirmed() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    let email = subscribe_new_subscriber(&app).await;
+    assert_eq!(get_subscription_status(&app, &email).await, "pending");
+    let confirmation_links = app.get_confirmation_links(&email).await;
+
+    // Act
+    app.click_confirmation_link(&confirmation_links).await;
+    // Clicking the link again doesn't change anything
+    app.click_confirmation_link(&confirmation_links).await;
+
+    // Assert
+    assert_eq!(get_subscription_status(&app, &email).await, "confirmed");
+}
+
+async fn count_welcome_emails(app: &TestApp, email: &str) -> usize {
+    app.get_email_messages_json()
+        .await
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|msg| {
+            msg["to"][0]["email"].as_str() == Some(email)
+                && msg["subject"].as_str() == Some(DEFAULT_WELCOME_SUBJECT)
+        })
+        .count()
+}
+
+#[tokio::test]
+async fn confirming_sends_one_welcome_email() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    let email = subscribe_new_subscriber(&app).await;
+    let confirmation_links = app.get_confirmation_links(&email).await;
+    assert_eq!(count_welcome_emails(&app, &email).await, 0);
+
+    // Act 1
+    app.click_confirmation_link(&confirmation_links).await;
+
+    // Assert
+    assert_eq!(count_welcome_emails(&app, &email).await, 1);
+
+    // Act 2 subscriber is already confirmed
+    app.click_confirmation_link(&confirmation_links).await;
+
+    // Assert
+    assert_eq!(count_welcome_emails(&app, &email).await, 1);
+}
+
+async fn get_subscription_status_by_token(app: &TestApp, token: &str) -> reqwest::Response {
+    app.get(&format!("/subscriptions/status?token={}", token))
+        .await
+}
+
+#[tokio::test]
+async fn subscription_status_reports_pending_then_confirmed() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    let email = subscribe_new_subscriber(&app).await;
+    let subscription_token = sqlx::query!("SELECT subscription_token FROM subscription_tokens")
+        .fetch_one(&app.pg_pool)
+        .await
+        .expect("Failed to fetch subscription token")
+        .subscription_token;
+
+    // Act 1 before confirmation
+    let response = get_subscription_status_by_token(&app, &subscription_token).await;
+
+    // Assert 1
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "pending");
+    assert!(!body.to_string().contains(&email));
+
+    // Act 2 after confirmation
+    let confirmation_links = app.get_confirmation_links(&email).await;
+    app.click_confirmation_link(&confirmation_links).await;
+    let response = get_subscription_status_by_token(&app, &subscription_token).await;
+
+    // Assert 2
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "confirmed");
+}
+
+#[tokio::test]
+async fn subscription_status_with_unknown_token_ret_404() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+
+    // Act
+    let response = get_subscription_status_by_token(&app, "unknowntoken").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+async fn get_subscription_token(app: &TestApp) -> String {
+    sqlx::query!("SELECT subscription_token FROM subscription_tokens")
+        .fetch_one(&app.pg_pool)
+        .await
+        .expect("Failed to fetch subscription token")
+        .subscription_token
+}
+
+#[tokio::test]
+async fn get_unsubscribe_link_does_not_change_status_but_post_does() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    let email = subscribe_new_subscriber(&app).await;
+    let confirmation_links = app.get_confirmation_links(&email).await;
+    app.click_confirmation_link(&confirmation_links).await;
+    let subscription_token = get_subscription_token(&app).await;
+    let unsubscribe_path = format!(
+        "/subscriptions/unsubscribe?subscription_token={}",
+        subscription_token
+    );
+
+    // Act 1 open unsubscribe link
+    let response = app.get(&unsubscribe_path).await;
+
+    // Assert 1
+    assert_eq!(response.status().as_u16(), 200);
+    let html = response.text().await.unwrap();
+    assert!(html.contains(r#"method="post""#));
+    assert_eq!(get_subscription_status(&app, &email).await, "confirmed");
+
+    // Act 2 submit confirmation form
+    let response = app
+        .post_form(&unsubscribe_path, serde_json::json!({}))
+        .await;
+
+    // Assert 2
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(get_subscription_status(&app, &email).await, "unsubscribed");
+}
+
+#[tokio::test]
+async fn unsubscribe_token_is_single_use() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    subscribe_new_subscriber(&app).await;
+    let unsubscribe_path = format!(
+        "/subscriptions/unsubscribe?subscription_token={}",
+        get_subscription_token(&app).await
+    );
+    let response = app
+        .post_form(&unsubscribe_path, serde_json::json!({}))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Act
+    let response = app
+        .post_form(&unsubscribe_path, serde_json::json!({}))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+// Random client IP so counters left in Redis by previous runs don't interfere
+fn random_client_ip() -> String {
+    format!(
+        "10.{}.{}.{}",
+        rand::random::<u8>(),
+        rand::random::<u8>(),
+        rand::random::<u8>()
+    )
+}
+
+#[tokio::test]
+async fn subscribe_over_rate_limit_from_same_client_ret_429() {
+    // Arrange
+    let max_requests = 3;
+    let app = TestApp::builder()
+        .subscribe_rate_limit(max_requests, 60)
+        .build()
+        .await
+        .unwrap();
+    let client_ip = random_client_ip();
+    let subscribe_body = || {
+        serde_urlencoded::to_string(serde_json::json!({
+            "name": Name().fake::<String>(),
+            "email": SafeEmail().fake::<String>()
+        }))
+        .unwrap()
+    };
+
+    // Act 1 subscribe up to the limit
+    for _ in 0..max_requests {
+        let response = app
+            .post_subscriptions_from(&client_ip, subscribe_body())
+            .await;
+
+        // Assert 1
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    // Act 2 one more subscribe from the same client
+    let response = app
+        .post_subscriptions_from(&client_ip, subscribe_body())
+        .await;
+
+    // Assert 2
+    assert_eq!(response.status().as_u16(), 429);
+    let retry_after: u64 = response
+        .headers()
+        .get("Retry-After")
+        .expect("Missing Retry-After header")
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(retry_after > 0 && retry_after <= 60);
+
+    // Act 3 other clients are not limited
+    let response = app
+        .post_subscriptions_from(&random_client_ip(), subscribe_body())
+        .await;
+
+    // Assert 3
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn spoofed_forwarded_for_hops_do_not_bypass_rate_limit() {
+    // Arrange
+    let max_requests = 2;
+    let app = TestApp::builder()
+        .subscribe_rate_limit(max_requests, 60)
+        .build()
+        .await
+        .unwrap();
+    let client_ip = random_client_ip();
+    let subscribe_body = || {
+        serde_urlencoded::to_string(serde_json::json!({
+            "name": Name().fake::<String>(),
+            "email": SafeEmail().fake::<String>()
+        }))
+        .unwrap()
+    };
+
+    // Act client prepends a new fake address to each request
+    let mut statuses = Vec::new();
+    for _ in 0..=max_requests {
+        let forwarded_for = format!("{}, {}", random_client_ip(), client_ip);
+        let response = app
+            .post_subscriptions_from(&forwarded_for, subscribe_body())
+            .await;
+        statuses.push(response.status().as_u16());
+    }
+
+    // Assert
+    assert_eq!(statuses, vec![200, 200, 429]);
+}
+
+#[tokio::test]
+async fn confirmation_link_respects_configured_base_path() {
+    // Arrange
+    let app = TestApp::builder()
+        .base_path("/newsletter")
+        .build()
+        .await
+        .unwrap();
+    let email: String = SafeEmail().fake();
+    let body = serde_json::json!({
+        "name": Name().fake::<String>(),
+        "email": email
+    });
+
+    // Act
+    let response = app.post_form("/newsletter/subscriptions", body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Assert
+    let confirmation_links = app.get_confirmation_links(&email).await;
+    let confirmation_link = reqwest::Url::parse(&confirmation_links.html).unwrap();
+    assert_eq!(
+        confirmation_link.path(),
+        "/newsletter/subscriptions/confirm"
+    );
+}
+
+#[tokio::test]
+async fn confirmation_link_uses_configured_public_scheme_and_host() {
+    // Arrange
+    let app = TestApp::builder()
+        .public_url("https", "newsletter.example.com", None)
+        .build()
+        .await
+        .unwrap();
+    let email: String = SafeEmail().fake();
+    let body = serde_json::json!({
+        "name": Name().fake::<String>(),
+        "email": email
+    });
+
+    // Act
+    let response = app.post_form("/subscriptions", body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Assert
+    let confirmation_links = app.get_confirmation_links(&email).await;
+    let confirmation_link = reqwest::Url::parse(&confirmation_links.html).unwrap();
+    assert_eq!(confirmation_link.scheme(), "https");
+    assert_eq!(confirmation_link.host_str(), Some("newsletter.example.com"));
+    assert_eq!(confirmation_link.port(), None);
+    assert_eq!(confirmation_link.path(), "/subscriptions/confirm");
+}
+
+async fn preflight_subscriptions(app: &TestApp, origin: &str) -> reqwest::Response {
+    app.client
+        .request(
+            reqwest::Method::OPTIONS,
+            format!("{}/subscriptions", app.addr),
+        )
+        .header("Origin", origin)
+        .header("Access-Control-Request-Method", "POST")
+        .header("Access-Control-Request-Headers", "Content-Type")
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+#[tokio::test]
+async fn cors_preflight_allows_only_configured_origins() {
+    // Arrange
+    let allowed_origin = "https://allowed.example.com";
+    let app = TestApp::builder()
+        .cors_allowed_origins(&[allowed_origin])
+        .build()
+        .await
+        .unwrap();
+
+    // Act 1 preflight from allowed origin
+    let response = preflight_subscriptions(&app, allowed_origin).await;
+
+    // Assert 1
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response
+            .headers()
+            .get("Access-Control-Allow-Origin")
+            .expect("Missing Access-Control-Allow-Origin header"),
+        allowed_origin
+    );
+
+    // Act 2 preflight from disallowed origin
+    let response = preflight_subscriptions(&app, "https://evil.example.com").await;
+
+    // Assert 2
+    assert_eq!(response.status().as_u16(), 403);
+    assert!(response
+        .headers()
+        .get("Access-Control-Allow-Origin")
+        .is_none());
+
+    // Act 3 actual request from disallowed origin
+    let response = app
+        .client
+        .get(&format!("{}/health", app.addr))
+        .header("Origin", "https://evil.example.com")
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // Assert 3
+    assert_eq!(response.status().as_u16(), 403);
+    assert!(response
+        .headers()
+        .get("Access-Control-Allow-Origin")
+        .is_none());
+}
+
+#[tokio::test]
+async fn cors_headers_are_not_added_when_no_origin_is_configured() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+
+    // Act
+    let response = preflight_subscriptions(&app, "https://allowed.example.com").await;
+
+    // Assert
+    assert!(response
+        .headers()
+        .get("Access-Control-Allow-Origin")
+        .is_none());
+}
+
+#[tokio::test]
+async fn subscribe_when_database_pool_is_exhausted_ret_503() {
+    // Arrange
+    let app = TestApp::builder()
+        .database_max_connections(1)
+        .build()
+        .await
+        .unwrap();
+    // Hold the only connection of app's pool
+    let _connection = app
+        .app_pg_pool
+        .acquire()
+        .await
+        .expect("Failed to acquire connection");
+
+    // Act
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 503);
+    assert!(response.headers().get("Retry-After").is_some());
+}
+
+#[tokio::test]
+async fn subscribe_with_domain_without_mx_records_is_accepted_when_mx_check_is_disabled() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+
+    // Act
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula%40gmial.con".into())
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn subscribe_with_domain_without_mx_records_ret_400() {
+    // Arrange
+    let app = TestApp::builder()
+        .mx_resolver(Arc::new(StubMxResolver(vec!["gmial.con"])))
+        .build()
+        .await
+        .unwrap();
+
+    // Act
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula%40gmial.con".into())
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(response.text().await.unwrap().contains("gmial.con"));
+    let n_subscriptions = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&app.pg_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_subscriptions, 0);
+
+    // Act 2 subscribe with domain that has MX records
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula%40gmail.com".into())
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn retried_subscribe_with_same_idempotency_key_creates_one_subscription_and_one_email() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    let email: String = SafeEmail().fake();
+    let body = serde_urlencoded::to_string(serde_json::json!({
+        "name": Name().fake::<String>(),
+        "email": email,
+        "idempotency_key": "double-submit"
+    }))
+    .unwrap();
+
+    // Act
+    let first_response = app.post_subscriptions(body.clone()).await;
+    let second_response = app.post_subscriptions(body).await;
+
+    // Assert
+    assert_eq!(first_response.status().as_u16(), 200);
+    assert_eq!(second_response.status().as_u16(), 200);
+
+    let n_subscriptions = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) AS "count!" FROM subscriptions WHERE email = $1"#,
+        email
+    )
+    .fetch_one(&app.pg_pool)
+    .await
+    .unwrap();
+    assert_eq!(n_subscriptions, 1);
+
+    let messages = app.get_email_messages_json().await;
+    let n_emails = messages
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|msg| {
+            msg["from"]["email"].as_str() == Some(app.email_client.sender_email())
+                && msg["to"][0]["email"].as_str() == Some(email.as_str())
+        })
+        .count();
+    assert_eq!(n_emails, 1);
+}
+
+#[tokio::test]
+async fn same_idempotency_key_for_different_emails_subscribes_both() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+
+    for email in ["ursula@example.com", "frank@example.com"] {
+        // Act
+        let response = app
+            .post_subscriptions(format!(
+                "name=le%20guin&email={}&idempotency_key=shared-key",
+                email
+            ))
+            .await;
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    let n_subscriptions = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&app.pg_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_subscriptions, 2);
+}
+
+#[tokio::test]
+async fn plus_addressed_email_is_stored_canonical_with_its_tag() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    let body = serde_json::json!({
+        "name": Name().fake::<String>(),
+        "email": "ursula+blog@example.com"
+    });
+
+    // Act
+    let response = app
+        .post_subscriptions(serde_urlencoded::to_string(body).unwrap())
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT email, plus_tag, delivery_email FROM subscriptions")
+        .fetch_one(&app.pg_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.email, "ursula@example.com");
+    assert_eq!(saved.plus_tag.as_deref(), Some("blog"));
+    assert_eq!(saved.delivery_email, "ursula+blog@example.com");
+}
+
+fn plus_addressed_email(tag: &str) -> String {
+    let email: String = SafeEmail().fake();
+    let (user, domain) = email.split_once('@').unwrap();
+    format!("{}+{}@{}", user, tag, domain)
+}
+
+#[tokio::test]
+async fn plus_addressed_subscriber_is_confirmed_by_code_sent_to_original_email() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    let email = plus_addressed_email("blog");
+    let body = serde_json::json!({"name": Name().fake::<String>(), "email": email});
+    app.post_subscriptions(serde_urlencoded::to_string(body).unwrap())
+        .await;
+    let code = app.get_confirmation_code(&email).await;
+
+    // Act
+    let response = app.post_confirm_code(&email, &code).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let status = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.pg_pool)
+        .await
+        .expect("Failed to fetch saved subscription")
+        .status;
+    assert_eq!(status, "confirmed");
+}
+
+#[tokio::test]
+async fn subscribing_another_plus_tag_of_subscribed_mailbox_is_accepted_once() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    let name: String = Name().fake();
+    let first_email = plus_addressed_email("blog");
+    let second_email = first_email.replacen("+blog@", "+news@", 1);
+    let body = serde_json::json!({"name": name, "email": first_email});
+    app.post_subscriptions(serde_urlencoded::to_string(body).unwrap())
+        .await;
+
+    // Act
+    let body = serde_json::json!({"name": name, "email": second_email});
+    let response = app
+        .post_subscriptions(serde_urlencoded::to_string(body).unwrap())
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT delivery_email FROM subscriptions")
+        .fetch_all(&app.pg_pool)
+        .await
+        .expect("Failed to fetch saved subscriptions");
+    assert_eq!(saved.len(), 1);
+    assert_eq!(saved[0].delivery_email, first_email);
+}
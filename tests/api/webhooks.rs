@@ -0,0 +1,115 @@
+use crate::helpers::TestApp;
+use fake::faker::internet::en::SafeEmail;
+use fake::faker::name::en::Name;
+use fake::Fake;
+
+async fn get_subscription_status(app: &TestApp, email: &str) -> String {
+    sqlx::query!("SELECT status FROM subscriptions WHERE email = $1", email)
+        .fetch_one(&app.pg_pool)
+        .await
+        .expect("Failed to fetch subscription status")
+        .status
+}
+
+#[tokio::test]
+async fn bounce_event_with_valid_signature_marks_subscriber_as_bounced() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    let email: String = SafeEmail().fake();
+    app.create_confirmed_subscriber(serde_json::json!({
+        "name": Name().fake::<String>(),
+        "email": email
+    }))
+    .await;
+
+    // Act
+    let response = app
+        .post_email_event(
+            &serde_json::json!({
+                "email": email,
+                "event": "bounce"
+            }),
+            None,
+        )
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(get_subscription_status(&app, &email).await, "bounced");
+}
+
+#[tokio::test]
+async fn email_event_with_invalid_signature_ret_401() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    let email: String = SafeEmail().fake();
+    app.create_confirmed_subscriber(serde_json::json!({
+        "name": Name().fake::<String>(),
+        "email": email
+    }))
+    .await;
+
+    // Act
+    let response = app
+        .post_email_event(
+            &serde_json::json!({
+                "email": email,
+                "event": "bounce"
+            }),
+            Some(&"0".repeat(64)),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(get_subscription_status(&app, &email).await, "confirmed");
+}
+
+#[tokio::test]
+async fn email_event_signed_outside_tolerance_window_ret_401() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+    let email: String = SafeEmail().fake();
+    app.create_confirmed_subscriber(serde_json::json!({
+        "name": Name().fake::<String>(),
+        "email": email
+    }))
+    .await;
+    let an_hour_ago = chrono::Utc::now().timestamp() - 3600;
+
+    // Act
+    let response = app
+        .post_email_event_signed_at(
+            &serde_json::json!({
+                "email": email,
+                "event": "bounce"
+            }),
+            an_hour_ago,
+            None,
+        )
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(get_subscription_status(&app, &email).await, "confirmed");
+}
+
+#[tokio::test]
+async fn email_event_of_unknown_email_is_ignored_with_200() {
+    // Arrange
+    let app = TestApp::builder().build().await.unwrap();
+
+    // Act
+    let response = app
+        .post_email_event(
+            &serde_json::json!({
+                "email": SafeEmail().fake::<String>(),
+                "event": "complaint"
+            }),
+            None,
+        )
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+}